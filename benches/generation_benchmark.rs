@@ -66,7 +66,7 @@ fn bench_crypto_operations(c: &mut Criterion) {
     
     group.bench_function("keypair_generation", |b| {
         b.iter(|| {
-            black_box(generate_keypair());
+            black_box(generate_keypair().unwrap());
         })
     });
     
@@ -100,11 +100,44 @@ fn bench_crypto_operations(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_candidate_kernels(c: &mut Criterion) {
+    use onion_generator::crypto::*;
+    use onion_generator::dispatch::masked_eq;
+    use onion_generator::Kernel;
+
+    // The per-candidate work after key generation: the key prefix filter on
+    // every candidate, the hostname encoding on those that pass it
+    let mut group = c.benchmark_group("candidate_kernels");
+    let public_key = generate_keypair().unwrap().1.to_bytes();
+    let mut mask = [0u8; 32];
+    mask[..4].copy_from_slice(&[0xff; 4]);
+    let value = [0u8; 32];
+
+    for kernel in Kernel::ALL.into_iter().filter(|kernel| kernel.is_supported()) {
+        group.bench_with_input(BenchmarkId::new("key_filter", kernel), &kernel, |b, &kernel| {
+            b.iter(|| black_box(masked_eq(kernel, black_box(&public_key), &mask, &value)))
+        });
+    }
+
+    group.bench_function("encode_onion_address", |b| {
+        b.iter(|| black_box(encode_onion_address(black_box(&public_key))))
+    });
+    group.bench_function("encode_via_base32_encode", |b| {
+        b.iter(|| {
+            let data = [&public_key[..], &calculate_checksum(black_box(&public_key)).unwrap(), &[3]].concat();
+            black_box(format!("{}.onion", base32_encode(&data)))
+        })
+    });
+
+    group.finish();
+}
+
 criterion_group!(
     benches,
     bench_single_generation,
     bench_prefix_generation,
     bench_common_prefixes,
-    bench_crypto_operations
+    bench_crypto_operations,
+    bench_candidate_kernels
 );
 criterion_main!(benches);
@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use onion_generator::{parse_onion_address, ParseError, ParsedOnion};
+
+// Every 32-byte key encodes to an address that parses back to it, unless the
+// key is not a curve point
+fuzz_target!(|public_key: [u8; 32]| {
+    let address = ParsedOnion::from_public_key(public_key);
+    match parse_onion_address(&address.address()) {
+        Ok(parsed) => assert_eq!(parsed, address),
+        Err(e) => assert_eq!(e, ParseError::InvalidPublicKey),
+    }
+});
@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use onion_generator::parse_onion_address;
+
+// Arbitrary strings must parse or fail cleanly, and whatever parses must
+// print back to an address that parses to the same thing
+fuzz_target!(|input: &str| {
+    if let Ok(parsed) = parse_onion_address(input) {
+        assert_eq!(parse_onion_address(&parsed.to_string()), Ok(parsed));
+        assert_eq!(parsed.address(), input.to_lowercase().trim_end_matches(".onion"));
+    }
+});
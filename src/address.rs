@@ -0,0 +1,185 @@
+use crate::onion::{ADDRESS_LEN, BASE32_ALPHABET};
+use ed25519_dalek::VerifyingKey;
+use crate::crypto::onion_checksum;
+use std::fmt;
+
+/// Version byte of v3 onion addresses
+pub const ONION_VERSION: u8 = 0x03;
+
+/// The parts of a valid v3 onion address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParsedOnion {
+    pub public_key: [u8; 32],
+    pub checksum: [u8; 2],
+    pub version: u8,
+}
+
+impl ParsedOnion {
+    /// The address of `public_key`, with its checksum computed
+    pub fn from_public_key(public_key: [u8; 32]) -> Self {
+        Self {
+            public_key,
+            checksum: onion_checksum(&public_key),
+            version: ONION_VERSION,
+        }
+    }
+
+    /// The 56-character lowercase address without `.onion`
+    pub fn address(&self) -> String {
+        let mut data = [0u8; 35];
+        data[..32].copy_from_slice(&self.public_key);
+        data[32..34].copy_from_slice(&self.checksum);
+        data[34] = self.version;
+        crate::crypto::base32_encode(&data)
+    }
+}
+
+impl fmt::Display for ParsedOnion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.onion", self.address())
+    }
+}
+
+/// Why a string is not a v3 onion address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The address part is not 56 characters long
+    InvalidLength { len: usize },
+    /// A character outside the base32 alphabet, at this character index
+    InvalidCharacter { position: usize, character: char },
+    /// The version byte is not 3
+    UnsupportedVersion(u8),
+    /// The checksum does not belong to the public key
+    ChecksumMismatch { expected: [u8; 2], found: [u8; 2] },
+    /// The public key is not a point on the ed25519 curve
+    InvalidPublicKey,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::InvalidLength { len } => {
+                write!(f, "address has {} characters instead of {}", len, ADDRESS_LEN)
+            }
+            ParseError::InvalidCharacter { position, character } => {
+                write!(f, "invalid character {:?} at position {}", character, position)
+            }
+            ParseError::UnsupportedVersion(version) => write!(f, "unsupported onion address version {}", version),
+            ParseError::ChecksumMismatch { expected, found } => write!(
+                f,
+                "checksum mismatch: expected {:02x}{:02x}, found {:02x}{:02x}",
+                expected[0], expected[1], found[0], found[1]
+            ),
+            ParseError::InvalidPublicKey => write!(f, "public key is not a valid ed25519 point"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parse and validate a v3 onion address
+///
+/// Accepts the 56-character address with or without `.onion`, in any letter
+/// case. Checks, in order, the length, the alphabet, the version byte, the
+/// checksum and that the public key decodes to a curve point. Never panics,
+/// whatever the input.
+pub fn parse_onion_address(input: &str) -> Result<ParsedOnion, ParseError> {
+    let address = match input.len().checked_sub(".onion".len()) {
+        Some(end) if input.is_char_boundary(end) && input[end..].eq_ignore_ascii_case(".onion") => &input[..end],
+        _ => input,
+    };
+    let len = address.chars().count();
+    if len != ADDRESS_LEN {
+        return Err(ParseError::InvalidLength { len });
+    }
+
+    // 56 characters of 5 bits each are exactly 35 bytes
+    let mut data = [0u8; 35];
+    let (mut buffer, mut bits, mut filled) = (0u16, 0, 0);
+    for (position, character) in address.chars().enumerate() {
+        let value = BASE32_ALPHABET
+            .find(character.to_ascii_lowercase())
+            .ok_or(ParseError::InvalidCharacter { position, character })?;
+        buffer = (buffer << 5) | value as u16;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            data[filled] = (buffer >> bits) as u8;
+            buffer &= (1 << bits) - 1;
+            filled += 1;
+        }
+    }
+
+    let mut public_key = [0u8; 32];
+    public_key.copy_from_slice(&data[..32]);
+    let found = [data[32], data[33]];
+    let version = data[34];
+
+    if version != ONION_VERSION {
+        return Err(ParseError::UnsupportedVersion(version));
+    }
+    let expected = onion_checksum(&public_key);
+    if found != expected {
+        return Err(ParseError::ChecksumMismatch { expected, found });
+    }
+    if VerifyingKey::from_bytes(&public_key).is_err() {
+        return Err(ParseError::InvalidPublicKey);
+    }
+
+    Ok(ParsedOnion { public_key, checksum: found, version })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate_onion_address;
+
+    #[test]
+    fn test_parse_onion_address() {
+        let hostname = generate_onion_address().unwrap().hostname;
+        let parsed = parse_onion_address(&hostname).unwrap();
+
+        assert_eq!(parsed.to_string(), hostname);
+        assert_eq!(parse_onion_address(&hostname.to_uppercase()), Ok(parsed));
+        assert_eq!(parse_onion_address(&parsed.address()), Ok(parsed));
+        assert_eq!(ParsedOnion::from_public_key(parsed.public_key), parsed);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let address = generate_onion_address().unwrap().hostname.replace(".onion", "");
+
+        assert_eq!(parse_onion_address(""), Err(ParseError::InvalidLength { len: 0 }));
+        assert_eq!(parse_onion_address("example.onion"), Err(ParseError::InvalidLength { len: 7 }));
+        assert_eq!(
+            parse_onion_address(&format!("{}1{}", &address[..3], &address[4..])),
+            Err(ParseError::InvalidCharacter { position: 3, character: '1' })
+        );
+        assert!(matches!(
+            parse_onion_address(&format!("{}ab", &address[..54])),
+            Err(ParseError::UnsupportedVersion(_))
+        ));
+
+        let mut corrupted = address.clone();
+        corrupted.replace_range(0..1, if address.starts_with('a') { "b" } else { "a" });
+        assert!(matches!(parse_onion_address(&corrupted), Err(ParseError::ChecksumMismatch { .. })));
+    }
+
+    #[test]
+    fn test_parse_arbitrary_input() {
+        // Multi-byte characters must not be split when looking for ".onion"
+        for input in ["é.onion", "ééééé", ".onion", "\u{0}", &"é".repeat(56), &"a".repeat(56)] {
+            assert!(parse_onion_address(input).is_err());
+        }
+    }
+
+    #[test]
+    fn test_invalid_public_key() {
+        // y = 2 is not on the curve
+        let mut public_key = [0u8; 32];
+        public_key[0] = 2;
+        let address = ParsedOnion::from_public_key(public_key).address();
+
+        assert_eq!(parse_onion_address(&address), Err(ParseError::InvalidPublicKey));
+    }
+}
@@ -0,0 +1,84 @@
+use crate::{ErrorCode, KeyCustody, ResultExt};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use std::fs;
+use std::path::Path;
+
+/// File in a service directory holding its label, see [`crate::DirectorySink::labels`]
+pub(crate) const LABEL_FILE: &str = "label";
+
+/// One service directory written by [`crate::DirectorySink`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressEntry {
+    pub hostname: String,
+    /// What the address is for, if it was given one
+    pub label: Option<String>,
+    /// How its secret key is kept here; `None` if there is no secret key file
+    pub custody: Option<KeyCustody>,
+    /// When the hostname file was written
+    pub created: Option<DateTime<Local>>,
+}
+
+/// The addresses saved under an output directory, oldest first
+///
+/// Entries that are not service directories are skipped.
+pub fn read_address_book(dir: impl AsRef<Path>) -> Result<Vec<AddressEntry>> {
+    let dir = dir.as_ref();
+    let entries = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read output directory {}", dir.display()))
+        .with_code(ErrorCode::InvalidConfig)?;
+
+    let mut book = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let hostname_file = path.join("hostname");
+        let Ok(hostname) = fs::read_to_string(&hostname_file) else {
+            continue;
+        };
+        let label = fs::read_to_string(path.join(LABEL_FILE))
+            .ok()
+            .map(|label| label.trim().to_string())
+            .filter(|label| !label.is_empty());
+        let custody = if path.join("hs_ed25519_secret_key").is_file() {
+            Some(KeyCustody::Local)
+        } else if path.join("hs_ed25519_secret_key.sealed").is_file() {
+            Some(KeyCustody::Sealed)
+        } else {
+            None
+        };
+        let created = fs::metadata(&hostname_file).and_then(|metadata| metadata.modified()).ok().map(DateTime::from);
+        book.push(AddressEntry { hostname: hostname.trim().to_string(), label, custody, created });
+    }
+    book.sort_by(|a, b| a.created.cmp(&b.created).then_with(|| a.hostname.cmp(&b.hostname)));
+    Ok(book)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+    use crate::{DirectorySink, OnionResult, ResultSink};
+
+    #[test]
+    fn test_labels_are_listed_with_their_keys() {
+        let dir = TempPath::new("book");
+        let mut labels = vec![None, Some(" shop backend \n".to_string())].into_iter();
+        let mut sink = DirectorySink::new(&dir).unwrap().labels(move |_| labels.next().flatten());
+
+        let results: Vec<OnionResult> = (0..2).map(|_| crate::generate_onion_address().unwrap()).collect();
+        for result in &results {
+            sink.found(result).unwrap();
+        }
+        sink.found(&crate::without_private_key(crate::generate_onion_address().unwrap())).unwrap();
+        fs::write(dir.join("notes.txt"), "not a service").unwrap();
+
+        let book = read_address_book(&dir).unwrap();
+        assert_eq!(book.len(), 3);
+        let entry = |hostname: &str| book.iter().find(|entry| entry.hostname == hostname).unwrap();
+        assert_eq!(entry(&results[0].hostname).label, None);
+        assert_eq!(entry(&results[1].hostname).label.as_deref(), Some("shop backend"));
+        assert_eq!(entry(&results[1].hostname).custody, Some(KeyCustody::Local));
+        assert_eq!(book.iter().filter(|entry| entry.custody.is_none()).count(), 1);
+        assert!(book.iter().all(|entry| entry.created.is_some()));
+    }
+}
@@ -0,0 +1,175 @@
+use crate::sink::ResultSink;
+use crate::crypto::base64_encode;
+use crate::logging::log_event;
+use crate::{confined_join, json_logs, parse_master_seed, print_plain, GenerationStats, LiveMatcher, OnionResult};
+use anyhow::{Context, Result};
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use zeroize::Zeroizing;
+
+/// Domain separator at the start of every signed attestation message
+const ATTESTATION_CONTEXT: &str = "onion-generator attestation v1";
+
+/// Signed statement that an instance found an address; never contains key material
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attestation {
+    pub hostname: String,
+    pub timestamp: String,
+    /// The pattern the address matched
+    pub pattern: String,
+    /// Keys the instance had generated when it recorded the find
+    pub attempts: u128,
+    /// Base64 public attestation key of the instance
+    pub instance: String,
+    /// Base64 ed25519 signature over [`Attestation::message`]
+    pub signature: String,
+}
+
+impl Attestation {
+    /// The exact bytes that are signed: the context line and each field on its own line
+    pub fn message(&self) -> String {
+        format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            ATTESTATION_CONTEXT, self.hostname, self.timestamp, self.pattern, self.attempts, self.instance
+        )
+    }
+
+    /// Check the signature against the embedded instance key
+    ///
+    /// Collectors should also check `instance` against the keys they expect.
+    pub fn verify(&self) -> Result<()> {
+        let engine = base64::engine::general_purpose::STANDARD;
+        let instance: [u8; 32] = engine
+            .decode(&self.instance)?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Instance key must be 32 bytes"))?;
+        let signature: [u8; 64] = engine
+            .decode(&self.signature)?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Signature must be 64 bytes"))?;
+
+        VerifyingKey::from_bytes(&instance)?
+            .verify(self.message().as_bytes(), &Signature::from_bytes(&signature))
+            .context("Invalid attestation signature")
+    }
+}
+
+/// Per-instance key that signs attestations
+pub struct Attestor {
+    key: SigningKey,
+}
+
+impl Attestor {
+    /// Load the key seed from `path`, creating a new random key if the file is missing
+    pub fn load_or_create(path: &Path) -> Result<Self> {
+        let seed = match fs::read(path) {
+            Ok(data) => Zeroizing::new(parse_master_seed(&Zeroizing::new(data))?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let key = SigningKey::generate(&mut rand::rngs::OsRng);
+                let hex: Zeroizing<String> =
+                    Zeroizing::new(key.to_bytes().iter().map(|b| format!("{:02x}", b)).collect());
+                let mut options = fs::OpenOptions::new();
+                options.write(true).create_new(true);
+                #[cfg(unix)]
+                std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+                writeln!(options.open(path)?, "{}", hex.as_str())?;
+                Zeroizing::new(key.to_bytes())
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        Ok(Self { key: SigningKey::from_bytes(&seed) })
+    }
+
+    /// Base64 public key identifying this instance
+    pub fn instance(&self) -> String {
+        base64_encode(self.key.verifying_key().as_bytes())
+    }
+
+    pub fn attest(&self, hostname: &str, pattern: &str, attempts: u128) -> Attestation {
+        let mut attestation = Attestation {
+            hostname: hostname.to_string(),
+            timestamp: chrono::Local::now().to_rfc3339(),
+            pattern: pattern.to_string(),
+            attempts,
+            instance: self.instance(),
+            signature: String::new(),
+        };
+        attestation.signature = base64_encode(&self.key.sign(attestation.message().as_bytes()).to_bytes());
+        attestation
+    }
+}
+
+/// Prints a signed attestation for every find and stores it next to the keys
+///
+/// Add it with `WorkerPool::add_sink`, since it reads the pool's statistics
+/// and live patterns. In each of `dirs`, the record is written to
+/// `<dir>/<hostname>/attestation.json`.
+pub struct AttestationSink {
+    attestor: Attestor,
+    matcher: LiveMatcher,
+    stats: Arc<GenerationStats>,
+    dirs: Vec<PathBuf>,
+}
+
+impl AttestationSink {
+    pub fn new(attestor: Attestor, matcher: LiveMatcher, stats: Arc<GenerationStats>, dirs: Vec<PathBuf>) -> Self {
+        Self { attestor, matcher, stats, dirs }
+    }
+}
+
+impl ResultSink for AttestationSink {
+    fn found(&mut self, result: &OnionResult) -> Result<()> {
+        let address = result.hostname.trim_end_matches(".onion");
+        let matcher = self.matcher.current();
+        // The pattern may have been removed through the control socket meanwhile
+        let pattern = matcher
+            .includes()
+            .iter()
+            .find(|spec| spec.is_match(address))
+            .map_or_else(|| "unknown".to_string(), |spec| spec.to_string());
+
+        let attestation = self.attestor.attest(&result.hostname, &pattern, self.stats.get().0);
+        let json = serde_json::to_string(&attestation)?;
+        log_event(
+            "attestation",
+            &format!("Attestation of {}", result.hostname),
+            serde_json::json!({ "attestation": attestation }),
+        );
+        if !json_logs() {
+            print_plain(&format!("[i] Attestation: {}", json));
+        }
+        for dir in &self.dirs {
+            let service_dir = confined_join(dir, &result.hostname)?;
+            fs::create_dir_all(&service_dir)?;
+            fs::write(service_dir.join("attestation.json"), format!("{}\n", json))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+
+    #[test]
+    fn test_attestation_roundtrip() {
+        let path = TempPath::new("attest.key");
+        let attestor = Attestor::load_or_create(&path).unwrap();
+        let reloaded = Attestor::load_or_create(&path).unwrap();
+        assert_eq!(attestor.instance(), reloaded.instance());
+
+        let attestation = attestor.attest("abc.onion", "prefix \"ab\"", 1 << 70);
+        let parsed: Attestation = serde_json::from_str(&serde_json::to_string(&attestation).unwrap()).unwrap();
+        assert!(parsed.verify().is_ok());
+
+        let mut forged = parsed.clone();
+        forged.attempts = 1;
+        assert!(forged.verify().is_err());
+    }
+}
@@ -0,0 +1,210 @@
+use crate::bench::{append_json_line, hardware_fingerprint, read_json_lines};
+use crate::crypto::{expand_secret_key, random_seed};
+use crate::onion::onion_result_from_expanded_key;
+use crate::{CandidateSource, OnionResult};
+use anyhow::{anyhow, bail, Result};
+use curve25519_dalek::edwards::EdwardsPoint;
+use curve25519_dalek::scalar::Scalar;
+use ed25519_dalek::SigningKey;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha512};
+use std::hint::black_box;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
+
+/// Batch sizes tried by [`tune_batch_size`]
+pub const BATCH_SIZES: &[usize] = &[16, 64, 256, 1024, 4096];
+
+/// Candidates stepped from one random key by point addition
+///
+/// Each batch starts from a fresh key and walks `batch_size` keys from it by
+/// adding 8·B to the public key and 8 to the secret scalar, which keeps the
+/// scalar clamped, so a single base point multiplication serves the whole
+/// batch. Larger batches amortise that multiplication further but keep more
+/// compressed keys in cache, which is what [`tune_batch_size`] weighs.
+///
+/// A match is saved as the expanded secret key Tor stores, with a nonce
+/// prefix of its own. There is no seed behind it, so results have no
+/// [`OnionResult::seed`] and cannot be handed to a token.
+pub struct BatchedKeys {
+    batch_size: usize,
+    /// 8·B, the step between two public keys of a batch
+    step: EdwardsPoint,
+    /// Expanded secret key of the first candidate of the batch
+    base: Zeroizing<[u8; 64]>,
+    keys: Vec<[u8; 32]>,
+    next: usize,
+}
+
+impl BatchedKeys {
+    pub fn new(batch_size: usize) -> Self {
+        let batch_size = batch_size.max(1);
+        Self {
+            batch_size,
+            step: EdwardsPoint::mul_base(&Scalar::from(8u8)),
+            base: Zeroizing::new([0; 64]),
+            keys: Vec::with_capacity(batch_size),
+            next: 0,
+        }
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    fn refill(&mut self) -> Result<()> {
+        let expanded = Zeroizing::new(expand_secret_key(&*random_seed()?)?);
+        self.base.copy_from_slice(&expanded);
+        let mut point = EdwardsPoint::mul_base_clamped(scalar_bytes(&self.base));
+        self.keys.clear();
+        for _ in 0..self.batch_size {
+            self.keys.push(point.compress().to_bytes());
+            point += self.step;
+        }
+        self.next = 0;
+        Ok(())
+    }
+
+    /// Expanded secret key of the candidate at `index` in the batch
+    fn expanded_key(&self, index: usize) -> Result<Zeroizing<[u8; 64]>> {
+        let mut key = Zeroizing::new([0u8; 64]);
+        // The scalar plus 8 * index, as a little-endian 256-bit integer
+        let mut carry = 8 * index as u64;
+        for (byte, base) in key[..32].iter_mut().zip(&self.base[..32]) {
+            let sum = *base as u64 + (carry & 0xff);
+            *byte = sum as u8;
+            carry = (carry >> 8) + (sum >> 8);
+        }
+        if key[31] & 0x80 != 0 {
+            bail!("Batch scalar left the clamped range");
+        }
+        // Keys of a batch must not share a nonce prefix: two signatures of
+        // one message by related scalars would give the scalar away
+        let prefix = Sha512::new()
+            .chain_update(&self.base[32..])
+            .chain_update((index as u64).to_le_bytes())
+            .finalize();
+        key[32..].copy_from_slice(&prefix[..32]);
+        Ok(key)
+    }
+}
+
+impl CandidateSource for BatchedKeys {
+    fn next_public_key(&mut self) -> Result<[u8; 32]> {
+        if self.next == self.keys.len() {
+            self.refill()?;
+        }
+        self.next += 1;
+        Ok(self.keys[self.next - 1])
+    }
+
+    fn signing_key(&mut self) -> Result<SigningKey> {
+        bail!("Batched keys have no seed; their results carry the expanded secret key")
+    }
+
+    fn onion_result(&mut self, hostname: String) -> Result<OnionResult> {
+        let index = self.next.checked_sub(1).ok_or_else(|| anyhow!("No candidate has been drawn yet"))?;
+        let expanded = self.expanded_key(index)?;
+        let public_key = self.keys[index];
+        // One multiplication per match guards the additions
+        if EdwardsPoint::mul_base_clamped(scalar_bytes(&expanded)).compress().to_bytes() != public_key {
+            bail!("Batched key of {} does not match its public key", hostname);
+        }
+        Ok(onion_result_from_expanded_key(&*expanded, &public_key, hostname))
+    }
+}
+
+fn scalar_bytes(expanded: &[u8; 64]) -> [u8; 32] {
+    expanded[..32].try_into().unwrap()
+}
+
+/// Fastest batch size on a machine, stored as a JSON line in the state file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchTuning {
+    pub timestamp: String,
+    /// CPU model, architecture and core count the size was measured on
+    pub hardware: String,
+    pub batch_size: usize,
+    pub keys_per_sec: f64,
+}
+
+/// Time [`BatchedKeys`] with each of [`BATCH_SIZES`] for `duration` and return the fastest
+///
+/// Runs on the calling thread. Every size draws whole batches, so each pays
+/// for its own multiplications.
+pub fn tune_batch_size(duration: Duration) -> Result<BatchTuning> {
+    let mut best: Option<(usize, f64)> = None;
+    for &batch_size in BATCH_SIZES {
+        let mut keys = BatchedKeys::new(batch_size);
+        let (start, mut count) = (Instant::now(), 0u64);
+        while start.elapsed() < duration {
+            for _ in 0..batch_size {
+                black_box(keys.next_public_key()?);
+            }
+            count += batch_size as u64;
+        }
+        let rate = count as f64 / start.elapsed().as_secs_f64();
+        if best.is_none_or(|(_, best_rate)| rate > best_rate) {
+            best = Some((batch_size, rate));
+        }
+    }
+    let (batch_size, keys_per_sec) = best.ok_or_else(|| anyhow!("No batch sizes to try"))?;
+    Ok(BatchTuning {
+        timestamp: chrono::Local::now().to_rfc3339(),
+        hardware: hardware_fingerprint(),
+        batch_size,
+        keys_per_sec,
+    })
+}
+
+/// The latest tuning stored in `path` for this machine, if any
+pub fn stored_batch_tuning(path: &Path) -> Result<Option<BatchTuning>> {
+    let hardware = hardware_fingerprint();
+    Ok(read_json_lines::<BatchTuning>(path)?.into_iter().rev().find(|tuning| tuning.hardware == hardware))
+}
+
+/// Append `tuning` to the state file at `path`, creating it if needed
+pub fn store_batch_tuning(path: &Path, tuning: &BatchTuning) -> Result<()> {
+    append_json_line(path, tuning)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+    use crate::{decode_onion_address, hostname_for_public_key, self_check_result};
+
+    #[test]
+    fn test_batched_keys_save_working_keys() {
+        let mut keys = BatchedKeys::new(3);
+        assert!(keys.onion_result(String::new()).is_err());
+        let drawn: Vec<_> = (0..7).map(|_| keys.next_public_key().unwrap()).collect();
+        assert_eq!(keys.keys.len(), 3);
+        // Within a batch each key is the previous one plus 8·B
+        let step = EdwardsPoint::mul_base(&Scalar::from(8u8));
+        let first = curve25519_dalek::edwards::CompressedEdwardsY(drawn[3]).decompress().unwrap();
+        assert_eq!((first + step).compress().to_bytes(), drawn[4]);
+        assert!(keys.signing_key().is_err());
+
+        let result = keys.onion_result(hostname_for_public_key(&drawn[6])).unwrap();
+        assert_eq!(decode_onion_address(&result.hostname).unwrap(), drawn[6]);
+        assert!(result.seed.is_none());
+        self_check_result(&result).unwrap();
+
+        // Keys of one batch get different nonce prefixes
+        assert_ne!(keys.expanded_key(0).unwrap()[32..], keys.expanded_key(1).unwrap()[32..]);
+    }
+
+    #[test]
+    fn test_batch_tuning_is_stored_per_machine() {
+        let path = TempPath::new("batch.jsonl");
+        let tuning = tune_batch_size(Duration::from_millis(5)).unwrap();
+        assert!(BATCH_SIZES.contains(&tuning.batch_size) && tuning.keys_per_sec > 0.0);
+        assert!(stored_batch_tuning(&path).unwrap().is_none());
+        let other = BatchTuning { hardware: "another machine".to_string(), batch_size: 1, ..tuning.clone() };
+        store_batch_tuning(&path, &tuning).unwrap();
+        store_batch_tuning(&path, &other).unwrap();
+        assert_eq!(stored_batch_tuning(&path).unwrap().unwrap().batch_size, tuning.batch_size);
+    }
+}
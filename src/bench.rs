@@ -0,0 +1,176 @@
+use crate::{calibrate_rate, crypto_backend};
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+/// One benchmark run, stored as a JSON line in the history file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchRecord {
+    pub timestamp: String,
+    pub version: String,
+    /// CPU model, architecture and core count; runs are only compared on equal fingerprints
+    pub hardware: String,
+    pub backend: String,
+    pub features: Vec<String>,
+    pub workers: usize,
+    pub keys_per_sec: f64,
+}
+
+/// Generate keys on `workers` threads for `duration` and return the total rate
+pub fn run_benchmark(workers: usize, duration: Duration) -> Result<BenchRecord> {
+    let keys_per_sec = thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers.max(1))
+            .map(|_| scope.spawn(|| calibrate_rate(duration)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().map_err(|_| anyhow::anyhow!("Benchmark thread panicked"))?)
+            .sum::<Result<f64>>()
+    })?;
+
+    Ok(BenchRecord {
+        timestamp: chrono::Local::now().to_rfc3339(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        hardware: hardware_fingerprint(),
+        backend: crypto_backend().to_string(),
+        features: enabled_features(),
+        workers,
+        keys_per_sec,
+    })
+}
+
+/// Read all records from a history file; a missing file is an empty history
+pub fn load_history(path: &Path) -> Result<Vec<BenchRecord>> {
+    read_json_lines(path)
+}
+
+/// Append a record to a history file, creating it if needed
+pub fn append_history(path: &Path, record: &BenchRecord) -> Result<()> {
+    append_json_line(path, record)
+}
+
+/// One value per non-empty line of `path`; a missing file has none
+pub(crate) fn read_json_lines<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).with_context(|| format!("Invalid record in {}", path.display())))
+        .collect()
+}
+
+/// Append `value` to `path` as one JSON line, creating the file if needed
+pub(crate) fn append_json_line<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(value)?)?;
+    Ok(())
+}
+
+/// Previous and best earlier runs on the same hardware with the same worker count
+pub fn comparable<'a>(
+    history: &'a [BenchRecord],
+    record: &BenchRecord,
+) -> (Option<&'a BenchRecord>, Option<&'a BenchRecord>) {
+    let same: Vec<&BenchRecord> = history
+        .iter()
+        .filter(|past| past.hardware == record.hardware && past.workers == record.workers)
+        .collect();
+    let previous = same.last().copied();
+    let best = same.into_iter().max_by(|a, b| a.keys_per_sec.total_cmp(&b.keys_per_sec));
+    (previous, best)
+}
+
+/// Relative change from `before` to `after` in percent
+pub fn percent_change(before: f64, after: f64) -> f64 {
+    if before > 0.0 {
+        (after - before) / before * 100.0
+    } else {
+        0.0
+    }
+}
+
+pub(crate) fn enabled_features() -> Vec<String> {
+    let mut features = Vec::new();
+    if cfg!(feature = "neon") {
+        features.push("neon".to_string());
+    }
+    if cfg!(feature = "regex") {
+        features.push("regex".to_string());
+    }
+    features
+}
+
+pub(crate) fn hardware_fingerprint() -> String {
+    let cpu = fs::read_to_string("/proc/cpuinfo")
+        .ok()
+        .and_then(|info| {
+            info.lines()
+                .find(|line| line.starts_with("model name") || line.starts_with("Hardware"))
+                .and_then(|line| line.split_once(':'))
+                .map(|(_, model)| model.trim().to_string())
+        })
+        .unwrap_or_else(|| "unknown CPU".to_string());
+    let cores = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    format!("{} ({}, {} cores)", cpu, std::env::consts::ARCH, cores)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+
+    fn record(hardware: &str, workers: usize, keys_per_sec: f64) -> BenchRecord {
+        BenchRecord {
+            timestamp: String::new(),
+            version: String::new(),
+            hardware: hardware.to_string(),
+            backend: String::new(),
+            features: Vec::new(),
+            workers,
+            keys_per_sec,
+        }
+    }
+
+    #[test]
+    fn test_comparable_runs() {
+        let history = vec![
+            record("cpu", 4, 100.0),
+            record("cpu", 4, 300.0),
+            record("other", 4, 900.0),
+            record("cpu", 2, 500.0),
+            record("cpu", 4, 200.0),
+        ];
+        let (previous, best) = comparable(&history, &record("cpu", 4, 250.0));
+
+        assert_eq!(previous.unwrap().keys_per_sec, 200.0);
+        assert_eq!(best.unwrap().keys_per_sec, 300.0);
+        assert_eq!(comparable(&history, &record("new", 4, 1.0)).0.map(|r| r.workers), None);
+        assert_eq!(percent_change(200.0, 250.0), 25.0);
+    }
+
+    #[test]
+    fn test_history_roundtrip() {
+        let path = TempPath::new("bench.jsonl");
+        assert!(load_history(&path).unwrap().is_empty());
+
+        append_history(&path, &record("cpu", 1, 10.0)).unwrap();
+        append_history(&path, &record("cpu", 1, 20.0)).unwrap();
+        let history = load_history(&path).unwrap();
+
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].keys_per_sec, 20.0);
+    }
+}
@@ -0,0 +1,207 @@
+use crate::logging::log_event;
+use crate::subcredential;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use curve25519_dalek::Scalar;
+use serde_json::json;
+use sha3::{Digest, Sha3_256};
+
+/// Length of a time period in minutes, the network's default `hsdir-interval`
+pub const TIME_PERIOD_LENGTH: u64 = 1440;
+
+/// Time periods start this many minutes after midnight UTC
+const ROTATION_OFFSET: u64 = 12 * 60;
+
+/// Number of HSDir rings a descriptor is uploaded to, `hsdir_n_replicas`
+pub const HSDIR_REPLICAS: u64 = 2;
+
+/// Prefix of the hash that becomes the blinding factor, with its NUL byte
+const BLIND_STRING: &[u8] = b"Derive temporary signing key\0";
+
+/// The ed25519 base point as written in rend-spec-v3, appendix A.2
+const BASEPOINT: &[u8] = b"(15112221349535400772501151409588531511454012693041857206046113283949847762202, \
+                           46316835694926478169428394003475163141307993866256225615783033603165251855960)";
+
+/// A time period, the span for which an onion service uses one blinded key
+///
+/// Period `n` starts `n * length + 720` minutes after the Unix epoch, so with
+/// the default length each period runs from 12:00 UTC to 12:00 UTC the next day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TimePeriod {
+    pub number: u64,
+    /// Minutes
+    pub length: u64,
+}
+
+impl TimePeriod {
+    /// Period `number` of the default length
+    pub fn new(number: u64) -> Self {
+        Self { number, length: TIME_PERIOD_LENGTH }
+    }
+
+    /// The default-length period `time` falls into
+    pub fn at(time: DateTime<Utc>) -> Self {
+        let minutes = time.timestamp().max(0) as u64 / 60;
+        Self::new(minutes.saturating_sub(ROTATION_OFFSET) / TIME_PERIOD_LENGTH)
+    }
+
+    pub fn start(&self) -> DateTime<Utc> {
+        let minutes = self.number * self.length + ROTATION_OFFSET;
+        Utc.timestamp_opt(minutes as i64 * 60, 0).single().unwrap_or(DateTime::<Utc>::MAX_UTC)
+    }
+
+    /// The start of the next period
+    pub fn end(&self) -> DateTime<Utc> {
+        self.next().start()
+    }
+
+    pub fn next(&self) -> Self {
+        Self { number: self.number + 1, length: self.length }
+    }
+}
+
+/// The blinded public key of `identity_key` for `period`
+///
+/// Multiplies the identity key by the clamped
+/// `SHA3-256(BLIND_STRING | A | B | "key-blind" | INT_8(number) | INT_8(length))`,
+/// as in rend-spec-v3 appendix A.2. Descriptors for the period are signed
+/// with the matching blinded private key and stored under this key.
+pub fn blind_public_key(identity_key: &[u8; 32], period: TimePeriod) -> Result<[u8; 32]> {
+    let point = CompressedEdwardsY(*identity_key)
+        .decompress()
+        .ok_or_else(|| anyhow!("Not a valid ed25519 public key"))?;
+    let mut factor: [u8; 32] = Sha3_256::new()
+        .chain_update(BLIND_STRING)
+        .chain_update(identity_key)
+        .chain_update(BASEPOINT)
+        .chain_update(b"key-blind")
+        .chain_update(period.number.to_be_bytes())
+        .chain_update(period.length.to_be_bytes())
+        .finalize()
+        .into();
+    factor[0] &= 248;
+    factor[31] &= 63;
+    factor[31] |= 64;
+    Ok((Scalar::from_bytes_mod_order(factor) * point).compress().to_bytes())
+}
+
+/// Position of a descriptor on HSDir ring `replica` (1-based), `hs_service_index`
+pub fn hs_index(blinded_key: &[u8; 32], replica: u64, period: TimePeriod) -> [u8; 32] {
+    Sha3_256::new()
+        .chain_update(b"store-at-idx")
+        .chain_update(blinded_key)
+        .chain_update(replica.to_be_bytes())
+        .chain_update(period.length.to_be_bytes())
+        .chain_update(period.number.to_be_bytes())
+        .finalize()
+        .into()
+}
+
+/// What an onion service publishes under during one time period
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlindedPeriod {
+    pub period: TimePeriod,
+    pub blinded_key: [u8; 32],
+    pub subcredential: [u8; 32],
+    /// Ring positions for replicas 1 to [`HSDIR_REPLICAS`]
+    pub hs_indexes: Vec<[u8; 32]>,
+}
+
+impl BlindedPeriod {
+    pub fn new(identity_key: &[u8; 32], period: TimePeriod) -> Result<Self> {
+        let blinded_key = blind_public_key(identity_key, period)?;
+        Ok(Self {
+            period,
+            blinded_key,
+            subcredential: subcredential(identity_key, &blinded_key),
+            hs_indexes: (1..=HSDIR_REPLICAS).map(|replica| hs_index(&blinded_key, replica, period)).collect(),
+        })
+    }
+
+    /// The blinded key in base64 without padding, the way Tor logs it
+    pub fn blinded_key_base64(&self) -> String {
+        use base64::Engine;
+        base64::engine::general_purpose::STANDARD_NO_PAD.encode(self.blinded_key)
+    }
+
+    /// Print in the selected log format, as a `rollover` event in JSON mode
+    pub fn log(&self) {
+        let (start, end) = (self.period.start(), self.period.end());
+        let hs_indexes: Vec<String> = self.hs_indexes.iter().map(|index| hex(index)).collect();
+        log_event(
+            "rollover",
+            &format!("Time period {}: blinded key {}", self.period.number, self.blinded_key_base64()),
+            json!({
+                "period": self.period.number,
+                "period_length": self.period.length,
+                "start": start.to_rfc3339(),
+                "end": end.to_rfc3339(),
+                "blinded_key": self.blinded_key_base64(),
+                "subcredential": hex(&self.subcredential),
+                "hs_indexes": hs_indexes,
+            }),
+        );
+        if !crate::json_logs() {
+            crate::print_plain(&format!(
+                "[@] Time period {}: {} to {} UTC",
+                self.period.number,
+                start.format("%Y-%m-%d %H:%M"),
+                end.format("%Y-%m-%d %H:%M")
+            ));
+            crate::print_plain(&format!("    Blinded key:    {}", self.blinded_key_base64()));
+            crate::print_plain(&format!("    Subcredential:  {}", hex(&self.subcredential)));
+            for (replica, index) in hs_indexes.iter().enumerate() {
+                crate::print_plain(&format!("    HSDir index {}:  {}", replica + 1, index));
+            }
+        }
+    }
+}
+
+/// Every time period overlapping `from..=to`, in order
+pub fn rollover(identity_key: &[u8; 32], from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<BlindedPeriod>> {
+    let mut period = TimePeriod::at(from);
+    let mut periods = Vec::new();
+    while period.start() <= to {
+        periods.push(BlindedPeriod::new(identity_key, period)?);
+        period = period.next();
+    }
+    Ok(periods)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_periods_and_blinding() {
+        // The worked example of rend-spec-v3 section 2.2.1
+        let period = TimePeriod::at(Utc.with_ymd_and_hms(2016, 4, 13, 11, 15, 1).unwrap());
+        assert_eq!(period.number, 16903);
+        assert_eq!(period.start(), Utc.with_ymd_and_hms(2016, 4, 12, 12, 0, 0).unwrap());
+        assert_eq!(period.end(), Utc.with_ymd_and_hms(2016, 4, 13, 12, 0, 0).unwrap());
+
+        // The RFC 8032 test 1 public key, checked against an independent implementation of appendix A.2
+        let identity = [
+            0xd7, 0x5a, 0x98, 0x01, 0x82, 0xb1, 0x0a, 0xb7, 0xd5, 0x4b, 0xfe, 0xd3, 0xc9, 0x64, 0x07, 0x3a,
+            0x0e, 0xe1, 0x72, 0xf3, 0xda, 0xa6, 0x23, 0x25, 0xaf, 0x02, 0x1a, 0x68, 0xf7, 0x07, 0x51, 0x1a,
+        ];
+        let blinded = BlindedPeriod::new(&identity, period).unwrap();
+        assert_eq!(hex(&blinded.blinded_key), "7948ed13529f27e000d091082cec9ec457759cf1ca016be18521f7db5cd24682");
+        assert_eq!(hex(&blinded.hs_indexes[0]), "0e891322103da07a40b69b1f5b1f96472d1312f4846443b755ea653727fabf36");
+        assert_eq!(blinded.blinded_key_base64(), "eUjtE1KfJ+AA0JEILOyexFd1nPHKAWvhhSH321zSRoI");
+
+        let from = Utc.with_ymd_and_hms(2016, 4, 13, 11, 15, 1).unwrap();
+        let periods = rollover(&identity, from, from + chrono::Duration::days(2)).unwrap();
+        let numbers: Vec<u64> = periods.iter().map(|blinded| blinded.period.number).collect();
+        assert_eq!(numbers, [16903, 16904, 16905]);
+        // y = 2 is not on the curve
+        let mut not_a_point = [0; 32];
+        not_a_point[0] = 2;
+        assert!(blind_public_key(&not_a_point, period).is_err());
+    }
+}
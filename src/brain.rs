@@ -0,0 +1,79 @@
+use crate::onion::onion_result_from_signing_key;
+use crate::OnionResult;
+use anyhow::Result;
+use argon2::{Algorithm, Argon2, Params, Version};
+use ed25519_dalek::SigningKey;
+use zeroize::Zeroize;
+
+/// Argon2id memory cost in KiB (512 MiB)
+pub const BRAIN_MEMORY_KIB: u32 = 512 * 1024;
+
+/// Argon2id iteration count
+pub const BRAIN_ITERATIONS: u32 = 4;
+
+/// Shortest passphrase accepted for key derivation
+pub const MIN_PASSPHRASE_LEN: usize = 16;
+
+/// Domain separation prefix for the Argon2id salt
+const SALT_PREFIX: &str = "onion-generator brain onion v1:";
+
+/// Derive an onion identity from a passphrase and optional salt
+///
+/// WARNING: anyone who guesses the passphrase obtains the private key, and
+/// guesses can be checked offline against the public address forever. This is
+/// deliberately kept apart from the random key path in `crypto.rs`.
+///
+/// The same passphrase and salt always yield the same address. A per-user
+/// salt (e.g. an email address) prevents one precomputed guess list from
+/// attacking every brain onion at once.
+pub fn generate_brain_onion(passphrase: &str, salt: &str) -> Result<OnionResult> {
+    let params = Params::new(BRAIN_MEMORY_KIB, BRAIN_ITERATIONS, 1, Some(32))
+        .map_err(|e| anyhow::anyhow!("Invalid Argon2 parameters: {}", e))?;
+    let signing_key = derive_signing_key(passphrase, salt, params)?;
+    onion_result_from_signing_key(&signing_key)
+}
+
+fn derive_signing_key(passphrase: &str, salt: &str, params: Params) -> Result<SigningKey> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        return Err(anyhow::anyhow!(
+            "Passphrase must be at least {} characters long",
+            MIN_PASSPHRASE_LEN
+        ));
+    }
+
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+    let salt = format!("{}{}", SALT_PREFIX, salt);
+
+    let mut seed = [0u8; 32];
+    argon2
+        .hash_password_into(passphrase.as_bytes(), salt.as_bytes(), &mut seed)
+        .map_err(|e| anyhow::anyhow!("Key derivation failed: {}", e))?;
+
+    let signing_key = SigningKey::from_bytes(&seed);
+    seed.zeroize();
+    Ok(signing_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_params() -> Params {
+        Params::new(64, 1, 1, Some(32)).unwrap()
+    }
+
+    #[test]
+    fn test_derivation_is_deterministic() {
+        let a = derive_signing_key("correct horse battery staple", "", test_params()).unwrap();
+        let b = derive_signing_key("correct horse battery staple", "", test_params()).unwrap();
+        let salted = derive_signing_key("correct horse battery staple", "me", test_params()).unwrap();
+
+        assert_eq!(a.to_bytes(), b.to_bytes());
+        assert_ne!(a.to_bytes(), salted.to_bytes());
+    }
+
+    #[test]
+    fn test_short_passphrase_rejected() {
+        assert!(derive_signing_key("hunter2", "", test_params()).is_err());
+    }
+}
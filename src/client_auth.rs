@@ -0,0 +1,80 @@
+use crate::crypto::base32_encode;
+use crate::onion::decode_onion_address;
+use anyhow::Result;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroize;
+
+/// Client authorization credentials for a v3 onion service
+///
+/// `auth_private` is the content of `<address>.auth_private` in the client's
+/// `ClientOnionAuthDir`; `authorized_client` is the content of a
+/// `<name>.auth` file in the service's `authorized_clients/` directory.
+#[derive(Debug, Clone)]
+pub struct ClientAuth {
+    pub address: String,
+    pub auth_private: String,
+    pub authorized_client: String,
+}
+
+/// Generate a fresh x25519 client authorization keypair for `address`
+pub fn generate_client_auth(address: &str) -> Result<ClientAuth> {
+    // Validates the address and its checksum
+    decode_onion_address(address)?;
+    let address = address.trim().to_lowercase();
+    let address = address.trim_end_matches(".onion").to_string();
+
+    let mut seed = [0u8; 32];
+    OsRng
+        .try_fill_bytes(&mut seed)
+        .map_err(|e| anyhow::anyhow!("OS random number generator failed: {}", e))?;
+    let secret = StaticSecret::from(seed);
+    seed.zeroize();
+    let public = PublicKey::from(&secret);
+
+    // Tor expects the keys base32-encoded in upper case
+    let private_key = base32_encode(secret.as_bytes()).to_uppercase();
+    let public_key = base32_encode(public.as_bytes()).to_uppercase();
+
+    Ok(ClientAuth {
+        auth_private: format!("{}:descriptor:x25519:{}", address, private_key),
+        authorized_client: format!("descriptor:x25519:{}", public_key),
+        address,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate_onion_address;
+
+    #[test]
+    fn test_generate_client_auth() {
+        let onion = generate_onion_address().unwrap();
+        let auth = generate_client_auth(&onion.hostname).unwrap();
+
+        let private_key = auth.auth_private.rsplit(':').next().unwrap();
+        let public_key = auth.authorized_client.rsplit(':').next().unwrap();
+        assert_eq!(format!("{}.onion", auth.address), onion.hostname);
+        assert!(auth.auth_private.starts_with(&format!("{}:descriptor:x25519:", auth.address)));
+        assert_eq!(private_key.len(), 52);
+        assert_eq!(public_key.len(), 52);
+
+        // The server entry must hold the public half of the client's key
+        let secret_bytes: [u8; 32] = base32::decode(
+            base32::Alphabet::Rfc4648 { padding: false },
+            private_key,
+        )
+        .unwrap()
+        .try_into()
+        .unwrap();
+        let derived = PublicKey::from(&StaticSecret::from(secret_bytes));
+        assert_eq!(base32_encode(derived.as_bytes()).to_uppercase(), public_key);
+    }
+
+    #[test]
+    fn test_generate_client_auth_rejects_invalid_address() {
+        assert!(generate_client_auth("not-an-onion").is_err());
+    }
+}
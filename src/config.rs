@@ -0,0 +1,846 @@
+use crate::doctor::check_output_dir;
+use crate::schedule::parse_time_of_day;
+use crate::{
+    check_address_chars, CharTemplate, ErrorCode, GeneratorConfig, Glob, MatchSpec, PatternExpr, ResultExt, Severity, ADDRESS_LEN,
+};
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Settings read from the TOML file given with `--config`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileConfig {
+    /// Chat notifications sent for every found address
+    #[serde(default)]
+    pub notify: Vec<NotifyConfig>,
+    /// OpenTelemetry export; requires the `otel` feature
+    pub otel: Option<OtelConfig>,
+    /// Listener settings for the `serve` command
+    #[serde(default)]
+    pub serve: ServeConfig,
+    /// Named `[profile.NAME]` tables, selected with `--profile NAME`
+    #[serde(default)]
+    pub profile: BTreeMap<String, Profile>,
+    /// `[[route]]` tables sending some finds to their own outputs, see [`crate::RoutingSink`]
+    #[serde(default)]
+    pub route: Vec<RouteConfig>,
+    /// `[[schedule]]` tables switching to a profile's patterns at set times, see [`crate::PatternSchedule`]
+    #[serde(default)]
+    pub schedule: Vec<ScheduleConfig>,
+}
+
+impl FileConfig {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))
+            .with_code(ErrorCode::InvalidConfig)?;
+        Self::parse(&text)
+            .with_context(|| format!("Invalid config file {}", path.display()))
+            .with_code(ErrorCode::InvalidConfig)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+
+    /// The profile called `name`
+    pub fn profile(&self, name: &str) -> Result<&Profile> {
+        self.profile
+            .get(name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = self.profile.keys().map(String::as_str).collect();
+                match names.is_empty() {
+                    true => anyhow!("No profile {:?}: the config file has no [profile.NAME] tables", name),
+                    false => anyhow!("No profile {:?}; available: {}", name, names.join(", ")),
+                }
+            })
+            .with_code(ErrorCode::InvalidConfig)
+    }
+}
+
+/// The patterns listed in a `--patterns-file`
+///
+/// One pattern per line; `#` starts a comment and blank lines are skipped.
+pub fn read_patterns_file(path: impl AsRef<Path>) -> Result<Vec<String>> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read patterns file {}", path.display()))
+        .with_code(ErrorCode::InvalidConfig)?;
+    Ok(parse_patterns(&text))
+}
+
+pub(crate) fn parse_patterns(text: &str) -> Vec<String> {
+    text.lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|pattern| !pattern.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// A `[profile.NAME]` table: the patterns, limits and outputs of one search
+///
+/// Each key stands for the command line option of the same name, with `-`
+/// replaced by `_` and the pattern options in the plural. Options given on
+/// the command line as well replace single values and add to the lists.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    #[serde(default)]
+    pub prefixes: Vec<String>,
+    #[serde(default)]
+    pub suffixes: Vec<String>,
+    #[serde(default)]
+    pub contains: Vec<String>,
+    /// `PREFIX:SUFFIX` pairs
+    #[serde(default)]
+    pub prefix_suffixes: Vec<String>,
+    /// `OFFSET:TEXT` pairs, for `--at`
+    #[serde(default)]
+    pub positioned: Vec<String>,
+    #[serde(default)]
+    pub classes: Vec<String>,
+    #[serde(default)]
+    pub runs: Vec<usize>,
+    #[serde(default)]
+    pub doubled: Vec<usize>,
+    #[serde(default)]
+    pub palindromes: Vec<usize>,
+    #[serde(default)]
+    pub regexes: Vec<String>,
+    #[serde(default)]
+    pub expressions: Vec<String>,
+    #[serde(default)]
+    pub excludes: Vec<String>,
+    #[serde(default)]
+    pub fix_patterns: bool,
+    pub workers: Option<usize>,
+    pub max_restarts: Option<usize>,
+    /// Seconds, or a duration such as `"2m"`
+    #[serde(default, deserialize_with = "crate::duration::seconds")]
+    pub stall_timeout: Option<Duration>,
+    /// Seconds, or a duration such as `"1h"`
+    #[serde(default, deserialize_with = "crate::duration::seconds")]
+    pub self_check_interval: Option<Duration>,
+    /// Hours, or a duration such as `"2d"`
+    #[serde(default, deserialize_with = "crate::duration::hours")]
+    pub confirm_over: Option<Duration>,
+    pub output_dir: Option<PathBuf>,
+    pub results_file: Option<PathBuf>,
+    pub journal: Option<PathBuf>,
+    pub log_file: Option<PathBuf>,
+}
+
+impl Profile {
+    /// The profile's patterns, as the command line would give them
+    pub fn generator_config(&self) -> Result<GeneratorConfig> {
+        let words = |patterns: &[String]| patterns.iter().map(|pattern| pattern.trim().to_lowercase()).collect();
+        let mut prefix_suffixes = Vec::new();
+        for pair in &self.prefix_suffixes {
+            match pair.trim().to_lowercase().split_once(':') {
+                Some((prefix, suffix)) => prefix_suffixes.push((prefix.to_string(), suffix.to_string())),
+                None => {
+                    return Err(anyhow!("prefix_suffixes takes PREFIX:SUFFIX, got {:?}", pair))
+                        .with_code(ErrorCode::InvalidPattern)
+                }
+            }
+        }
+        Ok(GeneratorConfig::new(words(&self.prefixes))
+            .with_suffixes(words(&self.suffixes))
+            .with_contains(words(&self.contains))
+            .with_prefix_suffixes(prefix_suffixes)
+            .with_positioned(self.positioned.clone())
+            .with_classes(self.classes.clone())
+            .with_runs(self.runs.clone())
+            .with_doubled(self.doubled.clone())
+            .with_palindromes(self.palindromes.clone())
+            .with_regexes(self.regexes.clone())
+            .with_expressions(self.expressions.clone())
+            .with_excludes(words(&self.excludes)))
+    }
+}
+
+/// One `[[notify]]` entry
+#[derive(Debug, Clone, Deserialize)]
+pub struct NotifyConfig {
+    #[serde(flatten)]
+    pub target: NotifyTarget,
+    /// Message text; see [`crate::ChatSink`] for the available placeholders
+    pub template: Option<String>,
+    /// Allow `{private_key}` in the message (default: false)
+    #[serde(default)]
+    pub include_secrets: bool,
+}
+
+/// One `[[route]]` entry
+///
+/// Finds matching `pattern` go to these outputs instead of `--output-dir`
+/// and the `[[notify]]` entries; the first matching route wins.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteConfig {
+    /// Address pattern where `*` stands for any characters, e.g. `shop*`
+    pub pattern: String,
+    /// Directory for the keys of these finds
+    pub output_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub notify: Vec<NotifyConfig>,
+}
+
+/// One `[[schedule]]` entry
+///
+/// From `from` until `to` every day, local time, the pool searches for the
+/// patterns of `profile` instead of those it was started with.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleConfig {
+    /// `HH:MM`
+    pub from: String,
+    /// `HH:MM`; before `from` for windows past midnight
+    pub to: String,
+    /// Name of the `[profile.NAME]` whose patterns are searched
+    pub profile: String,
+}
+
+/// The `[otel]` table
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OtelConfig {
+    /// OTLP/HTTP base URL, e.g. `http://localhost:4318`
+    pub endpoint: String,
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+    /// Extra request headers, e.g. for collector authentication
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+/// The `[serve]` table; command line options take precedence
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServeConfig {
+    /// Address to listen on, e.g. `127.0.0.1:8080`
+    pub listen: Option<String>,
+    /// Bearer token required on every request
+    pub token: Option<String>,
+    /// PEM certificate chain; enables TLS together with `tls_key`
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+}
+
+fn default_service_name() -> String {
+    "onion-generator".to_string()
+}
+
+/// Chat service a notification is delivered to
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum NotifyTarget {
+    Slack { webhook_url: String },
+    Discord { webhook_url: String },
+    Telegram { bot_token: String, chat_id: String },
+}
+
+/// One problem found by [`ConfigCheck`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigProblem {
+    /// Option or config key at fault, e.g. `--workers` or `notify[0].webhook_url`
+    pub field: String,
+    pub message: String,
+    /// What to change
+    pub fix: String,
+    /// [`ErrorCode::InvalidPattern`] for patterns, [`ErrorCode::InvalidConfig`] otherwise
+    pub code: ErrorCode,
+}
+
+impl ConfigProblem {
+    fn new(field: impl Into<String>, message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self { field: field.into(), message: message.into(), fix: fix.into(), code: ErrorCode::InvalidConfig }
+    }
+
+    fn pattern(field: impl Into<String>, message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self { code: ErrorCode::InvalidPattern, ..Self::new(field, message, fix) }
+    }
+}
+
+/// Validation of the command line and config file settings as a whole
+///
+/// Finds every problem before anything starts, instead of failing on the
+/// first one or halfway through a run.
+#[derive(Debug, Default)]
+pub struct ConfigCheck<'a> {
+    generator: Option<&'a GeneratorConfig>,
+    file: Option<&'a FileConfig>,
+    output_dirs: Vec<&'a Path>,
+}
+
+impl<'a> ConfigCheck<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check the patterns and worker count
+    pub fn generator(mut self, config: &'a GeneratorConfig) -> Self {
+        self.generator = Some(config);
+        self
+    }
+
+    /// Check the notifications, OpenTelemetry and serve settings
+    pub fn file(mut self, config: &'a FileConfig) -> Self {
+        self.file = Some(config);
+        self
+    }
+
+    /// Check that results can be written to `dir`, see [`crate::Doctor::output_dir`]
+    pub fn output_dir(mut self, dir: &'a Path) -> Self {
+        self.output_dirs.push(dir);
+        self
+    }
+
+    pub fn problems(&self) -> Vec<ConfigProblem> {
+        let mut problems = Vec::new();
+        if let Some(config) = self.generator {
+            check_generator(config, &mut problems);
+        }
+        if let Some(config) = self.file {
+            check_file(config, &mut problems);
+        }
+        for dir in &self.output_dirs {
+            let finding = check_output_dir(dir);
+            if finding.severity == Severity::Problem {
+                problems.push(ConfigProblem::new("-o", finding.message, finding.advice.unwrap_or_default()));
+            }
+        }
+        problems
+    }
+
+    /// Fail with every problem and its fix in one error
+    ///
+    /// The error carries [`ErrorCode::InvalidPattern`] if only patterns are
+    /// at fault, and [`ErrorCode::InvalidConfig`] otherwise.
+    pub fn check(&self) -> Result<()> {
+        let problems = self.problems();
+        if problems.is_empty() {
+            return Ok(());
+        }
+        let code = if problems.iter().all(|problem| problem.code == ErrorCode::InvalidPattern) {
+            ErrorCode::InvalidPattern
+        } else {
+            ErrorCode::InvalidConfig
+        };
+        let mut message = match problems.len() {
+            1 => "1 configuration problem:".to_string(),
+            n => format!("{} configuration problems:", n),
+        };
+        for problem in &problems {
+            message.push_str(&format!("\n  {}: {}\n    Fix: {}", problem.field, problem.message, problem.fix));
+        }
+        Err(anyhow!(message)).with_code(code)
+    }
+}
+
+fn check_generator(config: &GeneratorConfig, problems: &mut Vec<ConfigProblem>) {
+    if config.num_workers == 0 {
+        problems.push(ConfigProblem::new("--workers", "needs at least 1 worker", "Leave out -w to use one per CPU core"));
+    }
+
+    let pairs = config.prefix_suffixes.iter().flat_map(|(prefix, suffix)| [prefix, suffix]);
+    let words = [
+        ("--prefix", config.prefixes.iter().filter(|prefix| !Glob::is_glob(prefix) && !CharTemplate::has_brackets(prefix)).collect::<Vec<_>>()),
+        ("--suffix", config.suffixes.iter().collect()),
+        ("--contains", config.contains.iter().collect()),
+        ("--prefix-suffix", pairs.collect()),
+        ("--exclude", config.excludes.iter().collect()),
+    ];
+    for (field, patterns) in &words {
+        for pattern in patterns {
+            if pattern.is_empty() {
+                problems.push(ConfigProblem::pattern(*field, "is empty and would match every address", "Remove it"));
+            } else if let Err(e) = check_address_chars(pattern) {
+                problems.push(ConfigProblem::pattern(*field, e.to_string(), "Pass --fix-patterns to use the first suggestion, or change it"));
+            } else if pattern.len() > ADDRESS_LEN {
+                problems.push(ConfigProblem::pattern(
+                    *field,
+                    format!("{:?} is longer than the {} characters of an address", pattern, ADDRESS_LEN),
+                    "Shorten it",
+                ));
+            }
+        }
+    }
+    // The last two characters come from the checksum and the version byte
+    let ends = config.suffixes.iter().map(|suffix| ("--suffix", suffix));
+    let ends = ends.chain(config.prefix_suffixes.iter().map(|(_, suffix)| ("--prefix-suffix", suffix)));
+    for (field, suffix) in ends.filter(|(_, suffix)| suffix.len() <= ADDRESS_LEN && check_address_chars(suffix).is_ok()) {
+        let spec = MatchSpec::Suffix(suffix.clone());
+        if spec.expected_attempts().is_some_and(|expected| !expected.is_finite()) {
+            problems.push(ConfigProblem::pattern(
+                field,
+                format!("{} can never match: addresses end in 'd' preceded by a, i, q or y", spec),
+                "Drop the last characters, or end it with one of a, i, q or y followed by d",
+            ));
+        }
+    }
+    for glob in config.prefixes.iter().filter(|prefix| Glob::is_glob(prefix)) {
+        match Glob::parse(glob) {
+            Err(e) => problems.push(ConfigProblem::pattern("--prefix", format!("{:#}", e), "Use address characters, ? and * only")),
+            Ok(parsed) if parsed.expected_attempts().is_infinite() => problems.push(ConfigProblem::pattern(
+                "--prefix",
+                format!("glob {:?} can never match: it must cover all {} characters, ending in 'd' preceded by a, i, q or y", glob, ADDRESS_LEN),
+                "End it with * to allow any characters after",
+            )),
+            Ok(_) => {}
+        }
+    }
+    for pattern in &config.positioned {
+        match MatchSpec::parse_at(pattern) {
+            Err(e) => problems.push(ConfigProblem::pattern("--at", format!("{:#}", e), "Write OFFSET:TEXT, e.g. 10:tor for \"tor\" after the first 10 characters")),
+            Ok(spec) if spec.expected_attempts().is_some_and(f64::is_infinite) => problems.push(ConfigProblem::pattern(
+                "--at",
+                format!("{} can never match: addresses end in 'd' preceded by a, i, q or y", spec),
+                "Move it away from the last two characters",
+            )),
+            Ok(_) => {}
+        }
+    }
+    for template in &config.classes {
+        if let Err(e) = CharTemplate::parse(template) {
+            problems.push(ConfigProblem::pattern("--class", format!("{:#}", e), "See --help for the template syntax, e.g. L{8}"));
+        }
+    }
+    let bracketed = config.prefixes.iter().filter(|prefix| !Glob::is_glob(prefix) && CharTemplate::has_brackets(prefix));
+    for prefix in bracketed {
+        if let Err(e) = CharTemplate::parse(prefix) {
+            problems.push(ConfigProblem::pattern("--prefix", format!("{:?}: {:#}", prefix, e), "Write sets of a-z and 2-7 in brackets, e.g. t[ou]r"));
+        }
+    }
+    let repeats = config.runs.iter().map(|n| ("--run", MatchSpec::Run(*n)));
+    let repeats = repeats
+        .chain(config.doubled.iter().map(|n| ("--doubled", MatchSpec::Doubled(*n))))
+        .chain(config.palindromes.iter().map(|n| ("--palindrome", MatchSpec::Palindrome(*n))));
+    for (field, spec) in repeats {
+        if let Err(e) = spec.check_length() {
+            problems.push(ConfigProblem::pattern(field, e.to_string(), "Choose a length in that range"));
+        }
+    }
+    #[cfg(feature = "regex")]
+    for pattern in &config.regexes {
+        if let Err(e) = regex::Regex::new(pattern) {
+            problems.push(ConfigProblem::pattern("--regex", format!("{:?}: {}", pattern, e), "Fix the expression"));
+        } else if let Err(e) = crate::onion::check_regex_start(pattern) {
+            problems.push(ConfigProblem::pattern("--regex", e.to_string(), "Use a-z and 2-7 after ^, or start with (?i) to ignore case"));
+        }
+    }
+    #[cfg(not(feature = "regex"))]
+    if !config.regexes.is_empty() {
+        problems.push(ConfigProblem::pattern("--regex", "needs the `regex` feature", "Build with --features regex"));
+    }
+    for expression in &config.expressions {
+        match PatternExpr::parse(expression) {
+            Err(e) => problems.push(ConfigProblem::pattern("--expr", format!("{:#}", e), "Join kind:pattern terms with &, | and !, e.g. prefix:foo & !contains:xxx")),
+            Ok(spec) if spec.expected_attempts().is_some_and(f64::is_infinite) => problems.push(ConfigProblem::pattern(
+                "--expr",
+                format!("{:?} can never match", expression),
+                "Check for terms that contradict each other",
+            )),
+            Ok(_) => {}
+        }
+    }
+
+    let includes = config.prefixes.len()
+        + config.suffixes.len()
+        + config.contains.len()
+        + config.prefix_suffixes.len()
+        + config.positioned.len()
+        + config.classes.len()
+        + config.runs.len()
+        + config.doubled.len()
+        + config.palindromes.len()
+        + config.regexes.len()
+        + config.expressions.len();
+    if includes == 0 {
+        problems.push(ConfigProblem::pattern(
+            "patterns",
+            "no prefix, suffix, contains or regex pattern is left to search for",
+            "Give at least one, e.g. onion-generator abc",
+        ));
+        return;
+    }
+
+    // Word patterns containing an excluded string can never match
+    let excluded = |word: &String| config.excludes.iter().find(|exclude| word.contains(exclude.as_str()));
+    let words: Vec<&String> = config.prefixes.iter().chain(&config.suffixes).chain(&config.contains).collect();
+    let pair_excluded = |(prefix, suffix): &(String, String)| excluded(prefix).or_else(|| excluded(suffix));
+    let only_words = words.len() + config.prefix_suffixes.len() == includes;
+    let blocked: Vec<&String> = words.iter().filter_map(|word| excluded(word)).chain(config.prefix_suffixes.iter().filter_map(pair_excluded)).collect();
+    if only_words && blocked.len() == includes {
+        let mut blocking: Vec<&str> = blocked.iter().map(|exclude| exclude.as_str()).collect();
+        blocking.sort_unstable();
+        blocking.dedup();
+        problems.push(ConfigProblem::pattern(
+            "--exclude",
+            format!("every pattern contains an excluded string ({}), so nothing can match", blocking.join(", ")),
+            "Remove those --exclude values or change the patterns",
+        ));
+    }
+}
+
+fn check_file(config: &FileConfig, problems: &mut Vec<ConfigProblem>) {
+    for (i, notify) in config.notify.iter().enumerate() {
+        check_notify(&format!("notify[{}]", i), notify, problems);
+    }
+
+    for (i, route) in config.route.iter().enumerate() {
+        let field = |key: &str| format!("route[{}].{}", i, key);
+        let chars = route.pattern.to_lowercase().replace('*', "");
+        if route.pattern.is_empty() {
+            problems.push(ConfigProblem::new(field("pattern"), "is empty", "Use e.g. \"shop*\" for addresses starting with shop"));
+        } else if let Err(e) = check_address_chars(&chars) {
+            problems.push(ConfigProblem::new(field("pattern"), format!("{:#}", e), "Use address characters and * only"));
+        } else if chars.len() > ADDRESS_LEN {
+            problems.push(ConfigProblem::new(field("pattern"), format!("is longer than the {} characters of an address", ADDRESS_LEN), "Shorten the pattern"));
+        }
+        if route.output_dir.is_none() && route.notify.is_empty() {
+            problems.push(ConfigProblem::new(
+                format!("route[{}]", i),
+                "has no outputs, so its finds would be dropped",
+                "Set output_dir or add [[route.notify]] entries",
+            ));
+        }
+        if let Some(dir) = &route.output_dir {
+            let finding = check_output_dir(dir);
+            if finding.severity == Severity::Problem {
+                problems.push(ConfigProblem::new(field("output_dir"), finding.message, finding.advice.unwrap_or_default()));
+            }
+        }
+        for (j, notify) in route.notify.iter().enumerate() {
+            check_notify(&field(&format!("notify[{}]", j)), notify, problems);
+        }
+    }
+
+    for (i, schedule) in config.schedule.iter().enumerate() {
+        let field = |key: &str| format!("schedule[{}].{}", i, key);
+        let from = parse_time_of_day(&schedule.from);
+        let to = parse_time_of_day(&schedule.to);
+        for (key, time) in [("from", &from), ("to", &to)] {
+            if let Err(e) = time {
+                problems.push(ConfigProblem::new(field(key), format!("{:#}", e), "Use a 24-hour time such as \"22:30\""));
+            }
+        }
+        if matches!((&from, &to), (Ok(from), Ok(to)) if from == to) {
+            problems.push(ConfigProblem::new(field("to"), "equals from, so the window is empty", "Set an end time different from the start"));
+        }
+        match config.profile.get(&schedule.profile) {
+            None => problems.push(ConfigProblem::new(
+                field("profile"),
+                format!("names no [profile.{}] table", schedule.profile),
+                "Add the profile, or use one of the config file's profiles",
+            )),
+            Some(profile) => {
+                if let Err(e) = profile.generator_config().and_then(|generator| generator.build_matcher()) {
+                    problems.push(ConfigProblem::pattern(
+                        field("profile"),
+                        format!("[profile.{}] has no usable patterns: {:#}", schedule.profile, e),
+                        "Fix the profile's patterns",
+                    ));
+                }
+            }
+        }
+    }
+
+    if let Some(otel) = &config.otel {
+        if let Err(e) = check_url(&otel.endpoint) {
+            problems.push(ConfigProblem::new("otel.endpoint", e, "Use the collector's OTLP/HTTP URL, e.g. http://localhost:4318"));
+        }
+        if cfg!(not(feature = "otel")) {
+            problems.push(ConfigProblem::new("otel", "export requires the `otel` feature", "Build with --features otel, or remove [otel]"));
+        }
+    }
+
+    if config.serve.tls_cert.is_some() != config.serve.tls_key.is_some() {
+        let (set, missing) = if config.serve.tls_cert.is_some() { ("tls_cert", "tls_key") } else { ("tls_key", "tls_cert") };
+        problems.push(ConfigProblem::new(
+            format!("serve.{}", missing),
+            format!("is missing, so serve.{} would be ignored", set),
+            "Set both for TLS, or neither",
+        ));
+    }
+}
+
+/// Check one `[[notify]]` entry, reporting its keys under `prefix`, e.g. `notify[0]`
+fn check_notify(prefix: &str, notify: &NotifyConfig, problems: &mut Vec<ConfigProblem>) {
+    let field = |key: &str| format!("{}.{}", prefix, key);
+    match &notify.target {
+        NotifyTarget::Slack { webhook_url } | NotifyTarget::Discord { webhook_url } => {
+            if let Err(e) = check_url(webhook_url) {
+                problems.push(ConfigProblem::new(field("webhook_url"), e, "Copy the webhook URL from the service's integration settings"));
+            }
+        }
+        NotifyTarget::Telegram { bot_token, chat_id } => {
+            let well_formed = bot_token
+                .split_once(':')
+                .is_some_and(|(id, secret)| !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()) && !secret.is_empty());
+            if !well_formed {
+                problems.push(ConfigProblem::new(field("bot_token"), "is not of the form <bot id>:<secret>", "Copy the token @BotFather gave you"));
+            }
+            if chat_id.trim().is_empty() {
+                problems.push(ConfigProblem::new(field("chat_id"), "is empty", "Use the numeric chat id or @channelname"));
+            }
+        }
+    }
+    let template_leaks = notify.template.as_deref().is_some_and(|template| template.contains("{private_key}"));
+    if template_leaks && !notify.include_secrets {
+        problems.push(ConfigProblem::new(
+            field("template"),
+            "uses {private_key} but include_secrets is not enabled",
+            "Set include_secrets = true, or remove {private_key}",
+        ));
+    }
+}
+
+/// Accept only absolute http(s) URLs with a host
+fn check_url(text: &str) -> std::result::Result<(), String> {
+    match url::Url::parse(text) {
+        Ok(url) if !matches!(url.scheme(), "http" | "https") => Err(format!("{:?} is not an http(s) URL", text)),
+        Ok(url) if url.host_str().is_none_or(str::is_empty) => Err(format!("{:?} has no host", text)),
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("{:?} is not a valid URL: {}", text, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MatchMode;
+
+    #[test]
+    fn test_parse_notify_entries() {
+        let config = FileConfig::parse(
+            r#"
+            [[notify]]
+            kind = "slack"
+            webhook_url = "https://hooks.slack.com/services/T/B/X"
+
+            [[notify]]
+            kind = "telegram"
+            bot_token = "123:abc"
+            chat_id = "-10042"
+            template = "Got {hostname}"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.notify.len(), 2);
+        assert!(matches!(config.notify[0].target, NotifyTarget::Slack { .. }));
+        assert!(!config.notify[0].include_secrets);
+        assert_eq!(config.notify[1].template.as_deref(), Some("Got {hostname}"));
+    }
+
+    #[test]
+    fn test_parse_and_check_routes() {
+        let config = FileConfig::parse(
+            r#"
+            [[route]]
+            pattern = "shop*"
+            output_dir = "keys/shop"
+
+            [[route.notify]]
+            kind = "discord"
+            webhook_url = "not a url"
+
+            [[route]]
+            pattern = "blog1*"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.route.len(), 2);
+        assert_eq!(config.route[0].output_dir.as_deref(), Some(Path::new("keys/shop")));
+        assert_eq!(config.route[0].notify.len(), 1);
+        let fields: Vec<String> = ConfigCheck::new().file(&config).problems().into_iter().map(|problem| problem.field).collect();
+        assert_eq!(fields, ["route[0].notify[0].webhook_url", "route[1].pattern", "route[1]"]);
+    }
+
+    #[test]
+    fn test_parse_and_check_schedules() {
+        let config = FileConfig::parse(
+            r#"
+            [profile.night]
+            prefixes = ["Shop"]
+            prefix_suffixes = ["ab:cd"]
+
+            [profile.broken]
+            excludes = ["xyz"]
+
+            [[schedule]]
+            from = "22:00"
+            to = "06:00"
+            profile = "night"
+
+            [[schedule]]
+            from = "25:00"
+            to = "7:30"
+            profile = "day"
+
+            [[schedule]]
+            from = "12:00"
+            to = "12:00"
+            profile = "broken"
+            "#,
+        )
+        .unwrap();
+
+        let night = config.profile("night").unwrap().generator_config().unwrap();
+        assert_eq!(night.prefixes, ["shop"]);
+        assert_eq!(night.prefix_suffixes, [("ab".to_string(), "cd".to_string())]);
+        let problems = ConfigCheck::new().file(&config).problems();
+        let fields: Vec<&str> = problems.iter().map(|problem| problem.field.as_str()).collect();
+        assert_eq!(fields, ["schedule[1].from", "schedule[1].profile", "schedule[2].to", "schedule[2].profile"]);
+        assert_eq!(problems[3].code, ErrorCode::InvalidPattern);
+    }
+
+    #[test]
+    fn test_parse_otel() {
+        let config = FileConfig::parse(
+            "[otel]\nendpoint = \"http://localhost:4318\"\nheaders = { authorization = \"Bearer x\" }\n",
+        )
+        .unwrap();
+        let otel = config.otel.unwrap();
+
+        assert_eq!(otel.endpoint, "http://localhost:4318");
+        assert_eq!(otel.service_name, "onion-generator");
+        assert_eq!(otel.headers["authorization"], "Bearer x");
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown_kind() {
+        let result = FileConfig::parse("[[notify]]\nkind = \"irc\"\n");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_empty_config() {
+        let config = FileConfig::parse("").unwrap();
+        assert!(config.notify.is_empty());
+        assert!(config.serve.listen.is_none());
+    }
+
+    #[test]
+    fn test_check_reports_every_problem() {
+        let generator = GeneratorConfig::new(vec!["c0ffee".to_string(), "abc".to_string()])
+            .with_excludes(vec!["b".to_string()])
+            .with_workers(0);
+        let file = FileConfig::parse(
+            r#"
+            [[notify]]
+            kind = "slack"
+            webhook_url = "hooks.slack.com/services/T/B/X"
+
+            [serve]
+            tls_cert = "cert.pem"
+            "#,
+        )
+        .unwrap();
+
+        let check = ConfigCheck::new().generator(&generator).file(&file);
+        let fields: Vec<String> = check.problems().into_iter().map(|problem| problem.field).collect();
+        assert_eq!(fields, ["--workers", "--prefix", "notify[0].webhook_url", "serve.tls_key"]);
+        let error = check.check().unwrap_err();
+        assert_eq!(ErrorCode::of(&error), ErrorCode::InvalidConfig);
+        assert!(error.to_string().starts_with("4 configuration problems:"));
+
+        let excluded = GeneratorConfig::new(vec!["abc".to_string()]).with_excludes(vec!["bc".to_string()]);
+        let error = ConfigCheck::new().generator(&excluded).check().unwrap_err();
+        assert_eq!(ErrorCode::of(&error), ErrorCode::InvalidPattern);
+        assert!(ConfigCheck::new().generator(&GeneratorConfig::new(vec!["abc".to_string()])).check().is_ok());
+
+        let ends = GeneratorConfig::new(Vec::new())
+            .with_suffixes(vec!["xid".to_string(), "ab".to_string(), "xd".to_string(), "d".to_string()])
+            .with_prefix_suffixes(vec![("ab".to_string(), "yc".to_string())]);
+        let problems = ConfigCheck::new().generator(&ends).problems();
+        let messages: Vec<&str> = problems.iter().map(|problem| problem.message.as_str()).collect();
+        assert_eq!(messages.len(), 3);
+        assert!(messages[0].starts_with("suffix \"ab\" can never match"));
+        assert_eq!(problems[2].field, "--prefix-suffix");
+
+        let globs = GeneratorConfig::new(vec!["foo??bar*".to_string(), "ab?".to_string(), "a*1".to_string()]);
+        let problems = ConfigCheck::new().generator(&globs).problems();
+        let messages: Vec<&str> = problems.iter().map(|problem| problem.message.as_str()).collect();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].starts_with("glob \"ab?\" can never match"));
+        assert!(messages[1].contains("'1'"), "{}", messages[1]);
+    }
+
+    #[test]
+    fn test_match_mode_anywhere() {
+        let config = GeneratorConfig::new(vec!["shop".to_string(), "a?c*".to_string()]).with_match_mode(MatchMode::Anywhere);
+        let matcher = config.build_matcher().unwrap();
+        let address = |text: &str| format!("{}{}", text, "2".repeat(ADDRESS_LEN - text.len()));
+        assert!(matcher.is_match(&address("777shop")));
+        assert!(matcher.is_match(&address("abc")));
+        // Globs still cover the whole address
+        assert!(!matcher.is_match(&address("7abc")));
+        assert!(!GeneratorConfig::new(vec!["shop".to_string()]).build_matcher().unwrap().is_match(&address("777shop")));
+    }
+
+    #[test]
+    fn test_parse_serve() {
+        let config = FileConfig::parse(
+            r#"
+            [serve]
+            listen = "0.0.0.0:8443"
+            token = "secret"
+            tls_cert = "cert.pem"
+            tls_key = "key.pem"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.serve.listen.as_deref(), Some("0.0.0.0:8443"));
+        assert_eq!(config.serve.tls_key, Some(PathBuf::from("key.pem")));
+    }
+
+    #[test]
+    fn test_parse_patterns_file() {
+        let text = "# shop names\nshop\n\n  store  # the long one\nmy??shop*\n#blog\n";
+        assert_eq!(parse_patterns(text), ["shop", "store", "my??shop*"]);
+        let error = read_patterns_file("/nonexistent/patterns.txt").unwrap_err();
+        assert_eq!(ErrorCode::of(&error), ErrorCode::InvalidConfig);
+    }
+
+    #[test]
+    fn test_parse_profiles() {
+        let config = FileConfig::parse(
+            r#"
+            [profile.blog]
+            prefixes = ["blog", "news"]
+            excludes = ["zz"]
+            workers = 4
+            output_dir = "keys/blog"
+            stall_timeout = 90
+            confirm_over = "1d12h"
+
+            [profile.harvest]
+            runs = [4]
+            "#,
+        )
+        .unwrap();
+
+        let blog = config.profile("blog").unwrap();
+        assert_eq!(blog.prefixes, ["blog", "news"]);
+        assert_eq!(blog.workers, Some(4));
+        assert_eq!(blog.output_dir, Some(PathBuf::from("keys/blog")));
+        // Plain numbers keep their old unit
+        assert_eq!(blog.stall_timeout, Some(Duration::from_secs(90)));
+        assert_eq!(blog.confirm_over, Some(Duration::from_secs(36 * 3600)));
+        assert_eq!(config.profile("harvest").unwrap().runs, [4]);
+        let error = config.profile("shop").unwrap_err();
+        assert_eq!(ErrorCode::of(&error), ErrorCode::InvalidConfig);
+        assert!(error.to_string().ends_with("available: blog, harvest"));
+        assert!(FileConfig::parse("[profile.blog]\nprefix = [\"blog\"]").is_err());
+        assert!(FileConfig::parse("[profile.blog]\nstall_timeout = \"soon\"").is_err());
+    }
+}
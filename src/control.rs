@@ -0,0 +1,576 @@
+use crate::pattern::{pattern_spec, PatternKind};
+use crate::sink::write_difficulty_table;
+use crate::{
+    logln, print_stats, CharTemplate, ErrorCode, Glob, LiveMatcher, LogWriter, Matcher, PauseHandle, ResultExt, StatsSnapshots,
+};
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// How often the control transports re-check the stop flag while waiting for clients
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Command changing or listing the patterns of a running search
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternCommand {
+    Add(PatternKind, String),
+    Remove(PatternKind, String),
+    List,
+}
+
+impl PatternCommand {
+    /// Parse a text command: `ADD <kind> <pattern>`, `REMOVE <kind> <pattern>` or `LIST`
+    pub fn parse(line: &str) -> Result<Self> {
+        let mut words = line.trim().splitn(3, char::is_whitespace);
+        let verb = words.next().unwrap_or_default().to_ascii_uppercase();
+        let mut pattern = || -> Result<(PatternKind, String)> {
+            match (words.next(), words.next()) {
+                (Some(kind), Some(pattern)) => Ok((PatternKind::parse(kind)?, pattern.trim().to_string())),
+                _ => bail!("Usage: {} <prefix|suffix|contains|prefix-suffix|at|classes|run|doubled|palindrome|glob|expr|regex|exclude> <pattern>", verb),
+            }
+        };
+
+        match verb.as_str() {
+            "ADD" => pattern().map(|(kind, pattern)| Self::Add(kind, pattern)),
+            "REMOVE" => pattern().map(|(kind, pattern)| Self::Remove(kind, pattern)),
+            "LIST" => Ok(Self::List),
+            "" => bail!("Empty command"),
+            _ => bail!("Unknown command {:?}; expected ADD, REMOVE or LIST", verb),
+        }
+    }
+}
+
+impl PatternCommand {
+    /// Like [`parse`](Self::parse), but a single word that is not a command is added as a prefix,
+    /// or as a glob if it has wildcards and as a template if it has `[...]` sets
+    pub fn parse_or_prefix(line: &str) -> Result<Self> {
+        let line = line.trim();
+        if !line.is_empty() && !line.contains(char::is_whitespace) && !line.eq_ignore_ascii_case("LIST") {
+            let kind = if Glob::is_glob(line) {
+                PatternKind::Glob
+            } else if CharTemplate::has_brackets(line) {
+                PatternKind::Classes
+            } else {
+                PatternKind::Prefix
+            };
+            return Ok(Self::Add(kind, line.to_string()));
+        }
+        Self::parse(line)
+    }
+}
+
+/// Command pausing or resuming the workers of a running search, see [`WorkerPool::pause`](crate::WorkerPool::pause)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolCommand {
+    Pause,
+    Resume,
+}
+
+impl PoolCommand {
+    /// `PAUSE` or `RESUME` in any case; `None` for anything else, e.g. a [`PatternCommand`]
+    pub fn parse(line: &str) -> Option<Self> {
+        match line.trim().to_ascii_uppercase().as_str() {
+            "PAUSE" => Some(Self::Pause),
+            "RESUME" => Some(Self::Resume),
+            _ => None,
+        }
+    }
+}
+
+/// Run `command` against `pause`, returning the new state
+///
+/// Pausing a paused search or resuming a running one is an error, so a
+/// script can tell whether its command changed anything.
+pub fn execute_pool_command(pause: &PauseHandle, command: PoolCommand) -> Result<&'static str> {
+    let state = match command {
+        PoolCommand::Pause if pause.pause() => "paused",
+        PoolCommand::Resume if pause.resume() => "resumed",
+        PoolCommand::Pause => bail!("The search is already paused"),
+        PoolCommand::Resume => bail!("The search is not paused"),
+    };
+    logln!("[i] Control: {}", state);
+    Ok(state)
+}
+
+/// Patterns in effect after a command, with their difficulty
+#[derive(Debug, Clone, Serialize)]
+pub struct PatternReport {
+    pub message: String,
+    pub patterns: Vec<PatternInfo>,
+    /// Expected candidates until any include matches; `None` if a regex is involved
+    pub expected_attempts: Option<f64>,
+    /// Search speed the estimates are based on, in keys per second
+    pub rate: f64,
+    #[serde(skip)]
+    matcher: std::sync::Arc<Matcher>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PatternInfo {
+    pub kind: PatternKind,
+    pub pattern: String,
+    /// Expected candidates until this pattern matches; `None` for regexes and excludes
+    pub expected_attempts: Option<f64>,
+    /// Expected time until this pattern matches at the current rate
+    pub expected_secs: Option<f64>,
+}
+
+impl PatternReport {
+    /// Human-readable form used by the control socket
+    pub fn write_text(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "OK {}", self.message)?;
+        write_difficulty_table(out, &self.matcher, self.rate)?;
+        for spec in self.matcher.excludes() {
+            writeln!(out, "    excluding {}", spec)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run `command` against `live`, replacing its matcher for `ADD` and `REMOVE`
+///
+/// Invalid patterns, duplicates and removing the last include are rejected
+/// without touching the running search. `rate` is the current search speed,
+/// used for the time estimates in the report.
+pub fn execute_pattern_command(live: &LiveMatcher, command: &PatternCommand, rate: f64) -> Result<PatternReport> {
+    let message = match command {
+        PatternCommand::List => {
+            let count = live.current().includes().len();
+            format!("{} pattern{}", count, if count == 1 { "" } else { "s" })
+        }
+        PatternCommand::Add(kind, pattern) => {
+            let spec = pattern_spec(*kind, pattern).with_code(ErrorCode::InvalidPattern)?;
+            live.update(|matcher| {
+                let (mut includes, mut excludes) = (matcher.includes().to_vec(), matcher.excludes().to_vec());
+                let list = if *kind == PatternKind::Exclude { &mut excludes } else { &mut includes };
+                if list.iter().any(|existing| existing.to_string() == spec.to_string()) {
+                    bail!("Already searching with {}", spec);
+                }
+                let message = format!("added {}", spec);
+                list.push(spec);
+                Ok((Matcher::new(includes, excludes), message))
+            })?
+        }
+        PatternCommand::Remove(kind, pattern) => live.update(|matcher| {
+            let case_sensitive = matches!(kind, PatternKind::Regex | PatternKind::Classes);
+            let pattern = if case_sensitive { pattern.clone() } else { pattern.to_lowercase() };
+            let (mut includes, mut excludes) = (matcher.includes().to_vec(), matcher.excludes().to_vec());
+            let list = if *kind == PatternKind::Exclude { &mut excludes } else { &mut includes };
+            let excluded = *kind == PatternKind::Exclude;
+            let Some(index) = list.iter().position(|spec| PatternKind::of(spec, excluded) == (*kind, pattern.clone())) else {
+                bail!("No {} pattern {:?} in the current search", kind.as_str(), pattern);
+            };
+            let removed = list.remove(index);
+            if includes.is_empty() {
+                bail!("Cannot remove the last pattern; stop the search instead");
+            }
+            Ok((Matcher::new(includes, excludes), format!("removed {}", removed)))
+        })?,
+    };
+
+    let matcher = live.current();
+    let patterns = matcher
+        .includes()
+        .iter()
+        .map(|spec| {
+            let expected = spec.expected_attempts();
+            (spec, false, expected, expected.map(|expected| expected / rate).filter(|_| rate > 0.0))
+        })
+        .chain(matcher.excludes().iter().map(|spec| (spec, true, None, None)))
+        .map(|(spec, excluded, expected_attempts, expected_secs)| {
+            let (kind, pattern) = PatternKind::of(spec, excluded);
+            PatternInfo { kind, pattern, expected_attempts, expected_secs }
+        })
+        .collect();
+
+    Ok(PatternReport {
+        message,
+        patterns,
+        expected_attempts: matcher.expected_attempts(),
+        rate,
+        matcher,
+    })
+}
+
+/// Answer the commands of one control client, one per line, until it disconnects
+///
+/// Shared by the Unix socket and the Windows named pipe: each command is
+/// answered with `OK ...` and the resulting pattern table, or `ERR <reason>`.
+/// `PAUSE` and `RESUME` are answered with `OK paused` and `OK resumed`.
+fn handle_session(
+    reader: impl BufRead,
+    out: &mut impl Write,
+    live: &LiveMatcher,
+    pause: &PauseHandle,
+    rate: f64,
+    stop: &AtomicBool,
+) -> io::Result<()> {
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(command) = PoolCommand::parse(&line) {
+            match execute_pool_command(pause, command) {
+                Ok(state) => writeln!(out, "OK {}", state)?,
+                Err(e) => writeln!(out, "ERR {}", e)?,
+            }
+            out.flush()?;
+            continue;
+        }
+        let executed = PatternCommand::parse(&line).and_then(|command| {
+            let report = execute_pattern_command(live, &command, rate)?;
+            if command != PatternCommand::List {
+                logln!("[i] Control: {}", report.message);
+            }
+            Ok(report)
+        });
+        match executed {
+            Ok(report) => report.write_text(out)?,
+            Err(e) => writeln!(out, "ERR {}", e)?,
+        }
+        out.flush()?;
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Read pattern commands from stdin while a search runs
+///
+/// Each line is a [`PatternCommand`], a [`PoolCommand`], or just a word to add
+/// as a prefix, e.g. `shop`. Replies and difficulty tables go to stdout in the selected log
+/// format, and an empty line prints the current statistics. At the end of
+/// input the thread exits and the search goes on.
+pub fn spawn_stdin_patterns(
+    live: LiveMatcher,
+    snapshots: StatsSnapshots,
+    pause: PauseHandle,
+    stop: Arc<AtomicBool>,
+) -> Result<thread::JoinHandle<()>> {
+    let handle = thread::Builder::new().name("onion-stdin".to_string()).spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+            if line.trim().is_empty() {
+                let snapshot = snapshots.latest();
+                print_stats(snapshot.generated, snapshot.found);
+                continue;
+            }
+            if let Some(command) = PoolCommand::parse(&line) {
+                if let Err(e) = execute_pool_command(&pause, command) {
+                    logln!("[!] {:#}", e);
+                }
+                continue;
+            }
+            let executed = PatternCommand::parse_or_prefix(&line)
+                .and_then(|command| execute_pattern_command(&live, &command, snapshots.latest().average_rate()));
+            match executed {
+                Ok(report) => {
+                    let _ = report.write_text(&mut LogWriter::new());
+                }
+                Err(e) => logln!("[!] {:#}", e),
+            }
+        }
+    })?;
+    Ok(handle)
+}
+
+#[cfg(unix)]
+pub use socket::spawn_control_socket;
+#[cfg(windows)]
+pub use pipe::spawn_control_socket;
+
+#[cfg(unix)]
+mod socket {
+    use super::{handle_session, POLL_INTERVAL};
+    use crate::{LiveMatcher, PauseHandle, StatsSnapshots};
+    use anyhow::{Context, Result};
+    use std::io::{self, BufReader};
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    /// Clients idle for this long are disconnected
+    const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+    /// Accept pattern commands on a Unix socket until `stop` is set
+    ///
+    /// Each line is one command, see [`PatternCommand`](super::PatternCommand)
+    /// and [`PoolCommand`](super::PoolCommand). The socket is only accessible
+    /// to the current user and is removed again when the thread exits.
+    pub fn spawn_control_socket(
+        path: impl Into<PathBuf>,
+        live: LiveMatcher,
+        snapshots: StatsSnapshots,
+        pause: PauseHandle,
+        stop: Arc<AtomicBool>,
+    ) -> Result<thread::JoinHandle<()>> {
+        let path = path.into();
+        // A socket left behind by a crashed run would make bind() fail
+        if std::fs::symlink_metadata(&path).is_ok_and(|meta| meta.file_type().is_socket()) {
+            let _ = std::fs::remove_file(&path);
+        }
+        let listener = UnixListener::bind(&path)
+            .with_context(|| format!("Failed to create control socket {}", path.display()))?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        listener.set_nonblocking(true)?;
+
+        let handle = thread::Builder::new().name("onion-control".to_string()).spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        if let Err(e) = handle_client(stream, &live, &pause, snapshots.latest().average_rate(), &stop) {
+                            eprintln!("[!] Control socket error: {}", e);
+                        }
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+                    Err(e) => eprintln!("[!] Failed to accept control connection: {}", e),
+                }
+            }
+            let _ = std::fs::remove_file(&path);
+        })?;
+        Ok(handle)
+    }
+
+    fn handle_client(
+        stream: UnixStream,
+        live: &LiveMatcher,
+        pause: &PauseHandle,
+        rate: f64,
+        stop: &AtomicBool,
+    ) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut out = stream.try_clone()?;
+        handle_session(BufReader::new(stream), &mut out, live, pause, rate, stop)
+    }
+}
+
+#[cfg(windows)]
+mod pipe {
+    use super::{handle_session, POLL_INTERVAL};
+    use crate::{LiveMatcher, PauseHandle, StatsSnapshots};
+    use anyhow::{Context, Result};
+    use std::ffi::OsStr;
+    use std::fs::File;
+    use std::io::{self, BufReader};
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::io::{AsRawHandle, FromRawHandle, OwnedHandle};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use windows_sys::Win32::Foundation::{
+        ERROR_NO_DATA, ERROR_PIPE_CONNECTED, ERROR_PIPE_LISTENING, HANDLE, INVALID_HANDLE_VALUE,
+    };
+    use windows_sys::Win32::Storage::FileSystem::{FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX};
+    use windows_sys::Win32::System::Pipes::{
+        ConnectNamedPipe, CreateNamedPipeW, DisconnectNamedPipe, SetNamedPipeHandleState, PIPE_NOWAIT,
+        PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+    };
+
+    /// Prefix of local named pipe paths
+    const PIPE_PREFIX: &str = r"\\.\pipe\";
+
+    /// Size of the pipe's in and out buffers
+    const BUFFER_SIZE: u32 = 4096;
+
+    /// Accept pattern commands on a named pipe until `stop` is set
+    ///
+    /// `name` is either a full `\\.\pipe\...` path or just the pipe name.
+    /// Each line is one command, see [`PatternCommand`](super::PatternCommand)
+    /// and [`PoolCommand`](super::PoolCommand). Remote clients are rejected, and the run fails if another process
+    /// already owns a pipe of that name.
+    pub fn spawn_control_socket(
+        name: impl AsRef<str>,
+        live: LiveMatcher,
+        snapshots: StatsSnapshots,
+        pause: PauseHandle,
+        stop: Arc<AtomicBool>,
+    ) -> Result<thread::JoinHandle<()>> {
+        let name = name.as_ref();
+        let path = if name.starts_with(PIPE_PREFIX) { name.to_string() } else { format!("{}{}", PIPE_PREFIX, name) };
+        let wide: Vec<u16> = OsStr::new(&path).encode_wide().chain(std::iter::once(0)).collect();
+        let mut instance = create_instance(&wide, true)
+            .with_context(|| format!("Failed to create control pipe {}", path))?;
+
+        let handle = thread::Builder::new().name("onion-control".to_string()).spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                // Non-blocking: answers at once whether a client is there
+                if unsafe { ConnectNamedPipe(instance.as_raw_handle() as HANDLE, std::ptr::null_mut()) } != 0 {
+                    thread::sleep(POLL_INTERVAL);
+                    continue;
+                }
+                let error = io::Error::last_os_error();
+                match error.raw_os_error().map(|code| code as u32) {
+                    Some(ERROR_PIPE_LISTENING) => thread::sleep(POLL_INTERVAL),
+                    // The client connected and already left
+                    Some(ERROR_NO_DATA) => unsafe {
+                        DisconnectNamedPipe(instance.as_raw_handle() as HANDLE);
+                    },
+                    Some(ERROR_PIPE_CONNECTED) => {
+                        // Hold the name with a fresh instance while this client is served
+                        let next = match create_instance(&wide, false) {
+                            Ok(next) => next,
+                            Err(e) => {
+                                eprintln!("[!] Failed to create control pipe instance: {}", e);
+                                break;
+                            }
+                        };
+                        let client = std::mem::replace(&mut instance, next);
+                        if let Err(e) = handle_client(client, &live, &pause, snapshots.latest().average_rate(), &stop) {
+                            eprintln!("[!] Control pipe error: {}", e);
+                        }
+                    }
+                    _ => {
+                        eprintln!("[!] Failed to accept control connection: {}", error);
+                        thread::sleep(POLL_INTERVAL);
+                    }
+                }
+            }
+        })?;
+        Ok(handle)
+    }
+
+    /// A new listening instance of the pipe, in non-blocking mode for polling
+    fn create_instance(wide_path: &[u16], first: bool) -> io::Result<OwnedHandle> {
+        let open_mode = if first { PIPE_ACCESS_DUPLEX | FILE_FLAG_FIRST_PIPE_INSTANCE } else { PIPE_ACCESS_DUPLEX };
+        let handle = unsafe {
+            CreateNamedPipeW(
+                wide_path.as_ptr(),
+                open_mode,
+                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_NOWAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                BUFFER_SIZE,
+                BUFFER_SIZE,
+                0,
+                std::ptr::null(),
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(unsafe { OwnedHandle::from_raw_handle(handle) })
+    }
+
+    /// Serve a connected instance in blocking mode; the handle is closed afterwards
+    fn handle_client(
+        instance: OwnedHandle,
+        live: &LiveMatcher,
+        pause: &PauseHandle,
+        rate: f64,
+        stop: &AtomicBool,
+    ) -> io::Result<()> {
+        let mode = PIPE_READMODE_BYTE | PIPE_WAIT;
+        let file = File::from(instance);
+        if unsafe { SetNamedPipeHandleState(file.as_raw_handle() as HANDLE, &mode, std::ptr::null(), std::ptr::null()) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut out = file.try_clone()?;
+        handle_session(BufReader::new(file), &mut out, live, pause, rate, stop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn live(prefixes: &[&str]) -> LiveMatcher {
+        let prefixes: Vec<String> = prefixes.iter().map(|prefix| prefix.to_string()).collect();
+        LiveMatcher::new(Matcher::from_prefixes(&prefixes))
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(PatternCommand::parse("list").unwrap(), PatternCommand::List);
+        assert_eq!(
+            PatternCommand::parse("ADD prefix abc").unwrap(),
+            PatternCommand::Add(PatternKind::Prefix, "abc".to_string())
+        );
+        assert_eq!(
+            PatternCommand::parse("remove regex ^a b$").unwrap(),
+            PatternCommand::Remove(PatternKind::Regex, "^a b$".to_string())
+        );
+        assert!(PatternCommand::parse("ADD prefix").is_err());
+        assert!(PatternCommand::parse("ADD middle abc").is_err());
+        assert!(PatternCommand::parse("PAUSE").is_err());
+        assert_eq!(PoolCommand::parse(" pause "), Some(PoolCommand::Pause));
+        assert_eq!(PoolCommand::parse("RESUME"), Some(PoolCommand::Resume));
+        assert_eq!(PoolCommand::parse("LIST"), None);
+    }
+
+    #[test]
+    fn test_parse_or_prefix() {
+        assert_eq!(
+            PatternCommand::parse_or_prefix(" shop ").unwrap(),
+            PatternCommand::Add(PatternKind::Prefix, "shop".to_string())
+        );
+        assert_eq!(PatternCommand::parse_or_prefix("list").unwrap(), PatternCommand::List);
+        assert_eq!(
+            PatternCommand::parse_or_prefix("shop??2*").unwrap(),
+            PatternCommand::Add(PatternKind::Glob, "shop??2*".to_string())
+        );
+        assert_eq!(
+            PatternCommand::parse_or_prefix("ADD suffix xyd").unwrap(),
+            PatternCommand::Add(PatternKind::Suffix, "xyd".to_string())
+        );
+        assert!(PatternCommand::parse_or_prefix("").is_err());
+    }
+
+    #[test]
+    fn test_add_and_remove_patterns() {
+        let live = live(&["ab"]);
+
+        let report = execute_pattern_command(&live, &PatternCommand::parse("ADD suffix XYD").unwrap(), 1024.0).unwrap();
+        assert_eq!(report.message, "added suffix \"xyd\"");
+        assert_eq!(report.patterns.len(), 2);
+        assert_eq!(report.patterns[0].expected_secs, Some(1.0));
+        assert_eq!(live.version(), 1);
+        assert!(live.current().is_match("xxxxyd"));
+
+        execute_pattern_command(&live, &PatternCommand::parse("ADD exclude q").unwrap(), 0.0).unwrap();
+        assert!(!live.current().is_match("abqd"));
+
+        execute_pattern_command(&live, &PatternCommand::parse("REMOVE prefix ab").unwrap(), 0.0).unwrap();
+        assert_eq!(live.current().to_string(), "suffix \"xyd\", but not containing \"q\"");
+    }
+
+    #[test]
+    fn test_invalid_commands_leave_matcher_untouched() {
+        let live = live(&["ab"]);
+        for command in ["ADD prefix ab", "ADD prefix a1", "ADD suffix xa", "REMOVE prefix ab", "REMOVE suffix zz"] {
+            let command = PatternCommand::parse(command).unwrap();
+            assert!(execute_pattern_command(&live, &command, 0.0).is_err(), "{:?}", command);
+        }
+        assert_eq!(live.version(), 0);
+    }
+
+    #[test]
+    fn test_session_answers_each_line() {
+        let live = live(&["ab"]);
+        let pause = PauseHandle::default();
+        let input = "ADD suffix xyd\n\nADD prefix a1\nLIST\nPAUSE\npause\nRESUME\n";
+        let mut out = Vec::new();
+        handle_session(io::Cursor::new(input), &mut out, &live, &pause, 0.0, &AtomicBool::new(false)).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        let replies: Vec<&str> = out.lines().filter(|line| line.starts_with("OK") || line.starts_with("ERR")).collect();
+        assert_eq!(replies.len(), 6, "{}", out);
+        assert_eq!(replies[0], "OK added suffix \"xyd\"");
+        assert!(replies[1].starts_with("ERR "));
+        assert_eq!(replies[2], "OK 2 patterns");
+        assert_eq!(replies[3..], ["OK paused", "ERR The search is already paused", "OK resumed"]);
+        assert!(!pause.is_paused());
+    }
+}
@@ -0,0 +1,125 @@
+use std::cell::Cell;
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// One work-and-rest period of a cooperative worker
+const PERIOD: Duration = Duration::from_millis(20);
+
+/// Default share of each period a cooperative worker spends generating keys
+pub const DEFAULT_DUTY_CYCLE: u8 = 90;
+
+/// How workers leave the machine to other programs with `--cooperative`
+///
+/// Each worker thread drops to the lowest scheduling priority, `SCHED_IDLE`
+/// on Linux and `THREAD_PRIORITY_IDLE` on Windows, so it only gets a core no
+/// other thread wants. On top of that it gives up the core at the end of
+/// every 20ms period, sleeping for the rest of the period below a 100% duty
+/// cycle, which keeps the machine responsive where priorities do not reach,
+/// e.g. across containers sharing a CPU quota.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cooperative {
+    duty_cycle: u8,
+}
+
+impl Cooperative {
+    /// Work `duty_cycle` percent of each period, at least 1 and at most 100
+    pub fn new(duty_cycle: u8) -> Self {
+        Self { duty_cycle: duty_cycle.clamp(1, 100) }
+    }
+
+    pub fn duty_cycle(&self) -> u8 {
+        self.duty_cycle
+    }
+
+    /// Per-thread state for pacing one worker
+    pub(crate) fn pacer(&self) -> Pacer {
+        let work = PERIOD * u32::from(self.duty_cycle) / 100;
+        Pacer { work, rest: PERIOD - work, period_start: Cell::new(Instant::now()) }
+    }
+}
+
+impl Default for Cooperative {
+    fn default() -> Self {
+        Self::new(DEFAULT_DUTY_CYCLE)
+    }
+}
+
+/// Where a cooperative worker is in its current period
+pub(crate) struct Pacer {
+    work: Duration,
+    rest: Duration,
+    period_start: Cell<Instant>,
+}
+
+impl Pacer {
+    /// Called between candidates; yields or sleeps once the period's work share is used up
+    pub(crate) fn pace(&self) {
+        if self.period_start.get().elapsed() < self.work {
+            return;
+        }
+        match self.rest.is_zero() {
+            true => thread::yield_now(),
+            false => thread::sleep(self.rest),
+        }
+        self.period_start.set(Instant::now());
+    }
+}
+
+/// Move the calling thread to the lowest scheduling priority
+///
+/// On Linux, falls back to the highest niceness where `SCHED_IDLE` is not
+/// allowed. Fails on platforms without per-thread priorities.
+pub fn lower_thread_priority() -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        let param = libc::sched_param { sched_priority: 0 };
+        // Both act on the calling thread only, as Linux schedules threads individually
+        if unsafe { libc::sched_setscheduler(0, libc::SCHED_IDLE, &param) } == 0 {
+            return Ok(());
+        }
+        if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, 19) } == 0 {
+            return Ok(());
+        }
+        Err(io::Error::last_os_error())
+    }
+    #[cfg(windows)]
+    {
+        use windows_sys::Win32::System::Threading::{GetCurrentThread, SetThreadPriority, THREAD_PRIORITY_IDLE};
+        if unsafe { SetThreadPriority(GetCurrentThread(), THREAD_PRIORITY_IDLE) } != 0 {
+            return Ok(());
+        }
+        Err(io::Error::last_os_error())
+    }
+    #[cfg(not(any(target_os = "linux", windows)))]
+    Err(io::Error::new(io::ErrorKind::Unsupported, "thread priorities are only supported on Linux and Windows"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pacer_rests_between_periods() {
+        assert_eq!(Cooperative::new(0).duty_cycle(), 1);
+        assert_eq!(Cooperative::new(250).duty_cycle(), 100);
+
+        let pacer = Cooperative::new(50).pacer();
+        assert_eq!((pacer.work, pacer.rest), (PERIOD / 2, PERIOD / 2));
+        let started = Instant::now();
+        let mut rested = Duration::ZERO;
+        while started.elapsed() < PERIOD * 10 {
+            let before = Instant::now();
+            pacer.pace();
+            if before.elapsed() >= Duration::from_millis(1) {
+                rested += before.elapsed();
+            }
+        }
+        // About half of the time went to sleeping
+        assert!(rested >= PERIOD * 3 && rested <= PERIOD * 8, "{:?}", rested);
+        assert!(Cooperative::new(100).pacer().rest.is_zero());
+
+        #[cfg(target_os = "linux")]
+        thread::spawn(|| lower_thread_priority().unwrap()).join().unwrap();
+    }
+}
@@ -1,22 +1,188 @@
 use ed25519_dalek::{SigningKey, VerifyingKey};
 use rand::rngs::OsRng;
-use sha3::{Digest, Sha3_256, Sha3_512};
-use anyhow::Result;
+use rand::RngCore;
+use sha2::Sha512;
+use sha3::{Digest, Sha3_256};
+use crate::dispatch::Kernel;
+use anyhow::{Context, Result};
+use std::cell::RefCell;
+use zeroize::{Zeroize, Zeroizing};
+
+/// Bytes of OS entropy fetched per refill of a thread's entropy pool
+pub const ENTROPY_BLOCK_SIZE: usize = 64 * 1024;
+
+thread_local! {
+    static ENTROPY: RefCell<EntropyPool> = RefCell::new(EntropyPool::new(ENTROPY_BLOCK_SIZE));
+}
+
+/// Block of OS entropy sliced into key seeds
+///
+/// Fetching one large block per refill instead of 32 bytes per key saves a
+/// syscall per candidate on platforms where the OS RNG is not vDSO-backed.
+/// Seeds are wiped from the buffer as soon as they are handed out.
+struct EntropyPool {
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl EntropyPool {
+    fn new(block_size: usize) -> Self {
+        Self {
+            buf: vec![0; block_size],
+            pos: block_size,
+        }
+    }
+
+    fn next_seed(&mut self) -> Result<[u8; 32]> {
+        if self.pos + 32 > self.buf.len() {
+            OsRng
+                .try_fill_bytes(&mut self.buf)
+                .context("OS random number generator failed")?;
+            self.pos = 0;
+        }
+
+        let chunk = &mut self.buf[self.pos..self.pos + 32];
+        let mut seed = [0u8; 32];
+        seed.copy_from_slice(chunk);
+        chunk.zeroize();
+        self.pos += 32;
+
+        Ok(seed)
+    }
+}
+
+impl Drop for EntropyPool {
+    fn drop(&mut self) {
+        self.buf.zeroize();
+    }
+}
 
 /// Generate a new Ed25519 key pair
-pub fn generate_keypair() -> (SigningKey, VerifyingKey) {
-    let signing_key = SigningKey::generate(&mut OsRng);
+///
+/// Seeds come from a per-thread block of OS entropy. Returns an error instead
+/// of panicking if the OS random number generator fails.
+pub fn generate_keypair() -> Result<(SigningKey, VerifyingKey)> {
+    let seed = random_seed()?;
+    let signing_key = SigningKey::from_bytes(&seed);
+
+    let verifying_key = signing_key.verifying_key();
+    Ok((signing_key, verifying_key))
+}
+
+/// A fresh key seed from this thread's entropy pool
+pub(crate) fn random_seed() -> Result<Zeroizing<[u8; 32]>> {
+    ENTROPY.with(|pool| pool.borrow_mut().next_seed()).map(Zeroizing::new)
+}
+
+/// How seeded runs derive each candidate's key seed from the master seed
+pub const SEED_DERIVATION: &str = "sha3-256(master_seed || shard_u64_le || counter_u64_le)";
+
+/// Deterministic key stream for one shard of a seeded run
+///
+/// Candidate `counter` of shard `shard` is always the same key, see
+/// [`seeded_keypair`]. Distinct shards never produce the same key, so workers
+/// or machines with different shard numbers search disjoint spaces.
+pub struct SeededKeys {
+    master_seed: Zeroizing<[u8; 32]>,
+    shard: u64,
+    counter: u64,
+}
+
+impl SeededKeys {
+    pub fn new(master_seed: [u8; 32], shard: u64) -> Self {
+        Self {
+            master_seed: Zeroizing::new(master_seed),
+            shard,
+            counter: 0,
+        }
+    }
+
+    /// Continue the shard at `counter` instead of 0, e.g. where an earlier run stopped
+    pub fn starting_at(mut self, counter: u64) -> Self {
+        self.counter = counter;
+        self
+    }
+
+    pub fn shard(&self) -> u64 {
+        self.shard
+    }
+
+    pub(crate) fn master_seed(&self) -> &[u8; 32] {
+        &self.master_seed
+    }
+
+    /// Number of keys handed out so far, i.e. the counter of the next key
+    pub fn counter(&self) -> u64 {
+        self.counter
+    }
+
+    pub fn next_keypair(&mut self) -> (SigningKey, VerifyingKey) {
+        let keypair = seeded_keypair(&self.master_seed, self.shard, self.counter);
+        self.counter += 1;
+        keypair
+    }
+}
+
+/// Re-derive key `counter` of `shard` in a seeded run, e.g. to audit a find
+pub fn seeded_keypair(master_seed: &[u8; 32], shard: u64, counter: u64) -> (SigningKey, VerifyingKey) {
+    let mut hasher = Sha3_256::new();
+    hasher.update(master_seed);
+    hasher.update(shard.to_le_bytes());
+    hasher.update(counter.to_le_bytes());
+    let mut seed: [u8; 32] = hasher.finalize().into();
+
+    let signing_key = SigningKey::from_bytes(&seed);
+    seed.zeroize();
+
     let verifying_key = signing_key.verifying_key();
     (signing_key, verifying_key)
 }
 
+/// Parse a master seed given as 32 raw bytes or 64 hex digits
+pub fn parse_master_seed(data: &[u8]) -> Result<[u8; 32]> {
+    if let Ok(seed) = <[u8; 32]>::try_from(data) {
+        return Ok(seed);
+    }
+
+    let text = std::str::from_utf8(data).unwrap_or("").trim();
+    if text.len() != 64 || !text.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(anyhow::anyhow!("Master seed must be 32 raw bytes or 64 hex digits"));
+    }
+    let mut seed = [0u8; 32];
+    for (byte, pair) in seed.iter_mut().zip(text.as_bytes().chunks(2)) {
+        let pair = std::str::from_utf8(pair)?;
+        *byte = u8::from_str_radix(pair, 16)?;
+    }
+    Ok(seed)
+}
+
+/// Short public identifier of a master seed that does not reveal the seed
+pub fn seed_fingerprint(master_seed: &[u8; 32]) -> String {
+    let mut hasher = Sha3_256::new();
+    hasher.update(b"onion-generator seed fingerprint");
+    hasher.update(master_seed);
+    hasher.finalize()[..8].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Short identifier of a public key for notes and support requests
+///
+/// The first 8 hex digits of the key's SHA3-256, so anyone can recompute it
+/// from the address with standard tools.
+pub fn key_fingerprint(public_key: &[u8; 32]) -> String {
+    Sha3_256::digest(public_key)[..4].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
 /// Expand the secret key according to Tor's specification
+///
+/// This is the ed25519 expansion: SHA-512 of the seed, with the first half
+/// clamped into the signing scalar and the second half kept as the nonce
+/// prefix. Tor derives the service's public key from the scalar.
 pub fn expand_secret_key(secret_key: &[u8]) -> Result<Vec<u8>> {
     if secret_key.len() != 32 {
         return Err(anyhow::anyhow!("Secret key must be 32 bytes"));
     }
 
-    let mut hasher = Sha3_512::new();
+    let mut hasher = Sha512::new();
     hasher.update(secret_key);
     let hash = hasher.finalize();
     
@@ -32,17 +198,124 @@ pub fn expand_secret_key(secret_key: &[u8]) -> Result<Vec<u8>> {
 
 /// Calculate the checksum for the onion address
 pub fn calculate_checksum(public_key: &[u8]) -> Result<Vec<u8>> {
-    if public_key.len() != 32 {
-        return Err(anyhow::anyhow!("Public key must be 32 bytes"));
+    let public_key: &[u8; 32] = public_key
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Public key must be 32 bytes"))?;
+    Ok(onion_checksum(public_key).to_vec())
+}
+
+/// The two checksum bytes of the v3 address of `public_key`
+pub(crate) fn onion_checksum(public_key: &[u8; 32]) -> [u8; 2] {
+    let hash = Sha3_256::new()
+        .chain_update(b".onion checksum")
+        .chain_update(public_key)
+        .chain_update([0x03]) // Version byte
+        .finalize();
+    [hash[0], hash[1]]
+}
+
+/// The v3 hostname of `public_key`, with the `.onion` suffix
+///
+/// The crate's one hostname encoder, used by the search and the key helpers.
+/// It works on fixed arrays, so encoding a candidate allocates only the
+/// returned string.
+pub fn encode_onion_address(public_key: &[u8; 32]) -> String {
+    const ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+    let mut data = [0u8; 35];
+    data[..32].copy_from_slice(public_key);
+    data[32..34].copy_from_slice(&onion_checksum(public_key));
+    data[34] = 3;
+
+    // Each 5 bytes are 8 characters
+    let mut address = [0u8; 62];
+    for (chunk, out) in data.chunks_exact(5).zip(address.chunks_exact_mut(8)) {
+        let bits = chunk.iter().fold(0u64, |bits, &byte| bits << 8 | u64::from(byte));
+        for (i, c) in out.iter_mut().enumerate() {
+            *c = ALPHABET[(bits >> (35 - 5 * i)) as usize & 31];
+        }
     }
+    address[56..].copy_from_slice(b".onion");
+    String::from_utf8(address.to_vec()).expect("base32 is ASCII")
+}
 
-    let mut hasher = Sha3_256::new();
-    hasher.update(b".onion checksum");
-    hasher.update(public_key);
-    hasher.update(&[0x03]); // Version byte
-    
-    let hash = hasher.finalize();
-    Ok(hash[..2].to_vec()) // Take first 2 bytes
+/// The credential `N_hs_cred` of an onion service's identity key
+///
+/// `SHA3-256("credential" | identity_key)`, as in rend-spec-v3 section 2.1.
+/// It never changes for a service; see [`subcredential`] for the per-period
+/// value descriptors are encrypted with.
+pub fn credential(identity_key: &[u8; 32]) -> [u8; 32] {
+    Sha3_256::new()
+        .chain_update(b"credential")
+        .chain_update(identity_key)
+        .finalize()
+        .into()
+}
+
+/// The subcredential `N_hs_subcred` for one time period
+///
+/// `SHA3-256("subcredential" | N_hs_cred | blinded_key)`, where `blinded_key`
+/// is the service's blinded public key for the period. Clients and services
+/// derive the descriptor encryption keys from it, and it binds
+/// introduction and rendezvous handshakes to the service.
+pub fn subcredential(identity_key: &[u8; 32], blinded_key: &[u8; 32]) -> [u8; 32] {
+    Sha3_256::new()
+        .chain_update(b"subcredential")
+        .chain_update(credential(identity_key))
+        .chain_update(blinded_key)
+        .finalize()
+        .into()
+}
+
+/// Crypto code paths selected for the running CPU
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CryptoBackend {
+    pub arch: &'static str,
+    pub keccak: &'static str,
+    pub field: &'static str,
+    /// Instruction set of the key prefix filter
+    pub kernels: Kernel,
+}
+
+impl std::fmt::Display for CryptoBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (keccak: {}, field arithmetic: {}, kernels: {})", self.arch, self.keccak, self.field, self.kernels)
+    }
+}
+
+/// Detect which crypto code paths are used on this CPU
+///
+/// On aarch64 with the `neon` feature, SHA3 hashing is dispatched at runtime to
+/// the ARMv8 SHA3 instructions (Apple Silicon, Graviton 3+). Curve arithmetic
+/// uses curve25519-dalek's runtime-selected AVX2 backend on x86_64 and its
+/// 64-bit serial backend elsewhere. The key prefix filter uses the best
+/// [`Kernel`] for the CPU.
+pub fn crypto_backend() -> CryptoBackend {
+    CryptoBackend {
+        arch: std::env::consts::ARCH,
+        keccak: keccak_backend(),
+        field: field_backend(),
+        kernels: Kernel::selected(),
+    }
+}
+
+fn keccak_backend() -> &'static str {
+    #[cfg(all(target_arch = "aarch64", feature = "neon"))]
+    if std::arch::is_aarch64_feature_detected!("sha3") {
+        return "ARMv8 SHA3 instructions";
+    }
+    "portable"
+}
+
+fn field_backend() -> &'static str {
+    #[cfg(target_arch = "x86_64")]
+    if std::arch::is_x86_feature_detected!("avx2") {
+        return "AVX2";
+    }
+    if cfg!(target_pointer_width = "64") {
+        "64-bit serial"
+    } else {
+        "32-bit serial"
+    }
 }
 
 /// Encode data using base32 (without padding)
@@ -62,9 +335,57 @@ mod tests {
 
     #[test]
     fn test_keypair_generation() {
-        let (signing_key, verifying_key) = generate_keypair();
+        let (signing_key, verifying_key) = generate_keypair().unwrap();
         assert_eq!(signing_key.to_bytes().len(), 32);
         assert_eq!(verifying_key.to_bytes().len(), 32);
+        assert_eq!(signing_key.verifying_key(), verifying_key);
+    }
+
+    #[test]
+    fn test_entropy_pool_refills() {
+        let mut pool = EntropyPool::new(64);
+        let seeds: Vec<[u8; 32]> = (0..5).map(|_| pool.next_seed().unwrap()).collect();
+
+        for (i, a) in seeds.iter().enumerate() {
+            for b in &seeds[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+        assert!(pool.buf[..pool.pos].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_seeded_keys_are_deterministic_per_shard() {
+        let seed = [7u8; 32];
+        let mut shard0 = SeededKeys::new(seed, 0);
+        let mut shard1 = SeededKeys::new(seed, 1);
+
+        let a = shard0.next_keypair().1;
+        let b = shard0.next_keypair().1;
+        let c = shard1.next_keypair().1;
+
+        assert_ne!(a, b);
+        assert_ne!(a, c);
+        assert_eq!(shard0.counter(), 2);
+        assert_eq!(seeded_keypair(&seed, 0, 1).1, b);
+        assert_eq!(seeded_keypair(&seed, 1, 0).1, c);
+
+        let mut resumed = SeededKeys::new(seed, 0).starting_at(1);
+        assert_eq!(resumed.next_keypair().1, b);
+        assert_eq!(resumed.counter(), 2);
+    }
+
+    #[test]
+    fn test_parse_master_seed() {
+        let hex = "00ff".repeat(16);
+        let seed = parse_master_seed(format!("{}\n", hex).as_bytes()).unwrap();
+        assert_eq!(seed[0], 0x00);
+        assert_eq!(seed[1], 0xff);
+
+        assert_eq!(parse_master_seed(&[9u8; 32]).unwrap(), [9u8; 32]);
+        assert!(parse_master_seed(b"abcd").is_err());
+        assert_eq!(seed_fingerprint(&seed).len(), 16);
+        assert_eq!(key_fingerprint(&[0; 32]), "9e629197");
     }
 
     #[test]
@@ -77,6 +398,10 @@ mod tests {
         assert_eq!(expanded[0] & 7, 0); // Last 3 bits should be 0
         assert_eq!(expanded[31] & 128, 0); // MSB should be 0
         assert_eq!(expanded[31] & 64, 64); // Second MSB should be 1
+
+        // Tor derives the public key from the expanded key, which must give the same address
+        let expanded = ed25519_dalek::hazmat::ExpandedSecretKey::from_bytes(&expanded[..].try_into().unwrap());
+        assert_eq!(VerifyingKey::from(&expanded), SigningKey::from_bytes(&secret).verifying_key());
     }
 
     #[test]
@@ -86,6 +411,21 @@ mod tests {
         assert_eq!(checksum.len(), 2);
     }
 
+    #[test]
+    fn test_subcredential() {
+        // RFC 8032 test 1 public key as the identity key
+        let identity = [
+            0xd7, 0x5a, 0x98, 0x01, 0x82, 0xb1, 0x0a, 0xb7, 0xd5, 0x4b, 0xfe, 0xd3, 0xc9, 0x64, 0x07, 0x3a,
+            0x0e, 0xe1, 0x72, 0xf3, 0xda, 0xa6, 0x23, 0x25, 0xaf, 0x02, 0x1a, 0x68, 0xf7, 0x07, 0x51, 0x1a,
+        ];
+        let hex = |bytes: [u8; 32]| bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        assert_eq!(hex(credential(&identity)), "3f4d04f78e32eddc950356db6e5c95ce760ae3134eb171b333d19a85cb239917");
+        assert_eq!(
+            hex(subcredential(&identity, &[0x42; 32])),
+            "c4a402fff5ef394c5091a4d35814d0ce94a9215863281730f3130eedb29804f7"
+        );
+    }
+
     #[test]
     fn test_base32_encoding() {
         let data = b"hello world";
@@ -93,4 +433,15 @@ mod tests {
         assert!(!encoded.is_empty());
         assert!(!encoded.contains('='));
     }
+
+    #[test]
+    fn test_encode_onion_address() {
+        let duckduckgo = "duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczad.onion";
+        assert_eq!(encode_onion_address(&crate::decode_onion_address(duckduckgo).unwrap()), duckduckgo);
+        for counter in 0..64 {
+            let key = seeded_keypair(&[1; 32], 0, counter).1.to_bytes();
+            let data = [&key[..], &calculate_checksum(&key).unwrap(), &[3]].concat();
+            assert_eq!(encode_onion_address(&key), format!("{}.onion", base32_encode(&data)));
+        }
+    }
 }
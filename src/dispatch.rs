@@ -0,0 +1,153 @@
+use std::sync::OnceLock;
+
+/// Instruction set the per-candidate kernels run with
+///
+/// The kernel is the key prefix filter, which compares the fixed bits of a
+/// candidate key in one vector operation. It is written for every instruction
+/// set of the target architecture and the best one the CPU supports is picked
+/// at startup, so prebuilt binaries need no `-C target-cpu`. Curve arithmetic
+/// is dispatched separately by curve25519-dalek, and SHA3 by the `neon`
+/// feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kernel {
+    Scalar,
+    /// x86_64 with AVX2 and BMI2
+    Avx2,
+    /// x86_64 with AVX-512 F, BW and VL, on top of AVX2
+    Avx512,
+    /// aarch64 Advanced SIMD
+    Neon,
+}
+
+static SELECTED: OnceLock<Kernel> = OnceLock::new();
+
+impl Kernel {
+    pub const ALL: [Kernel; 4] = [Kernel::Scalar, Kernel::Avx2, Kernel::Avx512, Kernel::Neon];
+
+    /// The best kernel for this CPU, detected once
+    pub fn selected() -> Self {
+        *SELECTED.get_or_init(|| Self::ALL.into_iter().rev().find(|kernel| kernel.is_supported()).unwrap_or(Kernel::Scalar))
+    }
+
+    /// Whether this CPU can run the kernel
+    pub fn is_supported(self) -> bool {
+        match self {
+            Kernel::Scalar => true,
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Avx2 => is_x86_feature_detected!("avx2") && is_x86_feature_detected!("bmi2"),
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Avx512 => {
+                Kernel::Avx2.is_supported()
+                    && is_x86_feature_detected!("avx512f")
+                    && is_x86_feature_detected!("avx512bw")
+                    && is_x86_feature_detected!("avx512vl")
+            }
+            #[cfg(target_arch = "aarch64")]
+            Kernel::Neon => std::arch::is_aarch64_feature_detected!("neon"),
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Kernel::Scalar => "scalar",
+            Kernel::Avx2 => "AVX2",
+            Kernel::Avx512 => "AVX-512",
+            Kernel::Neon => "NEON",
+        }
+    }
+}
+
+impl std::fmt::Display for Kernel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Whether `key & mask == value` for all 32 bytes
+///
+/// `kernel` must be supported by this CPU, see [`Kernel::is_supported`].
+#[inline]
+pub fn masked_eq(kernel: Kernel, key: &[u8; 32], mask: &[u8; 32], value: &[u8; 32]) -> bool {
+    match kernel {
+        // SAFETY: the kernel was checked to be supported
+        #[cfg(target_arch = "x86_64")]
+        Kernel::Avx2 => unsafe { masked_eq_avx2(key, mask, value) },
+        #[cfg(target_arch = "x86_64")]
+        Kernel::Avx512 => unsafe { masked_eq_avx512(key, mask, value) },
+        #[cfg(target_arch = "aarch64")]
+        Kernel::Neon => unsafe { masked_eq_neon(key, mask, value) },
+        _ => masked_eq_scalar(key, mask, value),
+    }
+}
+
+fn masked_eq_scalar(key: &[u8; 32], mask: &[u8; 32], value: &[u8; 32]) -> bool {
+    let word = |bytes: &[u8; 32], i: usize| u64::from_ne_bytes(bytes[i * 8..i * 8 + 8].try_into().unwrap());
+    (0..4).all(|i| word(key, i) & word(mask, i) == word(value, i))
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn masked_eq_avx2(key: &[u8; 32], mask: &[u8; 32], value: &[u8; 32]) -> bool {
+    use std::arch::x86_64::*;
+    let key = _mm256_loadu_si256(key.as_ptr().cast());
+    let mask = _mm256_loadu_si256(mask.as_ptr().cast());
+    let value = _mm256_loadu_si256(value.as_ptr().cast());
+    let diff = _mm256_xor_si256(_mm256_and_si256(key, mask), value);
+    _mm256_testz_si256(diff, diff) == 1
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,avx512f,avx512bw,avx512vl")]
+unsafe fn masked_eq_avx512(key: &[u8; 32], mask: &[u8; 32], value: &[u8; 32]) -> bool {
+    use std::arch::x86_64::*;
+    let key = _mm256_loadu_si256(key.as_ptr().cast());
+    let mask = _mm256_loadu_si256(mask.as_ptr().cast());
+    let value = _mm256_loadu_si256(value.as_ptr().cast());
+    _mm256_cmpneq_epi8_mask(_mm256_and_si256(key, mask), value) == 0
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn masked_eq_neon(key: &[u8; 32], mask: &[u8; 32], value: &[u8; 32]) -> bool {
+    use std::arch::aarch64::*;
+    let low = vandq_u8(vld1q_u8(key.as_ptr()), vld1q_u8(mask.as_ptr()));
+    let high = vandq_u8(vld1q_u8(key.as_ptr().add(16)), vld1q_u8(mask.as_ptr().add(16)));
+    let low = veorq_u8(low, vld1q_u8(value.as_ptr()));
+    let high = veorq_u8(high, vld1q_u8(value.as_ptr().add(16)));
+    vmaxvq_u8(vorrq_u8(low, high)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kernels_agree_with_scalar() {
+        assert!(Kernel::selected().is_supported());
+        let mut mask = [0u8; 32];
+        mask[..3].copy_from_slice(&[0xff, 0xff, 0xf0]);
+        let mut value = [0u8; 32];
+        value[..3].copy_from_slice(&[0x12, 0x34, 0x50]);
+
+        for kernel in Kernel::ALL.into_iter().filter(|kernel| kernel.is_supported()) {
+            for seed in 0..64u8 {
+                let key = crate::seeded_keypair(&[seed; 32], 0, 0).1.to_bytes();
+                let mut near = value;
+                near[31] = key[31];
+                near[seed as usize % 3] ^= 1 << (seed % 8);
+                for candidate in [key, value, near] {
+                    assert_eq!(
+                        masked_eq(kernel, &candidate, &mask, &value),
+                        masked_eq_scalar(&candidate, &mask, &value),
+                        "{}",
+                        kernel
+                    );
+                }
+            }
+        }
+        let key = crate::seeded_keypair(&[0; 32], 0, 0).1.to_bytes();
+        assert!(masked_eq(Kernel::selected(), &key, &[0; 32], &[0; 32]));
+    }
+}
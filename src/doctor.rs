@@ -0,0 +1,329 @@
+use crate::crypto_backend;
+use crate::logging::log_event;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde_json::json;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Bytes drawn from the OS RNG for the entropy check
+const ENTROPY_SAMPLE: usize = 64 * 1024;
+
+/// Chi-square statistic of a byte histogram that a working RNG exceeds about once in 10^9 draws
+const CHI_SQUARE_LIMIT: f64 = 420.0;
+
+/// Reading the sample should take well under this
+const ENTROPY_SLOW: Duration = Duration::from_millis(250);
+
+/// Temperature from which a thermal zone counts as hot, in degrees Celsius
+const HOT_CELSIUS: f64 = 85.0;
+
+/// How long the Tor control port may take to connect and answer
+const TOR_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How bad a `doctor` finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Ok,
+    /// Searches work, but slower or less safely than they could
+    Warning,
+    /// Searches fail or their keys cannot be trusted
+    Problem,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Ok => "ok",
+            Severity::Warning => "warning",
+            Severity::Problem => "problem",
+        }
+    }
+}
+
+/// Result of one `doctor` check
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub check: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    /// What to do about it, for warnings and problems
+    pub advice: Option<String>,
+}
+
+impl Finding {
+    fn ok(check: &'static str, message: impl Into<String>) -> Self {
+        Self { check, severity: Severity::Ok, message: message.into(), advice: None }
+    }
+
+    fn warning(check: &'static str, message: impl Into<String>, advice: impl Into<String>) -> Self {
+        Self { check, severity: Severity::Warning, message: message.into(), advice: Some(advice.into()) }
+    }
+
+    fn problem(check: &'static str, message: impl Into<String>, advice: impl Into<String>) -> Self {
+        Self { check, severity: Severity::Problem, message: message.into(), advice: Some(advice.into()) }
+    }
+
+    /// Print in the selected log format: `[√]` or `[!]` lines in text mode, a `doctor` event in JSON mode
+    pub fn log(&self) {
+        log_event(
+            "doctor",
+            &format!("{}: {}", self.check, self.message),
+            json!({ "check": self.check, "severity": self.severity.as_str(), "advice": self.advice }),
+        );
+        if !crate::json_logs() {
+            let sign = if self.severity == Severity::Ok { '√' } else { '!' };
+            crate::print_plain(&format!("[{}] {}: {}", sign, self.check, self.message));
+            if let Some(advice) = &self.advice {
+                crate::print_plain(&format!("    {}", advice));
+            }
+        }
+    }
+}
+
+/// Environment checks behind the `doctor` command
+///
+/// Covers the OS entropy source, the CPU features the crypto backend uses,
+/// signs of thermal throttling, the output directories and, when given, the
+/// Tor control port.
+#[derive(Debug, Clone, Default)]
+pub struct Doctor {
+    output_dirs: Vec<PathBuf>,
+    tor_control: Option<String>,
+}
+
+impl Doctor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check that keys can be written to `dir` without others reading them
+    pub fn output_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.output_dirs.push(dir.into());
+        self
+    }
+
+    /// Check that a Tor control port answers at `addr`, e.g. `127.0.0.1:9051`
+    pub fn tor_control(mut self, addr: impl Into<String>) -> Self {
+        self.tor_control = Some(addr.into());
+        self
+    }
+
+    /// Run every check, in a fixed order
+    pub fn run(&self) -> Vec<Finding> {
+        let mut findings = vec![check_entropy(), check_cpu()];
+        findings.push(check_thermal());
+        findings.extend(self.output_dirs.iter().map(|dir| check_output_dir(dir)));
+        findings.extend(self.tor_control.as_deref().map(check_tor_control));
+        findings
+    }
+}
+
+fn check_entropy() -> Finding {
+    const CHECK: &str = "Entropy source";
+    let mut sample = vec![0u8; ENTROPY_SAMPLE];
+    let started = Instant::now();
+    if let Err(e) = OsRng.try_fill_bytes(&mut sample) {
+        return Finding::problem(CHECK, format!("the OS RNG failed: {}", e), "Keys cannot be generated; check the kernel's getrandom support");
+    }
+    let elapsed = started.elapsed();
+
+    let mut counts = [0u32; 256];
+    for byte in &sample {
+        counts[*byte as usize] += 1;
+    }
+    let chi_square = byte_chi_square(&counts, sample.len());
+    if chi_square > CHI_SQUARE_LIMIT {
+        return Finding::problem(
+            CHECK,
+            format!("OS RNG output is far from uniform (chi-square {:.0} over 255 degrees of freedom)", chi_square),
+            "Do not use keys generated on this machine; check for a broken or emulated RNG device",
+        );
+    }
+    if elapsed > ENTROPY_SLOW {
+        return Finding::warning(
+            CHECK,
+            format!("reading {} KiB from the OS RNG took {} ms", ENTROPY_SAMPLE / 1024, elapsed.as_millis()),
+            "Key generation will be slow; on a VM, add a virtio-rng device",
+        );
+    }
+    Finding::ok(CHECK, format!("OS RNG uniform (chi-square {:.0}), {} KiB in {} ms", chi_square, ENTROPY_SAMPLE / 1024, elapsed.as_millis()))
+}
+
+/// Pearson's chi-square statistic of byte counts against a uniform distribution
+fn byte_chi_square(counts: &[u32; 256], total: usize) -> f64 {
+    let expected = total as f64 / 256.0;
+    counts.iter().map(|&count| (count as f64 - expected).powi(2) / expected).sum()
+}
+
+fn check_cpu() -> Finding {
+    const CHECK: &str = "CPU features";
+    let backend = crypto_backend();
+    let cores = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let message = format!("{}, {} cores", backend, cores);
+    if backend.arch == "x86_64" && backend.field != "AVX2" {
+        return Finding::warning(CHECK, message, "No AVX2: curve arithmetic uses the slower serial backend");
+    }
+    Finding::ok(CHECK, message)
+}
+
+/// Hot thermal zones and CPU throttle events, on Linux
+fn check_thermal() -> Finding {
+    const CHECK: &str = "Thermal throttling";
+    let temperatures: Vec<f64> = sysfs_values("/sys/class/thermal", "thermal_zone", "temp")
+        .into_iter()
+        .map(|millidegrees| millidegrees as f64 / 1000.0)
+        .collect();
+    let throttled: u64 = sysfs_values("/sys/devices/system/cpu", "cpu", "thermal_throttle/core_throttle_count")
+        .into_iter()
+        .sum();
+    if temperatures.is_empty() && throttled == 0 {
+        return Finding::ok(CHECK, "no temperature sensors or throttle counters to check");
+    }
+
+    let hottest = temperatures.iter().copied().fold(f64::NAN, f64::max);
+    if hottest >= HOT_CELSIUS || throttled > 0 {
+        let mut message = format!("{} CPU throttle events since boot", throttled);
+        if !hottest.is_nan() {
+            message.push_str(&format!(", hottest zone at {:.0}°C", hottest));
+        }
+        return Finding::warning(CHECK, message, "Long searches will slow down; improve cooling or use fewer workers (-w)");
+    }
+    Finding::ok(CHECK, format!("no throttle events, hottest zone at {:.0}°C", hottest))
+}
+
+/// Integers read from `<dir>/<prefix>*/<file>`, skipping unreadable ones
+fn sysfs_values(dir: &str, prefix: &str, file: &str) -> Vec<u64> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(prefix))
+        .filter_map(|entry| fs::read_to_string(entry.path().join(file)).ok())
+        .filter_map(|text| text.trim().parse().ok())
+        .collect()
+}
+
+pub(crate) fn check_output_dir(dir: &Path) -> Finding {
+    const CHECK: &str = "Output directory";
+    let name = dir.display();
+    let metadata = match fs::metadata(dir) {
+        Ok(metadata) if metadata.is_dir() => metadata,
+        Ok(_) => return Finding::problem(CHECK, format!("{} is not a directory", name), "Choose another path for -o"),
+        Err(_) => {
+            // Output directories are created with all missing parents
+            let parent = dir
+                .ancestors()
+                .skip(1)
+                .find(|ancestor| ancestor.as_os_str().is_empty() || ancestor.is_dir())
+                .filter(|ancestor| !ancestor.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+            return match writable(parent) {
+                Ok(()) => Finding::ok(CHECK, format!("{} does not exist yet and can be created", name)),
+                Err(e) => Finding::problem(
+                    CHECK,
+                    format!("{} does not exist and cannot be created in {}: {}", name, parent.display(), e),
+                    "Create it, or choose a directory you can write to",
+                ),
+            };
+        }
+    };
+
+    if let Err(e) = writable(dir) {
+        return Finding::problem(CHECK, format!("{} is not writable: {}", name, e), format!("Fix its ownership or permissions, e.g. chmod u+w {}", name));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = metadata.permissions().mode() & 0o777;
+        if mode & 0o077 != 0 {
+            return Finding::warning(
+                CHECK,
+                format!("{} is writable, but other users can access it (mode {:o})", name, mode),
+                format!("Key directories are created private, but restrict the parent too: chmod 700 {}", name),
+            );
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = metadata;
+    Finding::ok(CHECK, format!("{} is writable", name))
+}
+
+/// Create and remove a probe file in `dir`
+fn writable(dir: &Path) -> std::io::Result<()> {
+    let probe = dir.join(format!(".onion-doctor-{}", std::process::id()));
+    fs::write(&probe, b"")?;
+    fs::remove_file(&probe)
+}
+
+fn check_tor_control(addr: &str) -> Finding {
+    const CHECK: &str = "Tor control port";
+    match tor_protocol_info(addr) {
+        Ok(line) if line.starts_with("250") => Finding::ok(CHECK, format!("{} answers", addr)),
+        Ok(line) => Finding::problem(CHECK, format!("{} answered {:?}", addr, line), "Check that it is Tor's ControlPort"),
+        Err(e) => Finding::problem(
+            CHECK,
+            format!("cannot reach {}: {}", addr, e),
+            "Start Tor with ControlPort set in torrc, or fix the address",
+        ),
+    }
+}
+
+/// First reply line of `PROTOCOLINFO`, which Tor answers before authentication
+fn tor_protocol_info(addr: &str) -> std::io::Result<String> {
+    let addr = addr
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no address"))?;
+    let mut stream = TcpStream::connect_timeout(&addr, TOR_TIMEOUT)?;
+    stream.set_read_timeout(Some(TOR_TIMEOUT))?;
+    stream.write_all(b"PROTOCOLINFO 1\r\n")?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    Ok(line.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_entropy_check() {
+        assert_eq!(check_entropy().severity, Severity::Ok);
+
+        let mut skewed = [256u32; 256];
+        skewed[0] += 1000;
+        skewed[1] -= 200;
+        assert!(byte_chi_square(&skewed, 256 * 256 + 800) > CHI_SQUARE_LIMIT);
+    }
+
+    #[test]
+    fn test_output_dir_check() {
+        let dir = TempPath::new("doctor");
+        assert_eq!(check_output_dir(&dir).severity, Severity::Ok);
+        fs::write(&dir, b"").unwrap();
+        assert_eq!(check_output_dir(&dir).severity, Severity::Problem);
+    }
+
+    #[test]
+    fn test_tor_control_port() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let tor = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            BufReader::new(stream.try_clone().unwrap()).read_line(&mut request).unwrap();
+            assert_eq!(request, "PROTOCOLINFO 1\r\n");
+            stream.write_all(b"250-PROTOCOLINFO 1\r\n250 OK\r\n").unwrap();
+        });
+
+        assert_eq!(check_tor_control(&addr).severity, Severity::Ok);
+        tor.join().unwrap();
+    }
+}
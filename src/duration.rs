@@ -0,0 +1,121 @@
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Deserializer};
+use std::time::Duration;
+
+const UNITS: [(&str, Duration); 5] = [
+    ("d", Duration::from_secs(86400)),
+    ("h", Duration::from_secs(3600)),
+    ("m", Duration::from_secs(60)),
+    ("s", Duration::from_secs(1)),
+    ("ms", Duration::from_millis(1)),
+];
+
+/// Parse a duration such as `90s`, `2h30m`, `1d` or `250ms`
+///
+/// The units are `d`, `h`, `m`, `s` and `ms`, combined from the largest to
+/// the smallest, and numbers may have a fraction, as in `1.5h`. A bare number
+/// is taken in `unit`, so options that took plain seconds or hours still do.
+pub fn parse_duration(text: &str, unit: Duration) -> Result<Duration> {
+    let text = text.trim();
+    let invalid = || anyhow!("{:?} is not a duration; use e.g. 90s, 2h30m or 1d", text);
+    if text.is_empty() {
+        return Err(invalid());
+    }
+    if let Ok(number) = text.parse::<f64>() {
+        return scaled(number, unit).ok_or_else(invalid);
+    }
+
+    let mut total = Duration::ZERO;
+    let mut rest = text;
+    let mut smallest = None;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit() && c != '.').ok_or_else(invalid)?;
+        let letters = rest[digits..].find(|c: char| !c.is_ascii_alphabetic()).map_or(rest.len(), |end| digits + end);
+        let number: f64 = rest[..digits].parse().map_err(|_| invalid())?;
+        let Some(position) = UNITS.iter().position(|(name, _)| *name == &rest[digits..letters]) else {
+            bail!("Unknown unit {:?} in duration {:?}; use d, h, m, s or ms", &rest[digits..letters], text);
+        };
+        if smallest.is_some_and(|smallest| position <= smallest) {
+            bail!("Duration {:?} must list its units from days down to milliseconds, each once", text);
+        }
+        smallest = Some(position);
+        total += scaled(number, UNITS[position].1).ok_or_else(invalid)?;
+        rest = &rest[letters..];
+    }
+    Ok(total)
+}
+
+fn scaled(number: f64, unit: Duration) -> Option<Duration> {
+    Duration::try_from_secs_f64(number * unit.as_secs_f64()).ok()
+}
+
+/// The shortest text [`parse_duration`] reads back as `duration`, e.g. `2h30m`
+pub fn format_duration(duration: Duration) -> String {
+    let mut rest = duration.as_millis();
+    let mut text = String::new();
+    for (name, unit) in UNITS {
+        let count = rest / unit.as_millis();
+        if count > 0 {
+            text.push_str(&format!("{}{}", count, name));
+            rest -= count * unit.as_millis();
+        }
+    }
+    if text.is_empty() {
+        text.push_str("0s");
+    }
+    text
+}
+
+/// A config file duration: a number in `unit`, or text for [`parse_duration`]
+fn deserialize_in<'de, D: Deserializer<'de>>(deserializer: D, unit: Duration) -> Result<Option<Duration>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Value {
+        Number(f64),
+        Text(String),
+    }
+    let value = match Option::<Value>::deserialize(deserializer)? {
+        Some(Value::Number(number)) => scaled(number, unit).ok_or_else(|| anyhow!("{} is not a duration", number)),
+        Some(Value::Text(text)) => parse_duration(&text, unit),
+        None => return Ok(None),
+    };
+    value.map(Some).map_err(serde::de::Error::custom)
+}
+
+/// `deserialize_with` for optional durations given in seconds by default
+pub(crate) fn seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    deserialize_in(deserializer, Duration::from_secs(1))
+}
+
+/// `deserialize_with` for optional durations given in hours by default
+pub(crate) fn hours<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    deserialize_in(deserializer, Duration::from_secs(3600))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_format_durations() {
+        let secs = Duration::from_secs;
+        assert_eq!(parse_duration("90s", secs(1)).unwrap(), secs(90));
+        assert_eq!(parse_duration("2h30m", secs(1)).unwrap(), secs(9000));
+        assert_eq!(parse_duration("1d", secs(1)).unwrap(), secs(86400));
+        assert_eq!(parse_duration("1.5h", secs(1)).unwrap(), secs(5400));
+        assert_eq!(parse_duration("1m250ms", secs(1)).unwrap(), Duration::from_millis(60_250));
+        // Bare numbers keep the option's old unit
+        assert_eq!(parse_duration("30", secs(1)).unwrap(), secs(30));
+        assert_eq!(parse_duration("0.5", secs(3600)).unwrap(), secs(1800));
+
+        for text in ["", "h", "2x", "30m2h", "1h1h", "-5s", "1..5s", "5 s"] {
+            assert!(parse_duration(text, secs(1)).is_err(), "{:?}", text);
+        }
+
+        assert_eq!(format_duration(secs(9000)), "2h30m");
+        assert_eq!(format_duration(Duration::from_millis(90_250)), "1m30s250ms");
+        assert_eq!(format_duration(Duration::ZERO), "0s");
+        let odd = secs(86400 + 61);
+        assert_eq!(parse_duration(&format_duration(odd), secs(1)).unwrap(), odd);
+    }
+}
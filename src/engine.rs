@@ -0,0 +1,135 @@
+use crate::crypto::encode_onion_address;
+use crate::{MatchSpec, Matcher};
+use rand::Rng;
+use std::time::{Duration, Instant};
+
+/// Prefix and contains patterns from which on [`MatchEngine::Auto`] indexes them
+const INDEX_MIN_WORDS: usize = 16;
+
+/// Prefixes up to which comparing them on the raw key beats encoding every candidate
+const KEY_MASK_MAX_WORDS: usize = 200;
+
+/// Random public keys each engine checks over and over in [`bench_match_engines`]
+const BENCH_CANDIDATES: usize = 4096;
+
+/// How a [`Matcher`] checks candidates against its include patterns
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatchEngine {
+    /// The key mask while it applies and there are fewer than 200 words,
+    /// otherwise the trie for 16 or more prefix and contains patterns
+    #[default]
+    Auto,
+    /// Every pattern in turn on the encoded address
+    Naive,
+    /// Fixed characters compared on the raw key bits first, see [`Matcher::key_may_match`]
+    KeyMask,
+    /// Prefix and contains patterns in Aho-Corasick automatons
+    AhoCorasick,
+    /// Prefixes in a trie walked character by character, contains patterns in an automaton
+    Trie,
+}
+
+impl MatchEngine {
+    /// Every engine [`Auto`](Self::Auto) can pick
+    pub const ALL: [MatchEngine; 4] = [MatchEngine::Naive, MatchEngine::KeyMask, MatchEngine::AhoCorasick, MatchEngine::Trie];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MatchEngine::Auto => "auto",
+            MatchEngine::Naive => "naive",
+            MatchEngine::KeyMask => "key mask",
+            MatchEngine::AhoCorasick => "aho-corasick",
+            MatchEngine::Trie => "trie",
+        }
+    }
+
+    /// The engine [`Auto`](Self::Auto) stands for with these includes
+    ///
+    /// `key_mask` tells whether every include has a condition on the raw key.
+    pub(crate) fn pick(includes: &[MatchSpec], key_mask: bool) -> Self {
+        let words = includes.iter().filter(|spec| matches!(spec, MatchSpec::Prefix(_) | MatchSpec::Contains(_))).count();
+        if key_mask && words < KEY_MASK_MAX_WORDS {
+            MatchEngine::KeyMask
+        } else if words >= INDEX_MIN_WORDS {
+            MatchEngine::Trie
+        } else {
+            MatchEngine::KeyMask
+        }
+    }
+}
+
+impl std::fmt::Display for MatchEngine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Candidates per second `matcher` gets through with each engine, fastest first
+///
+/// Every engine checks the same random public keys the way a search does: the
+/// key check, then encoding and the address check for keys that pass it. Key
+/// generation costs the same for all of them and is left out. Each engine runs
+/// for `duration`.
+pub fn bench_match_engines(matcher: &Matcher, duration: Duration) -> Vec<(MatchEngine, f64)> {
+    let mut rng = rand::thread_rng();
+    let keys: Vec<[u8; 32]> = (0..BENCH_CANDIDATES).map(|_| rng.gen()).collect();
+
+    let mut rates: Vec<(MatchEngine, f64)> = MatchEngine::ALL
+        .iter()
+        .map(|&engine| {
+            let matcher = matcher.clone().with_engine(engine);
+            let started = Instant::now();
+            let mut checked = 0;
+            while checked == 0 || started.elapsed() < duration {
+                for key in &keys {
+                    if matcher.key_may_match(key) {
+                        std::hint::black_box(matcher.is_match(&encode_onion_address(key)));
+                    }
+                }
+                checked += keys.len();
+            }
+            (engine, checked as f64 / started.elapsed().as_secs_f64())
+        })
+        .collect();
+    rates.sort_by(|a, b| b.1.total_cmp(&a.1));
+    rates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_engines_agree() {
+        let words: Vec<String> = (0..40).map(|i| format!("{}{}", crate::BASE32_ALPHABET.as_bytes()[i % 32] as char, i % 6 + 2)).collect();
+        let few = Matcher::from_prefixes(&words[..3]);
+        let many = Matcher::new(
+            words.iter().cloned().map(MatchSpec::Prefix).chain([MatchSpec::Suffix("yd".to_string())]).collect(),
+            vec![MatchSpec::Contains("zz".to_string())],
+        );
+        assert_eq!(few.engine(), MatchEngine::KeyMask);
+        // The suffix has no condition on the key
+        assert_eq!(many.engine(), MatchEngine::Trie);
+        assert_eq!(Matcher::from_prefixes(&words).engine(), MatchEngine::KeyMask);
+        let lots: Vec<String> = (0..KEY_MASK_MAX_WORDS).map(|i| format!("{}{}", words[i % 40], i)).collect();
+        assert_eq!(Matcher::from_prefixes(&lots).engine(), MatchEngine::Trie);
+        assert_eq!(few.clone().with_engine(MatchEngine::Auto).engine(), MatchEngine::KeyMask);
+
+        for _ in 0..2000 {
+            let key: [u8; 32] = rand::thread_rng().gen();
+            let address = encode_onion_address(&key);
+            for matcher in [&few, &many] {
+                let expected = matcher.includes().iter().any(|spec| spec.is_match(address.trim_end_matches(".onion")))
+                    && !matcher.excludes().iter().any(|spec| spec.is_match(address.trim_end_matches(".onion")));
+                for engine in MatchEngine::ALL {
+                    let matcher = matcher.clone().with_engine(engine);
+                    assert_eq!(matcher.key_may_match(&key) && matcher.is_match(&address), expected, "{} {}", engine, address);
+                }
+            }
+        }
+
+        let rates = bench_match_engines(&many, Duration::from_millis(20));
+        assert_eq!(rates.len(), MatchEngine::ALL.len());
+        assert!(rates.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+    }
+}
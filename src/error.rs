@@ -0,0 +1,134 @@
+use std::fmt;
+
+/// Class of failure, with a stable exit status and machine-readable code
+///
+/// Scripts can branch on the exit status, or on the `code` field of the error
+/// record in `--log-format json` mode and of HTTP API error responses. The
+/// numbers and names never change meaning; new classes get new ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// Anything not covered below
+    Other,
+    /// A pattern is malformed or can never match
+    InvalidPattern,
+    /// An output directory or file cannot be created or written
+    OutputNotWritable,
+    /// Credentials were missing or rejected, e.g. a token file or unseal key
+    AuthFailed,
+    /// Key generation could not start: worker threads, the OS RNG or a key backend
+    BackendInit,
+    /// The config file cannot be read or parsed
+    InvalidConfig,
+    /// A periodic self-check found a key or hostname that does not verify
+    SelfCheckFailed,
+}
+
+impl ErrorCode {
+    /// Every code, in exit status order
+    pub const ALL: [ErrorCode; 7] = [
+        ErrorCode::Other,
+        ErrorCode::InvalidPattern,
+        ErrorCode::OutputNotWritable,
+        ErrorCode::AuthFailed,
+        ErrorCode::BackendInit,
+        ErrorCode::InvalidConfig,
+        ErrorCode::SelfCheckFailed,
+    ];
+
+    /// Process exit status; 2 is left to command line usage errors and 130 to Ctrl-C
+    pub fn exit_status(self) -> i32 {
+        match self {
+            ErrorCode::Other => 1,
+            ErrorCode::InvalidPattern => 3,
+            ErrorCode::OutputNotWritable => 4,
+            ErrorCode::AuthFailed => 5,
+            ErrorCode::BackendInit => 6,
+            ErrorCode::InvalidConfig => 7,
+            ErrorCode::SelfCheckFailed => 8,
+        }
+    }
+
+    /// Name used in JSON records and API responses
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::Other => "other",
+            ErrorCode::InvalidPattern => "invalid_pattern",
+            ErrorCode::OutputNotWritable => "output_not_writable",
+            ErrorCode::AuthFailed => "auth_failed",
+            ErrorCode::BackendInit => "backend_init_failed",
+            ErrorCode::InvalidConfig => "invalid_config",
+            ErrorCode::SelfCheckFailed => "self_check_failed",
+        }
+    }
+
+    /// The code attached to `error` or any error it wraps, see [`ResultExt::with_code`]
+    pub fn of(error: &anyhow::Error) -> Self {
+        error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<CodedError>())
+            .map_or(ErrorCode::Other, |coded| coded.code)
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// An error tagged with its [`ErrorCode`]; displays exactly like the error it wraps
+#[derive(Debug)]
+pub struct CodedError {
+    pub code: ErrorCode,
+    error: anyhow::Error,
+}
+
+impl fmt::Display for CodedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for CodedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
+/// Attach an [`ErrorCode`] to the error of a `Result`
+pub trait ResultExt<T> {
+    fn with_code(self, code: ErrorCode) -> anyhow::Result<T>;
+}
+
+impl<T, E: Into<anyhow::Error>> ResultExt<T> for Result<T, E> {
+    fn with_code(self, code: ErrorCode) -> anyhow::Result<T> {
+        self.map_err(|error| CodedError { code, error: error.into() }.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_code_survives_context() {
+        let error = Err::<(), _>(anyhow::anyhow!("bad prefix"))
+            .context("Template \"L{x}\"")
+            .with_code(ErrorCode::InvalidPattern)
+            .context("Invalid job")
+            .unwrap_err();
+
+        assert_eq!(ErrorCode::of(&error), ErrorCode::InvalidPattern);
+        assert_eq!(format!("{:#}", error), "Invalid job: Template \"L{x}\": bad prefix");
+        assert_eq!(ErrorCode::of(&anyhow::anyhow!("plain")), ErrorCode::Other);
+    }
+
+    #[test]
+    fn test_exit_statuses_are_distinct() {
+        let mut statuses: Vec<i32> = ErrorCode::ALL.iter().map(|code| code.exit_status()).collect();
+        statuses.dedup();
+        assert_eq!(statuses.len(), ErrorCode::ALL.len());
+        assert!(!statuses.contains(&2) && !statuses.contains(&130));
+    }
+}
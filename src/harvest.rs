@@ -0,0 +1,527 @@
+use crate::config::parse_patterns;
+use crate::hsm::shell;
+use crate::trie::char_value;
+use crate::{generate_with_predicate_counted_until, logln, ErrorCode, ResultExt, print_stats, GenerationStats, KeyCustody, OnionResult, ADDRESS_LEN};
+use aho_corasick::{AhoCorasick, MatchKind};
+use anyhow::{bail, Context, Result};
+use serde_json::json;
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Shortest time between two rewrites of the ranked file
+const WRITE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Shorter words turn up in nearly every address, so they never score
+pub const MIN_WORD_LEN: usize = 3;
+
+/// The built-in list of [`WordScorer::english`]
+const ENGLISH_WORDS: &str = include_str!("../data/english_words.txt");
+
+/// Lowest `--min-score` accepted: one shortest word, or about one address in
+/// 2^15; anything lower would report most candidates
+pub const MIN_REPORTED_SCORE: f64 = 5.0 * MIN_WORD_LEN as f64;
+
+/// How harvested addresses are ranked; higher scores are better
+#[derive(Debug, Clone, Default)]
+pub enum Scorer {
+    /// The built-in [`aesthetic_score`]
+    #[default]
+    Aesthetic,
+    /// A shell command that reads one address per line on stdin and answers
+    /// one score per line on stdout; each worker runs its own copy
+    Script(String),
+    /// The words of a list found in the address, see [`WordScorer`]
+    Words(WordScorer),
+}
+
+/// Settings of a harvest run
+#[derive(Debug, Clone)]
+pub struct HarvestConfig {
+    /// Ranked file, rewritten whenever the top addresses change
+    pub output: PathBuf,
+    pub top: usize,
+    pub scorer: Scorer,
+    /// Report every address scoring at least this much, not only new bests
+    pub min_score: Option<f64>,
+    pub num_workers: usize,
+    /// Stop after this long; `None` runs until stopped
+    pub duration: Option<Duration>,
+    pub update_interval: u64,
+}
+
+impl HarvestConfig {
+    pub fn new(output: impl Into<PathBuf>) -> Self {
+        Self {
+            output: output.into(),
+            top: 20,
+            scorer: Scorer::Aesthetic,
+            min_score: None,
+            num_workers: thread::available_parallelism().map(|n| n.get()).unwrap_or(4),
+            duration: None,
+            update_interval: 30,
+        }
+    }
+
+    pub fn with_top(mut self, top: usize) -> Self {
+        self.top = top;
+        self
+    }
+
+    pub fn with_scorer(mut self, scorer: Scorer) -> Self {
+        self.scorer = scorer;
+        self
+    }
+
+    pub fn with_min_score(mut self, min_score: Option<f64>) -> Self {
+        self.min_score = min_score;
+        self
+    }
+
+    pub fn with_workers(mut self, num_workers: usize) -> Self {
+        self.num_workers = num_workers;
+        self
+    }
+
+    pub fn with_duration(mut self, duration: Option<Duration>) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    pub fn with_update_interval(mut self, interval: u64) -> Self {
+        self.update_interval = interval;
+        self
+    }
+}
+
+/// Rate how nice an address looks, in bits
+///
+/// Each feature of the start of the address is scored by how unlikely it is
+/// in a random address: a run of identical characters, a repeated or
+/// palindromic start, only letters, or alternating consonants and vowels. The
+/// score is the rarest of them, so 15 means about one address in 2^15 looks
+/// at least this good. The fixed last two characters are ignored.
+pub fn aesthetic_score(address: &str) -> f64 {
+    let address = address.strip_suffix(".onion").unwrap_or(address);
+    let chars = &address.as_bytes()[..address.len().min(ADDRESS_LEN - 2)];
+    let len = chars.len();
+
+    let run = chars.iter().take_while(|&&c| Some(&c) == chars.first()).count();
+    let doubled = (1..=len / 2).filter(|&n| chars[..n] == chars[n..2 * n]).max().unwrap_or(0);
+    let palindrome = (2..=len)
+        .filter(|&n| (0..n / 2).all(|i| chars[i] == chars[n - 1 - i]))
+        .max()
+        .unwrap_or(0);
+    let letters = chars.iter().take_while(|c| c.is_ascii_lowercase()).count();
+
+    [
+        5.0 * run.saturating_sub(1) as f64,
+        5.0 * doubled as f64,
+        5.0 * (palindrome / 2) as f64,
+        letters as f64 * (32f64 / 26.0).log2(),
+        alternating_bits(chars),
+    ]
+    .into_iter()
+    .fold(0.0, f64::max)
+}
+
+/// Bits of the longest start alternating between consonants and vowels
+fn alternating_bits(chars: &[u8]) -> f64 {
+    let is_vowel = |c: &u8| b"aeiou".contains(c);
+    let mut bits = 0.0;
+    for (i, c) in chars.iter().enumerate() {
+        if !c.is_ascii_lowercase() || (i > 0 && is_vowel(c) == is_vowel(&chars[i - 1])) {
+            break;
+        }
+        bits += if is_vowel(c) { (32f64 / 5.0).log2() } else { (32f64 / 21.0).log2() };
+    }
+    bits
+}
+
+/// Rate an address by the words of a list it contains, in bits
+///
+/// Each character covered by a word counts 5 bits, the chance of that
+/// character in a random address. Words are taken leftmost and longest
+/// first and may not overlap, so `torch` counts once rather than also as
+/// `tor`. The fixed last two characters are ignored. Clones share the list.
+#[derive(Debug, Clone)]
+pub struct WordScorer {
+    automaton: AhoCorasick,
+    len: usize,
+}
+
+impl WordScorer {
+    /// Words are lowercased; those shorter than [`MIN_WORD_LEN`] or with
+    /// characters that never appear in addresses are left out
+    pub fn new<'a>(words: impl IntoIterator<Item = &'a str>) -> Result<Self> {
+        let mut words: Vec<String> = words
+            .into_iter()
+            .map(str::to_ascii_lowercase)
+            .filter(|word| word.len() >= MIN_WORD_LEN && word.bytes().all(|c| char_value(c).is_some()))
+            .collect();
+        words.sort_unstable();
+        words.dedup();
+        if words.is_empty() {
+            bail!("The word list has no usable words: they need {} or more characters, all a-z or 2-7", MIN_WORD_LEN);
+        }
+        let automaton = AhoCorasick::builder().match_kind(MatchKind::LeftmostLongest).build(&words)?;
+        Ok(Self { automaton, len: words.len() })
+    }
+
+    /// Scorer over a built-in list of common English words
+    pub fn english() -> Self {
+        Self::new(parse_patterns(ENGLISH_WORDS).iter().map(String::as_str)).expect("built-in word list is valid")
+    }
+
+    /// Number of distinct words
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn score(&self, address: &str) -> f64 {
+        self.words(address).iter().map(|word| 5.0 * word.len() as f64).sum()
+    }
+
+    /// The words counted by [`score`](Self::score), in order
+    pub fn words<'a>(&self, address: &'a str) -> Vec<&'a str> {
+        let address = address.strip_suffix(".onion").unwrap_or(address);
+        let address = &address[..address.len().min(ADDRESS_LEN - 2)];
+        self.automaton.find_iter(address).map(|found| &address[found.range()]).collect()
+    }
+}
+
+/// A harvested address with its score
+#[derive(Debug, Clone)]
+pub struct Scored {
+    pub score: f64,
+    pub result: OnionResult,
+}
+
+/// The best `capacity` addresses seen so far, highest score first
+#[derive(Debug, Clone)]
+pub struct Leaderboard {
+    capacity: usize,
+    entries: Vec<Scored>,
+}
+
+impl Leaderboard {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, entries: Vec::with_capacity(capacity + 1) }
+    }
+
+    /// Insert `scored` if it ranks; returns its rank, 0 being the best
+    ///
+    /// Ties keep the earlier address ahead.
+    pub fn offer(&mut self, scored: Scored) -> Option<usize> {
+        let rank = self.entries.partition_point(|entry| entry.score >= scored.score);
+        if rank >= self.capacity {
+            return None;
+        }
+        self.entries.insert(rank, scored);
+        self.entries.truncate(self.capacity);
+        Some(rank)
+    }
+
+    /// Score an address must beat to get onto the board
+    pub fn threshold(&self) -> f64 {
+        match self.entries.get(self.capacity.saturating_sub(1)) {
+            Some(last) if self.entries.len() == self.capacity => last.score,
+            _ => f64::NEG_INFINITY,
+        }
+    }
+
+    pub fn entries(&self) -> &[Scored] {
+        &self.entries
+    }
+
+    /// Replace `path` with the ranked list, one JSON record per line
+    ///
+    /// The file holds private keys, so it is only readable by the current
+    /// user, and it is swapped in whole so readers never see a partial list.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        let _ = fs::remove_file(&temp);
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options
+            .open(&temp)
+            .with_context(|| format!("Failed to create {}", temp.display()))
+            .with_code(ErrorCode::OutputNotWritable)?;
+        for (rank, entry) in self.entries.iter().enumerate() {
+            let result = &entry.result;
+            let private_key = (result.custody == KeyCustody::Local).then_some(&result.private_key);
+            let record = json!({
+                "rank": rank + 1,
+                "score": entry.score,
+                "hostname": result.hostname,
+                "public_key": result.public_key,
+                "private_key": private_key,
+            });
+            writeln!(file, "{}", record)?;
+        }
+        file.sync_all()?;
+        fs::rename(&temp, path).with_context(|| format!("Failed to replace {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// A running score script, answering one line per address
+struct ScriptScorer {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl ScriptScorer {
+    fn spawn(command: &str) -> Result<Self> {
+        let mut child = shell(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run {:?}", command))?;
+        let stdin = child.stdin.take().context("Score script has no stdin")?;
+        let stdout = BufReader::new(child.stdout.take().context("Score script has no stdout")?);
+        Ok(Self { child, stdin, stdout })
+    }
+
+    fn score(&mut self, address: &str) -> Result<f64> {
+        writeln!(self.stdin, "{}", address).context("Score script stopped reading")?;
+        self.stdin.flush()?;
+        let mut line = String::new();
+        if self.stdout.read_line(&mut line)? == 0 {
+            bail!("Score script exited");
+        }
+        line.trim()
+            .parse()
+            .with_context(|| format!("Score script answered {:?} instead of a number", line.trim()))
+    }
+}
+
+impl Drop for ScriptScorer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Generate and score addresses until `stop` is set or the duration is over
+///
+/// Every worker scores each candidate and only passes on those that beat the
+/// current board, so the ranked file costs nothing while the board is stable.
+/// With a minimum score they pass on every address reaching it instead, and
+/// each is logged as it comes.
+/// Returns the final board, which is also in `config.output`.
+pub fn harvest(config: &HarvestConfig, stats: &GenerationStats, stop: &AtomicBool) -> Result<Leaderboard> {
+    if config.top == 0 {
+        bail!("The ranked list needs room for at least one address");
+    }
+    if let Some(min_score) = config.min_score.filter(|&min_score| min_score.is_nan() || min_score < MIN_REPORTED_SCORE) {
+        return Err(anyhow::anyhow!("Minimum score {} is too low: use {} or more", min_score, MIN_REPORTED_SCORE))
+            .with_code(ErrorCode::InvalidConfig);
+    }
+    let started = Instant::now();
+    let done = AtomicBool::new(false);
+    let threshold = AtomicU64::new(f64::NEG_INFINITY.to_bits());
+    let (sender, receiver) = mpsc::channel::<Scored>();
+    let mut board = Leaderboard::new(config.top);
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..config.num_workers.max(1))
+            .map(|_| {
+                let sender = sender.clone();
+                let (done, threshold) = (&done, &threshold);
+                scope.spawn(move || {
+                    let result = harvest_worker(&config.scorer, config.min_score, stats, done, threshold, &sender);
+                    if result.is_err() {
+                        done.store(true, Ordering::Relaxed);
+                    }
+                    result
+                })
+            })
+            .collect();
+        drop(sender);
+
+        let (mut last_write, mut last_stats, mut dirty) = (Instant::now(), Instant::now(), false);
+        loop {
+            if stop.load(Ordering::Relaxed) || config.duration.is_some_and(|duration| started.elapsed() >= duration) {
+                done.store(true, Ordering::Relaxed);
+            }
+            match receiver.recv_timeout(Duration::from_millis(200)) {
+                Ok(scored) => {
+                    let (hostname, score) = (scored.result.hostname.clone(), scored.score);
+                    let words = match &config.scorer {
+                        Scorer::Words(scorer) => format!(", {}", scorer.words(&hostname).join(" ")),
+                        _ => String::new(),
+                    };
+                    let rank = board.offer(scored);
+                    match config.min_score {
+                        Some(_) => logln!("[+] {} (score {:.1}{})", hostname, score, words),
+                        None if rank == Some(0) => logln!("[+] New best: {} (score {:.1}{})", hostname, score, words),
+                        None => {}
+                    }
+                    threshold.store(board.threshold().to_bits(), Ordering::Relaxed);
+                    dirty = true;
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+            if dirty && last_write.elapsed() >= WRITE_INTERVAL {
+                board.write(&config.output)?;
+                (last_write, dirty) = (Instant::now(), false);
+            }
+            if last_stats.elapsed() >= Duration::from_secs(config.update_interval) {
+                let (generated, found) = stats.get();
+                print_stats(generated, found);
+                last_stats = Instant::now();
+            }
+        }
+
+        for worker in workers {
+            worker.join().map_err(|_| anyhow::anyhow!("Harvest worker panicked"))??;
+        }
+        board.write(&config.output)
+    })?;
+
+    Ok(board)
+}
+
+fn harvest_worker(
+    scorer: &Scorer,
+    min_score: Option<f64>,
+    stats: &GenerationStats,
+    done: &AtomicBool,
+    threshold: &AtomicU64,
+    sender: &mpsc::Sender<Scored>,
+) -> Result<()> {
+    let script = match scorer {
+        Scorer::Script(command) => Some(RefCell::new(ScriptScorer::spawn(command)?)),
+        _ => None,
+    };
+    let error = RefCell::new(None);
+    let last_score = Cell::new(0.0);
+
+    // The predicate cannot fail, so a script error stops the search and is checked after
+    let beats_board = |hostname: &str| {
+        let address = hostname.trim_end_matches(".onion");
+        let score = match (scorer, &script) {
+            (Scorer::Words(words), _) => words.score(address),
+            (_, Some(script)) => match script.borrow_mut().score(address) {
+                Ok(score) => score,
+                Err(e) => {
+                    *error.borrow_mut() = Some(e);
+                    return true;
+                }
+            },
+            (_, None) => aesthetic_score(address),
+        };
+        last_score.set(score);
+        // With a minimum, everything reaching it is reported and nothing below gets on the board
+        match min_score {
+            Some(min_score) => score >= min_score,
+            None => score > f64::from_bits(threshold.load(Ordering::Relaxed)),
+        }
+    };
+
+    while let Some(result) = generate_with_predicate_counted_until(beats_board, stats, done)? {
+        if let Some(e) = error.take() {
+            return Err(e);
+        }
+        if sender.send(Scored { score: last_score.get(), result }).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+    use crate::generate_onion_address;
+
+    #[test]
+    fn test_aesthetic_score() {
+        let padded = |start: &str| format!("{}{}", start, "3".repeat(56 - start.len()));
+        assert_eq!(aesthetic_score(&padded("2a")), 0.0);
+        assert_eq!(aesthetic_score(&padded("22223")), 15.0);
+        assert_eq!(aesthetic_score(&padded("x7kx7k")), 15.0);
+        assert_eq!(aesthetic_score(&padded("x7k7x")), 10.0);
+        assert!(aesthetic_score(&padded("torabikema")) > aesthetic_score(&padded("tttrabikema")));
+        assert!(aesthetic_score(&(padded("x7k7x") + ".onion")) == 10.0);
+    }
+
+    #[test]
+    fn test_word_scorer() {
+        let scorer = WordScorer::new(["Tor", "torch", "cat", "ox", "h1dden", "tor"]).unwrap();
+        assert_eq!(scorer.len(), 3);
+        let address = format!("{}{}", "torchxcat", "2".repeat(45)) + "at";
+        assert_eq!(scorer.words(&address), ["torch", "cat"]);
+        assert_eq!(scorer.score(&address), 40.0);
+        // The fixed last characters never count
+        assert_eq!(scorer.words(&format!("{}cat", "2".repeat(53))), Vec::<&str>::new());
+        assert!(WordScorer::new(["ox", "h1dden"]).is_err());
+
+        let english = WordScorer::english();
+        assert!(english.len() > 1000);
+        assert!(english.score(&format!("{}garden{}", "2".repeat(10), "2".repeat(40))) >= 30.0);
+    }
+
+    #[test]
+    fn test_leaderboard() {
+        let entry = |score| Scored { score, result: generate_onion_address().unwrap() };
+        let mut board = Leaderboard::new(2);
+        assert_eq!(board.threshold(), f64::NEG_INFINITY);
+        assert_eq!(board.offer(entry(1.0)), Some(0));
+        assert_eq!(board.offer(entry(3.0)), Some(0));
+        assert_eq!(board.threshold(), 1.0);
+        assert_eq!(board.offer(entry(2.0)), Some(1));
+        assert_eq!(board.offer(entry(0.5)), None);
+        let scores: Vec<f64> = board.entries().iter().map(|entry| entry.score).collect();
+        assert_eq!(scores, [3.0, 2.0]);
+    }
+
+    #[test]
+    fn test_leaderboard_is_written_by_rank() {
+        let mut board = Leaderboard::new(2);
+        for score in [1.0, 3.0] {
+            board.offer(Scored { score, result: generate_onion_address().unwrap() });
+        }
+        let path = TempPath::new("harvest.jsonl");
+        board.write(&path).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        let first: serde_json::Value = serde_json::from_str(text.lines().next().unwrap()).unwrap();
+        assert_eq!(first["rank"], 1);
+        assert_eq!(first["hostname"], board.entries()[0].result.hostname.as_str());
+    }
+
+    #[test]
+    fn test_low_minimum_score_is_rejected() {
+        let config = HarvestConfig::new("unused.jsonl").with_min_score(Some(MIN_REPORTED_SCORE - 1.0));
+        let error = harvest(&config, &GenerationStats::new(), &AtomicBool::new(true)).unwrap_err();
+        assert_eq!(ErrorCode::of(&error), ErrorCode::InvalidConfig);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_script_scorer() {
+        let mut scorer = ScriptScorer::spawn("while read a; do echo ${#a}; done").unwrap();
+        assert_eq!(scorer.score("abcd").unwrap(), 4.0);
+        assert_eq!(scorer.score("ab").unwrap(), 2.0);
+        assert!(ScriptScorer::spawn("echo nope").unwrap().score("abc").is_err());
+    }
+}
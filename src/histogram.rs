@@ -0,0 +1,160 @@
+use std::fmt::Write;
+use portable_atomic::AtomicU128;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Number of power-of-two buckets, enough for any `u128` gap
+const BUCKETS: usize = 128;
+
+/// Distribution of the number of attempts between consecutive finds
+///
+/// Bucket `i` counts gaps in `[2^i, 2^(i+1))`. For a fair generator the gaps
+/// follow a geometric distribution, so the histogram should peak around the
+/// bucket holding the expected number of attempts.
+///
+/// Gaps are exact when one thread records into the histogram, as each worker
+/// of a pool does into its own [`crate::GenerationStats`]. The pool's totals
+/// take the gaps the workers measured instead of their own counter, so two
+/// workers finding at once do not record a gap of zero. When threads share a
+/// single histogram, such concurrent finds land in bucket 0 and the histogram
+/// is only approximate.
+#[derive(Debug)]
+pub struct FindHistogram {
+    buckets: [AtomicU64; BUCKETS],
+    last_find: AtomicU128,
+}
+
+impl Default for FindHistogram {
+    fn default() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            last_find: AtomicU128::new(0),
+        }
+    }
+}
+
+impl FindHistogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a find made when `generated` candidates had been tried in total
+    ///
+    /// Returns the gap to the previous find.
+    pub fn record(&self, generated: u128) -> u128 {
+        let previous = self.last_find.fetch_max(generated, Ordering::Relaxed);
+        let gap = generated.saturating_sub(previous).max(1);
+        self.record_gap(gap, generated);
+        gap
+    }
+
+    /// Record a find `gap` attempts after the previous one, measured elsewhere,
+    /// when `generated` candidates had been tried in total
+    pub fn record_gap(&self, gap: u128, generated: u128) {
+        self.last_find.fetch_max(generated, Ordering::Relaxed);
+        let bucket = (u128::BITS - 1 - gap.max(1).leading_zeros()) as usize;
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count per bucket; index `i` covers gaps in `[2^i, 2^(i+1))`
+    pub fn counts(&self) -> [u64; BUCKETS] {
+        std::array::from_fn(|i| self.buckets[i].load(Ordering::Relaxed))
+    }
+
+    /// Number of recorded finds
+    pub fn total(&self) -> u64 {
+        self.counts().iter().sum()
+    }
+
+    /// Average number of attempts per find
+    pub fn mean(&self) -> Option<f64> {
+        match self.total() {
+            0 => None,
+            total => Some(self.last_find.load(Ordering::Relaxed) as f64 / total as f64),
+        }
+    }
+
+    /// ASCII bar chart of the non-empty range of buckets, bars up to `width` wide
+    pub fn render(&self, width: usize) -> String {
+        let counts = self.counts();
+        let (Some(first), Some(last)) = (
+            counts.iter().position(|&count| count > 0),
+            counts.iter().rposition(|&count| count > 0),
+        ) else {
+            return String::new();
+        };
+        let max = counts[first..=last].iter().copied().max().unwrap_or(1);
+
+        let mut out = String::new();
+        for (bucket, &count) in counts.iter().enumerate().take(last + 1).skip(first) {
+            let bar = (count as f64 / max as f64 * width as f64).round() as usize;
+            let _ = writeln!(
+                out,
+                "  2^{:<3} .. 2^{:<3} |{:<width$}| {}",
+                bucket,
+                bucket + 1,
+                "#".repeat(bar),
+                count,
+                width = width
+            );
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_buckets_gaps() {
+        let histogram = FindHistogram::new();
+        histogram.record(1); // gap 1
+        histogram.record(4); // gap 3
+        histogram.record(12); // gap 8
+
+        let counts = histogram.counts();
+        assert_eq!(counts[0], 1);
+        assert_eq!(counts[1], 1);
+        assert_eq!(counts[3], 1);
+        assert_eq!(histogram.total(), 3);
+        assert_eq!(histogram.mean(), Some(4.0));
+
+        histogram.record(12 + (1 << 100));
+        assert_eq!(histogram.counts()[100], 1);
+    }
+
+    #[test]
+    fn test_concurrent_finds_keep_the_worker_gaps() {
+        let pool = std::sync::Arc::new(crate::GenerationStats::new());
+        let workers = [crate::GenerationStats::child_of(&pool), crate::GenerationStats::child_of(&pool)];
+        for _ in 0..8 {
+            for worker in &workers {
+                worker.increment_generated();
+            }
+        }
+        // Both find on their 8th candidate; the pool has seen 16 by then
+        for worker in &workers {
+            worker.increment_found();
+        }
+        assert_eq!(pool.find_histogram().counts()[3], 2);
+        assert_eq!(pool.find_histogram().counts()[0], 0);
+        assert_eq!(pool.find_histogram().mean(), Some(8.0));
+    }
+
+    #[test]
+    fn test_render_covers_non_empty_range() {
+        let histogram = FindHistogram::new();
+        assert_eq!(histogram.render(10), "");
+
+        histogram.record(2);
+        histogram.record(18);
+        histogram.record(34);
+
+        let rendered = histogram.render(10);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("  2^1   .. 2^2  "));
+        assert!(lines[0].ends_with("| 1"));
+        assert!(lines[3].contains("##########| 2"));
+    }
+}
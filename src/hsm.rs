@@ -0,0 +1,116 @@
+use crate::{KeyCustody, OnionResult};
+use anyhow::{bail, Context, Result};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use zeroize::Zeroizing;
+
+/// DER header of an RFC 8410 PKCS#8 ed25519 private key; the 32-byte seed follows
+const PKCS8_ED25519_PREFIX: [u8; 16] = [
+    0x30, 0x2e, 0x02, 0x01, 0x00, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22, 0x04, 0x20,
+];
+
+/// Encode an ed25519 seed as PKCS#8 DER, the format PKCS#11 and HSM import tools accept
+pub(crate) fn ed25519_pkcs8_der(seed: &[u8; 32]) -> Zeroizing<Vec<u8>> {
+    let mut der = Zeroizing::new(Vec::with_capacity(PKCS8_ED25519_PREFIX.len() + seed.len()));
+    der.extend_from_slice(&PKCS8_ED25519_PREFIX);
+    der.extend_from_slice(seed);
+    der
+}
+
+/// Hands found keys to a token import command instead of keeping them locally
+///
+/// The command runs through the shell with the key as PKCS#8 DER on stdin and
+/// `ONION_HOSTNAME` set, e.g. `pkcs11-tool --write-object /dev/stdin --type
+/// privkey ...`. Nothing is written to disk; the seed only ever exists in
+/// memory and in the pipe to the command.
+pub struct TokenImport {
+    command: String,
+}
+
+impl TokenImport {
+    pub fn new(command: impl Into<String>) -> Self {
+        Self { command: command.into() }
+    }
+
+    /// Import the key of `result` and return it without its private key
+    ///
+    /// Fails, leaving `result` untouched, if the key is no longer held locally
+    /// or the command does not exit successfully.
+    pub fn import(&self, result: &OnionResult) -> Result<OnionResult> {
+        let Some(seed) = result.seed.as_ref().filter(|_| result.custody == KeyCustody::Local) else {
+            bail!("The private key of {} is not held locally", result.hostname);
+        };
+
+        let mut child = shell(&self.command)
+            .env("ONION_HOSTNAME", &result.hostname)
+            .stdin(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run {:?}", self.command))?;
+        // Dropping stdin closes the pipe so the command sees end of input
+        let written = child.stdin.take().map(|mut stdin| stdin.write_all(&ed25519_pkcs8_der(seed)));
+        let status = child.wait()?;
+        if !status.success() {
+            bail!("Import command failed with {}", status);
+        }
+        if let Some(Err(e)) = written {
+            bail!("Failed to pass the key to the import command: {}", e);
+        }
+
+        Ok(OnionResult {
+            private_key: String::new(),
+            custody: KeyCustody::Token,
+            seed: None,
+            ..result.clone()
+        })
+    }
+}
+
+#[cfg(unix)]
+pub(crate) fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+pub(crate) fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+    use crate::generate_onion_address;
+    use ed25519_dalek::SigningKey;
+
+    #[test]
+    fn test_import_pipes_pkcs8_seed() {
+        let path = TempPath::new("hsm.der");
+        let result = generate_onion_address().unwrap();
+
+        let imported = TokenImport::new(format!("test -n \"$ONION_HOSTNAME\" && cat > {}", path.display()))
+            .import(&result)
+            .unwrap();
+        assert_eq!(imported.custody, KeyCustody::Token);
+        assert!(imported.private_key.is_empty() && imported.seed.is_none());
+
+        let der = std::fs::read(&path).unwrap();
+        assert_eq!(der.len(), 48);
+        assert_eq!(der[..16], PKCS8_ED25519_PREFIX);
+        let seed: [u8; 32] = der[16..].try_into().unwrap();
+        let rebuilt = crate::onion_result_from_signing_key(&SigningKey::from_bytes(&seed)).unwrap();
+        assert_eq!(rebuilt.hostname, result.hostname);
+    }
+
+    #[test]
+    fn test_failed_import_keeps_key() {
+        let result = generate_onion_address().unwrap();
+        assert!(TokenImport::new("cat > /dev/null; exit 3").import(&result).is_err());
+
+        let sealed = OnionResult { custody: KeyCustody::Sealed, ..result };
+        assert!(TokenImport::new("true").import(&sealed).is_err());
+    }
+}
@@ -0,0 +1,251 @@
+use crate::{ErrorCode, KeyCustody, OnionResult, ResultExt};
+use anyhow::{bail, Context, Result};
+use memmap2::MmapMut;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+/// First bytes of a journal file, followed by a little-endian format version
+const MAGIC: &[u8; 8] = b"ONIONJNL";
+const VERSION: u32 = 1;
+const FILE_HEADER_LEN: usize = 16;
+
+/// Length, state, three reserved bytes and an 8-byte checksum
+const RECORD_HEADER_LEN: usize = 16;
+
+/// The journal grows in steps of this many bytes
+const GROWTH: usize = 64 * 1024;
+
+/// A record whose result has not reached every sink yet
+const PENDING: u8 = 1;
+/// A record whose result every sink has handled
+const DELIVERED: u8 = 2;
+
+/// Crash-safe journal of finds in a memory-mapped file
+///
+/// Every find is appended as a checksummed record and synced to disk before
+/// any sink sees it, then marked delivered once all sinks have handled it.
+/// After a crash, even SIGKILL or a kernel panic, [`pending`](Self::pending)
+/// returns the finds that may not have reached the sinks; a record that was
+/// only partly written fails its checksum and is dropped. The file holds
+/// private keys (sealed ones with `--seal-to`) and is only readable by the
+/// owner.
+pub struct ResultsJournal {
+    path: PathBuf,
+    file: File,
+    map: MmapMut,
+    /// Offset of the next record
+    end: usize,
+    /// Offsets of the records not yet marked delivered
+    pending: Vec<usize>,
+}
+
+/// Position of a record, for [`ResultsJournal::mark_delivered`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JournalEntry(usize);
+
+#[derive(Serialize, Deserialize)]
+struct Record {
+    hostname: String,
+    public_key: String,
+    private_key: String,
+    custody: String,
+}
+
+impl ResultsJournal {
+    /// Open or create the journal at `path`
+    ///
+    /// Records behind a torn or corrupt one are discarded. If every record was
+    /// delivered, the journal starts over empty.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        Self::open_file(&path)
+            .with_context(|| format!("Failed to open results journal {}", path.display()))
+            .with_code(ErrorCode::OutputNotWritable)
+    }
+
+    fn open_file(path: &Path) -> Result<Self> {
+        let mut options = OpenOptions::new();
+        options.read(true).write(true).create(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let file = options.open(path)?;
+        let new = file.metadata()?.len() == 0;
+        if new {
+            file.set_len(GROWTH as u64)?;
+        }
+        // SAFETY: the journal is only written through this mapping, by this process
+        let mut map = unsafe { MmapMut::map_mut(&file)? };
+        if new {
+            map[..8].copy_from_slice(MAGIC);
+            map[8..12].copy_from_slice(&VERSION.to_le_bytes());
+            map.flush_range(0, FILE_HEADER_LEN)?;
+        } else if map.len() < FILE_HEADER_LEN || &map[..8] != MAGIC {
+            bail!("Not a results journal");
+        } else if map[8..12] != VERSION.to_le_bytes() {
+            bail!("Unsupported results journal version");
+        }
+
+        let mut journal = Self { path: path.to_path_buf(), file, map, end: FILE_HEADER_LEN, pending: Vec::new() };
+        while let Some((state, payload)) = journal.record_at(journal.end) {
+            let next = journal.end + RECORD_HEADER_LEN + payload.len();
+            if state == PENDING {
+                journal.pending.push(journal.end);
+            }
+            journal.end = next;
+        }
+        if journal.pending.is_empty() {
+            journal.end = FILE_HEADER_LEN;
+        }
+        // Clear what is left of a torn record, or of the delivered ones
+        let end = journal.end;
+        journal.map[end..].fill(0);
+        journal.map.flush()?;
+        Ok(journal)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Finds recorded but not marked delivered, e.g. by a run that crashed
+    pub fn pending(&self) -> Result<Vec<(JournalEntry, OnionResult)>> {
+        self.pending
+            .iter()
+            .map(|&offset| {
+                let (_, payload) = self.record_at(offset).expect("pending records were validated on open");
+                let record: Record = serde_json::from_slice(payload)?;
+                let custody = match record.custody.as_str() {
+                    "sealed" => KeyCustody::Sealed,
+                    "token" => KeyCustody::Token,
+                    "discarded" => KeyCustody::Discarded,
+                    _ => KeyCustody::Local,
+                };
+                let result = OnionResult {
+                    hostname: record.hostname,
+                    public_key: record.public_key,
+                    private_key: record.private_key,
+                    custody,
+                    seed: None,
+                };
+                Ok((JournalEntry(offset), result))
+            })
+            .collect()
+    }
+
+    /// Append `result` and sync it to disk
+    pub fn append(&mut self, result: &OnionResult) -> Result<JournalEntry> {
+        let payload = serde_json::to_vec(&Record {
+            hostname: result.hostname.clone(),
+            public_key: result.public_key.clone(),
+            private_key: result.private_key.clone(),
+            custody: result.custody.as_str().to_string(),
+        })?;
+        let offset = self.end;
+        let len = RECORD_HEADER_LEN + payload.len();
+        // Keep a zeroed length after the record, marking the end
+        if offset + len + 4 > self.map.len() {
+            self.grow(offset + len + 4)?;
+        }
+
+        let checksum = Sha3_256::digest(&payload);
+        self.map[offset + 4] = PENDING;
+        self.map[offset + 8..offset + RECORD_HEADER_LEN].copy_from_slice(&checksum[..8]);
+        self.map[offset + RECORD_HEADER_LEN..offset + len].copy_from_slice(&payload);
+        self.map.flush_range(offset + 4, len - 4)?;
+        // The length goes last: until it is on disk, the record does not exist
+        self.map[offset..offset + 4].copy_from_slice(&(payload.len() as u32).to_le_bytes());
+        self.map.flush_range(offset, 4)?;
+
+        self.end = offset + len;
+        self.pending.push(offset);
+        Ok(JournalEntry(offset))
+    }
+
+    /// Record that every sink has handled the find of `entry`
+    pub fn mark_delivered(&mut self, entry: JournalEntry) -> Result<()> {
+        self.map[entry.0 + 4] = DELIVERED;
+        self.map.flush_range(entry.0 + 4, 1)?;
+        self.pending.retain(|&offset| offset != entry.0);
+        Ok(())
+    }
+
+    /// State and payload of a valid record at `offset`, or `None` at the end or a torn record
+    fn record_at(&self, offset: usize) -> Option<(u8, &[u8])> {
+        let header = self.map.get(offset..offset + RECORD_HEADER_LEN)?;
+        let len = u32::from_le_bytes(header[..4].try_into().ok()?) as usize;
+        let state = header[4];
+        if len == 0 || !matches!(state, PENDING | DELIVERED) {
+            return None;
+        }
+        let payload = self.map.get(offset + RECORD_HEADER_LEN..offset + RECORD_HEADER_LEN + len)?;
+        (Sha3_256::digest(payload)[..8] == header[8..16]).then_some((state, payload))
+    }
+
+    fn grow(&mut self, min_len: usize) -> Result<()> {
+        let len = min_len.div_ceil(GROWTH) * GROWTH;
+        self.map.flush()?;
+        self.file.set_len(len as u64)?;
+        // SAFETY: as in `open_file`; the old mapping is dropped on assignment
+        self.map = unsafe { MmapMut::map_mut(&self.file)? };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+    use crate::generate_onion_address;
+
+    #[test]
+    fn test_pending_finds_survive_reopen() {
+        let path = TempPath::new("journal-reopen.bin");
+        let (first, second) = (generate_onion_address().unwrap(), generate_onion_address().unwrap());
+
+        let mut journal = ResultsJournal::open(&path).unwrap();
+        let entry = journal.append(&first).unwrap();
+        journal.append(&second).unwrap();
+        journal.mark_delivered(entry).unwrap();
+        drop(journal);
+
+        let journal = ResultsJournal::open(&path).unwrap();
+        let pending = journal.pending().unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].1.hostname, second.hostname);
+        assert_eq!(pending[0].1.private_key, second.private_key);
+    }
+
+    #[test]
+    fn test_torn_record_is_dropped() {
+        let path = TempPath::new("journal-torn.bin");
+        let mut journal = ResultsJournal::open(&path).unwrap();
+        journal.append(&generate_onion_address().unwrap()).unwrap();
+        let torn = journal.append(&generate_onion_address().unwrap()).unwrap();
+        // A crash in the middle of the payload
+        journal.map[torn.0 + RECORD_HEADER_LEN + 10] ^= 0xff;
+        drop(journal);
+
+        let mut journal = ResultsJournal::open(&path).unwrap();
+        assert_eq!(journal.pending().unwrap().len(), 1);
+        assert_eq!(journal.append(&generate_onion_address().unwrap()).unwrap(), torn);
+    }
+
+    #[test]
+    fn test_journal_grows_and_restarts_when_delivered() {
+        let path = TempPath::new("journal-grow.bin");
+        let mut journal = ResultsJournal::open(&path).unwrap();
+        let result = generate_onion_address().unwrap();
+        let entries: Vec<_> = (0..400).map(|_| journal.append(&result).unwrap()).collect();
+        assert!(journal.map.len() > GROWTH);
+        for entry in entries {
+            journal.mark_delivered(entry).unwrap();
+        }
+        drop(journal);
+
+        let journal = ResultsJournal::open(&path).unwrap();
+        assert!(journal.pending().unwrap().is_empty());
+        assert_eq!(journal.end, FILE_HEADER_LEN);
+    }
+}
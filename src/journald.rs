@@ -0,0 +1,147 @@
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Socket of systemd-journald's native protocol
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// `SYSLOG_IDENTIFIER` of every entry, so `journalctl -t onion-generator` finds them
+const IDENTIFIER: &str = "onion-generator";
+
+static JOURNAL: OnceLock<Journal> = OnceLock::new();
+
+/// Syslog priority of a journal entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    Error = 3,
+    Warning = 4,
+    Info = 6,
+}
+
+/// Connection to systemd-journald
+///
+/// Entries are sent as datagrams in the native protocol, one `KEY=value` line
+/// per field, so structured fields stay queryable with `journalctl -o json`.
+pub struct Journal {
+    socket: UnixDatagram,
+}
+
+impl Journal {
+    pub fn connect() -> Result<Self> {
+        Self::connect_to(Path::new(JOURNAL_SOCKET))
+    }
+
+    /// Connect to a journald socket at a non-default path, e.g. in a container
+    pub fn connect_to(path: &Path) -> Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket
+            .connect(path)
+            .with_context(|| format!("Failed to connect to journald at {}", path.display()))?;
+        Ok(Self { socket })
+    }
+
+    /// Send one entry; `fields` are added after `MESSAGE`, `PRIORITY` and the identifier
+    pub fn send(&self, priority: Priority, message: &str, fields: &[(String, String)]) -> Result<()> {
+        let mut entry = Vec::new();
+        encode_field(&mut entry, "MESSAGE", message);
+        encode_field(&mut entry, "PRIORITY", &(priority as u8).to_string());
+        encode_field(&mut entry, "SYSLOG_IDENTIFIER", IDENTIFIER);
+        for (key, value) in fields {
+            encode_field(&mut entry, key, value);
+        }
+        self.socket.send(&entry)?;
+        Ok(())
+    }
+}
+
+/// Send all log output to journald as well, for the rest of the process
+pub fn enable_journald() -> Result<()> {
+    let journal = Journal::connect()?;
+    let _ = JOURNAL.set(journal);
+    Ok(())
+}
+
+/// Send an entry if journald output is enabled; failures are ignored so logging never stops a search
+pub(crate) fn journal_send(priority: Priority, message: &str, fields: &[(String, String)]) {
+    if let Some(journal) = JOURNAL.get() {
+        let _ = journal.send(priority, message, fields);
+    }
+}
+
+/// Journal fields of an event: `ONION_EVENT` plus one `ONION_<KEY>` per field
+pub(crate) fn event_fields(event: &str, fields: &Value) -> Vec<(String, String)> {
+    let mut out = vec![("ONION_EVENT".to_string(), event.to_string())];
+    if let Value::Object(fields) = fields {
+        for (key, value) in fields {
+            let value = match value {
+                Value::String(text) => text.clone(),
+                Value::Null => continue,
+                other => other.to_string(),
+            };
+            out.push((format!("ONION_{}", key.to_ascii_uppercase()), value));
+        }
+    }
+    out
+}
+
+/// Append `key=value`, or the length-prefixed binary form for values with newlines
+fn encode_field(entry: &mut Vec<u8>, key: &str, value: &str) {
+    entry.extend_from_slice(key.as_bytes());
+    if value.contains('\n') {
+        entry.push(b'\n');
+        entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        entry.push(b'=');
+    }
+    entry.extend_from_slice(value.as_bytes());
+    entry.push(b'\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+    use serde_json::json;
+
+    #[test]
+    fn test_encode_field() {
+        let mut entry = Vec::new();
+        encode_field(&mut entry, "MESSAGE", "found");
+        encode_field(&mut entry, "ONION_TABLE", "a\nb");
+
+        let mut expected = b"MESSAGE=found\nONION_TABLE\n".to_vec();
+        expected.extend_from_slice(&3u64.to_le_bytes());
+        expected.extend_from_slice(b"a\nb\n");
+        assert_eq!(entry, expected);
+    }
+
+    #[test]
+    fn test_send_entry() {
+        let path = TempPath::new("journal.sock");
+        let receiver = UnixDatagram::bind(&path).unwrap();
+
+        let journal = Journal::connect_to(&path).unwrap();
+        journal
+            .send(Priority::Warning, "All workers stopped", &[("ONION_EVENT".to_string(), "stopped".to_string())])
+            .unwrap();
+        let mut buffer = [0u8; 256];
+        let len = receiver.recv(&mut buffer).unwrap();
+
+        assert_eq!(
+            String::from_utf8_lossy(&buffer[..len]),
+            "MESSAGE=All workers stopped\nPRIORITY=4\nSYSLOG_IDENTIFIER=onion-generator\nONION_EVENT=stopped\n"
+        );
+    }
+
+    #[test]
+    fn test_event_fields() {
+        let fields = event_fields("stats", &json!({ "generated": 10, "worker": "onion-worker-0", "seal": null }));
+
+        assert_eq!(fields[0], ("ONION_EVENT".to_string(), "stats".to_string()));
+        assert!(fields.contains(&("ONION_GENERATED".to_string(), "10".to_string())));
+        assert!(fields.contains(&("ONION_WORKER".to_string(), "onion-worker-0".to_string())));
+        assert_eq!(fields.len(), 3);
+    }
+}
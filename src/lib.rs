@@ -1,16 +1,182 @@
+mod address;
+mod addressbook;
+mod attest;
+mod batch;
+mod blinding;
+/// Benchmark runs and their history file
+///
+/// Public only for the command-line tool and not part of the stable API.
+#[doc(hidden)]
+pub mod bench;
+/// Passphrase-derived addresses
+///
+/// Public only for the command-line tool and not part of the stable API.
+#[doc(hidden)]
+pub mod brain;
+mod client_auth;
+mod config;
+mod control;
+mod cooperative;
+/// Key and encoding primitives
+///
+/// Public only for the benchmarks and not part of the stable API; the
+/// supported items are re-exported at the crate root.
+#[doc(hidden)]
 pub mod crypto;
-pub mod onion;
-pub mod worker;
+/// Per-CPU kernels
+///
+/// Public only for the benchmarks, like [`crypto`]; [`Kernel`] is
+/// re-exported at the crate root.
+#[doc(hidden)]
+pub mod dispatch;
+mod doctor;
+mod duration;
+mod engine;
+mod error;
+/// Scoring and collecting the nicest addresses
+///
+/// Public only for the command-line tool and not part of the stable API.
+#[doc(hidden)]
+pub mod harvest;
+mod histogram;
+mod hsm;
+mod journal;
+/// Logging to the systemd journal
+///
+/// Public only for the command-line tool and not part of the stable API.
+#[cfg(target_os = "linux")]
+#[doc(hidden)]
+pub mod journald;
+mod logging;
+mod mkp224o;
+mod notify;
+mod onion;
+#[cfg(feature = "otel")]
+mod otel;
+mod pattern;
+mod progress;
+mod rotate;
+mod route;
+mod sample;
+mod sandbox;
+mod schedule;
+mod seal;
+mod selftest;
+mod serve;
+mod shared;
+mod sink;
+mod snapshot;
+mod source;
+mod sparkline;
+mod standby;
+mod suggest;
+#[cfg(test)]
+mod testutil;
+mod trie;
+mod usage;
+mod vectors;
+mod wordlist;
+mod wordset;
+mod worker;
 
-pub use crypto::*;
-pub use onion::*;
-pub use worker::*;
+pub use address::{parse_onion_address, ParseError, ParsedOnion, ONION_VERSION};
+pub use addressbook::{read_address_book, AddressEntry};
+pub use attest::{Attestation, AttestationSink, Attestor};
+pub use batch::{store_batch_tuning, stored_batch_tuning, tune_batch_size, BatchTuning, BatchedKeys, BATCH_SIZES};
+pub use blinding::{
+    blind_public_key, hs_index, rollover, BlindedPeriod, TimePeriod, HSDIR_REPLICAS, TIME_PERIOD_LENGTH,
+};
+pub use client_auth::{generate_client_auth, ClientAuth};
+pub use config::{
+    read_patterns_file, ConfigCheck, ConfigProblem, FileConfig, Profile, NotifyConfig, NotifyTarget, OtelConfig, RouteConfig, ScheduleConfig,
+    ServeConfig,
+};
+#[cfg(any(unix, windows))]
+pub use control::spawn_control_socket;
+pub use cooperative::{lower_thread_priority, Cooperative, DEFAULT_DUTY_CYCLE};
+pub use control::{
+    execute_pattern_command, execute_pool_command, spawn_stdin_patterns, PatternCommand, PatternInfo, PatternReport,
+    PoolCommand,
+};
+pub use crypto::{
+    credential, crypto_backend, key_fingerprint, parse_master_seed, seed_fingerprint, seeded_keypair, subcredential, CryptoBackend,
+    SeededKeys, SEED_DERIVATION,
+};
+pub use dispatch::Kernel;
+pub use doctor::{Doctor, Finding, Severity};
+pub use duration::{format_duration, parse_duration};
+pub use engine::{bench_match_engines, MatchEngine};
+pub use error::{CodedError, ErrorCode, ResultExt};
+pub use histogram::FindHistogram;
+pub use hsm::TokenImport;
+pub use journal::{JournalEntry, ResultsJournal};
+#[doc(hidden)]
+pub use logging::log_text;
+pub use logging::{
+    enable_log_file, json_logs, log_error, on_stdout_closed, print_plain, set_log_format, set_stdout_closed, stdout_closed,
+    LogFormat, LogWriter, StdoutClosed,
+};
+pub use mkp224o::Mkp224oFilters;
+pub use notify::ChatSink;
+pub use onion::{
+    calibrate_parallel_rate, calibrate_rate, decode_onion_address, expand_variants, generate_n, generate_onion_address,
+    generate_with_live_matcher_until, generate_with_matcher_seeded_until, generate_with_matcher_until,
+    generate_with_predicate, generate_with_predicate_counted_until, generate_with_predicate_until,
+    generate_with_prefix, generate_with_prefix_counted, generate_with_prefix_max_attempts,
+    generate_with_prefix_timeout, generate_with_prefix_until, hostname_for_public_key, onion_result_from_signing_key,
+    par_candidates, public_key_matches_prefix, without_private_key,
+    CharTemplate, Glob, LiveMatcher, MatchSpec, Matcher, PatternExpr, ADDRESS_LEN, BASE32_ALPHABET, MAX_VARIANTS,
+};
+#[cfg(feature = "otel")]
+pub use otel::OtelSink;
+pub use pattern::PatternKind;
+pub use rotate::{RotatingFile, Rotation};
+pub use route::RoutingSink;
+pub use sample::CandidateSample;
+pub use schedule::PatternSchedule;
+pub use snapshot::{StatsSnapshot, StatsSnapshots, WorkerSnapshot};
+#[cfg(target_os = "linux")]
+pub use sandbox::{apply_seccomp_filter, seccomp_filter};
+pub use sandbox::{confine_writes, confined_join};
+pub use seal::{generate_seal_keypair, parse_x25519_key, seal_result, unseal, SealKeypair};
+pub use selftest::{self_check, self_check_result};
+pub use serve::{JobRequest, Server};
+pub use shared::{HostState, SharedDir, SharedDirSink, STALE_AFTER};
+pub use sink::{
+    format_eta, median_seconds, write_difficulty_table, write_summary, DirectorySink, JsonProgressSink,
+    ProjectionSink, ResultSink, ResultsFileSink, SparklineSink, StdoutSink,
+};
+pub use source::{CandidateSource, RandomKeys, SourceFactory};
+pub use sparkline::Sparkline;
+pub use standby::StandbyPool;
+pub use suggest::{check_address_chars, max_prefix_length, nearest_valid_patterns, suggest_alternatives, suggest_prefixes};
+pub use usage::{append_usage, load_usage, write_usage_history, UsageRecord};
+pub use vectors::{TestVector, TestVectors, DEFAULT_VECTOR_COUNT, VECTOR_MASTER_SEED};
+pub use wordlist::Wordlist;
+pub use worker::{
+    print_result, print_stats, run_single_threaded, ErrorBackoff, PauseHandle, PoolSummary, RestartPolicy, StallPolicy,
+    WorkerHealth, WorkerPool, WorkerPoolBuilder, DEFAULT_STALL_TIMEOUT,
+};
 
-use std::sync::atomic::{AtomicU64, Ordering};
+/// What most programs need, for `use onion_generator::prelude::*;`
+///
+/// Only grows within a major version; everything else stays at the crate root.
+pub mod prelude {
+    pub use crate::{
+        generate_onion_address, generate_with_predicate, generate_with_prefix, par_candidates, parse_onion_address,
+        CandidateSource, DirectorySink, ErrorCode, GenerationStats, GeneratorConfig, KeyCustody, LiveMatcher, MatchSpec, Matcher,
+        OnionResult, ParseError, PoolSummary, ResultExt, ResultSink, RestartPolicy, StallPolicy, StdoutSink,
+        WorkerPool, WorkerPoolBuilder,
+    };
+}
+
+use portable_atomic::AtomicU128;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use zeroize::Zeroizing;
 
-/// Global counters for tracking generation statistics
-pub static GENERATED_COUNT: AtomicU64 = AtomicU64::new(0);
-pub static FOUND_COUNT: AtomicU64 = AtomicU64::new(0);
+/// Prefixes from which on they are matched as one [`Wordlist`] rather than one spec each
+const WORDLIST_MIN_PREFIXES: usize = 10_000;
 
 /// Result structure for generated onion addresses
 #[derive(Debug, Clone)]
@@ -18,12 +184,79 @@ pub struct OnionResult {
     pub hostname: String,
     pub public_key: String,
     pub private_key: String,
+    /// What `private_key` holds
+    pub custody: KeyCustody,
+    /// The 32-byte ed25519 seed `private_key` was expanded from, while still held locally
+    pub seed: Option<Zeroizing<[u8; 32]>>,
+}
+
+impl OnionResult {
+    /// Short identifier of the address's key, see [`key_fingerprint`]
+    pub fn fingerprint(&self) -> String {
+        decode_onion_address(&self.hostname).map(|key| key_fingerprint(&key)).unwrap_or_default()
+    }
+}
+
+/// Where the private key of a result is kept
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyCustody {
+    /// `private_key` is the Tor secret key file
+    #[default]
+    Local,
+    /// `private_key` is a sealed box for a `--seal-to` recipient
+    Sealed,
+    /// The key was imported into a hardware token; `private_key` is empty
+    Token,
+    /// The key was dropped as soon as it was found; `private_key` is empty
+    Discarded,
+}
+
+impl KeyCustody {
+    /// Name used in JSON records
+    pub fn as_str(self) -> &'static str {
+        match self {
+            KeyCustody::Local => "local",
+            KeyCustody::Sealed => "sealed",
+            KeyCustody::Token => "token",
+            KeyCustody::Discarded => "discarded",
+        }
+    }
+}
+
+/// Where in the address the entries of [`GeneratorConfig::prefixes`] have to appear
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatchMode {
+    /// At the start, as prefixes
+    #[default]
+    Start,
+    /// Anywhere, like [`GeneratorConfig::contains`]
+    Anywhere,
 }
 
 /// Configuration for the onion generator
 #[derive(Debug, Clone)]
 pub struct GeneratorConfig {
     pub prefixes: Vec<String>,
+    /// Where `prefixes` match; globs always cover the whole address and prefixes with `[...]` sets always match at the start
+    pub match_mode: MatchMode,
+    pub suffixes: Vec<String>,
+    pub contains: Vec<String>,
+    /// Prefix and suffix pairs that must both match
+    pub prefix_suffixes: Vec<(String, String)>,
+    /// Strings at a position, written `OFFSET:TEXT`; see [`MatchSpec::At`]
+    pub positioned: Vec<String>,
+    /// Character templates, see [`CharTemplate`]
+    pub classes: Vec<String>,
+    /// Lengths of identical leading runs, see [`MatchSpec::Run`]
+    pub runs: Vec<usize>,
+    /// Lengths of doubled starts, see [`MatchSpec::Doubled`]
+    pub doubled: Vec<usize>,
+    /// Lengths of palindromic starts, see [`MatchSpec::Palindrome`]
+    pub palindromes: Vec<usize>,
+    pub regexes: Vec<String>,
+    /// Patterns combined with and, or and not, see [`PatternExpr`]
+    pub expressions: Vec<String>,
+    pub excludes: Vec<String>,
     pub num_workers: usize,
     pub update_interval: u64,
 }
@@ -36,11 +269,169 @@ impl GeneratorConfig {
         
         Self {
             prefixes,
+            match_mode: MatchMode::Start,
+            suffixes: Vec::new(),
+            contains: Vec::new(),
+            prefix_suffixes: Vec::new(),
+            positioned: Vec::new(),
+            classes: Vec::new(),
+            runs: Vec::new(),
+            doubled: Vec::new(),
+            palindromes: Vec::new(),
+            regexes: Vec::new(),
+            expressions: Vec::new(),
+            excludes: Vec::new(),
             num_workers,
             update_interval: 30,
         }
     }
 
+    /// Match the prefixes anywhere in the address instead of at the start
+    pub fn with_match_mode(mut self, mode: MatchMode) -> Self {
+        self.match_mode = mode;
+        self
+    }
+
+    pub fn with_suffixes(mut self, suffixes: Vec<String>) -> Self {
+        self.suffixes = suffixes;
+        self
+    }
+
+    /// Match addresses containing any of these strings anywhere
+    pub fn with_contains(mut self, contains: Vec<String>) -> Self {
+        self.contains = contains;
+        self
+    }
+
+    /// Match addresses that start with the first and end with the second string of a pair
+    pub fn with_prefix_suffixes(mut self, pairs: Vec<(String, String)>) -> Self {
+        self.prefix_suffixes = pairs;
+        self
+    }
+
+    /// Match addresses with a string right after a number of characters, e.g. `10:tor`
+    pub fn with_positioned(mut self, positioned: Vec<String>) -> Self {
+        self.positioned = positioned;
+        self
+    }
+
+    /// Match addresses whose leading characters fit any of these templates, e.g. `L{8}`
+    pub fn with_classes(mut self, templates: Vec<String>) -> Self {
+        self.classes = templates;
+        self
+    }
+
+    /// Match addresses starting with this many identical characters, e.g. `aaaa`
+    pub fn with_runs(mut self, runs: Vec<usize>) -> Self {
+        self.runs = runs;
+        self
+    }
+
+    /// Match addresses whose first n characters appear again right after, e.g. `abcabc`
+    pub fn with_doubled(mut self, doubled: Vec<usize>) -> Self {
+        self.doubled = doubled;
+        self
+    }
+
+    /// Match addresses whose first n characters read the same backwards, e.g. `abcba`
+    pub fn with_palindromes(mut self, palindromes: Vec<usize>) -> Self {
+        self.palindromes = palindromes;
+        self
+    }
+
+    /// Regular expressions matched against the 56-character address
+    pub fn with_regexes(mut self, regexes: Vec<String>) -> Self {
+        self.regexes = regexes;
+        self
+    }
+
+    /// Match addresses satisfying any of these expressions, e.g. `prefix:foo & suffix:bad`
+    pub fn with_expressions(mut self, expressions: Vec<String>) -> Self {
+        self.expressions = expressions;
+        self
+    }
+
+    /// Reject addresses containing any of these strings
+    pub fn with_excludes(mut self, excludes: Vec<String>) -> Self {
+        self.excludes = excludes;
+        self
+    }
+
+    /// Compile all configured patterns into a single matcher
+    ///
+    /// Errors carry [`ErrorCode::InvalidPattern`].
+    pub fn build_matcher(&self) -> anyhow::Result<Matcher> {
+        self.compile_patterns().with_code(ErrorCode::InvalidPattern)
+    }
+
+    fn compile_patterns(&self) -> anyhow::Result<Matcher> {
+        let (globs, prefixes): (Vec<&String>, Vec<&String>) = self.prefixes.iter().partition(|prefix| Glob::is_glob(prefix));
+        let (templates, prefixes): (Vec<&String>, Vec<&String>) = prefixes.into_iter().partition(|prefix| CharTemplate::has_brackets(prefix));
+        let words = prefixes.iter().copied().chain(&self.suffixes).chain(&self.contains).chain(&self.excludes);
+        let pairs = self.prefix_suffixes.iter().flat_map(|(prefix, suffix)| [prefix, suffix]);
+        for pattern in words.chain(pairs) {
+            check_address_chars(pattern)?;
+        }
+
+        let start = match self.match_mode {
+            MatchMode::Start => MatchSpec::Prefix,
+            MatchMode::Anywhere => MatchSpec::Contains,
+        };
+        let mut includes: Vec<MatchSpec> = match self.match_mode {
+            MatchMode::Start if prefixes.len() >= WORDLIST_MIN_PREFIXES => {
+                vec![MatchSpec::Wordlist(Wordlist::new(prefixes.into_iter().map(String::as_str)))]
+            }
+            _ => prefixes.into_iter().cloned().map(start).collect(),
+        };
+        for glob in globs {
+            includes.push(MatchSpec::Glob(Glob::parse(glob)?));
+        }
+        for template in templates {
+            includes.push(MatchSpec::Classes(CharTemplate::parse(template)?));
+        }
+        includes.extend(self.suffixes.iter().cloned().map(MatchSpec::Suffix));
+        includes.extend(self.contains.iter().cloned().map(MatchSpec::Contains));
+        includes.extend(
+            self.prefix_suffixes
+                .iter()
+                .map(|(prefix, suffix)| MatchSpec::PrefixSuffix(prefix.clone(), suffix.clone())),
+        );
+        for pattern in &self.positioned {
+            includes.push(MatchSpec::parse_at(pattern)?);
+        }
+        for template in &self.classes {
+            includes.push(MatchSpec::Classes(CharTemplate::parse(template)?));
+        }
+        let repeats = self.runs.iter().map(|n| MatchSpec::Run(*n));
+        let repeats = repeats
+            .chain(self.doubled.iter().map(|n| MatchSpec::Doubled(*n)))
+            .chain(self.palindromes.iter().map(|n| MatchSpec::Palindrome(*n)));
+        for spec in repeats {
+            spec.check_length()?;
+            includes.push(spec);
+        }
+
+        #[cfg(feature = "regex")]
+        for pattern in &self.regexes {
+            onion::check_regex_start(pattern)?;
+            includes.push(MatchSpec::Regex(regex::Regex::new(pattern)?));
+        }
+        #[cfg(not(feature = "regex"))]
+        if let Some(pattern) = self.regexes.first() {
+            return Err(anyhow::anyhow!("Regex pattern {:?} requires the `regex` feature", pattern));
+        }
+        for expression in &self.expressions {
+            includes.push(PatternExpr::parse(expression)?);
+        }
+
+        if includes.is_empty() {
+            return Err(anyhow::anyhow!("At least one prefix, suffix, contains or regex pattern must be provided"));
+        }
+
+        let excludes = self.excludes.iter().cloned().map(MatchSpec::Contains).collect();
+        Ok(Matcher::new(includes, excludes))
+    }
+
     pub fn with_workers(mut self, num_workers: usize) -> Self {
         self.num_workers = num_workers;
         self
@@ -52,20 +443,68 @@ impl GeneratorConfig {
     }
 }
 
-/// Get current generation statistics
-pub fn get_stats() -> (u64, u64) {
-    (
-        GENERATED_COUNT.load(Ordering::Relaxed),
-        FOUND_COUNT.load(Ordering::Relaxed),
-    )
+/// Counters for tracking generation statistics
+///
+/// Each worker pool (or single-threaded run) owns its own instance, so several
+/// generators in one process never mix their numbers. Counts are 128-bit so
+/// totals summed over long runs, workers and hosts cannot overflow.
+#[derive(Debug, Default)]
+pub struct GenerationStats {
+    generated: AtomicU128,
+    found: AtomicU128,
+    find_gaps: FindHistogram,
+    parent: Option<Arc<GenerationStats>>,
 }
 
-/// Increment the generated counter
-pub fn increment_generated() {
-    GENERATED_COUNT.fetch_add(1, Ordering::Relaxed);
-}
+impl GenerationStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counters that also roll up into `parent`, e.g. one per worker of a pool
+    pub fn child_of(parent: &Arc<GenerationStats>) -> Self {
+        Self {
+            parent: Some(Arc::clone(parent)),
+            ..Self::default()
+        }
+    }
+
+    /// Get current (generated, found) counts
+    pub fn get(&self) -> (u128, u128) {
+        (
+            self.generated.load(Ordering::Relaxed),
+            self.found.load(Ordering::Relaxed),
+        )
+    }
 
-/// Increment the found counter
-pub fn increment_found() {
-    FOUND_COUNT.fetch_add(1, Ordering::Relaxed);
+    /// Distribution of attempts between finds
+    pub fn find_histogram(&self) -> &FindHistogram {
+        &self.find_gaps
+    }
+
+    /// Increment the generated counter
+    pub fn increment_generated(&self) {
+        self.generated.fetch_add(1, Ordering::Relaxed);
+        if let Some(parent) = &self.parent {
+            parent.increment_generated();
+        }
+    }
+
+    /// Increment the found counter
+    pub fn increment_found(&self) {
+        self.found.fetch_add(1, Ordering::Relaxed);
+        let gap = self.find_gaps.record(self.generated.load(Ordering::Relaxed));
+        if let Some(parent) = &self.parent {
+            parent.found_after(gap);
+        }
+    }
+
+    /// Count a find a child measured `gap` attempts after its previous one
+    fn found_after(&self, gap: u128) {
+        self.found.fetch_add(1, Ordering::Relaxed);
+        self.find_gaps.record_gap(gap, self.generated.load(Ordering::Relaxed));
+        if let Some(parent) = &self.parent {
+            parent.found_after(gap);
+        }
+    }
 }
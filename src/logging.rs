@@ -0,0 +1,370 @@
+use crate::{ErrorCode, OnionResult, Rotation, RotatingFile};
+use anyhow::bail;
+use serde_json::{json, Map, Value};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+/// How lines on stdout are formatted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// The usual `[@]`-prefixed lines
+    #[default]
+    Text,
+    /// One JSON record per line, for log pipelines
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => bail!("Unknown log format {:?}, expected text or json", s),
+        }
+    }
+}
+
+/// What happens once stdout turns out to be a closed pipe, e.g. after `| head`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StdoutClosed {
+    /// Stop as if interrupted, see [`on_stdout_closed`]
+    #[default]
+    Exit,
+    /// Keep going and write results only to the other outputs
+    Continue,
+}
+
+impl FromStr for StdoutClosed {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "exit" => Ok(Self::Exit),
+            "continue" => Ok(Self::Continue),
+            _ => bail!("Unknown action {:?}, expected exit or continue", s),
+        }
+    }
+}
+
+static JSON_LOGS: AtomicBool = AtomicBool::new(false);
+
+static STDOUT_CLOSED: AtomicBool = AtomicBool::new(false);
+
+static CONTINUE_WITHOUT_STDOUT: AtomicBool = AtomicBool::new(false);
+
+static STDOUT_CLOSED_HANDLER: OnceLock<Box<dyn Fn() + Send + Sync>> = OnceLock::new();
+
+static LOG_FILE: OnceLock<Mutex<RotatingFile>> = OnceLock::new();
+
+/// Select the stdout format for the rest of the process
+pub fn set_log_format(format: LogFormat) {
+    JSON_LOGS.store(format == LogFormat::Json, Ordering::Relaxed);
+}
+
+/// Whether stdout carries JSON records instead of text lines
+pub fn json_logs() -> bool {
+    JSON_LOGS.load(Ordering::Relaxed)
+}
+
+/// Select what happens once stdout is closed, for the rest of the process
+pub fn set_stdout_closed(action: StdoutClosed) {
+    CONTINUE_WITHOUT_STDOUT.store(action == StdoutClosed::Continue, Ordering::Relaxed);
+}
+
+/// Run `stop` instead of exiting when stdout is closed under [`StdoutClosed::Exit`]
+///
+/// A search passes its stop flag here, so it winds down like on Ctrl-C and
+/// results already found reach the other outputs. Without a handler the
+/// process exits at once with status 0. Only the first call counts.
+pub fn on_stdout_closed(stop: impl Fn() + Send + Sync + 'static) {
+    let _ = STDOUT_CLOSED_HANDLER.set(Box::new(stop));
+}
+
+/// Whether a write to stdout has failed with a broken pipe
+pub fn stdout_closed() -> bool {
+    STDOUT_CLOSED.load(Ordering::Relaxed)
+}
+
+/// Print `text` and a newline to stdout as is, in either log format
+///
+/// For output that other programs read, such as hostnames; unlike
+/// `println!` it does not panic once the reader has gone away.
+pub fn print_plain(text: &str) {
+    let _ = write_stdout(|out| writeln!(out, "{}", text));
+}
+
+/// Run `write` on the locked stdout unless it is closed
+///
+/// A broken pipe marks stdout as closed, acts on the [`StdoutClosed`]
+/// setting and is not an error; every later write is skipped.
+pub(crate) fn write_stdout(write: impl FnOnce(&mut dyn Write) -> io::Result<()>) -> io::Result<()> {
+    if stdout_closed() {
+        return Ok(());
+    }
+    #[cfg(not(test))]
+    let result = write(&mut io::stdout().lock());
+    // The test harness only captures what goes through `print!`
+    #[cfg(test)]
+    let result = {
+        let mut buffer = Vec::new();
+        write(&mut buffer).map(|()| print!("{}", String::from_utf8_lossy(&buffer)))
+    };
+    close_on_broken_pipe(result)
+}
+
+/// Turn a broken pipe into success, marking stdout as closed and acting on it the first time
+fn close_on_broken_pipe(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+            if !STDOUT_CLOSED.swap(true, Ordering::Relaxed) {
+                if CONTINUE_WITHOUT_STDOUT.load(Ordering::Relaxed) {
+                    eprintln!("[!] Stdout was closed; results only go to the other outputs from now on");
+                } else {
+                    match STDOUT_CLOSED_HANDLER.get() {
+                        Some(stop) => stop(),
+                        None => std::process::exit(0),
+                    }
+                }
+            }
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Also append every log record to `path` as a JSON line, for the rest of the process
+pub fn enable_log_file(path: impl Into<PathBuf>, rotation: Rotation) -> anyhow::Result<()> {
+    let file = RotatingFile::open(path, rotation)?;
+    let _ = LOG_FILE.set(Mutex::new(file));
+    Ok(())
+}
+
+/// Print a line to stdout in the selected log format
+///
+/// Use it like `println!`; the `[@]`-style prefix becomes the record's level.
+#[macro_export]
+macro_rules! logln {
+    ($($arg:tt)*) => {
+        $crate::log_text(&format!($($arg)*))
+    };
+}
+
+/// Print `text` to stdout, as one record per non-empty line in JSON mode
+///
+/// Each non-empty line also goes to journald and the log file when enabled.
+pub fn log_text(text: &str) {
+    let json = json_logs();
+    if !json {
+        print_plain(text);
+    }
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        let record = text_record(line);
+        journal(record["level"].as_str().unwrap_or("info"), record["message"].as_str().unwrap_or(line), None);
+        log_file(&record);
+        if json {
+            print_plain(&record.to_string());
+        }
+    }
+}
+
+/// Structured record with `fields` merged in
+///
+/// Printed in JSON mode and sent to journald and the log file when enabled;
+/// text mode callers print their own human-readable form next to it.
+pub(crate) fn log_event(event: &str, message: &str, fields: Value) {
+    journal("info", message, Some((event, &fields)));
+    let mut record = record("info", message);
+    record.insert("event".to_string(), json!(event));
+    if let Value::Object(fields) = fields {
+        record.extend(fields);
+    }
+    let record = Value::Object(record);
+    log_file(&record);
+    if json_logs() {
+        print_plain(&record.to_string());
+    }
+}
+
+/// Worker thread lifecycle, e.g. `[+] onion-worker-0 started` in text mode
+pub(crate) fn log_worker(worker: &str, started: bool) {
+    let (event, sign, verb) = if started {
+        ("worker_started", '+', "started")
+    } else {
+        ("worker_stopped", '-', "stopped")
+    };
+    log_event(event, &format!("{} {}", worker, verb), json!({ "worker": worker }));
+    if !json_logs() {
+        print_plain(&format!("[{}] {} {}", sign, worker, verb));
+    }
+}
+
+/// Record of a found address, without the private key in any form
+pub(crate) fn log_found(result: &OnionResult) {
+    log_event(
+        "found",
+        &format!("Address generated: {} ({})", result.hostname, result.fingerprint()),
+        json!({
+            "hostname": result.hostname,
+            "fingerprint": result.fingerprint(),
+            "public_key": result.public_key,
+            "custody": result.custody.as_str(),
+        }),
+    );
+}
+
+/// Record of a statistics update
+pub(crate) fn log_stats(generated: u128, found: u128) {
+    log_event(
+        "stats",
+        &format!("Generated {} addresses, Found {} addresses", generated, found),
+        json!({ "generated": generated, "found": found }),
+    );
+}
+
+/// Report the error that ends the process, with its stable code in JSON mode and journald
+pub fn log_error(error: &anyhow::Error) {
+    let message = format!("{:#}", error);
+    let code = ErrorCode::of(error).as_str();
+    journal("error", &message, Some(("error", &json!({ "code": code }))));
+    let mut record = record("error", &message);
+    record.insert("code".to_string(), json!(code));
+    let record = Value::Object(record);
+    log_file(&record);
+    if json_logs() {
+        print_plain(&record.to_string());
+    } else {
+        eprintln!("[!] Error: {}", message);
+    }
+}
+
+/// Append a record to the log file when enabled; write errors are ignored like stdout's
+fn log_file(record: &Value) {
+    if let Some(file) = LOG_FILE.get() {
+        let mut file = file.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let _ = writeln!(file, "{}", record);
+    }
+}
+
+/// Mirror a record to journald when enabled, see [`crate::journald`]
+#[cfg(target_os = "linux")]
+fn journal(level: &str, message: &str, event: Option<(&str, &Value)>) {
+    use crate::journald::{event_fields, journal_send, Priority};
+
+    let priority = match level {
+        "error" => Priority::Error,
+        "warn" => Priority::Warning,
+        _ => Priority::Info,
+    };
+    let fields = event.map_or_else(Vec::new, |(event, fields)| event_fields(event, fields));
+    journal_send(priority, message, &fields);
+}
+
+#[cfg(not(target_os = "linux"))]
+fn journal(_level: &str, _message: &str, _event: Option<(&str, &Value)>) {}
+
+/// Turn a text line into a record, taking the level from its prefix
+fn text_record(line: &str) -> Value {
+    let line = line.trim();
+    let (level, message) = match line.get(..4) {
+        Some("[!] ") => ("warn", &line[4..]),
+        Some("[@] " | "[i] " | "[+] " | "[-] ") => ("info", &line[4..]),
+        _ => match line.strip_prefix("[√] ") {
+            Some(message) => ("info", message),
+            None => ("info", line),
+        },
+    };
+    Value::Object(record(level, message))
+}
+
+fn record(level: &str, message: &str) -> Map<String, Value> {
+    let mut record = Map::new();
+    record.insert("timestamp".to_string(), json!(chrono::Local::now().to_rfc3339()));
+    record.insert("level".to_string(), json!(level));
+    record.insert("message".to_string(), json!(message));
+    record
+}
+
+/// `Write` adapter that sends complete lines through [`log_text`]
+///
+/// Lets the `write_*` helpers that render tables and summaries follow the
+/// selected log format.
+#[derive(Default)]
+pub struct LogWriter {
+    buffer: Vec<u8>,
+}
+
+impl LogWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Write for LogWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(data);
+        if let Some(end) = self.buffer.iter().rposition(|&b| b == b'\n') {
+            let lines: Vec<u8> = self.buffer.drain(..=end).collect();
+            log_text(String::from_utf8_lossy(&lines[..end]).as_ref());
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            log_text(String::from_utf8_lossy(&std::mem::take(&mut self.buffer)).as_ref());
+        }
+        write_stdout(|out| out.flush())
+    }
+}
+
+impl Drop for LogWriter {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_record_levels() {
+        let record = text_record("[!] Shutting down...");
+        assert_eq!(record["level"], "warn");
+        assert_eq!(record["message"], "Shutting down...");
+
+        let record = text_record("[√] Server stopped");
+        assert_eq!(record["level"], "info");
+        assert_eq!(record["message"], "Server stopped");
+
+        assert_eq!(text_record("    abc  1.02 K").get("message").unwrap(), "abc  1.02 K");
+        assert_eq!("json".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        assert!("yaml".parse::<LogFormat>().is_err());
+    }
+
+    #[test]
+    fn test_broken_pipe_closes_stdout() {
+        assert_eq!("exit".parse::<StdoutClosed>().unwrap(), StdoutClosed::Exit);
+        assert!("ignore".parse::<StdoutClosed>().is_err());
+        set_stdout_closed(StdoutClosed::Continue);
+
+        let denied = close_on_broken_pipe(Err(io::ErrorKind::PermissionDenied.into()));
+        assert_eq!(denied.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert!(!stdout_closed());
+        assert!(close_on_broken_pipe(Err(io::ErrorKind::BrokenPipe.into())).is_ok());
+        assert!(stdout_closed());
+        // Later writes are skipped rather than failing again
+        let mut written = false;
+        write_stdout(|_| {
+            written = true;
+            Err(io::ErrorKind::BrokenPipe.into())
+        })
+        .unwrap();
+        assert!(!written);
+    }
+}
@@ -1,25 +1,169 @@
-use clap::{Arg, Command};
-use onion_generator::{GeneratorConfig, WorkerPool, run_single_threaded};
-use std::io;
+use clap::{Arg, ArgGroup, Command};
+use onion_generator::{bench, brain, harvest};
+use onion_generator::{stored_batch_tuning, store_batch_tuning, tune_batch_size, BatchedKeys, CandidateSource, BATCH_SIZES};
+use onion_generator::{append_usage, load_usage, write_usage_history, UsageRecord};
+use onion_generator::{
+    crypto_backend, decode_onion_address, key_fingerprint, generate_client_auth, CandidateSample, generate_n, print_result, ChatSink, DirectorySink,
+    calibrate_parallel_rate, format_eta, median_seconds, parse_master_seed, suggest_alternatives, suggest_prefixes, max_prefix_length, nearest_valid_patterns, seed_fingerprint, write_difficulty_table, ProjectionSink,
+    SharedDir, SharedDirSink, SEED_DERIVATION, write_summary, ConfigCheck, FileConfig, Profile, GenerationStats, GeneratorConfig, JobRequest, JsonProgressSink, Matcher, Server, RestartPolicy, StallPolicy, SparklineSink, StdoutSink, WorkerPool,
+    run_single_threaded, generate_seal_keypair, parse_x25519_key, unseal, TokenImport,
+    Attestation, AttestationSink, Attestor, logln, print_plain, print_stats, set_log_format, json_logs, LogFormat, LogWriter,
+    on_stdout_closed, set_stdout_closed, StdoutClosed,
+    log_error, confine_writes, spawn_stdin_patterns, ErrorCode, ResultExt, ResultsJournal, enable_log_file,
+    ResultSink, ResultsFileSink, RoutingSink, Rotation, Doctor, Severity, TestVectors, ErrorBackoff, Cooperative, DEFAULT_DUTY_CYCLE, VECTOR_MASTER_SEED,
+    parse_onion_address, parse_duration, StandbyPool, bench_match_engines, read_address_book, read_patterns_file, expand_variants, KeyCustody, Mkp224oFilters, OnionResult, format_duration, rollover, CharTemplate, Glob, MatchMode, PauseHandle, PatternSchedule, TIME_PERIOD_LENGTH,
+};
+#[cfg(any(unix, windows))]
+use onion_generator::spawn_control_socket;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use anyhow::Result;
+use zeroize::{Zeroize, Zeroizing};
 
 static RUNNING: AtomicBool = AtomicBool::new(true);
 
-fn main() -> Result<()> {
-    let matches = Command::new("onion-generator")
+/// How long the startup calibration generates keys
+const CALIBRATION_TIME: Duration = Duration::from_millis(2500);
+/// How long `--batch-size auto` times each batch size
+const BATCH_TUNING_TIME: Duration = Duration::from_millis(200);
+/// How long `--matcher-bench` times each engine
+const MATCHER_BENCH_TIME: Duration = Duration::from_millis(500);
+
+/// Listener address of `serve` when neither the command line nor the config file sets one
+const DEFAULT_LISTEN: &str = "127.0.0.1:8080";
+
+fn main() {
+    if let Err(e) = run() {
+        log_error(&e);
+        std::process::exit(ErrorCode::of(&e).exit_status());
+    }
+}
+
+fn cli() -> Command {
+    Command::new("onion-generator")
         .version("0.1.0")
         .author("ppyyr <ppyyr@live.jp>")
         .about("A fast Tor .onion V3 address generator with multi-process support")
         .arg(
             Arg::new("prefixes")
-                .help("List of prefixes for the hostname")
-                .required(true)
+                .help("List of prefixes for the hostname, or globs such as foo??bar* (? is any character, * any run)")
+                .required_unless_present_any(["generate", "prefix", "patterns-file", "mkp224o-filters", "mkp224o-args", "suffix", "contains", "prefix-suffix", "at", "classes", "run", "doubled", "palindrome", "regex", "expr", "profile"])
                 .num_args(1..)
                 .value_name("PREFIX")
         )
+        .arg(
+            Arg::new("prefix")
+                .long("prefix")
+                .help("Match addresses starting with PATTERN (repeatable)")
+                .value_name("PATTERN")
+                .action(clap::ArgAction::Append)
+        )
+        .arg(
+            Arg::new("suffix")
+                .long("suffix")
+                .help("Match addresses ending with PATTERN, before \".onion\" (repeatable)")
+                .value_name("PATTERN")
+                .action(clap::ArgAction::Append)
+        )
+        .arg(
+            Arg::new("contains")
+                .long("contains")
+                .help("Match addresses containing PATTERN anywhere (repeatable)")
+                .value_name("PATTERN")
+                .action(clap::ArgAction::Append)
+        )
+        .arg(
+            Arg::new("prefix-suffix")
+                .long("prefix-suffix")
+                .help("Match addresses starting with PREFIX and ending with SUFFIX (repeatable)")
+                .value_name("PREFIX:SUFFIX")
+                .action(clap::ArgAction::Append)
+        )
+        .arg(
+            Arg::new("at")
+                .long("at")
+                .help("Match addresses with TEXT right after the first OFFSET characters, e.g. 10:tor (repeatable)")
+                .value_name("OFFSET:TEXT")
+                .action(clap::ArgAction::Append)
+        )
+        .arg(
+            Arg::new("both")
+                .long("both")
+                .help("Require a prefix and a suffix on the same address instead of either")
+                .action(clap::ArgAction::SetTrue)
+                .requires("suffix")
+        )
+        .arg(
+            Arg::new("anywhere")
+                .long("anywhere")
+                .help("Match the prefixes anywhere in the address, like --contains")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("both")
+        )
+        .arg(
+            Arg::new("expand")
+                .long("expand")
+                .help("Also search every leetspeak spelling of the prefixes and --contains words, e.g. t34 for tea")
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("classes")
+                .long("classes")
+                .help("Match addresses whose characters fit TEMPLATE, e.g. L{8} for 8 letters or t[ou]r (repeatable)")
+                .value_name("TEMPLATE")
+                .action(clap::ArgAction::Append)
+        )
+        .arg(
+            Arg::new("run")
+                .long("run")
+                .help("Match addresses starting with N identical characters, e.g. aaaa for 4 (repeatable)")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .action(clap::ArgAction::Append)
+        )
+        .arg(
+            Arg::new("doubled")
+                .long("doubled")
+                .help("Match addresses whose first N characters repeat right after, e.g. abcabc for 3 (repeatable)")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .action(clap::ArgAction::Append)
+        )
+        .arg(
+            Arg::new("palindrome")
+                .long("palindrome")
+                .help("Match addresses whose first N characters read the same backwards, e.g. abcba for 5 (repeatable)")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .action(clap::ArgAction::Append)
+        )
+        .arg(
+            Arg::new("regex")
+                .long("regex")
+                .help("Match addresses against a regular expression (repeatable)")
+                .value_name("REGEX")
+                .action(clap::ArgAction::Append)
+        )
+        .arg(
+            Arg::new("expr")
+                .long("expr")
+                .help("Match addresses satisfying EXPR, e.g. 'prefix:foo & !contains:xxx' (repeatable)")
+                .value_name("EXPR")
+                .action(clap::ArgAction::Append)
+        )
+        .arg(
+            Arg::new("exclude")
+                .long("exclude")
+                .help("Reject addresses containing PATTERN anywhere (repeatable)")
+                .value_name("PATTERN")
+                .action(clap::ArgAction::Append)
+        )
         .arg(
             Arg::new("workers")
                 .short('w')
@@ -35,124 +179,2142 @@ fn main() -> Result<()> {
                 .help("Run in single-threaded mode")
                 .action(clap::ArgAction::SetTrue)
         )
+        .arg(
+            Arg::new("cooperative")
+                .long("cooperative")
+                .help("Run the workers at idle priority and pause them briefly, so other programs keep the CPU they need")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("single-threaded")
+        )
+        .arg(
+            Arg::new("duty-cycle")
+                .long("duty-cycle")
+                .help("Percent of each 20ms period a --cooperative worker spends generating keys, from 1 to 100 (default: 90)")
+                .value_name("PERCENT")
+                .value_parser(clap::value_parser!(u8).range(1..=100))
+                .requires("cooperative")
+        )
         .arg(
             Arg::new("update-interval")
                 .short('u')
                 .long("update-interval")
-                .help("Statistics update interval in seconds (default: 30)")
-                .value_name("SECONDS")
-                .value_parser(clap::value_parser!(u64))
+                .help("Statistics update interval, e.g. 90s or 5m; plain numbers are seconds (default: 30)")
+                .value_name("DURATION")
+                .value_parser(duration_arg(Duration::from_secs(1), Duration::from_secs(1)))
                 .default_value("30")
         )
-        .get_matches();
+        .arg(
+            Arg::new("progress-json")
+                .long("progress-json")
+                .help("Emit machine-readable JSON progress records on stderr")
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("stdin-patterns")
+                .long("stdin-patterns")
+                .help("Read patterns to add, one per line, or ADD/REMOVE/LIST/PAUSE/RESUME commands from stdin while searching")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("single-threaded")
+        )
+        .arg(
+            Arg::new("no-progress")
+                .long("no-progress")
+                .help("Disable the live per-worker progress display on interactive terminals")
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("sparkline")
+                .long("sparkline")
+                .help("On terminals, end each stats line with a sparkline of keys/s over the last NUM updates, 0 to hide (default: 20)")
+                .value_name("NUM")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("20")
+        )
+        .arg(
+            Arg::new("projection")
+                .long("projection")
+                .help("Print the chance of finding each outstanding pattern with every statistics update")
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("no-calibrate")
+                .long("no-calibrate")
+                .help("Skip the startup speed calibration and pattern difficulty table")
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("matcher-bench")
+                .long("matcher-bench")
+                .help("Time each matcher engine on the patterns and search with the fastest")
+                .action(clap::ArgAction::SetTrue)
+                .hide(true)
+        )
+        .arg(
+            Arg::new("confirm-over")
+                .long("confirm-over")
+                .help("Ask before starting if the median time to a match exceeds DURATION, e.g. 2d; plain numbers are hours (default: 24)")
+                .value_name("DURATION")
+                .value_parser(duration_arg(Duration::from_secs(3600), Duration::ZERO))
+                .default_value("24")
+        )
+        .arg(
+            Arg::new("yes")
+                .short('y')
+                .long("yes")
+                .help("Start without asking, however long the search is expected to take")
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("max-restarts")
+                .long("max-restarts")
+                .help("Restart panicked workers at most NUM times before giving up (default: 3)")
+                .value_name("NUM")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("3")
+        )
+        .arg(
+            Arg::new("stall-timeout")
+                .long("stall-timeout")
+                .help("Report workers that generate nothing for DURATION as stalled; plain numbers are seconds (default: 30)")
+                .value_name("DURATION")
+                .value_parser(duration_arg(Duration::from_secs(1), Duration::from_secs(1)))
+                .conflicts_with("single-threaded")
+        )
+        .arg(
+            Arg::new("restart-stalled")
+                .long("restart-stalled")
+                .help("Replace stalled workers with new threads at most NUM times in total")
+                .value_name("NUM")
+                .value_parser(clap::value_parser!(usize))
+                .conflicts_with_all(["single-threaded", "seed-file"])
+        )
+        .arg(
+            Arg::new("error-backoff")
+                .long("error-backoff")
+                .help("Wait DURATION before a worker retries after a transient error, doubling on each repeat; plain numbers are milliseconds (default: 100)")
+                .value_name("DURATION")
+                .value_parser(duration_arg(Duration::from_millis(1), Duration::from_millis(1)))
+                .conflicts_with("single-threaded")
+        )
+        .arg(
+            Arg::new("error-backoff-max")
+                .long("error-backoff-max")
+                .help("Longest wait between a worker's retries; plain numbers are milliseconds (default: 10s)")
+                .value_name("DURATION")
+                .value_parser(duration_arg(Duration::from_millis(1), Duration::from_millis(1)))
+                .conflicts_with("single-threaded")
+        )
+        .arg(
+            Arg::new("self-check-interval")
+                .long("self-check-interval")
+                .help("Every DURATION, check a fresh key end to end and stop the run if it does not verify; plain numbers are seconds")
+                .value_name("DURATION")
+                .value_parser(duration_arg(Duration::from_secs(1), Duration::from_secs(1)))
+                .conflicts_with("single-threaded")
+        )
+        .arg(
+            Arg::new("fix-patterns")
+                .long("fix-patterns")
+                .help("Replace characters that never appear in addresses (0, 1, 8, 9 and others) instead of failing")
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("generate")
+                .short('g')
+                .long("generate")
+                .help("Generate NUM addresses without matching any prefix, then exit")
+                .value_name("NUM")
+                .value_parser(clap::value_parser!(usize))
+                .conflicts_with_all(["prefixes", "prefix", "patterns-file", "mkp224o-filters", "mkp224o-args", "suffix", "contains", "prefix-suffix", "at", "classes", "run", "doubled", "palindrome", "regex", "expr"])
+        )
+        .arg(
+            Arg::new("patterns-file")
+                .long("patterns-file")
+                .help("Read more prefixes, or globs, from FILE: one per line, # starts a comment")
+                .value_name("FILE")
+        )
+        .arg(
+            Arg::new("mkp224o-filters")
+                .long("mkp224o-filters")
+                .help("Read the filters of an mkp224o filter file, as prefixes (can be repeated)")
+                .value_name("FILE")
+                .action(clap::ArgAction::Append)
+        )
+        .arg(
+            Arg::new("mkp224o-args")
+                .long("mkp224o-args")
+                .help("Take the filters of an mkp224o command line, up to the end or a ';', reading each -f FILE")
+                .value_name("ARGS")
+                .num_args(1..)
+                .allow_hyphen_values(true)
+                .value_terminator(";")
+        )
+        .group(ArgGroup::new("mkp224o").args(["mkp224o-filters", "mkp224o-args"]).multiple(true))
+        .arg(
+            Arg::new("mkp224o-regex")
+                .long("mkp224o-regex")
+                .help("Read the mkp224o filters as regexes, for filters written for its regex build")
+                .action(clap::ArgAction::SetTrue)
+                .requires("mkp224o")
+        )
+        .arg(
+            Arg::new("output-dir")
+                .short('o')
+                .long("output-dir")
+                .help("Also write each found address as a Tor hidden service directory under DIR")
+                .value_name("DIR")
+                .conflicts_with("single-threaded")
+        )
+        .arg(
+            Arg::new("label")
+                .long("label")
+                .help("Save TEXT as the label of every address written to --output-dir, shown by the list command")
+                .value_name("TEXT")
+                .requires("output-dir")
+        )
+        .arg(
+            Arg::new("ask-label")
+                .long("ask-label")
+                .help("Ask on the terminal for the label of each address written to --output-dir")
+                .action(clap::ArgAction::SetTrue)
+                .requires("output-dir")
+                .conflicts_with_all(["label", "stdin-patterns", "single-threaded"])
+        )
+        .arg(
+            Arg::new("seed-file")
+                .long("seed-file")
+                .help("Derive candidates deterministically from the 32-byte master seed in FILE")
+                .value_name("FILE")
+                .conflicts_with("single-threaded")
+        )
+        .arg(
+            Arg::new("start-at")
+                .long("start-at")
+                .help("Start the seeded shards at COUNTER, a comma-separated counter per shard, or where the run.json of an earlier run stopped")
+                .value_name("COUNTER")
+                .requires("seed-file")
+        )
+        .arg(
+            Arg::new("shared-dir")
+                .long("shared-dir")
+                .help("Coordinate with other machines through a shared directory")
+                .value_name("DIR")
+                .conflicts_with("single-threaded")
+        )
+        .arg(
+            Arg::new("claim")
+                .long("claim")
+                .help("Number of unclaimed patterns to take from the shared directory (default: 1)")
+                .value_name("NUM")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1")
+                .requires("shared-dir")
+        )
+        .arg(
+            Arg::new("batch-size")
+                .long("batch-size")
+                .help("Step SIZE candidates from each random key by point addition; auto picks the fastest size for this CPU")
+                .value_name("SIZE|auto")
+                .conflicts_with_all(["single-threaded", "seed-file", "hsm-import"])
+        )
+        .arg(
+            Arg::new("batch-state")
+                .long("batch-state")
+                .help("File keeping the batch size auto picked on each machine")
+                .value_name("FILE")
+                .default_value("batch-tuning.jsonl")
+        )
+        .arg(
+            Arg::new("seal-to")
+                .long("seal-to")
+                .help("Encrypt each private key to this X25519 public key (base64 or hex)")
+                .value_name("PUBKEY")
+                .conflicts_with("single-threaded")
+        )
+        .arg(
+            Arg::new("hsm-import")
+                .long("hsm-import")
+                .help("Pipe each key as PKCS#8 DER into COMMAND (e.g. pkcs11-tool) instead of keeping it")
+                .value_name("COMMAND")
+                .conflicts_with_all(["single-threaded", "seal-to"])
+        )
+        .arg(
+            Arg::new("public-only")
+                .long("public-only")
+                .help("Keep only hostnames and public keys; private keys are dropped as soon as they are found")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["single-threaded", "seal-to", "hsm-import", "seed-file"])
+        )
+        .arg(
+            Arg::new("attest-key")
+                .long("attest-key")
+                .help("Sign an attestation of each find with the key in FILE (created if missing)")
+                .value_name("FILE")
+                .conflicts_with("single-threaded")
+        )
+        .arg(
+            Arg::new("control-socket")
+                .long("control-socket")
+                .help("Accept ADD, REMOVE, LIST, PAUSE and RESUME commands on a Unix socket at PATH, or a named pipe on Windows")
+                .value_name("PATH")
+                .conflicts_with("single-threaded")
+        )
+        .arg(
+            Arg::new("confine")
+                .long("confine")
+                .help("Only allow file writes beneath the output, shared, journal and control socket directories (Landlock on Linux)")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("hsm-import")
+        )
+        .arg(
+            Arg::new("journal")
+                .long("journal")
+                .help("Record finds in a crash-safe journal FILE first; finds a crashed run did not deliver are delivered on the next start")
+                .value_name("FILE")
+                .conflicts_with("single-threaded")
+        )
+        .arg(
+            Arg::new("results-file")
+                .long("results-file")
+                .help("Append each find, including its private key, to FILE as a JSON line")
+                .value_name("FILE")
+                .conflicts_with("single-threaded")
+        )
+        .arg(
+            Arg::new("sample")
+                .long("sample")
+                .help("Write this FRACTION of the non-matching candidates, picked at random, to the --sample-file")
+                .value_name("FRACTION")
+                .value_parser(clap::value_parser!(f64))
+                .conflicts_with("single-threaded")
+        )
+        .arg(
+            Arg::new("sample-file")
+                .long("sample-file")
+                .help("File the --sample hostnames are appended to, one per line")
+                .value_name("FILE")
+                .default_value("sample.txt")
+        )
+        .arg(
+            Arg::new("seccomp")
+                .long("seccomp")
+                .help("Once started, restrict the process to the system calls a search needs (Linux only)")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("hsm-import")
+        )
+        .arg(
+            Arg::new("stats-file")
+                .long("stats-file")
+                .help("Append this run's totals and keys/s to FILE for `stats history`; nothing is sent anywhere")
+                .value_name("FILE")
+        )
+        .arg(
+            Arg::new("config")
+                .short('c')
+                .long("config")
+                .help("Read additional settings, e.g. chat notifications, from a TOML file")
+                .value_name("FILE")
+        )
+        .arg(
+            Arg::new("profile")
+                .short('p')
+                .long("profile")
+                .help("Use the patterns, limits and outputs of [profile.NAME] in the --config file")
+                .value_name("NAME")
+                .requires("config")
+        )
+        .arg(
+            Arg::new("log-format")
+                .long("log-format")
+                .help("Format of stdout lines: text or json (one record per line, no private keys)")
+                .value_name("FORMAT")
+                .value_parser(clap::value_parser!(LogFormat))
+                .default_value("text")
+                .global(true)
+        )
+        .arg(
+            Arg::new("on-stdout-closed")
+                .long("on-stdout-closed")
+                .help("When stdout is a pipe that closes: exit (stop like Ctrl-C) or continue with the other outputs")
+                .value_name("ACTION")
+                .value_parser(clap::value_parser!(StdoutClosed))
+                .default_value("exit")
+                .global(true)
+        )
+        .arg(
+            Arg::new("journald")
+                .long("journald")
+                .help("Also send log lines and structured events (finds, stats, workers) to journald (Linux only)")
+                .action(clap::ArgAction::SetTrue)
+                .global(true)
+        )
+        .arg(
+            Arg::new("log-file")
+                .long("log-file")
+                .help("Also append every log line and event to FILE as a JSON record")
+                .value_name("FILE")
+                .global(true)
+        )
+        .arg(
+            Arg::new("rotate-size")
+                .long("rotate-size")
+                .help("Rotate the results and log files once they reach MIB mebibytes")
+                .value_name("MIB")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .global(true)
+        )
+        .arg(
+            Arg::new("rotate-every")
+                .long("rotate-every")
+                .help("Rotate the results and log files every DURATION, e.g. 12h or 1d; plain numbers are hours")
+                .value_name("DURATION")
+                .value_parser(duration_arg(Duration::from_secs(3600), Duration::from_secs(1)))
+                .global(true)
+        )
+        .arg(
+            Arg::new("rotate-keep")
+                .long("rotate-keep")
+                .help("Rotated files to keep of each (default: 7)")
+                .value_name("NUM")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("7")
+                .global(true)
+        )
+        .arg(
+            Arg::new("rotate-compress")
+                .long("rotate-compress")
+                .help("Gzip rotated files")
+                .action(clap::ArgAction::SetTrue)
+                .global(true)
+        )
+        .subcommand_negates_reqs(true)
+        .args_conflicts_with_subcommands(true)
+        // A later value replaces an earlier one, e.g. one from a --profile
+        .args_override_self(true)
+        .subcommand(
+            Command::new("info")
+                .about("Show build and CPU information relevant to generation speed")
+        )
+        .subcommand(
+            Command::new("doctor")
+                .about("Check the entropy source, CPU, cooling, output directories and Tor for problems")
+                .arg(
+                    Arg::new("output-dir")
+                        .short('o')
+                        .long("output-dir")
+                        .help("Output directory to check (default: the current directory); may be repeated")
+                        .value_name("DIR")
+                        .action(clap::ArgAction::Append)
+                )
+                .arg(
+                    Arg::new("tor-control")
+                        .long("tor-control")
+                        .help("Check that Tor's control port answers at ADDR, e.g. 127.0.0.1:9051")
+                        .value_name("ADDR")
+                )
+        )
+        .subcommand(
+            Command::new("gen-vectors")
+                .about("Write reproducible seed, hostname and Tor key file test vectors as JSON")
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .help("Write the vectors to FILE instead of stdout")
+                        .value_name("FILE")
+                )
+                .arg(
+                    Arg::new("count")
+                        .short('n')
+                        .long("count")
+                        .help("Number of vectors")
+                        .value_name("NUM")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("16")
+                )
+        )
+        .subcommand(
+            Command::new("client-auth")
+                .about("Generate client authorization credentials for an onion service")
+                .arg(
+                    Arg::new("address")
+                        .help("Onion address of the service")
+                        .required(true)
+                        .value_name("ADDRESS")
+                )
+                .arg(
+                    Arg::new("name")
+                        .long("name")
+                        .help("Client name used for the server-side .auth file")
+                        .value_name("NAME")
+                        .default_value("client")
+                )
+        )
+        .subcommand(
+            Command::new("rollover")
+                .about("Preview the blinded keys and HSDir indexes of an onion service for upcoming time periods")
+                .arg(
+                    Arg::new("address")
+                        .help("Onion address of the service")
+                        .required(true)
+                        .value_name("ADDRESS")
+                )
+                .arg(
+                    Arg::new("from")
+                        .long("from")
+                        .help("First date, YYYY-MM-DD or RFC 3339, in UTC (default: now)")
+                        .value_name("DATE")
+                )
+                .arg(
+                    Arg::new("to")
+                        .long("to")
+                        .help("Last date, YYYY-MM-DD or RFC 3339, in UTC (default: 7 days after --from)")
+                        .value_name("DATE")
+                )
+        )
+        .subcommand(
+            Command::new("brain")
+                .about("Derive an address from a passphrase (DANGEROUS: read the warning)")
+                .arg(
+                    Arg::new("salt")
+                        .long("salt")
+                        .help("Extra salt, e.g. your email address; needed again for recovery")
+                        .value_name("SALT")
+                        .default_value("")
+                )
+        )
+        .subcommand(
+            Command::new("seal-keygen")
+                .about("Create a key pair for receiving results sealed with --seal-to")
+                .arg(
+                    Arg::new("secret-key")
+                        .help("File to write the secret key to")
+                        .required(true)
+                        .value_name("FILE")
+                )
+        )
+        .subcommand(
+            Command::new("unseal")
+                .about("Open a private key sealed with --seal-to")
+                .arg(
+                    Arg::new("sealed")
+                        .help("A hs_ed25519_secret_key.sealed file, or the sealed key in base64")
+                        .required(true)
+                        .value_name("SEALED")
+                )
+                .arg(
+                    Arg::new("key")
+                        .short('k')
+                        .long("key")
+                        .help("Secret key file written by seal-keygen")
+                        .required(true)
+                        .value_name("FILE")
+                )
+        )
+        .subcommand(
+            Command::new("standby")
+                .about("Fill a pool of plain addresses sealed at rest, to hand out later with claim")
+                .arg(
+                    Arg::new("pool")
+                        .help("Pool directory")
+                        .required(true)
+                        .value_name("DIR")
+                )
+                .arg(
+                    Arg::new("count")
+                        .short('n')
+                        .long("count")
+                        .help("Number of keys to keep ready (default: 100)")
+                        .value_name("NUM")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("100")
+                )
+                .arg(
+                    Arg::new("seal-to")
+                        .long("seal-to")
+                        .help("Seal the keys to this public key from seal-keygen")
+                        .required(true)
+                        .value_name("PUBKEY")
+                )
+        )
+        .subcommand(
+            Command::new("claim")
+                .about("Take one address from a standby pool and mark it as used")
+                .arg(
+                    Arg::new("pool")
+                        .help("Pool directory filled by standby")
+                        .required(true)
+                        .value_name("DIR")
+                )
+                .arg(
+                    Arg::new("key")
+                        .short('k')
+                        .long("key")
+                        .help("Secret key file written by seal-keygen")
+                        .required(true)
+                        .value_name("FILE")
+                )
+                .arg(
+                    Arg::new("output-dir")
+                        .short('o')
+                        .long("output-dir")
+                        .help("Write the address as a Tor hidden service directory under DIR instead of printing it")
+                        .value_name("DIR")
+                )
+        )
+        .subcommand(
+            Command::new("list")
+                .about("List the addresses saved under an output directory with their labels")
+                .arg(
+                    Arg::new("dir")
+                        .help("Output directory of earlier runs")
+                        .value_name("DIR")
+                        .default_value(".")
+                )
+        )
+        .subcommand(
+            Command::new("verify-attestation")
+                .about("Verify attestation records, one JSON object per line")
+                .arg(
+                    Arg::new("file")
+                        .help("File with attestation records or generator output (default: stdin)")
+                        .value_name("FILE")
+                )
+        )
+        .subcommand(
+            Command::new("bench")
+                .about("Measure key generation speed")
+                .arg(
+                    Arg::new("workers")
+                        .short('w')
+                        .long("workers")
+                        .help("Number of threads (default: number of CPU cores)")
+                        .value_name("NUM")
+                        .value_parser(clap::value_parser!(usize))
+                )
+                .arg(
+                    Arg::new("duration")
+                        .short('d')
+                        .long("duration")
+                        .help("How long to generate keys, e.g. 30s; plain numbers are seconds (default: 5)")
+                        .value_name("DURATION")
+                        .value_parser(duration_arg(Duration::from_secs(1), Duration::from_millis(1)))
+                        .default_value("5")
+                )
+                .arg(
+                    Arg::new("history")
+                        .long("history")
+                        .help("Compare with earlier runs in FILE and append this one")
+                        .value_name("FILE")
+                )
+        )
+        .subcommand(
+            Command::new("suggest")
+                .about("Suggest readable prefixes that can be found within a time budget on this machine")
+                .arg(
+                    Arg::new("time")
+                        .short('t')
+                        .long("time")
+                        .help("Time budget, e.g. 8h; plain numbers are hours (default: 1h)")
+                        .value_name("DURATION")
+                        .value_parser(duration_arg(Duration::from_secs(3600), Duration::from_secs(1)))
+                        .default_value("1h")
+                )
+                .arg(
+                    Arg::new("count")
+                        .short('n')
+                        .long("count")
+                        .help("Number of prefixes to suggest (default: 10)")
+                        .value_name("NUM")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("10")
+                )
+                .arg(
+                    Arg::new("rate")
+                        .long("rate")
+                        .help("Assume RATE keys/s instead of measuring it")
+                        .value_name("RATE")
+                        .value_parser(clap::value_parser!(f64))
+                )
+                .arg(
+                    Arg::new("workers")
+                        .short('w')
+                        .long("workers")
+                        .help("Number of threads to measure with (default: number of CPU cores)")
+                        .value_name("NUM")
+                        .value_parser(clap::value_parser!(usize))
+                )
+        )
+        .subcommand(
+            Command::new("harvest")
+                .about("Keep the best-looking addresses in a ranked file instead of searching for a pattern")
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .help("Ranked file, rewritten as the list changes (default: harvest.jsonl)")
+                        .value_name("FILE")
+                        .default_value("harvest.jsonl")
+                )
+                .arg(
+                    Arg::new("top")
+                        .long("top")
+                        .help("Number of addresses to keep (default: 20)")
+                        .value_name("K")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("20")
+                )
+                .arg(
+                    Arg::new("score-script")
+                        .long("score-script")
+                        .help("Score with COMMAND, which reads one address per line and prints one number per line")
+                        .value_name("COMMAND")
+                )
+                .arg(
+                    Arg::new("words")
+                        .long("words")
+                        .help("Score by the words found in the address, from FILE or a built-in English list")
+                        .value_name("FILE")
+                        .num_args(0..=1)
+                        .default_missing_value("")
+                        .conflicts_with("score-script")
+                )
+                .arg(
+                    Arg::new("min-score")
+                        .long("min-score")
+                        .help("Report every address scoring at least SCORE, not only new bests (15 or more)")
+                        .value_name("SCORE")
+                        .value_parser(clap::value_parser!(f64))
+                )
+                .arg(
+                    Arg::new("workers")
+                        .short('w')
+                        .long("workers")
+                        .help("Number of threads (default: number of CPU cores)")
+                        .value_name("NUM")
+                        .value_parser(clap::value_parser!(usize))
+                )
+                .arg(
+                    Arg::new("duration")
+                        .short('d')
+                        .long("duration")
+                        .help("Stop after DURATION, e.g. 8h; plain numbers are seconds (default: run until Ctrl+C)")
+                        .value_name("DURATION")
+                        .value_parser(duration_arg(Duration::from_secs(1), Duration::from_secs(1)))
+                )
+        )
+        .subcommand(
+            Command::new("serve")
+                .about("Run searches behind an HTTP job API with status, metrics and a WebSocket stream")
+                .arg(
+                    Arg::new("prefixes")
+                        .help("Prefixes to start searching for immediately")
+                        .num_args(1..)
+                        .value_name("PREFIX")
+                )
+                .arg(
+                    Arg::new("listen")
+                        .long("listen")
+                        .help("Address to listen on (default: 127.0.0.1:8080)")
+                        .value_name("ADDR")
+                )
+                .arg(
+                    Arg::new("token-file")
+                        .long("token-file")
+                        .help("Require the bearer token stored in FILE on every request")
+                        .value_name("FILE")
+                )
+                .arg(
+                    Arg::new("tls-cert")
+                        .long("tls-cert")
+                        .help("Serve HTTPS with this PEM certificate chain")
+                        .value_name("FILE")
+                        .requires("tls-key")
+                )
+                .arg(
+                    Arg::new("tls-key")
+                        .long("tls-key")
+                        .help("PEM private key for --tls-cert")
+                        .value_name("FILE")
+                        .requires("tls-cert")
+                )
+                .arg(
+                    Arg::new("workers")
+                        .short('w')
+                        .long("workers")
+                        .help("Default number of worker threads per job (default: number of CPU cores)")
+                        .value_name("NUM")
+                        .value_parser(clap::value_parser!(usize))
+                )
+                .arg(
+                    Arg::new("config")
+                        .short('c')
+                        .long("config")
+                        .help("Read [serve] settings from a TOML file")
+                        .value_name("FILE")
+                )
+        )
+        .subcommand(
+            Command::new("stats")
+                .about("Show statistics recorded locally with --stats-file")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("history")
+                        .about("Print the keys/s of earlier runs per machine and flag slowdowns")
+                        .arg(
+                            Arg::new("file")
+                                .help("Stats file written by --stats-file")
+                                .required(true)
+                                .value_name("FILE")
+                        )
+                        .arg(
+                            Arg::new("last")
+                                .long("last")
+                                .help("Number of runs to list per machine (default: 10)")
+                                .value_name("NUM")
+                                .value_parser(clap::value_parser!(usize))
+                                .default_value("10")
+                        )
+                )
+        )
+}
 
-    // Parse prefixes
-    let prefixes: Vec<String> = matches
-        .get_many::<String>("prefixes")
-        .unwrap()
-        .map(|s| s.trim().to_lowercase())
-        .collect();
+fn run() -> Result<()> {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let mut matches = cli().get_matches_from(&args);
+    if let Some(name) = matches.get_one::<String>("profile") {
+        // The profile's settings go first, so the command line overrides or extends them
+        let file_config = FileConfig::load(matches.get_one::<String>("config").unwrap())?;
+        let profile = file_config.profile(name)?;
+        let mut with_profile = args[..1].to_vec();
+        with_profile.extend(profile_args(profile).into_iter().map(OsString::from));
+        with_profile.extend_from_slice(&args[1..]);
+        matches = cli().get_matches_from(with_profile);
+    }
+    set_log_format(*matches.get_one::<LogFormat>("log-format").unwrap());
+    set_stdout_closed(*matches.get_one::<StdoutClosed>("on-stdout-closed").unwrap());
+    if matches.get_flag("journald") {
+        #[cfg(target_os = "linux")]
+        onion_generator::journald::enable_journald()?;
+        #[cfg(not(target_os = "linux"))]
+        anyhow::bail!("--journald is only supported on Linux");
+    }
+    if let Some(path) = matches.get_one::<String>("log-file") {
+        enable_log_file(path, rotation(&matches))?;
+    }
 
-    if prefixes.is_empty() {
-        eprintln!("[!] Error: At least one prefix must be provided.");
-        std::process::exit(1);
+    match matches.subcommand() {
+        Some(("info", _)) => {
+            print_info();
+            return Ok(());
+        }
+        Some(("doctor", sub)) => return run_doctor(sub),
+        Some(("gen-vectors", sub)) => {
+            let count = *sub.get_one::<usize>("count").unwrap();
+            return run_gen_vectors(count, sub.get_one::<String>("output").map(Path::new));
+        }
+        Some(("client-auth", sub)) => {
+            let address = sub.get_one::<String>("address").unwrap();
+            let name = sub.get_one::<String>("name").unwrap();
+            return print_client_auth(address, name);
+        }
+        Some(("rollover", sub)) => {
+            return run_rollover(
+                sub.get_one::<String>("address").unwrap(),
+                sub.get_one::<String>("from").map(String::as_str),
+                sub.get_one::<String>("to").map(String::as_str),
+            );
+        }
+        Some(("brain", sub)) => {
+            let salt = sub.get_one::<String>("salt").unwrap();
+            return run_brain(salt);
+        }
+        Some(("seal-keygen", sub)) => {
+            return run_seal_keygen(Path::new(sub.get_one::<String>("secret-key").unwrap()));
+        }
+        Some(("unseal", sub)) => {
+            let key = Path::new(sub.get_one::<String>("key").unwrap());
+            return run_unseal(sub.get_one::<String>("sealed").unwrap(), key);
+        }
+        Some(("standby", sub)) => {
+            let pool = sub.get_one::<String>("pool").unwrap();
+            return run_standby(pool, *sub.get_one::<usize>("count").unwrap(), sub.get_one::<String>("seal-to").unwrap());
+        }
+        Some(("claim", sub)) => {
+            let key = Path::new(sub.get_one::<String>("key").unwrap());
+            return run_claim(sub.get_one::<String>("pool").unwrap(), key, sub.get_one::<String>("output-dir").map(Path::new));
+        }
+        Some(("list", sub)) => return run_list(Path::new(sub.get_one::<String>("dir").unwrap())),
+        Some(("verify-attestation", sub)) => {
+            return run_verify_attestation(sub.get_one::<String>("file").map(Path::new));
+        }
+        Some(("bench", sub)) => {
+            let workers = sub.get_one::<usize>("workers").copied()
+                .unwrap_or_else(|| GeneratorConfig::new(Vec::new()).num_workers);
+            let duration = *sub.get_one::<Duration>("duration").unwrap();
+            return run_bench(workers, duration, sub.get_one::<String>("history").map(Path::new));
+        }
+        Some(("suggest", sub)) => return run_suggest(sub),
+        Some(("harvest", sub)) => return run_harvest(sub),
+        Some(("serve", sub)) => return run_serve(sub),
+        Some(("stats", sub)) => return run_stats(sub),
+        _ => {}
     }
 
-    println!("[@] Onion V3 Address Generator");
-    println!("[@] Searching for prefixes: {:?}", prefixes);
+    if let Some(count) = matches.get_one::<usize>("generate") {
+        logln!("[@] Onion V3 Address Generator");
+        logln!("[@] Generating {} addresses...", count);
 
-    // Setup signal handler
-    setup_signal_handler();
+        for result in generate_n(*count)? {
+            print_result(&result);
+        }
+        return Ok(());
+    }
 
-    // Check if single-threaded mode is requested
-    if matches.get_flag("single-threaded") {
-        return run_single_threaded_with_input(&prefixes);
+    // Parse patterns
+    let patterns = |id: &str| -> Vec<String> {
+        matches
+            .get_many::<String>(id)
+            .unwrap_or_default()
+            .map(|s| s.trim().to_lowercase())
+            .collect()
+    };
+    // With --fix-patterns, characters that never appear in addresses are replaced or dropped
+    let fix_patterns = matches.get_flag("fix-patterns");
+    let fixed = |pattern: String| -> String {
+        match nearest_valid_patterns(&pattern).into_iter().next() {
+            // Fixing would drop the wildcards or brackets
+            Some(_) if Glob::is_glob(&pattern) || CharTemplate::has_brackets(&pattern) => pattern,
+            Some(fixed) if fix_patterns => {
+                logln!("[i] Using {:?} for {:?}", fixed, pattern);
+                fixed
+            }
+            _ => pattern,
+        }
+    };
+    let patterns = |id: &str| -> Vec<String> { patterns(id).into_iter().map(fixed).collect() };
+    let mut prefixes = patterns("prefixes");
+    prefixes.extend(patterns("prefix"));
+    if let Some(path) = matches.get_one::<String>("patterns-file") {
+        let listed = read_patterns_file(path)?;
+        logln!("[@] Read {} pattern(s) from {}", listed.len(), path);
+        prefixes.extend(listed.into_iter().map(|pattern| fixed(pattern.to_lowercase())));
     }
+    let mut regexes: Vec<String> = matches
+        .get_many::<String>("regex")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    for path in matches.get_many::<String>("mkp224o-filters").unwrap_or_default() {
+        let filters = Mkp224oFilters::read(path, matches.get_flag("mkp224o-regex"))?;
+        logln!("[@] Read {} mkp224o filter(s) from {}", filters.len(), path);
+        prefixes.extend(filters.prefixes);
+        regexes.extend(filters.regexes);
+    }
+    if let Some(args) = matches.get_many::<String>("mkp224o-args") {
+        let args: Vec<_> = args.collect();
+        let filters = Mkp224oFilters::from_args(&args, matches.get_flag("mkp224o-regex"))?;
+        logln!("[@] Took {} mkp224o filter(s) from its command line", filters.len());
+        if !filters.ignored_options.is_empty() {
+            logln!("[@] Ignored mkp224o options: {}", filters.ignored_options.join(" "));
+        }
+        prefixes.extend(filters.prefixes);
+        regexes.extend(filters.regexes);
+    }
+    // With --expand, each plain word stands for all of its spellings
+    let expand = |patterns: Vec<String>| -> Result<Vec<String>> {
+        if !matches.get_flag("expand") {
+            return Ok(patterns);
+        }
+        let mut expanded = Vec::new();
+        for pattern in patterns {
+            if Glob::is_glob(&pattern) || CharTemplate::has_brackets(&pattern) {
+                expanded.push(pattern);
+                continue;
+            }
+            let variants = expand_variants(&pattern)?;
+            logln!("[@] Expanded {:?} into {} variant(s)", pattern, variants.len());
+            expanded.extend(variants);
+        }
+        let mut seen = std::collections::HashSet::new();
+        expanded.retain(|pattern| seen.insert(pattern.clone()));
+        Ok(expanded)
+    };
+    let mut prefixes = expand(prefixes)?;
 
-    // Setup multi-threaded configuration
-    let mut config = GeneratorConfig::new(prefixes);
-    
+    let mut suffixes = patterns("suffix");
+    let mut prefix_suffixes = Vec::new();
+    // Every prefix with every suffix, instead of either on its own
+    if matches.get_flag("both") {
+        for prefix in prefixes.drain(..) {
+            prefix_suffixes.extend(suffixes.iter().map(|suffix| (prefix.clone(), suffix.clone())));
+        }
+        suffixes.clear();
+    }
+    for pair in matches.get_many::<String>("prefix-suffix").unwrap_or_default() {
+        match pair.trim().to_lowercase().split_once(':') {
+            Some((prefix, suffix)) => prefix_suffixes.push((fixed(prefix.to_string()), fixed(suffix.to_string()))),
+            None => {
+                return Err(anyhow::anyhow!("--prefix-suffix takes PREFIX:SUFFIX, got {:?}", pair))
+                    .with_code(ErrorCode::InvalidPattern);
+            }
+        }
+    }
+
+    let mut positioned = Vec::new();
+    for pattern in matches.get_many::<String>("at").unwrap_or_default() {
+        match pattern.trim().to_lowercase().split_once(':') {
+            Some((offset, text)) => positioned.push(format!("{}:{}", offset, fixed(text.to_string()))),
+            None => positioned.push(pattern.clone()),
+        }
+    }
+
+    let match_mode = if matches.get_flag("anywhere") { MatchMode::Anywhere } else { MatchMode::Start };
+    let mut config = GeneratorConfig::new(prefixes)
+        .with_match_mode(match_mode)
+        .with_suffixes(suffixes)
+        .with_contains(expand(patterns("contains"))?)
+        .with_prefix_suffixes(prefix_suffixes)
+        .with_positioned(positioned)
+        .with_classes(matches.get_many::<String>("classes").unwrap_or_default().cloned().collect())
+        .with_runs(matches.get_many::<usize>("run").unwrap_or_default().copied().collect())
+        .with_doubled(matches.get_many::<usize>("doubled").unwrap_or_default().copied().collect())
+        .with_palindromes(matches.get_many::<usize>("palindrome").unwrap_or_default().copied().collect())
+        .with_regexes(regexes)
+        .with_expressions(matches.get_many::<String>("expr").unwrap_or_default().cloned().collect())
+        .with_excludes(patterns("exclude"));
     if let Some(workers) = matches.get_one::<usize>("workers") {
         config = config.with_workers(*workers);
     }
-    
-    if let Some(interval) = matches.get_one::<u64>("update-interval") {
-        config = config.with_update_interval(*interval);
+    let file_config = match matches.get_one::<String>("config") {
+        Some(path) => FileConfig::load(path)?,
+        None => FileConfig::default(),
+    };
+    // Report every problem before starting, instead of the first one
+    let output_dir = matches.get_one::<String>("output-dir");
+    let mut check = ConfigCheck::new().generator(&config).file(&file_config);
+    if let Some(dir) = output_dir {
+        check = check.output_dir(Path::new(dir));
+    }
+    check.check()?;
+    // Label prompts read from the terminal, which the input monitor would compete for
+    let ask_label = matches.get_flag("ask-label");
+    if ask_label && !atty::is(atty::Stream::Stdin) {
+        return Err(anyhow::anyhow!("--ask-label needs a terminal to ask on; use --label instead")).with_code(ErrorCode::InvalidConfig);
+    }
+    let mut matcher = config.build_matcher()?;
+
+    logln!("[@] Onion V3 Address Generator");
+    logln!("[@] Crypto backend: {}", crypto_backend());
+
+    let mut shared = None;
+    if let Some(dir) = matches.get_one::<String>("shared-dir") {
+        let mut dir = SharedDir::open(dir)?;
+        matcher = dir.claim(&matcher, *matches.get_one::<usize>("claim").unwrap())?;
+        logln!("[@] Joined shared directory as {}", dir.host_id());
+        shared = Some(dir);
+    }
+    logln!("[@] Searching for: {}", matcher);
+
+    let single_threaded = matches.get_flag("single-threaded");
+    if !matches.get_flag("no-calibrate") {
+        let workers = if single_threaded { 1 } else { config.num_workers };
+        let rate = print_difficulty(&matcher, workers)?;
+        let threshold = matches.get_one::<Duration>("confirm-over").unwrap().as_secs_f64();
+        if !matches.get_flag("yes") {
+            // Patterns claimed from a shared directory must stay as they are
+            match confirm_difficulty(&matcher, rate, threshold, shared.is_none())? {
+                Some(confirmed) if confirmed.to_string() != matcher.to_string() => {
+                    matcher = confirmed;
+                    logln!("[@] Searching for: {}", matcher);
+                }
+                Some(_) => {}
+                None => {
+                    logln!("[!] Search cancelled");
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    if matches.get_flag("matcher-bench") {
+        matcher = pick_match_engine(matcher);
+    }
+
+    let schedule = match file_config.schedule.is_empty() {
+        true => None,
+        false if single_threaded || shared.is_some() => {
+            return Err(anyhow::anyhow!("[[schedule]] switches the patterns of the worker pool; drop --single-threaded and --shared-dir"))
+                .with_code(ErrorCode::InvalidConfig);
+        }
+        false => Some(PatternSchedule::from_config(&file_config, matcher.clone())?),
+    };
+
+    let seccomp = matches.get_flag("seccomp");
+    // Check if single-threaded mode is requested
+    if single_threaded {
+        if matches.get_flag("confine") {
+            confine(&matches, &file_config)?;
+        }
+        if seccomp {
+            apply_seccomp()?;
+        }
+        return run_single_threaded_with_input(&matcher, matches.get_one::<String>("stats-file").map(Path::new));
     }
+    let started = Instant::now();
 
-    println!("[@] Using {} worker threads", config.num_workers);
+    // Setup multi-threaded configuration
+    if let Some(interval) = matches.get_one::<Duration>("update-interval") {
+        config = config.with_update_interval(interval.as_secs());
+    }
+
+    logln!("[@] Using {} worker threads", config.num_workers);
 
     // Start worker pool
-    let mut pool = WorkerPool::new(config);
-    pool.start()?;
+    let progress_json = matches.get_flag("progress-json");
+    let stdin_patterns = matches.get_flag("stdin-patterns");
+    // Typed patterns and their replies would be overdrawn by the live display
+    let show_progress = !progress_json
+        && !stdin_patterns
+        && !json_logs()
+        && !matches.get_flag("no-progress")
+        && atty::is(atty::Stream::Stdout);
+
+    let num_workers = config.num_workers;
+    let mut builder = WorkerPool::builder(config)
+        .matcher(matcher.clone())
+        .progress_display(show_progress)
+        .collect_results(false);
+    let sparkline = *matches.get_one::<usize>("sparkline").unwrap();
+    builder = if sparkline > 0 && atty::is(atty::Stream::Stdout) {
+        builder.sink(SparklineSink::new(sparkline))
+    } else {
+        builder.sink(StdoutSink)
+    };
+    if let Some(size) = matches.get_one::<String>("batch-size") {
+        let batch_size = batch_size(size, Path::new(matches.get_one::<String>("batch-state").unwrap()))?;
+        logln!("[@] Batched candidates: {} keys per random key", batch_size);
+        builder = builder.candidate_source(move |_| Ok(Box::new(BatchedKeys::new(batch_size)) as Box<dyn CandidateSource>));
+    }
+    let seed = match matches.get_one::<String>("seed-file") {
+        Some(path) => Some(Zeroizing::new(parse_master_seed(&Zeroizing::new(fs::read(path)?))?)),
+        None => None,
+    };
+    if let Some(seed) = &seed {
+        logln!("[@] Seeded run: seed {}, shards 0..{} (worker N searches shard N), keys from {}",
+                 seed_fingerprint(seed), num_workers, SEED_DERIVATION);
+        builder = builder.seed(**seed);
+    }
+    let first_counters = match (&seed, matches.get_one::<String>("start-at")) {
+        (Some(seed), Some(start_at)) => {
+            let counters = parse_start_at(start_at, seed, num_workers)?;
+            logln!("[@] Starting shards at counters {}", join_counters(&counters));
+            counters
+        }
+        _ => vec![0; num_workers],
+    };
+    builder = builder.seed_counters(first_counters.clone());
+    if let Some(recipient) = matches.get_one::<String>("seal-to") {
+        let recipient = parse_x25519_key(recipient)?;
+        use base64::Engine;
+        logln!("[@] Sealing private keys to {}", base64::engine::general_purpose::STANDARD.encode(recipient));
+        builder = builder.seal_to(recipient);
+    }
+    if matches.get_flag("public-only") {
+        logln!("[@] Public-key-only mode: private keys are discarded, not saved");
+        builder = builder.public_only(true);
+    }
+    if let Some(command) = matches.get_one::<String>("hsm-import") {
+        logln!("[@] Handing private keys to: {}", command);
+        builder = builder.token_import(TokenImport::new(command));
+    }
+    if let Some(path) = matches.get_one::<String>("journal") {
+        builder = builder.journal(ResultsJournal::open(path)?);
+    }
+    if let Some(fraction) = matches.get_one::<f64>("sample") {
+        let sample = CandidateSample::create(matches.get_one::<String>("sample-file").unwrap(), *fraction)?;
+        logln!("[@] Sampling 1 in {:.0} candidates to {}", 1.0 / fraction, sample.path().display());
+        builder = builder.sample(sample);
+    }
+    if let Some(limit) = matches.get_one::<usize>("max-restarts") {
+        builder = builder.restart_policy(RestartPolicy::Limit(*limit));
+    }
+    if let Some(timeout) = matches.get_one::<Duration>("stall-timeout") {
+        builder = builder.stall_timeout(*timeout);
+    }
+    if let Some(limit) = matches.get_one::<usize>("restart-stalled") {
+        builder = builder.stall_policy(StallPolicy::Restart(*limit));
+    }
+    let mut backoff = ErrorBackoff::default();
+    if let Some(initial) = matches.get_one::<Duration>("error-backoff") {
+        backoff.initial = *initial;
+    }
+    if let Some(max) = matches.get_one::<Duration>("error-backoff-max") {
+        backoff.max = *max;
+    }
+    builder = builder.error_backoff(backoff);
+    if matches.get_flag("cooperative") {
+        let duty_cycle = matches.get_one::<u8>("duty-cycle").copied().unwrap_or(DEFAULT_DUTY_CYCLE);
+        builder = builder.cooperative(Cooperative::new(duty_cycle));
+    }
+    if let Some(interval) = matches.get_one::<Duration>("self-check-interval") {
+        builder = builder.self_check_interval(*interval);
+    }
+    if progress_json {
+        builder = builder.sink(JsonProgressSink::new(&matcher));
+    }
+    if matches.get_flag("projection") {
+        builder = builder.sink(ProjectionSink::new(&matcher));
+    }
+    // Finds a [[route]] takes skip these
+    let mut unrouted: Vec<Box<dyn ResultSink>> = Vec::new();
+    if let Some(dir) = output_dir {
+        let mut sink = DirectorySink::new(dir)?;
+        if let Some(label) = matches.get_one::<String>("label").cloned() {
+            sink = sink.labels(move |_| Some(label.clone()));
+        } else if ask_label {
+            sink = sink.labels(prompt_label);
+        }
+        unrouted.push(Box::new(sink));
+    }
+    if let Some(path) = matches.get_one::<String>("results-file") {
+        builder = builder.sink(ResultsFileSink::new(path, rotation(&matches))?);
+    }
+    let mut attestation_dirs: Vec<PathBuf> = output_dir.map(PathBuf::from).into_iter().collect();
+    if let Some(shared) = shared {
+        attestation_dirs.push(shared.results_dir());
+        builder = builder.sink(SharedDirSink::new(shared, &matcher)?);
+    }
+    for notify in &file_config.notify {
+        unrouted.push(Box::new(ChatSink::new(notify)?));
+    }
+    attestation_dirs.extend(file_config.route.iter().filter_map(|route| route.output_dir.clone()));
+    builder = builder.sink(RoutingSink::from_config(&file_config.route)?.fallback(unrouted));
+    if let Some(otel) = &file_config.otel {
+        #[cfg(feature = "otel")]
+        {
+            builder = builder.sink(onion_generator::OtelSink::new(otel, &matcher));
+        }
+        #[cfg(not(feature = "otel"))]
+        anyhow::bail!("[otel] export to {} requires building with `--features otel`", otel.endpoint);
+    }
+    let mut pool = builder.build();
+    if let Some(path) = matches.get_one::<String>("attest-key") {
+        let attestor = Attestor::load_or_create(Path::new(path)).with_code(ErrorCode::AuthFailed)?;
+        logln!("[@] Attesting finds as instance {}", attestor.instance());
+        pool.add_sink(AttestationSink::new(attestor, pool.live_matcher(), pool.stats(), attestation_dirs));
+    }
+    setup_signal_handler(pool.stop_handle())?;
+    // Workers, and whatever starts later, inherit the confinement
+    if matches.get_flag("confine") {
+        confine(&matches, &file_config)?;
+    }
+    pool.start().with_code(ErrorCode::BackendInit)?;
+    let schedule = schedule.map(|schedule| schedule.spawn(pool.live_matcher(), pool.stop_handle()));
 
-    // Start input monitoring thread
-    start_input_monitor();
+    let control = match matches.get_one::<String>("control-socket") {
+        #[cfg(any(unix, windows))]
+        Some(path) => {
+            let handle =
+                spawn_control_socket(path, pool.live_matcher(), pool.snapshots(), pool.pause_handle(), pool.stop_handle())?;
+            logln!("[i] Control socket: {}", path);
+            Some(handle)
+        }
+        #[cfg(not(any(unix, windows)))]
+        Some(_) => anyhow::bail!("--control-socket requires a Unix or Windows platform"),
+        None => None,
+    };
+    if seccomp {
+        apply_seccomp()?;
+    }
+
+    if stdin_patterns {
+        spawn_stdin_patterns(pool.live_matcher(), pool.snapshots(), pool.pause_handle(), pool.stop_handle())?;
+        logln!("[i] Type a word to add it as a prefix, a control command, or Enter for the current status");
+    } else if !ask_label {
+        // The progress display already shows live counts
+        let snapshots = pool.snapshots();
+        let status = move || {
+            let snapshot = snapshots.latest();
+            (snapshot.generated, snapshot.found)
+        };
+        start_input_monitor(status, Some(pool.pause_handle()), !show_progress);
+    }
 
     // Run the main loop
     let result = pool.run();
 
     // Shutdown
     if RUNNING.load(Ordering::Relaxed) {
-        println!("[!] Shutting down...");
+        logln!("[!] Shutting down...");
     }
     
+    let stats = pool.stats();
+    let worker_stats = pool.worker_stats().to_vec();
+    // Patterns may have changed through the control socket
+    let matcher = pool.live_matcher().current();
     pool.shutdown()?;
+    if let Some(control) = control {
+        let _ = control.join();
+    }
+    if let Some(schedule) = schedule {
+        let _ = schedule.join();
+    }
+    if let Some(seed) = &seed {
+        let next_counters: Vec<u64> = first_counters
+            .iter()
+            .zip(&worker_stats)
+            .map(|(first, stats)| first + stats.get().0 as u64)
+            .collect();
+        logln!("[i] Seeded shards stopped before counters {}; continue with --start-at {}",
+                 join_counters(&next_counters), join_counters(&next_counters));
+        if let Some(dir) = output_dir {
+            write_run_metadata(Path::new(dir), seed, &first_counters, &next_counters, &matcher)?;
+        }
+    }
+    write_summary(&mut LogWriter::new(), &stats, matcher.expected_attempts(), started.elapsed())?;
+    if let Some(path) = matches.get_one::<String>("stats-file") {
+        record_usage(Path::new(path), num_workers, &stats, started.elapsed());
+    }
     result
 }
 
-fn setup_signal_handler() {
+/// Append the totals of a finished run to the `--stats-file`
+///
+/// A failure is only logged, as the run itself has succeeded.
+fn record_usage(path: &Path, workers: usize, stats: &GenerationStats, elapsed: Duration) {
+    let (generated, found) = stats.get();
+    let record = UsageRecord::new(workers, generated, found, elapsed);
+    match append_usage(path, &record) {
+        Ok(()) => logln!("[i] Run totals appended to {}", path.display()),
+        Err(e) => logln!("[!] Failed to record run totals: {:#}", e),
+    }
+}
+
+/// Rotation of the results and log files from the `--rotate-*` options
+fn rotation(matches: &clap::ArgMatches) -> Rotation {
+    Rotation {
+        max_bytes: matches.get_one::<u64>("rotate-size").map(|mib| mib * 1024 * 1024),
+        max_age: matches.get_one::<Duration>("rotate-every").copied(),
+        keep: *matches.get_one::<usize>("rotate-keep").unwrap(),
+        compress: matches.get_flag("rotate-compress"),
+    }
+}
+
+/// Restrict file writes for `--confine` to the directories the run writes to
+fn confine(matches: &clap::ArgMatches, file_config: &FileConfig) -> Result<()> {
+    let mut dirs: Vec<PathBuf> = ["output-dir", "shared-dir"]
+        .iter()
+        .filter_map(|id| matches.get_one::<String>(id))
+        .map(PathBuf::from)
+        .collect();
+    dirs.extend(file_config.route.iter().filter_map(|route| route.output_dir.clone()));
+    // The control socket is created, and removed again, in its directory, the journal grows in
+    // place, and rotation renames the results and log files next to themselves
+    for file in ["control-socket", "journal", "results-file", "log-file", "stats-file"].iter().filter_map(|id| matches.get_one::<String>(id)) {
+        let parent = Path::new(file).parent().filter(|parent| !parent.as_os_str().is_empty());
+        dirs.push(parent.unwrap_or(Path::new(".")).to_path_buf());
+    }
+
+    if confine_writes(&dirs).with_code(ErrorCode::BackendInit)? {
+        let names: Vec<String> = dirs.iter().map(|dir| dir.display().to_string()).collect();
+        logln!("[@] File writes confined to: {}", if names.is_empty() { "nothing".to_string() } else { names.join(", ") });
+    } else {
+        logln!("[!] Landlock is not available here; only output path checks confine writes");
+    }
+    Ok(())
+}
+
+/// Install the `--seccomp` filter once everything the run needs at startup is open
+fn apply_seccomp() -> Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        onion_generator::apply_seccomp_filter().with_code(ErrorCode::BackendInit)?;
+        logln!("[@] Seccomp filter installed");
+        Ok(())
+    }
+    #[cfg(not(target_os = "linux"))]
+    anyhow::bail!("--seccomp is only supported on Linux");
+}
+
+/// Measure the key rate briefly and show how hard each pattern is at that rate
+fn print_difficulty(matcher: &Matcher, workers: usize) -> Result<f64> {
+    logln!("[@] Calibrating for {:.1}s...", CALIBRATION_TIME.as_secs_f64());
+    let rate = calibrate_parallel_rate(CALIBRATION_TIME, workers).with_code(ErrorCode::BackendInit)?;
+    logln!("[@] Calibrated rate: ~{:.0} keys/s with {} worker threads", rate, workers);
+    write_difficulty_table(&mut LogWriter::new(), matcher, rate)?;
+    if let Some(expected) = matcher.expected_attempts() {
+        logln!("[@] Median time to the first match: {}", format_eta(median_seconds(expected, rate)));
+    }
+    Ok(rate)
+}
+
+/// Time every matcher engine on `matcher`'s patterns and switch it to the fastest
+fn pick_match_engine(matcher: Matcher) -> Matcher {
+    logln!("[@] Timing matcher engines for {:.1}s each...", MATCHER_BENCH_TIME.as_secs_f64());
+    let rates = bench_match_engines(&matcher, MATCHER_BENCH_TIME);
+    for (engine, rate) in &rates {
+        let current = if *engine == matcher.engine() { " (default)" } else { "" };
+        logln!("[i]   {:<13} {:>12.0} candidates/s{}", engine.as_str(), rate, current);
+    }
+    let Some(&(fastest, _)) = rates.first() else {
+        return matcher;
+    };
+    logln!("[@] Matching with {}", fastest);
+    matcher.with_engine(fastest)
+}
+
+/// Ask on the terminal whether to start a search whose median time to a match exceeds `threshold` seconds
+///
+/// With `offer_alternatives`, cheaper variants of each hard pattern are listed
+/// and can be picked instead. Returns the patterns to search for, or `None` if
+/// the user declined. Always agrees when there is no one to ask, so scripted
+/// runs are not blocked.
+fn confirm_difficulty(matcher: &Matcher, rate: f64, threshold: f64, offer_alternatives: bool) -> Result<Option<Matcher>> {
+    // Regexes have no known difficulty
+    let Some(expected) = matcher.expected_attempts() else {
+        return Ok(Some(matcher.clone()));
+    };
+    let median = median_seconds(expected, rate);
+    if median <= threshold || json_logs() || !atty::is(atty::Stream::Stdin) || !atty::is(atty::Stream::Stdout) {
+        return Ok(Some(matcher.clone()));
+    }
+
+    let mut alternatives = Vec::new();
+    if offer_alternatives {
+        for (index, spec) in matcher.includes().iter().enumerate() {
+            let hard = spec.expected_attempts().is_some_and(|expected| median_seconds(expected, rate) > threshold);
+            if hard {
+                alternatives.extend(suggest_alternatives(spec).into_iter().map(|alternative| (index, alternative)));
+            }
+        }
+    }
+    if !alternatives.is_empty() {
+        println!("[i] Cheaper alternatives:");
+        for (number, (index, alternative)) in alternatives.iter().enumerate() {
+            let expected = alternative.expected_attempts().unwrap_or(f64::INFINITY);
+            println!("    {}. {} instead of {}: median {}", number + 1, alternative,
+                     matcher.includes()[*index], format_eta(median_seconds(expected, rate)));
+        }
+    }
+
+    if !median.is_finite() {
+        print!("[!] No address can match these patterns. Start anyway? ");
+    } else {
+        print!("[!] Half of all searches like this take longer than {}. Start anyway? ", format_eta(median));
+    }
+    if alternatives.is_empty() {
+        print!("[y/N] ");
+    } else {
+        print!("[y/N/1-{}] ", alternatives.len());
+    }
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    let answer = answer.trim().to_lowercase();
+
+    if answer == "y" || answer == "yes" {
+        return Ok(Some(matcher.clone()));
+    }
+    let Some((index, alternative)) = answer.parse::<usize>().ok()
+        .and_then(|number| alternatives.get(number.wrapping_sub(1)))
+    else {
+        return Ok(None);
+    };
+    let mut includes = matcher.includes().to_vec();
+    includes[*index] = alternative.clone();
+    Ok(Some(Matcher::new(includes, matcher.excludes().to_vec())))
+}
+
+/// Record how a seeded run's shards were searched so it can be reproduced, audited or continued
+///
+/// Shard N searched counters `starts[N]..ends[N]`.
+fn write_run_metadata(dir: &Path, seed: &[u8; 32], starts: &[u64], ends: &[u64], matcher: &Matcher) -> Result<()> {
+    let shards: Vec<_> = starts
+        .iter()
+        .zip(ends)
+        .enumerate()
+        .map(|(shard, (start, end))| serde_json::json!({ "shard": shard, "counter_start": start, "counter_end": end }))
+        .collect();
+    let metadata = serde_json::json!({
+        "seed_fingerprint": seed_fingerprint(seed),
+        "derivation": SEED_DERIVATION,
+        "patterns": matcher.to_string(),
+        "finished": chrono::Local::now().to_rfc3339(),
+        "shards": shards,
+    });
+
+    let path = dir.join("run.json");
+    fs::write(&path, serde_json::to_string_pretty(&metadata)? + "\n")?;
+    logln!("[i] Run metadata written to {}", path.display());
+    Ok(())
+}
+
+/// Batch size from `--batch-size`, profiling the sizes on first use of `auto` on a machine
+fn batch_size(value: &str, state: &Path) -> Result<usize> {
+    if value != "auto" {
+        return match value.parse::<usize>() {
+            Ok(size) if size > 0 => Ok(size),
+            _ => Err(anyhow::anyhow!("Invalid batch size {:?}: expected a positive number or auto", value))
+                .with_code(ErrorCode::InvalidConfig),
+        };
+    }
+    if let Some(tuning) = stored_batch_tuning(state)? {
+        logln!("[@] Batch size {} from {} ({:.0} keys/sec when measured)", tuning.batch_size, state.display(), tuning.keys_per_sec);
+        return Ok(tuning.batch_size);
+    }
+    logln!("[@] Profiling batch sizes {:?}...", BATCH_SIZES);
+    let tuning = tune_batch_size(BATCH_TUNING_TIME)?;
+    logln!("[@] Batch size {} is fastest at {:.0} keys/sec per thread, saved to {}", tuning.batch_size, tuning.keys_per_sec, state.display());
+    store_batch_tuning(state, &tuning)?;
+    Ok(tuning.batch_size)
+}
+
+/// The first counter of each of `shards` shards from `--start-at`
+///
+/// Takes one counter for every shard, one per shard separated by commas, or
+/// the path of a `run.json` to continue where that run stopped.
+fn parse_start_at(value: &str, seed: &[u8; 32], shards: usize) -> Result<Vec<u64>> {
+    let counters = if Path::new(value).is_file() {
+        let metadata: serde_json::Value = serde_json::from_str(&fs::read_to_string(value)?)
+            .map_err(|e| anyhow::anyhow!("{} is not a run.json: {}", value, e))
+            .with_code(ErrorCode::InvalidConfig)?;
+        if metadata["seed_fingerprint"].as_str() != Some(seed_fingerprint(seed).as_str()) {
+            return Err(anyhow::anyhow!("{} is from a run with another master seed", value)).with_code(ErrorCode::InvalidConfig);
+        }
+        let mut ends: Vec<(u64, u64)> = metadata["shards"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|shard| Some((shard["shard"].as_u64()?, shard["counter_end"].as_u64()?)))
+            .collect();
+        ends.sort_unstable();
+        if ends.iter().enumerate().any(|(index, &(shard, _))| shard != index as u64) {
+            return Err(anyhow::anyhow!("{} does not list every shard from 0", value)).with_code(ErrorCode::InvalidConfig);
+        }
+        ends.into_iter().map(|(_, end)| end).collect()
+    } else {
+        let counters = value
+            .split(',')
+            .map(|counter| counter.trim().parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| anyhow::anyhow!("--start-at takes counters or a run.json, not {:?}", value))
+            .with_code(ErrorCode::InvalidConfig)?;
+        match counters[..] {
+            [counter] => vec![counter; shards],
+            _ => counters,
+        }
+    };
+    if counters.len() != shards {
+        return Err(anyhow::anyhow!(
+            "--start-at gives {} shards but there are {} workers; run with -w {}",
+            counters.len(),
+            shards,
+            counters.len()
+        ))
+        .with_code(ErrorCode::InvalidConfig);
+    }
+    Ok(counters)
+}
+
+fn join_counters(counters: &[u64]) -> String {
+    counters.iter().map(|counter| counter.to_string()).collect::<Vec<_>>().join(",")
+}
+
+fn run_bench(workers: usize, duration: Duration, history: Option<&Path>) -> Result<()> {
+    logln!("[@] Benchmarking {} threads for {}s...", workers, duration.as_secs());
+    let record = bench::run_benchmark(workers, duration)?;
+
+    logln!("[@] Hardware: {}", record.hardware);
+    logln!("[@] Crypto backend: {}", record.backend);
+    logln!("[@] Features: {}", record.features.join(", "));
+    logln!("[√] {:.0} keys/s ({:.0} keys/s per thread)",
+             record.keys_per_sec, record.keys_per_sec / workers.max(1) as f64);
+
+    if let Some(path) = history {
+        let past = bench::load_history(path)?;
+        match bench::comparable(&past, &record) {
+            (Some(previous), Some(best)) => {
+                logln!("[i] vs previous run ({}): {:+.1}%", previous.timestamp,
+                         bench::percent_change(previous.keys_per_sec, record.keys_per_sec));
+                logln!("[i] vs best run ({}): {:+.1}%", best.timestamp,
+                         bench::percent_change(best.keys_per_sec, record.keys_per_sec));
+            }
+            _ => logln!("[i] No earlier runs on this hardware with {} threads", workers),
+        }
+        bench::append_history(path, &record)?;
+        logln!("[i] Result appended to {}", path.display());
+    }
+    Ok(())
+}
+
+fn run_stats(args: &clap::ArgMatches) -> Result<()> {
+    match args.subcommand() {
+        Some(("history", sub)) => {
+            let path = Path::new(sub.get_one::<String>("file").unwrap());
+            let records = load_usage(path)?;
+            if records.is_empty() {
+                logln!("[i] No runs recorded in {} yet; add --stats-file to a search", path.display());
+                return Ok(());
+            }
+            let mut out = Vec::new();
+            write_usage_history(&mut out, &records, *sub.get_one::<usize>("last").unwrap())?;
+            print_plain(String::from_utf8_lossy(&out).trim_end());
+            Ok(())
+        }
+        _ => unreachable!("stats requires a subcommand"),
+    }
+}
+
+fn run_suggest(args: &clap::ArgMatches) -> Result<()> {
+    let budget = *args.get_one::<Duration>("time").unwrap();
+    let rate = match args.get_one::<f64>("rate") {
+        Some(rate) => *rate,
+        None => {
+            let workers = args.get_one::<usize>("workers").copied()
+                .unwrap_or_else(|| GeneratorConfig::new(Vec::new()).num_workers);
+            logln!("[@] Calibrating for {:.1}s...", CALIBRATION_TIME.as_secs_f64());
+            let rate = calibrate_parallel_rate(CALIBRATION_TIME, workers).with_code(ErrorCode::BackendInit)?;
+            logln!("[@] Calibrated rate: ~{:.0} keys/s with {} worker threads", rate, workers);
+            rate
+        }
+    };
+
+    let length = max_prefix_length(rate, budget);
+    if length == 0 {
+        logln!("[!] Not even one character can be found in {} at {:.0} keys/s", format_duration(budget), rate);
+        return Ok(());
+    }
+    logln!("[i] Prefixes of up to {} characters are found within {} in half of all searches", length, format_duration(budget));
+    let words = suggest_prefixes(length, *args.get_one::<usize>("count").unwrap(), &mut rand::thread_rng());
+    if words.is_empty() {
+        logln!("[i] No built-in words are that short; try a longer --time");
+    }
+    for word in words {
+        let median = median_seconds(32f64.powi(word.len() as i32), rate);
+        print_plain(&format!("{:<10} median {}", word, format_eta(median)));
+    }
+    Ok(())
+}
+
+fn run_harvest(args: &clap::ArgMatches) -> Result<()> {
+    let mut config = harvest::HarvestConfig::new(args.get_one::<String>("output").unwrap())
+        .with_top(*args.get_one::<usize>("top").unwrap())
+        .with_duration(args.get_one::<Duration>("duration").copied());
+    if let Some(workers) = args.get_one::<usize>("workers") {
+        config = config.with_workers(*workers);
+    }
+    if let Some(command) = args.get_one::<String>("score-script") {
+        config = config.with_scorer(harvest::Scorer::Script(command.clone()));
+    }
+    if let Some(path) = args.get_one::<String>("words") {
+        let words = match path.is_empty() {
+            true => harvest::WordScorer::english(),
+            false => harvest::WordScorer::new(read_patterns_file(path)?.iter().map(String::as_str))
+                .with_code(ErrorCode::InvalidConfig)?,
+        };
+        config = config.with_scorer(harvest::Scorer::Words(words));
+    }
+    config = config.with_min_score(args.get_one::<f64>("min-score").copied());
+
+    logln!("[@] Onion V3 Address Generator");
+    match &config.scorer {
+        harvest::Scorer::Aesthetic => logln!("[@] Harvesting with the built-in aesthetics score"),
+        harvest::Scorer::Script(command) => logln!("[@] Harvesting with score script {:?}", command),
+        harvest::Scorer::Words(words) => logln!("[@] Harvesting with the word score over {} words", words.len()),
+    }
+    if let Some(min_score) = config.min_score {
+        logln!("[@] Reporting every address scoring at least {:.1}", min_score);
+    }
+    logln!("[@] Keeping the top {} addresses in {}", config.top, config.output.display());
+    logln!("[@] Using {} worker threads", config.num_workers);
+
+    let stop = Arc::new(AtomicBool::new(false));
+    setup_signal_handler(Arc::clone(&stop))?;
+    let started = Instant::now();
+    let stats = GenerationStats::new();
+    let board = harvest::harvest(&config, &stats, &stop)?;
+
+    logln!("[√] Harvest finished after {:.0}s and {} addresses", started.elapsed().as_secs_f64(), stats.get().0);
+    for (rank, entry) in board.entries().iter().enumerate() {
+        logln!("    {:>3}. {:>6.1}  {}", rank + 1, entry.score, entry.result.hostname);
+    }
+    logln!("[i] Keys are in {}", config.output.display());
+    Ok(())
+}
+
+/// The command line options equivalent to `profile`
+fn profile_args(profile: &Profile) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut push = |option: &str, value: &dyn std::fmt::Display| args.push(format!("--{}={}", option, value));
+    let lists: [(&str, &Vec<String>); 9] = [
+        ("prefix", &profile.prefixes),
+        ("suffix", &profile.suffixes),
+        ("contains", &profile.contains),
+        ("prefix-suffix", &profile.prefix_suffixes),
+        ("at", &profile.positioned),
+        ("classes", &profile.classes),
+        ("regex", &profile.regexes),
+        ("expr", &profile.expressions),
+        ("exclude", &profile.excludes),
+    ];
+    for (option, values) in lists {
+        values.iter().for_each(|value| push(option, value));
+    }
+    for (option, lengths) in [("run", &profile.runs), ("doubled", &profile.doubled), ("palindrome", &profile.palindromes)] {
+        lengths.iter().for_each(|length| push(option, length));
+    }
+    for (option, value) in [("workers", profile.workers), ("max-restarts", profile.max_restarts)] {
+        if let Some(value) = value {
+            push(option, &value);
+        }
+    }
+    let durations = [
+        ("stall-timeout", profile.stall_timeout),
+        ("self-check-interval", profile.self_check_interval),
+        ("confirm-over", profile.confirm_over),
+    ];
+    for (option, duration) in durations {
+        if let Some(duration) = duration {
+            push(option, &format_duration(duration));
+        }
+    }
+    let paths = [
+        ("output-dir", &profile.output_dir),
+        ("results-file", &profile.results_file),
+        ("journal", &profile.journal),
+        ("log-file", &profile.log_file),
+    ];
+    for (option, path) in paths {
+        if let Some(path) = path {
+            push(option, &path.display());
+        }
+    }
+    if profile.fix_patterns {
+        args.push("--fix-patterns".to_string());
+    }
+    args
+}
+
+/// Value parser for a time option such as `90s` or `2h30m`; plain numbers are in `unit`
+fn duration_arg(unit: Duration, min: Duration) -> impl Fn(&str) -> Result<Duration, String> + Clone + Send + Sync + 'static {
+    move |text| match parse_duration(text, unit) {
+        Ok(duration) if duration < min => Err(format!("must be at least {}", format_duration(min))),
+        Ok(duration) => Ok(duration),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn run_serve(args: &clap::ArgMatches) -> Result<()> {
+    let file_config = match args.get_one::<String>("config") {
+        Some(path) => FileConfig::load(path)?,
+        None => FileConfig::default(),
+    };
+    ConfigCheck::new().file(&file_config).check()?;
+    let serve_config = file_config.serve;
+
+    let listen = args.get_one::<String>("listen").cloned()
+        .or(serve_config.listen)
+        .unwrap_or_else(|| DEFAULT_LISTEN.to_string());
+    let token = match args.get_one::<String>("token-file") {
+        Some(path) => Some(fs::read_to_string(path).with_code(ErrorCode::AuthFailed)?.trim().to_string()),
+        None => serve_config.token,
+    };
+    let tls = match (args.get_one::<String>("tls-cert"), args.get_one::<String>("tls-key")) {
+        (Some(cert), Some(key)) => Some((PathBuf::from(cert), PathBuf::from(key))),
+        _ => serve_config.tls_cert.zip(serve_config.tls_key),
+    };
+
+    let mut config = GeneratorConfig::new(Vec::new());
+    if let Some(workers) = args.get_one::<usize>("workers") {
+        config = config.with_workers(*workers);
+    }
+
+    let authenticated = token.as_deref().is_some_and(|token| !token.is_empty());
+    let mut server = Server::bind(&listen, config)?.with_token(token);
+    if let Some((cert, key)) = tls {
+        server = server.with_tls(&cert, &key)?;
+    }
+
+    logln!("[@] Onion V3 Address Generator");
+    logln!("[@] Crypto backend: {}", crypto_backend());
+    logln!("[@] Listening on {}://{}", if server.is_tls() { "https" } else { "http" }, server.local_addr()?);
+    if !authenticated {
+        logln!("[!] No token configured: anyone who can reach the listener can start jobs and read keys");
+    }
+
+    if let Some(prefixes) = args.get_many::<String>("prefixes") {
+        server.start_job(JobRequest {
+            prefixes: prefixes.cloned().collect(),
+            ..Default::default()
+        })?;
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+    setup_signal_handler(Arc::clone(&stop))?;
+    server.run(&stop)?;
+    logln!("[√] Server stopped");
+    Ok(())
+}
+
+fn run_gen_vectors(count: usize, output: Option<&Path>) -> Result<()> {
+    let vectors = TestVectors::generate(&VECTOR_MASTER_SEED, count)?;
+    let json = serde_json::to_string_pretty(&vectors)? + "\n";
+    match output {
+        Some(path) => {
+            fs::write(path, json)
+                .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))
+                .with_code(ErrorCode::OutputNotWritable)?;
+            logln!("[√] Wrote {} test vectors to {}", count, path.display());
+        }
+        None => print_plain(json.trim_end()),
+    }
+    Ok(())
+}
+
+fn run_seal_keygen(secret_path: &Path) -> Result<()> {
+    let keypair = generate_seal_keypair();
+    writeln!(create_secret_file(secret_path)?, "{}", keypair.secret_key.as_str())?;
+
+    logln!("[√] Secret key written to {}", secret_path.display());
+    logln!("[i] Seal results to it with: --seal-to {}", keypair.public_key);
+    Ok(())
+}
+
+fn run_unseal(sealed: &str, key_path: &Path) -> Result<()> {
+    use base64::Engine;
+    let engine = base64::engine::general_purpose::STANDARD;
+    let secret_key = fs::read_to_string(key_path)
+        .map_err(anyhow::Error::from)
+        .and_then(|text| parse_x25519_key(&Zeroizing::new(text)))
+        .with_code(ErrorCode::AuthFailed)?;
+    let secret_key = Zeroizing::new(secret_key);
+
+    let sealed_path = Path::new(sealed);
+    if sealed_path.is_file() {
+        let key = unseal(&secret_key, &fs::read(sealed_path)?).with_code(ErrorCode::AuthFailed)?;
+        let target = sealed_path.with_extension("");
+        create_secret_file(&target)?.write_all(&key)?;
+        logln!("[√] Private key written to {}", target.display());
+    } else {
+        let key = unseal(&secret_key, &engine.decode(sealed.trim())?).with_code(ErrorCode::AuthFailed)?;
+        logln!("Private Key (Base64 encoded):  {}", Zeroizing::new(engine.encode(key.as_slice())).as_str());
+    }
+    Ok(())
+}
+
+fn run_standby(pool: &str, count: usize, recipient: &str) -> Result<()> {
+    let recipient = parse_x25519_key(recipient).with_code(ErrorCode::InvalidConfig)?;
+    let pool = StandbyPool::open(pool)?;
+    let added = pool.fill(count, &recipient)?;
+    logln!("[√] Added {} key(s); {} ready to claim", added, pool.ready()?);
+    Ok(())
+}
+
+fn run_claim(pool: &str, key_path: &Path, output_dir: Option<&Path>) -> Result<()> {
+    let secret_key = fs::read_to_string(key_path)
+        .map_err(anyhow::Error::from)
+        .and_then(|text| parse_x25519_key(&Zeroizing::new(text)))
+        .with_code(ErrorCode::AuthFailed)?;
+    let secret_key = Zeroizing::new(secret_key);
+
+    let pool = StandbyPool::open(pool)?;
+    let Some(result) = pool.claim(&secret_key)? else {
+        return Err(anyhow::anyhow!("The standby pool is empty; fill it with standby")).with_code(ErrorCode::Other);
+    };
+    match output_dir {
+        Some(dir) => {
+            DirectorySink::new(dir)?.found(&result)?;
+            print_plain(&result.hostname);
+        }
+        None => print_result(&result),
+    }
+    logln!("[i] {} key(s) left in the pool", pool.ready()?);
+    Ok(())
+}
+
+/// Create a new file readable only by the current user, refusing to overwrite
+fn create_secret_file(path: &Path) -> Result<fs::File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path).map_err(|e| anyhow::anyhow!("Failed to create {}: {}", path.display(), e))
+}
+
+/// Ask what a find is for; Enter leaves it without a label
+fn prompt_label(result: &OnionResult) -> Option<String> {
+    print!("[?] Label for {} (Enter to skip): ", result.hostname);
+    io::stdout().flush().ok()?;
+    let mut label = String::new();
+    io::stdin().read_line(&mut label).ok()?;
+    Some(label)
+}
+
+fn run_list(dir: &Path) -> Result<()> {
+    let book = read_address_book(dir)?;
+    if book.is_empty() {
+        logln!("[i] No addresses in {}", dir.display());
+        return Ok(());
+    }
+    for entry in &book {
+        let created = entry.created.map_or("-".to_string(), |created| created.format("%Y-%m-%d %H:%M").to_string());
+        let keys = entry.custody.map_or("public only", KeyCustody::as_str);
+        let fingerprint = decode_onion_address(&entry.hostname).map_or("-".to_string(), |key| key_fingerprint(&key));
+        print_plain(&format!(
+            "{}  {:<8}  {}  {:<11}  {}",
+            entry.hostname,
+            fingerprint,
+            created,
+            keys,
+            entry.label.as_deref().unwrap_or("-")
+        ));
+    }
+    Ok(())
+}
+
+fn run_verify_attestation(path: Option<&Path>) -> Result<()> {
+    let text = match path {
+        Some(path) => fs::read_to_string(path)?,
+        None => io::read_to_string(io::stdin())?,
+    };
+
+    let mut invalid = 0;
+    let mut valid = 0;
+    // Accept raw records as well as the generator's "[i] Attestation:" lines and JSON log records
+    for line in text.lines() {
+        let line = line.trim();
+        let line = line.strip_prefix("[i] Attestation:").unwrap_or(line).trim();
+        if !line.starts_with('{') {
+            continue;
+        }
+        let record = serde_json::from_str::<serde_json::Value>(line);
+        if let Ok(record) = &record {
+            // Other records of a --log-format json run
+            if record.get("level").is_some() && record.get("attestation").is_none() {
+                continue;
+            }
+        }
+        let attestation = record
+            .and_then(|mut record| match record.get_mut("attestation") {
+                Some(nested) => serde_json::from_value::<Attestation>(nested.take()),
+                None => serde_json::from_value::<Attestation>(record),
+            })
+            .map_err(anyhow::Error::from);
+        match attestation.and_then(|a| a.verify().map(|_| a)) {
+            Ok(attestation) => {
+                valid += 1;
+                logln!("[√] {} ({}, {} attempts) by {}",
+                         attestation.hostname, attestation.pattern, attestation.attempts, attestation.instance);
+            }
+            Err(e) => {
+                invalid += 1;
+                logln!("[!] Invalid record: {}", e);
+            }
+        }
+    }
+
+    logln!("[@] {} valid, {} invalid", valid, invalid);
+    if invalid > 0 {
+        anyhow::bail!("{} attestation records failed verification", invalid);
+    }
+    Ok(())
+}
+
+fn run_doctor(args: &clap::ArgMatches) -> Result<()> {
+    let mut doctor = Doctor::new();
+    let dirs: Vec<&String> = args.get_many::<String>("output-dir").unwrap_or_default().collect();
+    if dirs.is_empty() {
+        doctor = doctor.output_dir(".");
+    }
+    for dir in dirs {
+        doctor = doctor.output_dir(dir);
+    }
+    if let Some(addr) = args.get_one::<String>("tor-control") {
+        doctor = doctor.tor_control(addr);
+    }
+
+    logln!("[@] Onion V3 Address Generator {}", env!("CARGO_PKG_VERSION"));
+    let findings = doctor.run();
+    for finding in &findings {
+        finding.log();
+    }
+    let problems = findings.iter().filter(|finding| finding.severity == Severity::Problem).count();
+    let warnings = findings.iter().filter(|finding| finding.severity == Severity::Warning).count();
+    if problems > 0 {
+        anyhow::bail!("{} problem{} found", problems, if problems == 1 { "" } else { "s" });
+    }
+    logln!("[@] No problems found, {} warning{}", warnings, if warnings == 1 { "" } else { "s" });
+    Ok(())
+}
+
+fn print_info() {
+    let cores = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+
+    logln!("[@] Onion V3 Address Generator {}", env!("CARGO_PKG_VERSION"));
+    logln!("[@] Crypto backend: {}", crypto_backend());
+    logln!("[@] Available CPU cores: {}", cores);
+}
+
+fn print_client_auth(address: &str, name: &str) -> Result<()> {
+    let auth = generate_client_auth(address)?;
+
+    logln!("[√] Client authorization keys generated!");
+    logln!("[i] Client: save as <ClientOnionAuthDir>/{}.auth_private", auth.address);
+    logln!("{}\n", auth.auth_private);
+    logln!("[i] Service: save as <HiddenServiceDir>/authorized_clients/{}.auth", name);
+    logln!("{}", auth.authorized_client);
+    Ok(())
+}
+
+fn run_rollover(address: &str, from: Option<&str>, to: Option<&str>) -> Result<()> {
+    let identity = parse_onion_address(address).with_code(ErrorCode::InvalidPattern)?.public_key;
+    let from = from.map(parse_date).transpose()?.unwrap_or_else(chrono::Utc::now);
+    let to = match to {
+        Some(to) => parse_date(to)?,
+        None => from + chrono::Duration::days(7),
+    };
+    if to < from {
+        return Err(anyhow::anyhow!("--to is before --from")).with_code(ErrorCode::InvalidConfig);
+    }
+
+    logln!("[@] Blinded keys of {} for {} to {}", address, from.format("%Y-%m-%d %H:%M UTC"), to.format("%Y-%m-%d %H:%M UTC"));
+    logln!("[i] Time periods last {} minutes and start at 12:00 UTC", TIME_PERIOD_LENGTH);
+    for period in rollover(&identity, from, to)? {
+        period.log();
+    }
+    Ok(())
+}
+
+/// A UTC date, either `YYYY-MM-DD` for its midnight or an RFC 3339 timestamp
+fn parse_date(text: &str) -> Result<chrono::DateTime<chrono::Utc>> {
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return Ok(date.and_time(chrono::NaiveTime::MIN).and_utc());
+    }
+    chrono::DateTime::parse_from_rfc3339(text)
+        .map(|time| time.with_timezone(&chrono::Utc))
+        .map_err(|_| anyhow::anyhow!("{:?} is not a date: use YYYY-MM-DD or RFC 3339", text))
+        .with_code(ErrorCode::InvalidConfig)
+}
+
+fn run_brain(salt: &str) -> Result<()> {
+    eprintln!("[!] WARNING: brain onion mode derives the key from your passphrase.");
+    eprintln!("[!] Anyone who guesses the passphrase gets the private key, and guesses");
+    eprintln!("[!] can be checked offline against the public address forever.");
+    eprintln!("[!] Use a long, unique, randomly generated passphrase.\n");
+
+    let mut passphrase = if atty::is(atty::Stream::Stdin) {
+        rpassword::prompt_password("Passphrase: ")?
+    } else {
+        let mut line = String::new();
+        io::stdin().read_line(&mut line)?;
+        line.trim_end_matches(['\r', '\n']).to_string()
+    };
+
+    logln!("[@] Deriving key with Argon2id ({} MiB, {} iterations)...",
+             brain::BRAIN_MEMORY_KIB / 1024, brain::BRAIN_ITERATIONS);
+    let result = brain::generate_brain_onion(&passphrase, salt);
+    passphrase.zeroize();
+
+    print_result(&result?);
+    Ok(())
+}
+
+/// Request a graceful stop on the first Ctrl-C and exit immediately on the second
+///
+/// A closed stdout requests the same stop unless `--on-stdout-closed continue` is given.
+fn setup_signal_handler(stop: Arc<AtomicBool>) -> Result<()> {
+    let closed = Arc::clone(&stop);
+    on_stdout_closed(move || closed.store(true, Ordering::Relaxed));
     ctrlc::set_handler(move || {
-        println!("\n[!] Received interrupt signal, shutting down...");
-        RUNNING.store(false, Ordering::Relaxed);
-        std::process::exit(0);
-    }).expect("Error setting Ctrl-C handler");
+        if !RUNNING.swap(false, Ordering::Relaxed) {
+            std::process::exit(130);
+        }
+        logln!("\n[!] Received interrupt signal, shutting down...");
+        stop.store(true, Ordering::Relaxed);
+    })?;
+    Ok(())
 }
 
-fn start_input_monitor() {
-    thread::spawn(|| {
+/// Print the (generated, found) `status` on Enter if `show_status`, and toggle `pause` on `p` and Enter
+fn start_input_monitor(
+    status: impl Fn() -> (u128, u128) + Send + 'static,
+    pause: Option<PauseHandle>,
+    show_status: bool,
+) {
+    let spawned = thread::Builder::new().name("onion-input".to_string()).spawn(move || {
         if !atty::is(atty::Stream::Stdin) {
-            println!("[!] Non-TTY environment detected. Keypress updates are disabled.\n");
+            logln!("[!] Non-TTY environment detected. Keypress updates are disabled.\n");
             return;
         }
 
-        println!("[i] Press Enter to see the current status:\n");
+        match (&pause, show_status) {
+            (Some(_), true) => logln!("[i] Press Enter to see the current status, or type p and Enter to pause:\n"),
+            (Some(_), false) => logln!("[i] Type p and Enter to pause:\n"),
+            (None, _) => logln!("[i] Press Enter to see the current status:\n"),
+        }
         
         let stdin = io::stdin();
         let mut input = String::new();
         
         while RUNNING.load(Ordering::Relaxed) {
             input.clear();
-            if stdin.read_line(&mut input).is_ok() {
-                let (generated, found) = onion_generator::get_stats();
-                let now = chrono::Local::now();
-                println!("[@] {}: Generated {} addresses, Found {} addresses", 
-                         now.format("%H:%M:%S"), generated, found);
+            if stdin.read_line(&mut input).is_err() {
+                continue;
+            }
+            match &pause {
+                Some(pause) if input.trim().eq_ignore_ascii_case("p") => {
+                    if pause.resume() {
+                        logln!("[@] Resumed");
+                    } else {
+                        pause.pause();
+                        logln!("[@] Paused, type p and Enter to resume");
+                    }
+                }
+                _ if show_status => {
+                    let (generated, found) = status();
+                    print_stats(generated, found);
+                }
+                _ => {}
             }
         }
     });
+
+    if let Err(e) = spawned {
+        eprintln!("[!] Failed to start input monitor: {}", e);
+    }
 }
 
-fn run_single_threaded_with_input(prefixes: &[String]) -> Result<()> {
+fn run_single_threaded_with_input(matcher: &Matcher, stats_file: Option<&Path>) -> Result<()> {
+    let started = Instant::now();
+    let stats = Arc::new(GenerationStats::new());
+    let stop = Arc::new(AtomicBool::new(false));
+    setup_signal_handler(Arc::clone(&stop))?;
+
     // Start input monitoring for single-threaded mode
-    start_input_monitor();
+    let status_stats = Arc::clone(&stats);
+    start_input_monitor(move || status_stats.get(), None, true);
     
     // Start stats reporting thread
-    thread::spawn(move || {
+    let reporter_stats = Arc::clone(&stats);
+    thread::Builder::new().name("onion-stats".to_string()).spawn(move || {
         loop {
             thread::sleep(Duration::from_secs(30));
             if !RUNNING.load(Ordering::Relaxed) {
                 break;
             }
             
-            let (generated, found) = onion_generator::get_stats();
-            let now = chrono::Local::now();
-            println!("[@] {}: Generated {} addresses, Found {} addresses", 
-                     now.format("%H:%M:%S"), generated, found);
+            let (generated, found) = reporter_stats.get();
+            print_stats(generated, found);
         }
-    });
+    })?;
 
-    run_single_threaded(prefixes)
+    run_single_threaded(matcher, &stats, &stop)?;
+    write_summary(&mut LogWriter::new(), &stats, matcher.expected_attempts(), started.elapsed())?;
+    if let Some(path) = stats_file {
+        record_usage(path, 1, &stats, started.elapsed());
+    }
+    Ok(())
 }
 
 // Add atty and ctrlc dependencies to Cargo.toml
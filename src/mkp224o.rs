@@ -0,0 +1,162 @@
+use crate::trie::char_value;
+use crate::{ErrorCode, ResultExt};
+use anyhow::{anyhow, bail, Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// mkp224o options followed by a value, which is skipped along with them
+const VALUE_OPTIONS: &[&str] = &["-o", "-O", "-d", "-t", "-j", "-n", "-N", "-S", "-Y", "-p", "--checkpoint"];
+
+/// mkp224o options without a value
+const FLAG_OPTIONS: &str = "DqxvFzZBsTyPh";
+
+/// Long mkp224o options without a value
+const LONG_FLAGS: &[&str] = &["--skipnear", "--warnnear", "--rawyaml", "--help"];
+
+/// Filters written for mkp224o, as patterns of this crate
+///
+/// mkp224o matches its filters as prefixes of the address, or as regexes
+/// when it is built with regex support, which the caller says with `regex`.
+/// Prefixes are lowercased like any other pattern and must be base32.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Mkp224oFilters {
+    pub prefixes: Vec<String>,
+    pub regexes: Vec<String>,
+    /// Options of a command line that are not about filters, for the caller to report
+    pub ignored_options: Vec<String>,
+}
+
+impl Mkp224oFilters {
+    /// Filters of an mkp224o filter file, one per line
+    ///
+    /// Blank lines and lines starting with `#` or `//` are skipped, as
+    /// mkp224o does. `source` names the file in errors.
+    pub fn parse(text: &str, source: &str, regex: bool) -> Result<Self> {
+        let mut filters = Self::default();
+        for (number, line) in text.lines().enumerate() {
+            let filter = line.trim();
+            if filter.is_empty() || filter.starts_with('#') || filter.starts_with("//") {
+                continue;
+            }
+            filters.add(filter, regex).with_context(|| format!("{}:{}", source, number + 1))?;
+        }
+        Ok(filters)
+    }
+
+    /// Read an mkp224o filter file, see [`parse`](Self::parse)
+    pub fn read(path: impl AsRef<Path>, regex: bool) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read mkp224o filter file {}", path.display()))
+            .with_code(ErrorCode::InvalidConfig)?;
+        Self::parse(&text, &path.display().to_string(), regex)
+    }
+
+    /// Filters of an mkp224o command line, without the program name
+    ///
+    /// Takes the filters given as arguments, including everything after
+    /// `--`, and reads every `-f FILE`. Other mkp224o options are skipped with
+    /// their values and listed in [`ignored_options`](Self::ignored_options).
+    pub fn from_args<S: AsRef<str>>(args: &[S], regex: bool) -> Result<Self> {
+        let mut filters = Self::default();
+        let mut args = args.iter().map(AsRef::as_ref);
+        let mut options_done = false;
+        while let Some(arg) = args.next() {
+            if options_done || !arg.starts_with('-') {
+                filters.add(arg, regex)?;
+                continue;
+            }
+            if arg == "--" {
+                options_done = true;
+                continue;
+            }
+            if arg == "-f" {
+                let path = args.next().ok_or_else(|| anyhow!("mkp224o option -f needs a filter file"))?;
+                filters.extend(Self::read(path, regex)?);
+                continue;
+            }
+            if VALUE_OPTIONS.contains(&arg) {
+                let value = args.next().ok_or_else(|| anyhow!("mkp224o option {} needs a value", arg))?;
+                filters.ignored_options.push(format!("{} {}", arg, value));
+                continue;
+            }
+            let flags = arg.strip_prefix('-').filter(|flags| !flags.starts_with('-'));
+            match flags {
+                Some(flags) if flags.chars().all(|c| FLAG_OPTIONS.contains(c)) => filters.ignored_options.push(arg.to_string()),
+                _ if LONG_FLAGS.contains(&arg) => filters.ignored_options.push(arg.to_string()),
+                _ => bail!("Unknown mkp224o option {}", arg),
+            }
+        }
+        Ok(filters)
+    }
+
+    fn add(&mut self, filter: &str, regex: bool) -> Result<()> {
+        if regex {
+            self.regexes.push(filter.to_string());
+            return Ok(());
+        }
+        let prefix = filter.to_lowercase();
+        if !prefix.bytes().all(|byte| char_value(byte).is_some()) {
+            return Err(anyhow!("Filter {:?} is not valid base32: use a-z and 2-7", filter)).with_code(ErrorCode::InvalidPattern);
+        }
+        self.prefixes.push(prefix);
+        Ok(())
+    }
+
+    fn extend(&mut self, other: Self) {
+        self.prefixes.extend(other.prefixes);
+        self.regexes.extend(other.regexes);
+        self.ignored_options.extend(other.ignored_options);
+    }
+
+    pub fn len(&self) -> usize {
+        self.prefixes.len() + self.regexes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+
+    const FILTERS: &str = "# old filters\nneko\n\n// also a comment\n  Tor2 \n";
+
+    #[test]
+    fn test_filter_file_skips_comments() {
+        let filters = Mkp224oFilters::parse(FILTERS, "filters.txt", false).unwrap();
+        assert_eq!(filters.prefixes, ["neko", "tor2"]);
+    }
+
+    #[test]
+    fn test_invalid_filter_names_its_line() {
+        let error = Mkp224oFilters::parse("abc\nab1\n", "filters.txt", false).unwrap_err();
+        assert!(format!("{:#}", error).starts_with("filters.txt:2: Filter \"ab1\""), "{:#}", error);
+    }
+
+    #[test]
+    fn test_regex_filters_are_kept_as_written() {
+        let regexes = Mkp224oFilters::parse("^neko[2-7]\n", "filters.txt", true).unwrap();
+        assert_eq!(regexes.regexes, ["^neko[2-7]"]);
+    }
+
+    #[test]
+    fn test_command_line_filters_and_options() {
+        let path = TempPath::new("mkp224o.txt");
+        fs::write(&path, FILTERS).unwrap();
+        let path_arg = path.display().to_string();
+        let args = ["-d", "keys", "-qs", "-n", "3", "-f", &path_arg, "abc", "--", "xyz"];
+        let filters = Mkp224oFilters::from_args(&args, false).unwrap();
+        assert_eq!(filters.prefixes, ["neko", "tor2", "abc", "xyz"]);
+        assert_eq!(filters.ignored_options, ["-d keys", "-qs", "-n 3"]);
+    }
+
+    #[test]
+    fn test_unknown_or_incomplete_options_are_rejected() {
+        assert!(Mkp224oFilters::from_args(&["-w", "abc"], false).is_err());
+        assert!(Mkp224oFilters::from_args(&["abc", "-f"], false).is_err());
+    }
+}
@@ -0,0 +1,205 @@
+use crate::config::{NotifyConfig, NotifyTarget};
+use crate::sink::ResultSink;
+use crate::OnionResult;
+use anyhow::{bail, Result};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+const DEFAULT_TEMPLATE: &str = "Found {hostname}";
+const DEFAULT_SECRET_TEMPLATE: &str = "Found {hostname}\nPrivate key: {private_key}";
+
+/// How long a single notification request may take before it is abandoned
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Tor purple, used as the Discord embed colour
+const DISCORD_COLOR: u32 = 0x7D4698;
+
+/// Formats a piece of message text for a particular chat service
+type Formatter = fn(&str) -> String;
+
+/// Posts a platform-native message to a chat service for every found address
+///
+/// Message templates may use `{hostname}`, `{fingerprint}`, `{public_key}`, `{time}` and, only
+/// when `include_secrets` is set, `{private_key}`. Delivery failures are
+/// reported on stderr and never stop the search.
+pub struct ChatSink {
+    target: NotifyTarget,
+    template: String,
+    include_secrets: bool,
+    agent: ureq::Agent,
+}
+
+impl ChatSink {
+    pub fn new(config: &NotifyConfig) -> Result<Self> {
+        let template = match &config.template {
+            Some(template) => template.clone(),
+            None if config.include_secrets => DEFAULT_SECRET_TEMPLATE.to_string(),
+            None => DEFAULT_TEMPLATE.to_string(),
+        };
+        if template.contains("{private_key}") && !config.include_secrets {
+            bail!("Notification template uses {{private_key}} but include_secrets is not enabled");
+        }
+
+        Ok(Self {
+            target: config.target.clone(),
+            template,
+            include_secrets: config.include_secrets,
+            agent: ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build(),
+        })
+    }
+
+    fn service(&self) -> &'static str {
+        match self.target {
+            NotifyTarget::Slack { .. } => "Slack",
+            NotifyTarget::Discord { .. } => "Discord",
+            NotifyTarget::Telegram { .. } => "Telegram",
+        }
+    }
+
+    /// Fill in the template, escaping literal text and formatting values as code
+    fn render(&self, result: &OnionResult) -> String {
+        let (escape, code): (Formatter, Formatter) = match self.target {
+            NotifyTarget::Slack { .. } => (escape_html, |s| format!("`{}`", s)),
+            NotifyTarget::Discord { .. } => (|s| s.to_string(), |s| format!("`{}`", s)),
+            NotifyTarget::Telegram { .. } => (escape_html, |s| format!("<code>{}</code>", s)),
+        };
+
+        let private_key = if self.include_secrets {
+            code(&result.private_key)
+        } else {
+            String::new()
+        };
+        escape(&self.template)
+            .replace("{hostname}", &code(&result.hostname))
+            .replace("{fingerprint}", &code(&result.fingerprint()))
+            .replace("{public_key}", &code(&result.public_key))
+            .replace("{private_key}", &private_key)
+            .replace("{time}", &chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string())
+    }
+
+    /// Request URL and JSON body for a found address
+    fn request(&self, result: &OnionResult) -> (String, Value) {
+        let message = self.render(result);
+
+        match &self.target {
+            NotifyTarget::Slack { webhook_url } => (
+                webhook_url.clone(),
+                json!({
+                    "text": format!("Onion address found: {}", result.hostname),
+                    "blocks": [{
+                        "type": "section",
+                        "text": { "type": "mrkdwn", "text": message },
+                    }],
+                }),
+            ),
+            NotifyTarget::Discord { webhook_url } => (
+                webhook_url.clone(),
+                json!({
+                    "embeds": [{
+                        "title": "Onion address found",
+                        "description": message,
+                        "color": DISCORD_COLOR,
+                        "timestamp": chrono::Utc::now().to_rfc3339(),
+                    }],
+                }),
+            ),
+            NotifyTarget::Telegram { bot_token, chat_id } => (
+                format!("https://api.telegram.org/bot{}/sendMessage", bot_token),
+                json!({
+                    "chat_id": chat_id,
+                    "text": message,
+                    "parse_mode": "HTML",
+                    "disable_web_page_preview": true,
+                }),
+            ),
+        }
+    }
+}
+
+impl ResultSink for ChatSink {
+    fn found(&mut self, result: &OnionResult) -> Result<()> {
+        let (url, body) = self.request(result);
+        if let Err(e) = self.agent.post(&url).send_json(body) {
+            // ureq errors include the URL, which carries the token for some services
+            let reason = match e {
+                ureq::Error::Status(code, _) => format!("HTTP status {}", code),
+                ureq::Error::Transport(transport) => transport.kind().to_string(),
+            };
+            eprintln!("[!] {} notification failed: {}", self.service(), reason);
+        }
+        Ok(())
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyCustody;
+
+    fn result() -> OnionResult {
+        OnionResult {
+            hostname: "abc.onion".to_string(),
+            public_key: "PUB".to_string(),
+            private_key: "SECRET".to_string(),
+            custody: KeyCustody::Local,
+            seed: None,
+        }
+    }
+
+    fn sink(target: NotifyTarget, template: Option<&str>, include_secrets: bool) -> Result<ChatSink> {
+        ChatSink::new(&NotifyConfig {
+            target,
+            template: template.map(str::to_string),
+            include_secrets,
+        })
+    }
+
+    fn telegram() -> NotifyTarget {
+        NotifyTarget::Telegram {
+            bot_token: "123:abc".to_string(),
+            chat_id: "42".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_default_message_has_no_secrets() {
+        let target = NotifyTarget::Discord { webhook_url: "https://discord.test/hook".to_string() };
+        let (url, body) = sink(target, None, false).unwrap().request(&result());
+
+        assert_eq!(url, "https://discord.test/hook");
+        assert_eq!(body["embeds"][0]["description"], "Found `abc.onion`");
+        assert!(!body.to_string().contains("SECRET"));
+    }
+
+    #[test]
+    fn test_private_key_requires_include_secrets() {
+        assert!(sink(telegram(), Some("{hostname} {private_key}"), false).is_err());
+
+        let sink = sink(telegram(), None, true).unwrap();
+        assert!(sink.render(&result()).contains("<code>SECRET</code>"));
+    }
+
+    #[test]
+    fn test_telegram_message_is_escaped_html() {
+        let (url, body) = sink(telegram(), Some("<b>{hostname}</b> & more"), false)
+            .unwrap()
+            .request(&result());
+
+        assert_eq!(url, "https://api.telegram.org/bot123:abc/sendMessage");
+        assert_eq!(body["parse_mode"], "HTML");
+        assert_eq!(body["text"], "&lt;b&gt;<code>abc.onion</code>&lt;/b&gt; &amp; more");
+    }
+
+    #[test]
+    fn test_slack_uses_mrkdwn_block() {
+        let target = NotifyTarget::Slack { webhook_url: "https://hooks.slack.test/x".to_string() };
+        let (_, body) = sink(target, None, false).unwrap().request(&result());
+
+        assert_eq!(body["blocks"][0]["text"]["type"], "mrkdwn");
+        assert_eq!(body["blocks"][0]["text"]["text"], "Found `abc.onion`");
+    }
+}
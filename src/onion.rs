@@ -1,79 +1,1411 @@
 use crate::crypto::*;
-use crate::{OnionResult, increment_generated, increment_found};
+use crate::dispatch::{masked_eq, Kernel};
+use ed25519_dalek::SigningKey;
+use crate::pattern::{pattern_spec, PatternKind};
+use crate::source::{CandidateSource, RandomKeys};
+use crate::trie::char_value;
+use crate::wordset::WordSet;
+use crate::{parse_onion_address, MatchEngine, CandidateSample, ErrorCode, GenerationStats, KeyCustody, OnionResult, ResultExt, Wordlist};
+use zeroize::{Zeroize, Zeroizing};
 use anyhow::Result;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use rayon::iter::{self, ParallelIterator};
 
 /// Generate a single onion address
 pub fn generate_onion_address() -> Result<OnionResult> {
     // Generate key pair
-    let (signing_key, verifying_key) = generate_keypair();
+    let (signing_key, verifying_key) = generate_keypair()?;
     
+    // Generate onion address
+    let hostname = encode_public_key(&verifying_key.to_bytes())?;
+    
+    onion_result_from_key(&signing_key, hostname)
+}
+
+/// Build the full result (hostname and Tor key files) for an existing key
+pub fn onion_result_from_signing_key(signing_key: &SigningKey) -> Result<OnionResult> {
+    let hostname = encode_public_key(&signing_key.verifying_key().to_bytes())?;
+    onion_result_from_key(signing_key, hostname)
+}
+
+/// Build the full result for a key whose hostname is already known
+pub(crate) fn onion_result_from_key(signing_key: &SigningKey, hostname: String) -> Result<OnionResult> {
     // Get raw bytes
     let private_bytes = signing_key.to_bytes();
-    let public_bytes = verifying_key.to_bytes();
+    let public_bytes = signing_key.verifying_key().to_bytes();
     
     // Expand secret key
     let expanded_secret = expand_secret_key(&private_bytes)?;
     
-    // Generate onion address
-    let hostname = encode_public_key(&public_bytes)?;
-    
+    let mut result = onion_result_from_expanded_key(&expanded_secret, &public_bytes, hostname);
+    result.seed = Some(Zeroizing::new(private_bytes));
+    Ok(result)
+}
+
+/// Build the result for an expanded secret key that has no seed behind it
+pub(crate) fn onion_result_from_expanded_key(expanded_secret: &[u8], public_bytes: &[u8; 32], hostname: String) -> OnionResult {
     // Format keys according to Tor specification
     let mut public_key_data = Vec::new();
     public_key_data.extend_from_slice(b"== ed25519v1-public: type0 ==");
     public_key_data.extend_from_slice(&[0, 0, 0]); // 3 null bytes
-    public_key_data.extend_from_slice(&public_bytes);
+    public_key_data.extend_from_slice(public_bytes);
     
     let mut private_key_data = Vec::new();
     private_key_data.extend_from_slice(b"== ed25519v1-secret: type0 ==");
     private_key_data.extend_from_slice(&[0, 0, 0]); // 3 null bytes
-    private_key_data.extend_from_slice(&expanded_secret);
+    private_key_data.extend_from_slice(expanded_secret);
     
     // Encode to base64
     let public_key = base64_encode(&public_key_data);
     let private_key = base64_encode(&private_key_data);
     
-    increment_generated();
-    
-    Ok(OnionResult {
+    OnionResult {
         hostname,
         public_key,
         private_key,
+        custody: KeyCustody::Local,
+        seed: None,
+    }
+}
+
+/// `result` with its private key wiped and dropped, keeping the hostname and public key
+pub fn without_private_key(mut result: OnionResult) -> OnionResult {
+    result.private_key.zeroize();
+    result.seed = None;
+    result.custody = KeyCustody::Discarded;
+    result
+}
+
+/// Generate a batch of onion addresses without any pattern constraint
+pub fn generate_n(count: usize) -> Result<Vec<OnionResult>> {
+    (0..count).map(|_| generate_onion_address()).collect()
+}
+
+/// Measure single-threaded candidate throughput in keys per second
+///
+/// Runs the same generate-and-encode loop as a search for `duration`.
+pub fn calibrate_rate(duration: Duration) -> Result<f64> {
+    let stats = GenerationStats::new();
+    let started = Instant::now();
+    search(|_| false, &stats, || started.elapsed() < duration)?;
+    Ok(stats.get().0 as f64 / started.elapsed().as_secs_f64().max(f64::EPSILON))
+}
+
+/// Measure the combined throughput of `threads` threads generating at once
+///
+/// Unlike scaling [`calibrate_rate`], this accounts for shared cores and
+/// clock throttling under full load.
+pub fn calibrate_parallel_rate(duration: Duration, threads: usize) -> Result<f64> {
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads.max(1))
+            .map(|_| scope.spawn(|| calibrate_rate(duration)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().map_err(|_| anyhow::anyhow!("Calibration thread panicked"))?)
+            .sum()
     })
 }
 
+/// Endless stream of candidate addresses as a rayon parallel iterator
+///
+/// Lets callers use rayon's scheduling (e.g. `find_any`) instead of the
+/// built-in worker pool. Candidates that fail to generate are skipped.
+pub fn par_candidates() -> impl ParallelIterator<Item = OnionResult> {
+    iter::repeat(()).filter_map(|_| generate_onion_address().ok())
+}
+
+/// Generate an onion address whose hostname satisfies `predicate`
+///
+/// The predicate receives the full hostname including the `.onion` suffix.
+/// Key material is only encoded for the accepted candidate.
+pub fn generate_with_predicate(predicate: impl Fn(&str) -> bool) -> Result<OnionResult> {
+    search(predicate, &GenerationStats::new(), || true)?
+        .ok_or_else(|| anyhow::anyhow!("Search stopped unexpectedly"))
+}
+
+/// Like [`generate_with_predicate`], but gives up once `stop` is set
+///
+/// Returns `Ok(None)` if the search was stopped before a match was found.
+pub fn generate_with_predicate_until(
+    predicate: impl Fn(&str) -> bool,
+    stop: &AtomicBool,
+) -> Result<Option<OnionResult>> {
+    search(predicate, &GenerationStats::new(), || !stop.load(Ordering::Relaxed))
+}
+
+/// Like [`generate_with_predicate_until`], but records attempts in `stats`
+pub fn generate_with_predicate_counted_until(
+    predicate: impl Fn(&str) -> bool,
+    stats: &GenerationStats,
+    stop: &AtomicBool,
+) -> Result<Option<OnionResult>> {
+    search(predicate, stats, || !stop.load(Ordering::Relaxed))
+}
+
 /// Generate onion address with specific prefix
 pub fn generate_with_prefix(prefixes: &[String]) -> Result<OnionResult> {
+    generate_with_prefix_counted(prefixes, &GenerationStats::new())
+}
+
+/// Generate onion address with specific prefix, recording attempts in `stats`
+pub fn generate_with_prefix_counted(
+    prefixes: &[String],
+    stats: &GenerationStats,
+) -> Result<OnionResult> {
+    search(prefix_predicate(prefixes), stats, || true)?
+        .ok_or_else(|| anyhow::anyhow!("Search stopped unexpectedly"))
+}
+
+/// Generate onion address with specific prefix until `stop` is set
+///
+/// Returns `Ok(None)` if the search was stopped before a match was found.
+pub fn generate_with_prefix_until(
+    prefixes: &[String],
+    stats: &GenerationStats,
+    stop: &AtomicBool,
+) -> Result<Option<OnionResult>> {
+    search(prefix_predicate(prefixes), stats, || !stop.load(Ordering::Relaxed))
+}
+
+/// Generate onion address with specific prefix, giving up after `timeout`
+///
+/// Returns `Ok(None)` if no match was found in time.
+pub fn generate_with_prefix_timeout(
+    prefixes: &[String],
+    timeout: Duration,
+) -> Result<Option<OnionResult>> {
+    // A timeout too large to represent means no deadline at all
+    let deadline = Instant::now().checked_add(timeout);
+    search(prefix_predicate(prefixes), &GenerationStats::new(), || {
+        match deadline {
+            Some(deadline) => Instant::now() < deadline,
+            None => true,
+        }
+    })
+}
+
+/// Generate onion address with specific prefix, trying at most `max_attempts` candidates
+///
+/// Returns `Ok(None)` if none of the candidates matched.
+pub fn generate_with_prefix_max_attempts(
+    prefixes: &[String],
+    max_attempts: u128,
+) -> Result<Option<OnionResult>> {
+    let mut attempts = 0;
+    search(prefix_predicate(prefixes), &GenerationStats::new(), || {
+        attempts += 1;
+        attempts <= max_attempts
+    })
+}
+
+/// Check if hostname starts with any of the prefixes, walking them as a trie
+fn prefix_predicate(prefixes: &[String]) -> impl Fn(&str) -> bool {
+    let matcher = Matcher::from_prefixes(prefixes).with_engine(MatchEngine::Trie);
+    move |hostname| matcher.is_match(hostname)
+}
+
+/// Generate onion address accepted by `matcher` until `stop` is set
+///
+/// Returns `Ok(None)` if the search was stopped before a match was found.
+pub fn generate_with_matcher_until(
+    matcher: &Matcher,
+    stats: &GenerationStats,
+    stop: &AtomicBool,
+) -> Result<Option<OnionResult>> {
+    search_keys(
+        &mut RandomKeys::new(),
+        |key| matcher.key_may_match(key),
+        |hostname| matcher.is_match(hostname),
+        stats,
+        None,
+        || !stop.load(Ordering::Relaxed),
+    )
+}
+
+/// Like [`generate_with_matcher_until`], but drawing candidates from a seeded shard
+pub fn generate_with_matcher_seeded_until(
+    matcher: &Matcher,
+    keys: &mut SeededKeys,
+    stats: &GenerationStats,
+    stop: &AtomicBool,
+) -> Result<Option<OnionResult>> {
+    search_keys(
+        keys,
+        |key| matcher.key_may_match(key),
+        |hostname| matcher.is_match(hostname),
+        stats,
+        None,
+        || !stop.load(Ordering::Relaxed),
+    )
+}
+
+/// Like [`generate_with_matcher_until`], but picking up changes to `live` between candidates
+///
+/// Draws from `keys` when given, otherwise from the system RNG.
+pub fn generate_with_live_matcher_until(
+    live: &LiveMatcher,
+    keys: Option<&mut SeededKeys>,
+    stats: &GenerationStats,
+    stop: &AtomicBool,
+) -> Result<Option<OnionResult>> {
+    match keys {
+        Some(keys) => generate_with_live_matcher_while(live, keys, stats, None, || !stop.load(Ordering::Relaxed)),
+        None => generate_with_live_matcher_while(live, &mut RandomKeys::new(), stats, None, || !stop.load(Ordering::Relaxed)),
+    }
+}
+
+/// Like [`generate_with_live_matcher_until`], but searching as long as `running`
+/// returns true, drawing from `source` and adding candidates to `sample`
+pub(crate) fn generate_with_live_matcher_while(
+    live: &LiveMatcher,
+    source: &mut dyn CandidateSource,
+    stats: &GenerationStats,
+    sample: Option<&CandidateSample>,
+    running: impl Fn() -> bool,
+) -> Result<Option<OnionResult>> {
+    let current = RefCell::new((live.version(), live.current()));
+    let key_filter = |key: &[u8; 32]| current.borrow().1.key_may_match(key);
+    let predicate = |hostname: &str| current.borrow().1.is_match(hostname);
+    let keep_going = || {
+        let version = live.version();
+        if current.borrow().0 != version {
+            *current.borrow_mut() = (version, live.current());
+        }
+        running()
+    };
+    search_keys(source, key_filter, predicate, stats, sample, keep_going)
+}
+
+/// Length of a v3 address without the `.onion` suffix
+pub const ADDRESS_LEN: usize = 56;
+
+/// Characters that can appear in a v3 address, in order of their 5-bit value
+pub const BASE32_ALPHABET: &str = "abcdefghijklmnopqrstuvwxyz234567";
+
+/// A single pattern applied to the 56-character address (without `.onion`)
+#[derive(Debug, Clone)]
+pub enum MatchSpec {
+    Prefix(String),
+    Suffix(String),
+    Contains(String),
+    /// Starts with the first and ends with the second string
+    PrefixSuffix(String, String),
+    /// The string right after this many characters, e.g. `tor` after 10
+    At(usize, String),
+    /// Allowed characters per position, e.g. letters only
+    Classes(CharTemplate),
+    /// The first n characters are all the same, e.g. `aaaa`
+    Run(usize),
+    /// The first n characters appear again right after them, e.g. `abcabc`
+    Doubled(usize),
+    /// The first n characters read the same backwards, e.g. `abcba`
+    Palindrome(usize),
+    /// The whole address fits a wildcard pattern, e.g. `foo??bar*`
+    Glob(Glob),
+    /// Starts with any prefix of a large list, see [`Wordlist`]
+    Wordlist(Wordlist),
+    /// Other patterns combined with and, or and not, see [`PatternExpr`]
+    Expr(PatternExpr),
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+}
+
+impl MatchSpec {
+    pub fn is_match(&self, address: &str) -> bool {
+        match self {
+            MatchSpec::Prefix(prefix) => address.starts_with(prefix.as_str()),
+            MatchSpec::Suffix(suffix) => address.ends_with(suffix.as_str()),
+            MatchSpec::Contains(needle) => address.contains(needle.as_str()),
+            MatchSpec::PrefixSuffix(prefix, suffix) => {
+                address.len() >= prefix.len() + suffix.len()
+                    && address.starts_with(prefix.as_str())
+                    && address.ends_with(suffix.as_str())
+            }
+            MatchSpec::At(offset, text) => address.get(*offset..).is_some_and(|rest| rest.starts_with(text.as_str())),
+            MatchSpec::Classes(template) => template.is_match(address),
+            MatchSpec::Glob(glob) => glob.is_match(address),
+            MatchSpec::Wordlist(wordlist) => wordlist.is_match(address),
+            MatchSpec::Expr(expr) => expr.is_match(address),
+            MatchSpec::Run(_) | MatchSpec::Doubled(_) | MatchSpec::Palindrome(_) => {
+                let bytes = address.as_bytes();
+                self.equal_positions().iter().all(|&(a, b)| b < bytes.len() && bytes[a] == bytes[b])
+            }
+            #[cfg(feature = "regex")]
+            MatchSpec::Regex(regex) => regex.is_match(address),
+        }
+    }
+
+    /// Expected number of candidates until this pattern matches
+    ///
+    /// Returns `None` when it cannot be computed (regexes) and infinity for
+    /// patterns that can never match. The last two address characters are
+    /// constrained by the version byte: the last is always `d` and the one
+    /// before is one of `a`, `i`, `q` or `y`.
+    pub fn expected_attempts(&self) -> Option<f64> {
+        match self {
+            MatchSpec::Prefix(prefix) => Some(prefix_attempts(prefix)),
+            MatchSpec::Suffix(suffix) => Some(suffix_attempts(suffix)),
+            MatchSpec::Contains(needle) => {
+                let positions = (ADDRESS_LEN + 1).saturating_sub(needle.len()).max(1);
+                Some(32f64.powi(needle.len() as i32) / positions as f64)
+            }
+            MatchSpec::PrefixSuffix(prefix, suffix) => {
+                if prefix.len() + suffix.len() > ADDRESS_LEN {
+                    return Some(f64::INFINITY);
+                }
+                Some(prefix_attempts(prefix) * suffix_attempts(suffix))
+            }
+            MatchSpec::At(offset, text) => Some(at_attempts(*offset, text)),
+            MatchSpec::Classes(template) => Some(template.expected_attempts()),
+            MatchSpec::Glob(glob) => Some(glob.expected_attempts()),
+            MatchSpec::Wordlist(wordlist) => Some(wordlist.expected_attempts()),
+            MatchSpec::Expr(expr) => expr.expected_attempts(),
+            // Each pair of equal positions fixes one more character
+            MatchSpec::Run(_) | MatchSpec::Doubled(_) | MatchSpec::Palindrome(_) => Some(32f64.powi(self.equal_positions().len() as i32)),
+            #[cfg(feature = "regex")]
+            MatchSpec::Regex(_) => None,
+        }
+    }
+
+    /// Pairs of positions that must hold the same character
+    ///
+    /// Only the positions before the fixed last two characters are used; see
+    /// [`MatchSpec::check_length`].
+    fn equal_positions(&self) -> Vec<(usize, usize)> {
+        match self {
+            MatchSpec::Run(n) => (1..*n).map(|i| (0, i)).collect(),
+            MatchSpec::Doubled(n) => (0..*n).map(|i| (i, i + n)).collect(),
+            MatchSpec::Palindrome(n) => (0..n / 2).map(|i| (i, n - 1 - i)).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// [`MatchSpec::At`] from `OFFSET:TEXT`, e.g. `10:tor`
+    pub fn parse_at(pattern: &str) -> anyhow::Result<Self> {
+        let Some((offset, text)) = pattern.split_once(':') else {
+            anyhow::bail!("Write positioned patterns as OFFSET:TEXT, e.g. 10:tor, got {:?}", pattern);
+        };
+        let Ok(offset) = offset.trim().parse::<usize>() else {
+            anyhow::bail!("Offset {:?} is not a number of characters", offset);
+        };
+        let text = text.trim().to_lowercase();
+        if text.is_empty() {
+            anyhow::bail!("Nothing to match at offset {}", offset);
+        }
+        crate::check_address_chars(&text)?;
+        if offset + text.len() > ADDRESS_LEN {
+            anyhow::bail!("{:?} at offset {} runs past the end of the address ({} characters)", text, offset, ADDRESS_LEN);
+        }
+        Ok(MatchSpec::At(offset, text))
+    }
+
+    /// Reject repeat lengths that would reach the last two, fixed characters
+    pub fn check_length(&self) -> anyhow::Result<()> {
+        let (n, max) = match self {
+            MatchSpec::Run(n) | MatchSpec::Palindrome(n) => (*n, ADDRESS_LEN - 2),
+            MatchSpec::Doubled(n) => (*n, (ADDRESS_LEN - 2) / 2),
+            _ => return Ok(()),
+        };
+        if n < 2 || n > max {
+            anyhow::bail!("{} needs a length from 2 to {}", self, max);
+        }
+        Ok(())
+    }
+
+    /// Relative evaluation cost; cheaper patterns are checked first
+    fn cost(&self) -> u8 {
+        match self {
+            MatchSpec::Prefix(_) | MatchSpec::PrefixSuffix(..) | MatchSpec::At(..) => 0,
+            MatchSpec::Suffix(_)
+            | MatchSpec::Classes(_)
+            | MatchSpec::Glob(_)
+            | MatchSpec::Wordlist(_)
+            | MatchSpec::Run(_)
+            | MatchSpec::Doubled(_)
+            | MatchSpec::Palindrome(_) => 1,
+            MatchSpec::Contains(_) => 2,
+            MatchSpec::Expr(expr) => expr.specs().iter().map(MatchSpec::cost).max().unwrap_or(0),
+            #[cfg(feature = "regex")]
+            MatchSpec::Regex(_) => 3,
+        }
+    }
+
+    /// Conditions on the raw public key, one of which every match meets; `None` if there are none
+    fn key_patterns(&self) -> Option<Vec<KeyPattern>> {
+        match self {
+            MatchSpec::Prefix(prefix) | MatchSpec::PrefixSuffix(prefix, _) => Some(KeyPattern::from_prefix(prefix).into_iter().collect()),
+            MatchSpec::At(offset, text) => Some(KeyPattern::from_prefix(&format!("{}{}", "?".repeat(*offset), text)).into_iter().collect()),
+            MatchSpec::Classes(template) => Some(vec![KeyPattern::from_template(template)]),
+            MatchSpec::Glob(glob) => {
+                let start = glob.start();
+                start.contains(|c| c != '?').then(|| KeyPattern::from_prefix(start).into_iter().collect())
+            }
+            MatchSpec::Run(_) | MatchSpec::Doubled(_) | MatchSpec::Palindrome(_) => {
+                Some(vec![KeyPattern::from_equal_positions(self.equal_positions())])
+            }
+            // Any part's condition holds for the whole, and alternatives need one each
+            MatchSpec::Expr(PatternExpr::All(specs)) => specs.iter().find_map(MatchSpec::key_patterns),
+            MatchSpec::Expr(PatternExpr::Any(specs)) => specs
+                .iter()
+                .map(MatchSpec::key_patterns)
+                .collect::<Option<Vec<_>>>()
+                .map(|patterns| patterns.into_iter().flatten().collect()),
+            #[cfg(feature = "regex")]
+            MatchSpec::Regex(regex) => {
+                let prefix = regex_prefix(regex.as_str());
+                (!prefix.is_empty()).then(|| KeyPattern::from_prefix(&prefix).into_iter().collect())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The address characters every match of `regex` starts with, e.g. "foo" for `^foo(bar|baz)`
+#[cfg(feature = "regex")]
+fn regex_prefix(regex: &str) -> String {
+    regex_literal_start(regex).chars().take_while(|c| BASE32_ALPHABET.contains(*c)).collect()
+}
+
+/// Fail for a regex that must start with characters never found in addresses, such as `^Tor`
+#[cfg(feature = "regex")]
+pub(crate) fn check_regex_start(regex: &str) -> Result<()> {
+    crate::check_address_chars(&regex_literal_start(regex)).map_err(|e| anyhow::anyhow!("Regex {:?}: {}", regex, e))
+}
+
+/// The letters and digits every match of `regex` starts with
+///
+/// Only a leading `^` followed by plain characters counts; anything that may
+/// make the start vary, like a top-level `|` or a `?` after a character, ends
+/// the start early or leaves it empty.
+#[cfg(feature = "regex")]
+fn regex_literal_start(regex: &str) -> String {
+    let Some(rest) = regex.strip_prefix('^') else {
+        return String::new();
+    };
+    // Skip escapes and bracketed classes, where `|` and parentheses are plain characters
+    let (mut depth, mut escaped, mut in_class) = (0, false, false);
+    for c in rest.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '[' => in_class = true,
+            ']' => in_class = false,
+            _ if in_class => {}
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            '|' if depth == 0 => return String::new(),
+            _ => {}
+        }
+    }
+
+    let chars: Vec<char> = rest.chars().collect();
+    let mut prefix = String::new();
+    for (index, &c) in chars.iter().enumerate() {
+        if !c.is_ascii_alphanumeric() {
+            break;
+        }
+        match chars.get(index + 1) {
+            // The character may be left out
+            Some('?' | '*' | '{') => break,
+            Some('+') => {
+                prefix.push(c);
+                break;
+            }
+            _ => prefix.push(c),
+        }
+    }
+    prefix
+}
+
+fn prefix_attempts(prefix: &str) -> f64 {
+    32f64.powi(prefix.len() as i32)
+}
+
+fn suffix_attempts(suffix: &str) -> f64 {
+    let probability: f64 = suffix
+        .chars()
+        .rev()
+        .enumerate()
+        .map(|(from_end, c)| char_probability(from_end, c))
+        .product();
+    1.0 / probability
+}
+
+fn at_attempts(offset: usize, text: &str) -> f64 {
+    if offset + text.len() > ADDRESS_LEN {
+        return f64::INFINITY;
+    }
+    let probability: f64 = text
+        .chars()
+        .enumerate()
+        .map(|(i, c)| char_probability(ADDRESS_LEN - 1 - offset - i, c))
+        .product();
+    1.0 / probability
+}
+
+/// Chance of `c` at `from_end` characters before the last, which is always 'd'
+fn char_probability(from_end: usize, c: char) -> f64 {
+    match from_end {
+        0 if c == 'd' => 1.0,
+        1 if "aiqy".contains(c) => 0.25,
+        0 | 1 => 0.0,
+        _ => 1.0 / 32.0,
+    }
+}
+
+impl std::fmt::Display for MatchSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MatchSpec::Prefix(prefix) => write!(f, "prefix \"{}\"", prefix),
+            MatchSpec::Suffix(suffix) => write!(f, "suffix \"{}\"", suffix),
+            MatchSpec::Contains(needle) => write!(f, "containing \"{}\"", needle),
+            MatchSpec::PrefixSuffix(prefix, suffix) => {
+                write!(f, "prefix \"{}\" with suffix \"{}\"", prefix, suffix)
+            }
+            MatchSpec::At(offset, text) => write!(f, "\"{}\" at offset {}", text, offset),
+            MatchSpec::Classes(template) => write!(f, "classes \"{}\"", template),
+            MatchSpec::Glob(glob) => write!(f, "glob \"{}\"", glob),
+            MatchSpec::Wordlist(wordlist) => write!(f, "wordlist of {}", wordlist),
+            MatchSpec::Expr(expr) => write!(f, "expression \"{}\"", expr),
+            MatchSpec::Run(n) => write!(f, "{} identical leading characters", n),
+            MatchSpec::Doubled(n) => write!(f, "doubled {}-character start", n),
+            MatchSpec::Palindrome(n) => write!(f, "{}-character palindrome start", n),
+            #[cfg(feature = "regex")]
+            MatchSpec::Regex(regex) => write!(f, "regex /{}/", regex.as_str()),
+        }
+    }
+}
+
+/// Combination of include and exclude patterns
+///
+/// An address matches if any include pattern matches and no exclude pattern
+/// does. Includes are evaluated cheapest first, and excludes only run once an
+/// include has matched, so the hot loop mostly does plain prefix compares.
+/// Large sets of prefix and contains patterns are searched with a trie or
+/// Aho-Corasick automatons built once here instead of one word at a time;
+/// see [`MatchEngine`].
+#[derive(Debug, Clone, Default)]
+pub struct Matcher {
+    includes: Vec<MatchSpec>,
+    excludes: Vec<MatchSpec>,
+    /// Key-level conditions of the includes, if every include has one
+    key_patterns: Option<Vec<KeyPattern>>,
+    engine: MatchEngine,
+    include_words: Option<WordSet>,
+    exclude_words: Option<WordSet>,
+}
+
+impl Matcher {
+    pub fn new(mut includes: Vec<MatchSpec>, mut excludes: Vec<MatchSpec>) -> Self {
+        includes.sort_by_key(MatchSpec::cost);
+        excludes.sort_by_key(MatchSpec::cost);
+        let key_patterns = includes
+            .iter()
+            .map(MatchSpec::key_patterns)
+            .collect::<Option<Vec<_>>>()
+            .map(|patterns| patterns.into_iter().flatten().collect());
+        Self { includes, excludes, key_patterns, ..Self::default() }.with_engine(MatchEngine::Auto)
+    }
+
+    /// Check candidates with `engine`; [`MatchEngine::Auto`] picks one from the includes
+    pub fn with_engine(mut self, engine: MatchEngine) -> Self {
+        self.engine = match engine {
+            MatchEngine::Auto => MatchEngine::pick(&self.includes, self.key_patterns.is_some()),
+            engine => engine,
+        };
+        let trie = self.engine == MatchEngine::Trie;
+        let words = trie || self.engine == MatchEngine::AhoCorasick;
+        self.include_words = words.then(|| WordSet::build(&self.includes, trie)).flatten();
+        self.exclude_words = words.then(|| WordSet::build(&self.excludes, trie)).flatten();
+        self
+    }
+
+    /// The engine checking candidates, never [`MatchEngine::Auto`] for a built matcher
+    pub fn engine(&self) -> MatchEngine {
+        self.engine
+    }
+
+    /// Matcher accepting addresses starting with any of `prefixes`
+    pub fn from_prefixes(prefixes: &[String]) -> Self {
+        Self::new(prefixes.iter().cloned().map(MatchSpec::Prefix).collect(), Vec::new())
+    }
+
+    pub fn includes(&self) -> &[MatchSpec] {
+        &self.includes
+    }
+
+    pub fn excludes(&self) -> &[MatchSpec] {
+        &self.excludes
+    }
+
+    /// Cheap check on a raw public key before it is encoded
+    ///
+    /// False only if no include can match an address of this key; when every
+    /// include starts with a fixed prefix or is a character template, that
+    /// rules out most keys. Always true unless the engine is
+    /// [`MatchEngine::KeyMask`]: comparing the key with thousands of words
+    /// costs more than encoding it for the automatons.
+    pub fn key_may_match(&self, public_key: &[u8; 32]) -> bool {
+        match &self.key_patterns {
+            Some(patterns) if self.engine == MatchEngine::KeyMask => {
+                let kernel = Kernel::selected();
+                patterns.iter().any(|pattern| pattern.matches(kernel, public_key))
+            }
+            _ => true,
+        }
+    }
+
+    /// Check a hostname, with or without the `.onion` suffix
+    pub fn is_match(&self, hostname: &str) -> bool {
+        let address = hostname.strip_suffix(".onion").unwrap_or(hostname);
+        let any_match = |words: &Option<WordSet>, specs: &[MatchSpec]| match words {
+            Some(words) => words.any_match(address),
+            None => specs.iter().any(|spec| spec.is_match(address)),
+        };
+
+        any_match(&self.include_words, &self.includes) && !any_match(&self.exclude_words, &self.excludes)
+    }
+
+    /// Expected candidates per match for all includes combined, ignoring excludes
+    ///
+    /// Returns `None` if any include (e.g. a regex) has no estimate.
+    pub fn expected_attempts(&self) -> Option<f64> {
+        let mut probability = 0.0;
+        for spec in &self.includes {
+            probability += 1.0 / spec.expected_attempts()?;
+        }
+        Some(1.0 / probability.min(1.0))
+    }
+}
+
+/// Address characters as a condition on the bits of the raw public key
+///
+/// Every address character encodes 5 bits of the key, so a prefix of n
+/// characters fixes its first 5n bits, and a character class limits the
+/// values of a 5-bit group. Characters past the 256 key bits come from the
+/// checksum and are left to the string check.
+#[derive(Debug, Clone, Default)]
+struct KeyPattern {
+    /// Fixed bits, compared in one go by the dispatched [`masked_eq`] kernel
+    mask: [u8; 32],
+    value: [u8; 32],
+    /// Positions limited to a class, with the allowed values as a bit set
+    classes: Vec<(usize, u32)>,
+    /// Pairs of positions holding the same character
+    equal: Vec<(usize, usize)>,
+}
+
+/// Address characters made up entirely of public key bits
+const KEY_CHARS: usize = 256 / 5;
+
+impl KeyPattern {
+    /// `None` if the prefix has a character that never appears in an address
+    ///
+    /// A `?` leaves its position open.
+    fn from_prefix(prefix: &str) -> Option<Self> {
+        let mut pattern = Self::default();
+        for (position, c) in prefix.chars().enumerate() {
+            if c == '?' {
+                continue;
+            }
+            let bits = BASE32_ALPHABET.find(c)? as u8;
+            pattern.fix(position, bits);
+        }
+        Some(pattern)
+    }
+
+    fn from_template(template: &CharTemplate) -> Self {
+        let mut pattern = Self::default();
+        for (position, &allowed) in template.classes.iter().enumerate().take(KEY_CHARS) {
+            if allowed.count_ones() == 1 {
+                pattern.fix(position, allowed.trailing_zeros() as u8);
+            } else if allowed != u32::MAX {
+                pattern.classes.push((position, allowed));
+            }
+        }
+        pattern
+    }
+
+    fn from_equal_positions(positions: Vec<(usize, usize)>) -> Self {
+        Self {
+            equal: positions.into_iter().filter(|&(a, b)| a.max(b) < KEY_CHARS).collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Require the character at `position` to have the 5-bit value `bits`
+    fn fix(&mut self, position: usize, bits: u8) {
+        for offset in 0..5 {
+            let bit = position * 5 + offset;
+            if bit >= 256 {
+                break;
+            }
+            self.mask[bit / 8] |= 0x80 >> (bit % 8);
+            self.value[bit / 8] |= ((bits >> (4 - offset)) & 1) << (7 - bit % 8);
+        }
+    }
+
+    fn matches(&self, kernel: Kernel, public_key: &[u8; 32]) -> bool {
+        masked_eq(kernel, public_key, &self.mask, &self.value)
+            && self
+                .classes
+                .iter()
+                .all(|&(position, allowed)| allowed & (1 << char_bits(public_key, position)) != 0)
+            && self
+                .equal
+                .iter()
+                .all(|&(a, b)| char_bits(public_key, a) == char_bits(public_key, b))
+    }
+}
+
+/// 5-bit value of the address character at `position`, which must be below [`KEY_CHARS`]
+fn char_bits(public_key: &[u8; 32], position: usize) -> u32 {
+    let bit = position * 5;
+    let next = public_key.get(bit / 8 + 1).copied().unwrap_or(0);
+    let word = (u32::from(public_key[bit / 8]) << 8) | u32::from(next);
+    (word >> (11 - bit % 8)) & 0x1f
+}
+
+/// Allowed characters for each leading position of an address
+///
+/// Written as one symbol per position: `L` for a letter, `D` for a digit
+/// (2-7), `V` for a vowel, `C` for a consonant, `.` for any character, a
+/// literal lowercase character, or a set in brackets such as `[ou]`, `[a-f2]`
+/// or `[^xyz]` for anything but those. A symbol followed by `{n}` repeats it
+/// n times, so `L{8}` means "the first 8 characters are letters" and
+/// `t[ou]r` matches both "tor" and "tur".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CharTemplate {
+    text: String,
+    /// Bit set of allowed 5-bit values per position
+    classes: Vec<u32>,
+}
+
+impl CharTemplate {
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut classes = Vec::new();
+        let mut chars = text.chars().peekable();
+        while let Some(symbol) = chars.next() {
+            let allowed = match symbol {
+                'L' => char_set("abcdefghijklmnopqrstuvwxyz"),
+                'D' => char_set("234567"),
+                'V' => char_set("aeiou"),
+                'C' => char_set("bcdfghjklmnpqrstvwxyz"),
+                '.' => u32::MAX,
+                '[' => bracket_set(&mut chars)?,
+                c if BASE32_ALPHABET.contains(c) => char_set(&c.to_string()),
+                c => anyhow::bail!("Unknown template symbol {:?}; expected L, D, V, C, ., [set] or a-z, 2-7", c),
+            };
+            let mut count = 1;
+            if chars.peek() == Some(&'{') {
+                chars.next();
+                let digits: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                count = digits
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid repeat count {{{}}} in template", digits))?;
+            }
+            // `count` comes straight from the user, so it must not be added to the length unchecked
+            if count > ADDRESS_LEN - classes.len() {
+                anyhow::bail!("Template is longer than an address ({} characters)", ADDRESS_LEN);
+            }
+            classes.resize(classes.len() + count, allowed);
+        }
+        if classes.is_empty() {
+            anyhow::bail!("Template must not be empty");
+        }
+        Ok(Self { text: text.to_string(), classes })
+    }
+
+    /// Whether `pattern` has a bracketed set, and so is a template rather than a plain prefix
+    pub fn has_brackets(pattern: &str) -> bool {
+        pattern.contains('[')
+    }
+
+    pub fn is_match(&self, address: &str) -> bool {
+        address.len() >= self.classes.len()
+            && address
+                .chars()
+                .zip(&self.classes)
+                .all(|(c, allowed)| BASE32_ALPHABET.find(c).is_some_and(|bits| allowed & (1 << bits) != 0))
+    }
+
+    /// Expected candidates per match, taking the fixed last two characters into account
+    pub fn expected_attempts(&self) -> f64 {
+        let probability: f64 = self
+            .classes
+            .iter()
+            .enumerate()
+            .map(|(position, allowed)| match ADDRESS_LEN - 1 - position {
+                0 => f64::from(u8::from(allowed & char_set("d") != 0)),
+                1 => f64::from((allowed & char_set("aiqy")).count_ones()) / 4.0,
+                _ => f64::from(allowed.count_ones()) / 32.0,
+            })
+            .product();
+        1.0 / probability
+    }
+}
+
+/// Bit set of a bracketed set whose `[` has been read, consuming it up to the `]`
+fn bracket_set(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<u32> {
+    let negated = chars.next_if_eq(&'^').is_some();
+    let mut set = 0;
     loop {
-        let result = generate_onion_address()?;
-        
-        // Check if hostname starts with any of the prefixes
-        for prefix in prefixes {
-            if result.hostname.starts_with(prefix) {
-                increment_found();
-                return Ok(result);
+        let first = match chars.next() {
+            Some(']') if set != 0 => break,
+            Some(']') => anyhow::bail!("Empty [] in template"),
+            Some(c) => c,
+            None => anyhow::bail!("Missing ] in template"),
+        };
+        let last = match chars.next_if_eq(&'-') {
+            Some(_) => chars.next().filter(|c| *c != ']').ok_or_else(|| anyhow::anyhow!("Range {}- has no end in template", first))?,
+            None => first,
+        };
+        let same_kind = first.is_ascii_digit() == last.is_ascii_digit();
+        if !same_kind || first > last {
+            anyhow::bail!("Invalid range {}-{} in template; use e.g. a-f or 2-7", first, last);
+        }
+        let range: String = (first..=last).collect();
+        crate::check_address_chars(&range)?;
+        set |= char_set(&range);
+    }
+    Ok(if negated { !set & char_set(BASE32_ALPHABET) } else { set })
+}
+
+impl std::fmt::Display for CharTemplate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// Wildcard pattern over the whole address
+///
+/// `?` stands for any one character and `*` for any run of characters, so
+/// `foo??bar*` means "foo", two characters of any kind, "bar" and anything
+/// after. Without a `*`, the pattern has to cover all 56 characters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Glob {
+    text: String,
+    /// The pieces between the `*`s, some possibly empty
+    parts: Vec<String>,
+}
+
+impl Glob {
+    /// Whether `pattern` uses wildcards, and so is a glob rather than a plain prefix
+    pub fn is_glob(pattern: &str) -> bool {
+        pattern.contains(['*', '?'])
+    }
+
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let parts: Vec<String> = text.split('*').map(str::to_string).collect();
+        for part in &parts {
+            crate::check_address_chars(&part.replace('?', ""))?;
+        }
+        if parts.iter().map(String::len).sum::<usize>() > ADDRESS_LEN {
+            anyhow::bail!("Glob {:?} is longer than an address ({} characters)", text, ADDRESS_LEN);
+        }
+        if !text.contains(|c| BASE32_ALPHABET.contains(c)) {
+            anyhow::bail!("Glob {:?} has no fixed characters and would match every address", text);
+        }
+        Ok(Self { text: text.to_string(), parts })
+    }
+
+    /// The part before the first `*`, which has a fixed position
+    fn start(&self) -> &str {
+        &self.parts[0]
+    }
+
+    pub fn is_match(&self, address: &str) -> bool {
+        let address = address.as_bytes();
+        let (first, rest) = self.parts.split_first().expect("split yields at least one part");
+        let Some((last, middle)) = rest.split_last() else {
+            return address.len() == first.len() && glob_part_matches(first, address);
+        };
+        if address.len() < first.len() + last.len() {
+            return false;
+        }
+        let (head, tail) = address.split_at(address.len() - last.len());
+        if !glob_part_matches(first, &head[..first.len()]) || !glob_part_matches(last, tail) {
+            return false;
+        }
+        // Taking the first fit of each middle part leaves the most room for the rest
+        let mut rest = &head[first.len()..];
+        for part in middle.iter().filter(|part| !part.is_empty()) {
+            match rest.windows(part.len()).position(|window| glob_part_matches(part, window)) {
+                Some(index) => rest = &rest[index + part.len()..],
+                None => return false,
             }
         }
+        true
+    }
+
+    /// Expected candidates per match, taking the fixed last two characters into account
+    ///
+    /// Parts between two `*`s are approximated as free to sit anywhere in the room
+    /// the other parts leave.
+    pub fn expected_attempts(&self) -> f64 {
+        let at = |position: usize, part: &str| -> f64 {
+            part.chars().enumerate().map(|(offset, c)| glob_char_probability(position + offset, c)).product()
+        };
+        let (first, rest) = self.parts.split_first().expect("split yields at least one part");
+        let probability = match rest.split_last() {
+            None if first.len() == ADDRESS_LEN => at(0, first),
+            None => 0.0,
+            Some((last, middle)) => {
+                let room = ADDRESS_LEN + 1 - self.parts.iter().map(String::len).sum::<usize>();
+                let floating: f64 = middle.iter().map(|part| (at(0, part) * room as f64).min(1.0)).product();
+                at(0, first) * at(ADDRESS_LEN - last.len(), last) * floating
+            }
+        };
+        1.0 / probability
+    }
+}
+
+/// Whether `chars` fits `part` character for character, `?` fitting anything
+fn glob_part_matches(part: &str, chars: &[u8]) -> bool {
+    part.len() == chars.len() && part.bytes().zip(chars).all(|(expected, c)| expected == b'?' || expected == *c)
+}
+
+/// Chance that the address character at `position` is `c`, or anything for `?`
+fn glob_char_probability(position: usize, c: char) -> f64 {
+    match (ADDRESS_LEN - 1 - position, c) {
+        (_, '?') => 1.0,
+        (0, c) => f64::from(u8::from(c == 'd')),
+        (1, c) => f64::from(u8::from("aiqy".contains(c))) / 4.0,
+        _ => 1.0 / 32.0,
     }
 }
 
+impl std::fmt::Display for Glob {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// Patterns combined with and, or and not
+///
+/// Written as `kind:pattern` terms, with the kinds of the control socket's
+/// `ADD` command, joined by `&` (or `and`), `|` (or `or`) and `!` (or `not`)
+/// and grouped with parentheses, e.g. `prefix:crypto & !contains:xxx`. Not
+/// binds tightest, then and, then or. A pattern with spaces or any of
+/// `()&|!` goes in double quotes, e.g. `regex:"^(tor|onion)"`.
+#[derive(Debug, Clone)]
+pub enum PatternExpr {
+    /// Every pattern matches
+    All(Vec<MatchSpec>),
+    /// At least one pattern matches
+    Any(Vec<MatchSpec>),
+    /// The pattern does not match
+    Not(Box<MatchSpec>),
+}
+
+/// A piece of a [`PatternExpr`]'s text
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ExprToken {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Term(String, String),
+}
+
+impl PatternExpr {
+    /// Parse an expression; a single term gives its plain spec rather than an expression
+    pub fn parse(text: &str) -> Result<MatchSpec> {
+        let tokens = expr_tokens(text)?;
+        let mut position = 0;
+        let spec = parse_any(&tokens, &mut position)?;
+        if position < tokens.len() {
+            anyhow::bail!("Unexpected {} in expression {:?}", token_text(&tokens[position]), text);
+        }
+        Ok(spec)
+    }
+
+    pub fn is_match(&self, address: &str) -> bool {
+        match self {
+            PatternExpr::All(specs) => specs.iter().all(|spec| spec.is_match(address)),
+            PatternExpr::Any(specs) => specs.iter().any(|spec| spec.is_match(address)),
+            PatternExpr::Not(spec) => !spec.is_match(address),
+        }
+    }
+
+    /// Expected candidates per match, treating the parts as independent
+    ///
+    /// `None` if a part (e.g. a regex) has no estimate.
+    pub fn expected_attempts(&self) -> Option<f64> {
+        let probability = |spec: &MatchSpec| spec.expected_attempts().map(|attempts| 1.0 / attempts);
+        let probability = match self {
+            PatternExpr::All(specs) => specs.iter().map(probability).product::<Option<f64>>()?,
+            PatternExpr::Any(specs) => 1.0 - specs.iter().map(|spec| probability(spec).map(|p| 1.0 - p)).product::<Option<f64>>()?,
+            PatternExpr::Not(spec) => 1.0 - probability(spec)?,
+        };
+        Some(1.0 / probability)
+    }
+
+    /// The combined patterns
+    fn specs(&self) -> &[MatchSpec] {
+        match self {
+            PatternExpr::All(specs) | PatternExpr::Any(specs) => specs,
+            PatternExpr::Not(spec) => std::slice::from_ref(&**spec),
+        }
+    }
+}
+
+/// Split an expression into operators and `kind:pattern` terms
+fn expr_tokens(text: &str) -> Result<Vec<ExprToken>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        let operator = match c {
+            '(' => Some(ExprToken::Open),
+            ')' => Some(ExprToken::Close),
+            '&' => Some(ExprToken::And),
+            '|' => Some(ExprToken::Or),
+            '!' => Some(ExprToken::Not),
+            _ => None,
+        };
+        if c.is_whitespace() || operator.is_some() {
+            chars.next();
+            tokens.extend(operator);
+            continue;
+        }
+
+        let (mut word, mut quoted) = (String::new(), false);
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() || "()&|!".contains(c) {
+                break;
+            }
+            chars.next();
+            if c != '"' {
+                word.push(c);
+                continue;
+            }
+            quoted = true;
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => word.push(c),
+                    None => anyhow::bail!("Unclosed quote in expression {:?}", text),
+                }
+            }
+        }
+        let token = match word.to_ascii_lowercase().as_str() {
+            "and" if !quoted => ExprToken::And,
+            "or" if !quoted => ExprToken::Or,
+            "not" if !quoted => ExprToken::Not,
+            _ => match word.split_once(':') {
+                Some((kind, pattern)) => ExprToken::Term(kind.to_string(), pattern.to_string()),
+                None => anyhow::bail!("Expected kind:pattern such as prefix:{} in expression {:?}", word, text),
+            },
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn parse_any(tokens: &[ExprToken], position: &mut usize) -> Result<MatchSpec> {
+    let mut specs = vec![parse_all(tokens, position)?];
+    while tokens.get(*position) == Some(&ExprToken::Or) {
+        *position += 1;
+        specs.push(parse_all(tokens, position)?);
+    }
+    Ok(match specs.len() {
+        1 => specs.remove(0),
+        _ => MatchSpec::Expr(PatternExpr::Any(specs)),
+    })
+}
+
+fn parse_all(tokens: &[ExprToken], position: &mut usize) -> Result<MatchSpec> {
+    let mut specs = vec![parse_not(tokens, position)?];
+    while tokens.get(*position) == Some(&ExprToken::And) {
+        *position += 1;
+        specs.push(parse_not(tokens, position)?);
+    }
+    Ok(match specs.len() {
+        1 => specs.remove(0),
+        _ => MatchSpec::Expr(PatternExpr::All(specs)),
+    })
+}
+
+fn parse_not(tokens: &[ExprToken], position: &mut usize) -> Result<MatchSpec> {
+    let Some(token) = tokens.get(*position) else {
+        anyhow::bail!("Expression ends where a kind:pattern term is expected");
+    };
+    *position += 1;
+    match token {
+        ExprToken::Not => Ok(MatchSpec::Expr(PatternExpr::Not(Box::new(parse_not(tokens, position)?)))),
+        ExprToken::Open => {
+            let spec = parse_any(tokens, position)?;
+            if tokens.get(*position) != Some(&ExprToken::Close) {
+                anyhow::bail!("Missing ) in expression");
+            }
+            *position += 1;
+            Ok(spec)
+        }
+        ExprToken::Term(kind, pattern) => {
+            match PatternKind::parse(kind)? {
+                PatternKind::Exclude => anyhow::bail!("Write exclude:{} as !contains:{}", pattern, pattern),
+                PatternKind::Expr => anyhow::bail!("Group terms with parentheses rather than expr:"),
+                kind => pattern_spec(kind, pattern),
+            }
+        }
+        token => anyhow::bail!("Unexpected {} where a kind:pattern term is expected", token_text(token)),
+    }
+}
+
+fn token_text(token: &ExprToken) -> String {
+    match token {
+        ExprToken::Open => "(".to_string(),
+        ExprToken::Close => ")".to_string(),
+        ExprToken::And => "&".to_string(),
+        ExprToken::Or => "|".to_string(),
+        ExprToken::Not => "!".to_string(),
+        ExprToken::Term(kind, pattern) => format!("{}:{}", kind, pattern),
+    }
+}
+
+/// Written back in the syntax [`PatternExpr::parse`] reads
+impl std::fmt::Display for PatternExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let term = |spec: &MatchSpec, group: bool| match spec {
+            MatchSpec::Expr(expr) if group => format!("({})", expr),
+            MatchSpec::Expr(expr) => expr.to_string(),
+            spec => {
+                let (kind, pattern) = PatternKind::of(spec, false);
+                match pattern.contains(|c: char| c.is_whitespace() || "()&|!\"".contains(c)) {
+                    true => format!("{}:\"{}\"", kind.as_str(), pattern),
+                    false => format!("{}:{}", kind.as_str(), pattern),
+                }
+            }
+        };
+        match self {
+            PatternExpr::All(specs) => {
+                let terms: Vec<String> = specs
+                    .iter()
+                    .map(|spec| term(spec, matches!(spec, MatchSpec::Expr(PatternExpr::Any(_)))))
+                    .collect();
+                write!(f, "{}", terms.join(" & "))
+            }
+            PatternExpr::Any(specs) => {
+                let terms: Vec<String> = specs.iter().map(|spec| term(spec, false)).collect();
+                write!(f, "{}", terms.join(" | "))
+            }
+            PatternExpr::Not(spec) => write!(f, "!{}", term(spec, !matches!(**spec, MatchSpec::Expr(PatternExpr::Not(_))))),
+        }
+    }
+}
+
+/// Bit set of the 5-bit values of `chars`
+fn char_set(chars: &str) -> u32 {
+    chars.chars().filter_map(|c| BASE32_ALPHABET.find(c)).fold(0, |set, bits| set | 1 << bits)
+}
+
+impl std::fmt::Display for Matcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let includes: Vec<String> = self.includes.iter().map(|spec| spec.to_string()).collect();
+        write!(f, "{}", includes.join(" or "))?;
+        if !self.excludes.is_empty() {
+            let excludes: Vec<String> = self.excludes.iter().map(|spec| spec.to_string()).collect();
+            write!(f, ", but not {}", excludes.join(" or "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Matcher that can be replaced while workers are searching with it
+///
+/// Clones share the same matcher. Workers check a version counter before each
+/// candidate, which is a single atomic load, and only take the lock after a
+/// [`replace`](Self::replace).
+#[derive(Debug, Clone, Default)]
+pub struct LiveMatcher {
+    current: Arc<RwLock<Arc<Matcher>>>,
+    version: Arc<AtomicU64>,
+}
+
+impl LiveMatcher {
+    pub fn new(matcher: Matcher) -> Self {
+        Self {
+            current: Arc::new(RwLock::new(Arc::new(matcher))),
+            version: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// The matcher in effect right now
+    pub fn current(&self) -> Arc<Matcher> {
+        Arc::clone(&self.current.read().unwrap())
+    }
+
+    /// Incremented on every replacement
+    pub fn version(&self) -> u64 {
+        self.version.load(Ordering::Acquire)
+    }
+
+    /// Atomically switch all workers to `matcher`
+    pub fn replace(&self, matcher: Matcher) {
+        *self.current.write().unwrap() = Arc::new(matcher);
+        self.version.fetch_add(1, Ordering::Release);
+    }
+
+    /// Replace the matcher with `update(current)` unless it fails
+    ///
+    /// Concurrent updates are serialized, so none is lost.
+    pub fn update<T>(&self, update: impl FnOnce(&Matcher) -> Result<(Matcher, T)>) -> Result<T> {
+        let mut current = self.current.write().unwrap();
+        let (matcher, output) = update(&current)?;
+        *current = Arc::new(matcher);
+        self.version.fetch_add(1, Ordering::Release);
+        Ok(output)
+    }
+}
+
+/// Core search loop shared by all pattern-based generators
+///
+/// `keep_going` is consulted before every candidate.
+fn search(
+    predicate: impl Fn(&str) -> bool,
+    stats: &GenerationStats,
+    keep_going: impl FnMut() -> bool,
+) -> Result<Option<OnionResult>> {
+    search_keys(&mut RandomKeys::new(), |_| true, predicate, stats, None, keep_going)
+}
+
+/// [`search`] over candidates drawn from `source`
+///
+/// Candidates whose raw public key fails `key_filter` are counted but never
+/// encoded, which saves the checksum hash and base32 encoding for most of them.
+/// Those picked for `sample` are always encoded, so it is not skewed by the filter.
+fn search_keys(
+    source: &mut dyn CandidateSource,
+    key_filter: impl Fn(&[u8; 32]) -> bool,
+    predicate: impl Fn(&str) -> bool,
+    stats: &GenerationStats,
+    sample: Option<&CandidateSample>,
+    mut keep_going: impl FnMut() -> bool,
+) -> Result<Option<OnionResult>> {
+    let mut sampler = sample.map(CandidateSample::sampler);
+    while keep_going() {
+        let public_key = source.next_public_key()?;
+        stats.increment_generated();
+        let picked = sampler.as_mut().is_some_and(|sampler| sampler.pick());
+        if !picked && !key_filter(&public_key) {
+            continue;
+        }
+        let hostname = encode_onion_address(&public_key);
+
+        if predicate(&hostname) {
+            stats.increment_found();
+            return source.onion_result(hostname).map(Some);
+        }
+        if let (true, Some(sampler)) = (picked, &sampler) {
+            sampler.record(&hostname)?;
+        }
+    }
+
+    Ok(None)
+}
+
 /// Encode public key to onion address
 fn encode_public_key(public_key: &[u8]) -> Result<String> {
-    if public_key.len() != 32 {
-        return Err(anyhow::anyhow!("Public key must be 32 bytes"));
+    let public_key: &[u8; 32] = public_key
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Public key must be 32 bytes"))?;
+    Ok(encode_onion_address(public_key))
+}
+
+/// The v3 hostname of a raw public key, with the `.onion` suffix
+///
+/// The encoding the search itself uses, for tools that produce keys elsewhere.
+pub fn hostname_for_public_key(public_key: &[u8; 32]) -> String {
+    encode_onion_address(public_key)
+}
+
+/// Whether the address of `public_key` matches `spec`
+///
+/// Prefixes and `--at` text within the first 51 characters, which are all
+/// key bits, are compared on the bits without encoding the key or
+/// allocating. Other patterns are checked on the encoded address.
+pub fn public_key_matches_prefix(public_key: &[u8; 32], spec: &MatchSpec) -> bool {
+    let fixed = match spec {
+        MatchSpec::Prefix(prefix) => Some((0, prefix.as_str())),
+        MatchSpec::At(offset, text) => Some((*offset, text.as_str())),
+        _ => None,
+    };
+    match fixed {
+        Some((offset, text)) if offset + text.len() <= KEY_CHARS => text
+            .bytes()
+            .enumerate()
+            .all(|(i, c)| char_value(c) == Some(char_bits(public_key, offset + i) as usize)),
+        _ => spec.is_match(hostname_for_public_key(public_key).trim_end_matches(".onion")),
     }
-    
-    // Calculate checksum
-    let checksum = calculate_checksum(public_key)?;
-    
-    // Construct address data: public_key + checksum + version
-    let mut address_data = Vec::new();
-    address_data.extend_from_slice(public_key);
-    address_data.extend_from_slice(&checksum);
-    address_data.push(0x03); // Version byte
-    
-    // Encode to base32 and add .onion suffix
-    let encoded = base32_encode(&address_data);
-    Ok(format!("{}.onion", encoded))
+}
+
+/// Most variants [`expand_variants`] spells out for one word
+pub const MAX_VARIANTS: usize = 4096;
+
+/// Look-alikes of each character; digits that never appear in addresses only have letters
+const LEET: &[(char, &str)] = &[
+    ('a', "4"), ('4', "a"), ('b', "6"), ('e', "3"), ('3', "e"), ('g', "6"), ('6', "bg"), ('i', "l"), ('l', "i"),
+    ('s', "5"), ('5', "s"), ('t', "7"), ('7', "t"), ('z', "2"), ('2', "z"), ('0', "o"), ('1', "il"), ('8', "b"),
+    ('9', "g"),
+];
+
+/// Every leetspeak spelling of `word` that an address can hold
+///
+/// Each character may be swapped for a look-alike within the address
+/// alphabet, such as `4` for `a`, `3` for `e` or `l` for `i`. Digits that
+/// never appear in addresses, such as `0`, are always swapped for their
+/// letter. The word as given comes first when it is valid. Fails if the
+/// word is empty, a character has no spelling or there would be more than
+/// [`MAX_VARIANTS`].
+pub fn expand_variants(word: &str) -> Result<Vec<String>> {
+    let word = word.to_lowercase();
+    if word.is_empty() {
+        return Err(anyhow::anyhow!("Cannot expand an empty word")).with_code(ErrorCode::InvalidPattern);
+    }
+    let mut choices = Vec::new();
+    for c in word.chars() {
+        let mut options: Vec<char> = BASE32_ALPHABET.contains(c).then_some(c).into_iter().collect();
+        if let Some((_, look_alikes)) = LEET.iter().find(|&&(from, _)| from == c) {
+            options.extend(look_alikes.chars());
+        }
+        if options.is_empty() {
+            return Err(anyhow::anyhow!("{:?} in {:?} has no spelling in an address", c, word)).with_code(ErrorCode::InvalidPattern);
+        }
+        choices.push(options);
+    }
+    let count = choices
+        .iter()
+        .try_fold(1usize, |count, options| count.checked_mul(options.len()))
+        .filter(|&count| count <= MAX_VARIANTS)
+        .ok_or_else(|| anyhow::anyhow!("{:?} has more than {} variants; expand a shorter word", word, MAX_VARIANTS))
+        .with_code(ErrorCode::InvalidPattern)?;
+
+    // Variant n picks its characters by the digits of n, the last character changing fastest
+    let mut variants = Vec::with_capacity(count);
+    for mut n in 0..count {
+        let mut variant = vec![' '; choices.len()];
+        for (slot, options) in variant.iter_mut().zip(&choices).rev() {
+            *slot = options[n % options.len()];
+            n /= options.len();
+        }
+        variants.push(variant.into_iter().collect());
+    }
+    Ok(variants)
+}
+
+/// Decode a v3 onion address (with or without `.onion`) into its public key
+///
+/// Verifies the length, version byte and checksum; see [`parse_onion_address`]
+/// for the individual errors.
+pub fn decode_onion_address(address: &str) -> Result<[u8; 32]> {
+    let parsed = parse_onion_address(address.trim())
+        .map_err(|e| anyhow::anyhow!("Not a valid v3 onion address ({}): {}", e, address.trim()))?;
+    Ok(parsed.public_key)
 }
 
 #[cfg(test)]
@@ -100,7 +1432,472 @@ mod tests {
             generate_with_prefix(&prefixes)
         });
         
-        assert!(!result.is_err());
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_generate_n() {
+        let results = generate_n(5).unwrap();
+
+        assert_eq!(results.len(), 5);
+        assert!(results.iter().all(|r| r.hostname.ends_with(".onion")));
+        assert_ne!(results[0].hostname, results[1].hostname);
+        assert!(generate_n(0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_generate_with_prefix_counted() {
+        let stats = GenerationStats::new();
+        let result = generate_with_prefix_counted(&["a".to_string()], &stats).unwrap();
+        let (generated, found) = stats.get();
+
+        assert!(result.hostname.starts_with('a'));
+        assert!(generated >= 1);
+        assert_eq!(found, 1);
+    }
+
+    #[test]
+    fn test_generate_with_prefix_until_stopped() {
+        let stop = AtomicBool::new(true);
+        let result = generate_with_prefix_until(&["a".to_string()], &GenerationStats::new(), &stop);
+
+        assert!(result.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_generate_with_predicate() {
+        let result = generate_with_predicate(|hostname| hostname.starts_with('b')).unwrap();
+
+        assert!(result.hostname.starts_with('b'));
+        assert!(!result.private_key.is_empty());
+    }
+
+    #[test]
+    fn test_generate_with_predicate_until_stopped() {
+        let stop = AtomicBool::new(true);
+        let result = generate_with_predicate_until(|_| true, &stop).unwrap();
+
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_generate_with_prefix_timeout() {
+        let prefixes = vec!["zzzzzzzzzz".to_string()];
+        let started = Instant::now();
+        let result = generate_with_prefix_timeout(&prefixes, Duration::from_millis(50)).unwrap();
+
+        assert!(result.is_none());
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let anything = vec!["".to_string()];
+        assert!(generate_with_prefix_timeout(&anything, Duration::MAX).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_generate_with_prefix_max_attempts() {
+        let impossible = vec!["zzzzzzzzzz".to_string()];
+        assert!(generate_with_prefix_max_attempts(&impossible, 10).unwrap().is_none());
+        assert!(generate_with_prefix_max_attempts(&impossible, 0).unwrap().is_none());
+
+        let anything = vec!["".to_string()];
+        assert!(generate_with_prefix_max_attempts(&anything, 1).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_matcher_mixed_patterns() {
+        let matcher = Matcher::new(
+            vec![
+                MatchSpec::Suffix("7id".to_string()),
+                MatchSpec::Prefix("tor".to_string()),
+            ],
+            vec![MatchSpec::Contains("xxx".to_string())],
+        );
+        let padding = "a".repeat(50);
+
+        assert!(matches!(matcher.includes()[0], MatchSpec::Prefix(_)));
+        assert!(matcher.is_match(&format!("tor{}bid.onion", padding)));
+        assert!(matcher.is_match(&format!("abc{}7id.onion", padding)));
+        assert!(!matcher.is_match(&format!("abc{}bid.onion", padding)));
+        assert!(!matcher.is_match(&format!("torxxx{}id.onion", &padding[3..])));
+    }
+
+    #[test]
+    fn test_expected_attempts() {
+        let prefix = MatchSpec::Prefix("ab".to_string());
+        let suffix = MatchSpec::Suffix("xid".to_string());
+        let impossible = MatchSpec::Suffix("ab".to_string());
+
+        assert_eq!(prefix.expected_attempts(), Some(1024.0));
+        assert_eq!(suffix.expected_attempts(), Some(128.0));
+        assert_eq!(impossible.expected_attempts(), Some(f64::INFINITY));
+
+        let both = MatchSpec::PrefixSuffix("ab".to_string(), "xid".to_string());
+        assert_eq!(both.expected_attempts(), Some(1024.0 * 128.0));
+        assert!(both.is_match(&format!("ab{}xid", "z".repeat(51))));
+        assert!(!both.is_match(&format!("ab{}", "z".repeat(54))));
+
+        let matcher = Matcher::new(vec![prefix, suffix, impossible], Vec::new());
+        assert_eq!(matcher.expected_attempts(), Some(1.0 / (1.0 / 1024.0 + 1.0 / 128.0)));
+    }
+
+    #[test]
+    fn test_repeated_starts() {
+        assert!(MatchSpec::Run(4).is_match("aaaab"));
+        assert!(!MatchSpec::Run(4).is_match("aaaba"));
+        assert!(MatchSpec::Doubled(3).is_match("xyzxyzq"));
+        assert!(!MatchSpec::Doubled(3).is_match("xyzxyq"));
+        assert_eq!(MatchSpec::Run(4).expected_attempts(), Some(32f64.powi(3)));
+        assert_eq!(MatchSpec::Doubled(3).expected_attempts(), Some(32f64.powi(3)));
+
+        assert!(MatchSpec::Run(54).check_length().is_ok());
+        assert!(MatchSpec::Run(55).check_length().is_err());
+        assert!(MatchSpec::Doubled(27).check_length().is_ok());
+        assert!(MatchSpec::Doubled(28).check_length().is_err());
+        assert!(MatchSpec::Run(1).check_length().is_err());
+
+        assert!(MatchSpec::Palindrome(5).is_match("abxbaq"));
+        assert!(MatchSpec::Palindrome(4).is_match("abbaq"));
+        assert!(!MatchSpec::Palindrome(5).is_match("abxabq"));
+        assert_eq!(MatchSpec::Palindrome(5).expected_attempts(), Some(32f64.powi(2)));
+        assert!(MatchSpec::Palindrome(55).check_length().is_err());
+    }
+
+    #[test]
+    fn test_key_prefix_agrees_with_encoding() {
+        for _ in 0..200 {
+            let (_, verifying_key) = generate_keypair().unwrap();
+            let key = verifying_key.to_bytes();
+            let address = encode_public_key(&key).unwrap();
+
+            for len in [1, 3, 51, 52, 56] {
+                let matcher = Matcher::from_prefixes(&[address[..len].to_string()]);
+                assert!(matcher.key_may_match(&key));
+            }
+            let mut other = address[..8].to_string();
+            other.replace_range(7.., if other.ends_with('a') { "b" } else { "a" });
+            assert!(!Matcher::from_prefixes(&[other]).key_may_match(&key));
+
+            let both = MatchSpec::PrefixSuffix(address[..2].to_string(), address[54..56].to_string());
+            assert!(Matcher::new(vec![both], Vec::new()).key_may_match(&key));
+        }
+
+        // Character classes are checked on the key bits as well
+        for _ in 0..200 {
+            let (_, verifying_key) = generate_keypair().unwrap();
+            let key = verifying_key.to_bytes();
+            let address = encode_public_key(&key).unwrap();
+            for template in ["L{8}", "D.D", "tD{3}", "V{52}", "L{56}"] {
+                let template = CharTemplate::parse(template).unwrap();
+                let matcher = Matcher::new(vec![MatchSpec::Classes(template.clone())], Vec::new());
+                if template.is_match(&address) {
+                    assert!(matcher.key_may_match(&key));
+                }
+                if template.classes.len() <= KEY_CHARS {
+                    assert_eq!(matcher.key_may_match(&key), template.is_match(&address));
+                }
+            }
+        }
+
+        // So are repeated starts, as pairs of equal characters
+        for _ in 0..500 {
+            let (_, verifying_key) = generate_keypair().unwrap();
+            let key = verifying_key.to_bytes();
+            let address = encode_public_key(&key).unwrap();
+            for spec in [MatchSpec::Run(2), MatchSpec::Run(3), MatchSpec::Doubled(2), MatchSpec::Palindrome(5)] {
+                let expected = spec.is_match(&address);
+                assert_eq!(Matcher::new(vec![spec], Vec::new()).key_may_match(&key), expected);
+            }
+        }
+
+        // Suffixes have no key condition, and invalid characters never match
+        let key = [0u8; 32];
+        assert!(Matcher::new(vec![MatchSpec::Suffix("id".to_string())], Vec::new()).key_may_match(&key));
+        assert!(!Matcher::from_prefixes(&["a1".to_string()]).key_may_match(&key));
+    }
+
+    #[test]
+    fn test_char_template() {
+        let letters = CharTemplate::parse("L{8}").unwrap();
+        assert!(letters.is_match(&"abcdefgh".repeat(7)));
+        assert!(!letters.is_match(&format!("abc2{}", "a".repeat(52))));
+        assert!((letters.expected_attempts() / (32.0f64 / 26.0).powi(8) - 1.0).abs() < 1e-12);
+
+        // The last two characters are fixed by the version byte
+        let all_letters = CharTemplate::parse("L{56}").unwrap();
+        assert!((all_letters.expected_attempts() / (32.0f64 / 26.0).powi(54) - 1.0).abs() < 1e-12);
+        assert!(CharTemplate::parse(".{55}D").unwrap().expected_attempts().is_infinite());
+
+        assert_eq!(CharTemplate::parse("torD").unwrap().classes.len(), 4);
+        assert!(CharTemplate::parse("L{57}").is_err());
+        assert!(CharTemplate::parse("X").is_err());
+        assert!(CharTemplate::parse("L{x}").is_err());
+
+        // Bracketed sets, with ranges and negation
+        let tor = CharTemplate::parse("t[ou]r[^a-y]").unwrap();
+        assert_eq!(tor.classes.len(), 4);
+        for (address, expected) in [("torz", true), ("tur7", true), ("tarz", false), ("torb", false)] {
+            assert_eq!(tor.is_match(&format!("{}{}", address, "a".repeat(52))), expected, "{}", address);
+        }
+        assert!((tor.expected_attempts() / (32.0f64.powi(4) / 2.0 / 7.0) - 1.0).abs() < 1e-12);
+        assert_eq!(CharTemplate::parse("[2-4x]{2}").unwrap().classes, vec![char_set("234x"); 2]);
+        for invalid in ["t[o0]r", "[]", "[ab", "[a-3]", "[f-a]", "[a-]"] {
+            assert!(CharTemplate::parse(invalid).is_err(), "{}", invalid);
+        }
+        let key = [0u8; 32];
+        let matcher = Matcher::new(vec![MatchSpec::Classes(CharTemplate::parse("[ab]a").unwrap())], vec![]);
+        assert!(matcher.key_may_match(&key));
+        let matcher = Matcher::new(vec![MatchSpec::Classes(CharTemplate::parse("[^a]").unwrap())], vec![]);
+        assert!(!matcher.key_may_match(&key));
+    }
+
+    #[test]
+    fn test_char_template_rejects_oversized_repeat() {
+        for template in [format!("a.{{{}}}", usize::MAX), format!("L{{{}}}", usize::MAX), "L{55}.{2}".to_string()] {
+            let error = CharTemplate::parse(&template).unwrap_err();
+            assert!(error.to_string().starts_with("Template is longer than an address"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_public_key_matches_prefix() {
+        for _ in 0..50 {
+            let key = *generate_keypair().unwrap().1.as_bytes();
+            let hostname = hostname_for_public_key(&key);
+            assert_eq!(hostname, encode_public_key(&key).unwrap());
+            assert_eq!(decode_onion_address(&hostname).unwrap(), key);
+
+            let address = &hostname[..ADDRESS_LEN];
+            let other = |text: &str| text.chars().map(|c| if c == 'a' { 'b' } else { 'a' }).collect::<String>();
+            for spec in [
+                MatchSpec::Prefix(address[..8].to_string()),
+                MatchSpec::Prefix(address[..KEY_CHARS].to_string()),
+                MatchSpec::Prefix(address[..53].to_string()),
+                MatchSpec::At(20, address[20..25].to_string()),
+                MatchSpec::At(49, address[49..54].to_string()),
+                MatchSpec::Suffix(address[50..].to_string()),
+            ] {
+                assert!(public_key_matches_prefix(&key, &spec), "{} {}", spec, address);
+            }
+            for spec in [
+                MatchSpec::Prefix(other(&address[..8])),
+                MatchSpec::Prefix(format!("{}{}", &address[..50], other(&address[50..51]))),
+                MatchSpec::At(20, other(&address[20..25])),
+                MatchSpec::Prefix("h1dden".to_string()),
+            ] {
+                assert!(!public_key_matches_prefix(&key, &spec), "{} {}", spec, address);
+            }
+        }
+    }
+
+    #[test]
+    fn test_expand_variants() {
+        assert_eq!(expand_variants("Tea").unwrap(), ["tea", "te4", "t3a", "t34", "7ea", "7e4", "73a", "734"]);
+        assert_eq!(expand_variants("h0ck").unwrap(), ["hock"]);
+        assert_eq!(expand_variants("n1x").unwrap(), ["nix", "nlx"]);
+        assert_eq!(expand_variants("6").unwrap(), ["6", "b", "g"]);
+        assert_eq!(ErrorCode::of(&expand_variants("").unwrap_err()), ErrorCode::InvalidPattern);
+        assert!(expand_variants("tor!").is_err());
+        // 2^12 variants still fit, 2^13 do not
+        assert_eq!(expand_variants("tatatatatata").unwrap().len(), MAX_VARIANTS);
+        assert!(expand_variants("tatatatatatat").is_err());
+        assert!(expand_variants("hidden").unwrap().iter().all(|variant| variant.chars().all(|c| BASE32_ALPHABET.contains(c))));
+    }
+
+    #[test]
+    fn test_positioned_pattern() {
+        let at = MatchSpec::parse_at("10:TOR").unwrap();
+        assert!(matches!(&at, MatchSpec::At(10, text) if text == "tor"));
+        let address = |at: usize| format!("{}tor{}", "a".repeat(at), "a".repeat(ADDRESS_LEN - 3 - at));
+        assert!(at.is_match(&address(10)));
+        assert!(!at.is_match(&address(9)) && !at.is_match(&address(11)));
+        assert_eq!(at.expected_attempts(), Some(32f64.powi(3)));
+        // The last two characters are fixed by the checksum and version byte
+        assert_eq!(MatchSpec::parse_at("53:xyd").unwrap().expected_attempts(), Some(128.0));
+        assert!(MatchSpec::parse_at("54:xd").unwrap().expected_attempts().unwrap().is_infinite());
+        for invalid in ["10", "x:tor", "10:", "55:ab", "3:t0r"] {
+            assert!(MatchSpec::parse_at(invalid).is_err(), "{}", invalid);
+        }
+
+        let matcher = Matcher::new(vec![MatchSpec::parse_at("3:ab").unwrap()], vec![]);
+        for _ in 0..2000 {
+            let key = *generate_keypair().unwrap().1.as_bytes();
+            let address = encode_public_key(&key).unwrap();
+            assert_eq!(matcher.key_may_match(&key), matcher.is_match(&address), "{}", address);
+        }
+        assert!(Matcher::new(vec![MatchSpec::At(2, "aa".to_string())], vec![]).key_may_match(&[0; 32]));
+        assert!(!Matcher::new(vec![MatchSpec::At(2, "ab".to_string())], vec![]).key_may_match(&[0; 32]));
+    }
+
+    #[test]
+    fn test_glob() {
+        let glob = Glob::parse("foo??bar*").unwrap();
+        let address = |text: &str| format!("{}{}", text, "a".repeat(ADDRESS_LEN - text.len()));
+        assert!(glob.is_match(&address("foo27bar")));
+        assert!(!glob.is_match(&address("foo2bar")));
+        assert!(!glob.is_match(&address("fooa7baz")));
+        assert!((glob.expected_attempts() / 32f64.powi(6) - 1.0).abs() < 1e-12);
+
+        let middle = Glob::parse("a*xyz*?d").unwrap();
+        assert!(middle.is_match(&format!("a{}xyz{}qd", "b".repeat(20), "c".repeat(30))));
+        assert!(!middle.is_match(&format!("a{}xyz{}qd", "b".repeat(20), "c".repeat(30)).replace("xyz", "xy2")));
+        assert!(Glob::parse("*ab").unwrap().expected_attempts().is_infinite());
+        assert!(Glob::parse("abc").unwrap().expected_attempts().is_infinite());
+        assert!(!Glob::parse("abc").unwrap().is_match(&address("abc")));
+
+        // The part before the first `*` rules out keys before encoding
+        let matcher = Matcher::new(vec![MatchSpec::Glob(glob.clone())], Vec::new());
+        for _ in 0..200 {
+            let key = *generate_keypair().unwrap().1.as_bytes();
+            let candidate = encode_public_key(&key).unwrap();
+            if matcher.key_may_match(&key) {
+                assert!(candidate.starts_with("foo"));
+            }
+            assert_eq!(matcher.is_match(&candidate), glob.is_match(&candidate));
+        }
+
+        assert!(Glob::is_glob("ab?") && Glob::is_glob("ab*") && !Glob::is_glob("abc"));
+        assert!(Glob::parse("a?1*").is_err());
+        assert!(Glob::parse("??*").is_err());
+        assert!(Glob::parse(&"a?".repeat(29)).is_err());
+    }
+
+    #[test]
+    fn test_pattern_expr() {
+        let address = |text: &str| format!("{}{}ad", text, "b".repeat(ADDRESS_LEN - 2 - text.len()));
+        let spec = PatternExpr::parse("(prefix:AB or prefix:cd) and not contains:xxx & !suffix:qd").unwrap();
+        assert_eq!(spec.to_string(), "expression \"(prefix:ab | prefix:cd) & !contains:xxx & !suffix:qd\"");
+        assert!(spec.is_match(&address("ab")));
+        assert!(spec.is_match(&address("cd")));
+        assert!(!spec.is_match(&address("ef")));
+        assert!(!spec.is_match(&address("abxxx")));
+        let attempts = spec.expected_attempts().unwrap();
+        // 512 for either prefix, a quarter of addresses end in qd
+        assert!((attempts / (512.0 / 0.75) - 1.0).abs() < 0.01, "{}", attempts);
+
+        // Written back, an expression reads the same
+        let MatchSpec::Expr(expr) = &spec else { panic!("{:?}", spec) };
+        let again = PatternExpr::parse(&expr.to_string()).unwrap();
+        assert_eq!(again.to_string(), spec.to_string());
+        assert!(matches!(PatternExpr::parse("prefix:ab").unwrap(), MatchSpec::Prefix(_)));
+        #[cfg(feature = "regex")]
+        assert_eq!(PatternExpr::parse(r#"regex:"^(ab|cd)" & !run:3"#).unwrap().to_string(), r#"expression "regex:"^(ab|cd)" & !run:3""#);
+        for bad in ["prefix:ab &", "(prefix:ab", "prefix:ab)", "ab", "exclude:xx", "prefix:ab \"", "suffix:ab"] {
+            assert!(PatternExpr::parse(bad).is_err(), "{}", bad);
+        }
+
+        // The alternatives' prefixes rule out keys before encoding
+        let matcher = Matcher::new(vec![spec.clone()], Vec::new());
+        assert_eq!(matcher.engine(), MatchEngine::KeyMask);
+        // Seeded keys, so every run checks the same candidates
+        for counter in 0..500 {
+            let key = seeded_keypair(&[3u8; 32], 0, counter).1.to_bytes();
+            let hostname = encode_public_key(&key).unwrap();
+            let address = hostname.trim_end_matches(".onion");
+            if matcher.key_may_match(&key) {
+                assert!(address.starts_with("ab") || address.starts_with("cd"));
+            }
+            assert_eq!(matcher.is_match(address), spec.is_match(address), "{}", address);
+        }
+        assert!(Matcher::new(vec![PatternExpr::parse("prefix:ab | suffix:id").unwrap()], Vec::new()).key_patterns.is_none());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_matcher_regex() {
+        let regex = regex::Regex::new("^ab(cd|ef)").unwrap();
+        let matcher = Matcher::new(vec![MatchSpec::Regex(regex)], Vec::new());
+
+        assert!(matcher.is_match("abefzzz.onion"));
+        assert!(!matcher.is_match("abzzzzz.onion"));
+
+        for (regex, prefix) in [
+            ("^foo(bar|baz)", "foo"),
+            ("^ab+c", "ab"),
+            ("^abc?d", "ab"),
+            ("^ab{2}", "a"),
+            ("^a[|]b", "a"),
+            ("^ab\\|cd", "ab"),
+            ("^ab|cd", ""),
+            ("foo", ""),
+            ("(?i)^foo", ""),
+        ] {
+            assert_eq!(regex_prefix(regex), prefix, "{}", regex);
+        }
+        for regex in ["^tor0", "^Tor", "^ab8c"] {
+            assert!(check_regex_start(regex).is_err(), "{}", regex);
+        }
+        for regex in ["^tor0?", "(?i)^Tor", "^ab|0", "^ab\\d", "tor0"] {
+            assert!(check_regex_start(regex).is_ok(), "{}", regex);
+        }
+        // Candidates not starting with the literal prefix are rejected from the key
+        for _ in 0..200 {
+            let key = generate_keypair().unwrap().1.to_bytes();
+            let address = encode_public_key(&key).unwrap();
+            assert_eq!(matcher.key_may_match(&key), address.starts_with("ab"));
+        }
+    }
+
+    #[test]
+    fn test_generate_with_matcher_until() {
+        let matcher = Matcher::new(
+            vec![MatchSpec::Suffix("id".to_string())],
+            vec![MatchSpec::Prefix("a".to_string())],
+        );
+        let stop = AtomicBool::new(false);
+        let result = generate_with_matcher_until(&matcher, &GenerationStats::new(), &stop)
+            .unwrap()
+            .unwrap();
+
+        assert!(result.hostname.ends_with("id.onion"));
+        assert!(!result.hostname.starts_with('a'));
+    }
+
+    #[test]
+    fn test_live_matcher_switches_mid_search() {
+        let live = LiveMatcher::new(Matcher::from_prefixes(&["2222222222".to_string()]));
+        let stop = AtomicBool::new(false);
+
+        let result = std::thread::scope(|scope| {
+            let search = scope.spawn(|| generate_with_live_matcher_until(&live, None, &GenerationStats::new(), &stop));
+            std::thread::sleep(Duration::from_millis(50));
+            live.replace(Matcher::from_prefixes(&["a".to_string()]));
+            search.join().unwrap()
+        });
+
+        assert_eq!(live.version(), 1);
+        assert!(result.unwrap().unwrap().hostname.starts_with('a'));
+    }
+
+    #[test]
+    fn test_seeded_search_is_reproducible() {
+        let matcher = Matcher::from_prefixes(&["a".to_string()]);
+        let stop = AtomicBool::new(false);
+        let run = || {
+            let mut keys = SeededKeys::new([1u8; 32], 3);
+            let stats = GenerationStats::new();
+            let result = generate_with_matcher_seeded_until(&matcher, &mut keys, &stats, &stop)
+                .unwrap()
+                .unwrap();
+            assert_eq!(u128::from(keys.counter()), stats.get().0);
+            result.hostname
+        };
+
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn test_calibrate_rate() {
+        let rate = calibrate_rate(Duration::from_millis(50)).unwrap();
+        assert!(rate > 0.0);
+    }
+
+    #[test]
+    fn test_par_candidates_find_any() {
+        let result = par_candidates()
+            .find_any(|r| r.hostname.starts_with('a'))
+            .unwrap();
+
+        assert!(result.hostname.starts_with('a'));
     }
 
     #[test]
@@ -112,6 +1909,20 @@ mod tests {
         assert!(address.len() > 10);
     }
 
+    #[test]
+    fn test_decode_onion_address() {
+        let result = generate_onion_address().unwrap();
+        let public_key = decode_onion_address(&result.hostname).unwrap();
+
+        assert_eq!(encode_public_key(&public_key).unwrap(), result.hostname);
+        assert!(decode_onion_address(result.hostname.trim_end_matches(".onion")).is_ok());
+
+        let mut corrupted = result.hostname.clone();
+        corrupted.replace_range(0..1, if corrupted.starts_with('a') { "b" } else { "a" });
+        assert!(decode_onion_address(&corrupted).is_err());
+        assert!(decode_onion_address("example.onion").is_err());
+    }
+
     #[test]
     fn test_invalid_public_key_length() {
         let invalid_key = [0u8; 31]; // Wrong length
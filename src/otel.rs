@@ -0,0 +1,250 @@
+use crate::config::OtelConfig;
+use crate::sink::{RateMeter, ResultSink};
+use crate::{Matcher, OnionResult};
+use anyhow::Result;
+use rand::RngCore;
+use serde_json::{json, Value};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long a single export request may take before it is abandoned
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// OTLP span kind "internal"
+const SPAN_KIND_INTERNAL: u8 = 1;
+
+/// OTLP aggregation temporality "cumulative"
+const CUMULATIVE: u8 = 2;
+
+/// Exports the search as OpenTelemetry traces and metrics over OTLP/HTTP JSON
+///
+/// The whole run is one `onion.search` span, exported when the pool shuts
+/// down, with an `onion.find` child span per found address (hostname only,
+/// never keys). Every statistics update exports the generated and found
+/// counters and the current rate. Export failures are reported on stderr and
+/// never stop the search.
+pub struct OtelSink {
+    endpoint: String,
+    headers: Vec<(String, String)>,
+    agent: ureq::Agent,
+    resource: Value,
+    patterns: String,
+    trace_id: String,
+    job_span_id: String,
+    started: u64,
+    pending_spans: Vec<Value>,
+    totals: (u128, u128),
+    meter: RateMeter,
+}
+
+impl OtelSink {
+    pub fn new(config: &OtelConfig, matcher: &Matcher) -> Self {
+        Self {
+            endpoint: config.endpoint.trim_end_matches('/').to_string(),
+            headers: config.headers.clone().into_iter().collect(),
+            agent: ureq::AgentBuilder::new().timeout(EXPORT_TIMEOUT).build(),
+            resource: json!({
+                "attributes": [
+                    attribute("service.name", json!({ "stringValue": config.service_name })),
+                    attribute("service.version", json!({ "stringValue": env!("CARGO_PKG_VERSION") })),
+                ],
+            }),
+            patterns: matcher.to_string(),
+            trace_id: random_id::<16>(),
+            job_span_id: random_id::<8>(),
+            started: unix_nanos(),
+            pending_spans: Vec::new(),
+            totals: (0, 0),
+            meter: RateMeter::new(),
+        }
+    }
+
+    fn find_span(&self, result: &OnionResult) -> Value {
+        let now = unix_nanos().to_string();
+        json!({
+            "traceId": self.trace_id,
+            "spanId": random_id::<8>(),
+            "parentSpanId": self.job_span_id,
+            "name": "onion.find",
+            "kind": SPAN_KIND_INTERNAL,
+            "startTimeUnixNano": now,
+            "endTimeUnixNano": now,
+            "attributes": [attribute("onion.hostname", json!({ "stringValue": result.hostname }))],
+        })
+    }
+
+    fn job_span(&self) -> Value {
+        let (generated, found) = self.totals;
+        json!({
+            "traceId": self.trace_id,
+            "spanId": self.job_span_id,
+            "name": "onion.search",
+            "kind": SPAN_KIND_INTERNAL,
+            "startTimeUnixNano": self.started.to_string(),
+            "endTimeUnixNano": unix_nanos().to_string(),
+            "attributes": [
+                attribute("onion.patterns", json!({ "stringValue": self.patterns })),
+                attribute("onion.generated", json!({ "intValue": generated.to_string() })),
+                attribute("onion.found", json!({ "intValue": found.to_string() })),
+            ],
+        })
+    }
+
+    fn traces_body(&self, spans: Vec<Value>) -> Value {
+        json!({
+            "resourceSpans": [{
+                "resource": self.resource,
+                "scopeSpans": [{ "scope": scope(), "spans": spans }],
+            }],
+        })
+    }
+
+    fn metrics_body(&self, generated: u128, found: u128, rate: f64) -> Value {
+        let start = self.started.to_string();
+        let now = unix_nanos().to_string();
+        let counter = |name: &str, unit: &str, value: u128| {
+            json!({
+                "name": name,
+                "unit": unit,
+                "sum": {
+                    "dataPoints": [{
+                        "asInt": value.to_string(),
+                        "startTimeUnixNano": start,
+                        "timeUnixNano": now,
+                    }],
+                    "aggregationTemporality": CUMULATIVE,
+                    "isMonotonic": true,
+                },
+            })
+        };
+
+        json!({
+            "resourceMetrics": [{
+                "resource": self.resource,
+                "scopeMetrics": [{
+                    "scope": scope(),
+                    "metrics": [
+                        counter("onion.keys.generated", "{key}", generated),
+                        counter("onion.addresses.found", "{address}", found),
+                        {
+                            "name": "onion.keys.rate",
+                            "unit": "{key}/s",
+                            "gauge": { "dataPoints": [{ "asDouble": rate, "timeUnixNano": now }] },
+                        },
+                    ],
+                }],
+            }],
+        })
+    }
+
+    fn export(&self, signal: &str, body: Value) {
+        let mut request = self.agent.post(&format!("{}/v1/{}", self.endpoint, signal));
+        for (name, value) in &self.headers {
+            request = request.set(name, value);
+        }
+        if let Err(e) = request.send_json(body) {
+            eprintln!("[!] OpenTelemetry {} export failed: {}", signal, e);
+        }
+    }
+
+    fn export_pending_spans(&mut self) {
+        if !self.pending_spans.is_empty() {
+            let spans = std::mem::take(&mut self.pending_spans);
+            self.export("traces", self.traces_body(spans));
+        }
+    }
+}
+
+impl ResultSink for OtelSink {
+    fn found(&mut self, result: &OnionResult) -> Result<()> {
+        let span = self.find_span(result);
+        self.pending_spans.push(span);
+        Ok(())
+    }
+
+    fn stats(&mut self, generated: u128, found: u128) -> Result<()> {
+        self.totals = (generated, found);
+        let rate = self.meter.update(generated);
+        self.export("metrics", self.metrics_body(generated, found, rate));
+        self.export_pending_spans();
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let span = self.job_span();
+        self.pending_spans.push(span);
+        self.export_pending_spans();
+        Ok(())
+    }
+}
+
+fn scope() -> Value {
+    json!({ "name": "onion-generator", "version": env!("CARGO_PKG_VERSION") })
+}
+
+fn attribute(key: &str, value: Value) -> Value {
+    json!({ "key": key, "value": value })
+}
+
+/// Random non-zero trace or span id as lowercase hex
+fn random_id<const N: usize>() -> String {
+    let mut id = [0u8; N];
+    while id.iter().all(|&b| b == 0) {
+        rand::thread_rng().fill_bytes(&mut id);
+    }
+    id.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unix_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyCustody;
+
+    fn sink() -> OtelSink {
+        let config = OtelConfig {
+            endpoint: "http://localhost:4318/".to_string(),
+            service_name: "test".to_string(),
+            headers: Default::default(),
+        };
+        OtelSink::new(&config, &Matcher::from_prefixes(&["ab".to_string()]))
+    }
+
+    #[test]
+    fn test_find_spans_are_children_of_the_job() {
+        let mut sink = sink();
+        let result = OnionResult {
+            hostname: "abc.onion".to_string(),
+            public_key: "PUB".to_string(),
+            private_key: "SECRET".to_string(),
+            custody: KeyCustody::Local,
+            seed: None,
+        };
+        sink.found(&result).unwrap();
+
+        let body = sink.traces_body(vec![sink.pending_spans[0].clone(), sink.job_span()]);
+        let spans = &body["resourceSpans"][0]["scopeSpans"][0]["spans"];
+
+        assert_eq!(sink.endpoint, "http://localhost:4318");
+        assert_eq!(spans[0]["parentSpanId"], spans[1]["spanId"]);
+        assert_eq!(spans[0]["traceId"], spans[1]["traceId"]);
+        assert_eq!(spans[0]["traceId"].as_str().unwrap().len(), 32);
+        assert!(!body.to_string().contains("SECRET"));
+    }
+
+    #[test]
+    fn test_metrics_body() {
+        let body = sink().metrics_body(1000, 2, 50.0);
+        let metrics = &body["resourceMetrics"][0]["scopeMetrics"][0]["metrics"];
+
+        assert_eq!(metrics[0]["name"], "onion.keys.generated");
+        assert_eq!(metrics[0]["sum"]["dataPoints"][0]["asInt"], "1000");
+        assert_eq!(metrics[0]["sum"]["isMonotonic"], true);
+        assert_eq!(metrics[2]["gauge"]["dataPoints"][0]["asDouble"], 50.0);
+    }
+}
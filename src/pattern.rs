@@ -0,0 +1,157 @@
+use crate::{check_address_chars, CharTemplate, Glob, MatchSpec, PatternExpr, ADDRESS_LEN};
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// Kind of a pattern, as named in pattern expressions and control commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PatternKind {
+    Prefix,
+    Suffix,
+    Contains,
+    /// Both at once, written `PREFIX:SUFFIX`
+    #[serde(rename = "prefix-suffix")]
+    PrefixSuffix,
+    /// A string at a position, written `OFFSET:TEXT`
+    At,
+    /// A [`CharTemplate`](crate::CharTemplate) such as `L{8}`
+    Classes,
+    /// That many identical leading characters
+    Run,
+    /// That many leading characters, repeated right after
+    Doubled,
+    /// That many leading characters reading the same backwards
+    Palindrome,
+    /// A [`Glob`](crate::Glob) such as `foo??bar*`
+    Glob,
+    /// A large prefix list loaded at startup; it can be listed but not added
+    Wordlist,
+    /// A [`PatternExpr`](crate::PatternExpr) such as `prefix:foo & !contains:xxx`
+    Expr,
+    Regex,
+    Exclude,
+}
+
+impl PatternKind {
+    pub(crate) fn parse(kind: &str) -> Result<Self> {
+        match kind.to_ascii_lowercase().as_str() {
+            "prefix" => Ok(Self::Prefix),
+            "suffix" => Ok(Self::Suffix),
+            "contains" => Ok(Self::Contains),
+            "prefix-suffix" => Ok(Self::PrefixSuffix),
+            "at" => Ok(Self::At),
+            "classes" => Ok(Self::Classes),
+            "run" => Ok(Self::Run),
+            "doubled" => Ok(Self::Doubled),
+            "palindrome" => Ok(Self::Palindrome),
+            "glob" => Ok(Self::Glob),
+            "expr" => Ok(Self::Expr),
+            "regex" => Ok(Self::Regex),
+            "exclude" => Ok(Self::Exclude),
+            _ => bail!(
+                "Unknown pattern kind {:?}; expected prefix, suffix, contains, prefix-suffix, at, classes, run, doubled, palindrome, glob, expr, regex or exclude",
+                kind
+            ),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Prefix => "prefix",
+            Self::Suffix => "suffix",
+            Self::Contains => "contains",
+            Self::PrefixSuffix => "prefix-suffix",
+            Self::At => "at",
+            Self::Classes => "classes",
+            Self::Run => "run",
+            Self::Doubled => "doubled",
+            Self::Palindrome => "palindrome",
+            Self::Glob => "glob",
+            Self::Wordlist => "wordlist",
+            Self::Expr => "expr",
+            Self::Regex => "regex",
+            Self::Exclude => "exclude",
+        }
+    }
+
+    /// Kind and pattern text of a spec in a matcher's includes or `excluded` list
+    pub(crate) fn of(spec: &MatchSpec, excluded: bool) -> (Self, String) {
+        match spec {
+            MatchSpec::Prefix(prefix) => (Self::Prefix, prefix.clone()),
+            MatchSpec::Suffix(suffix) => (Self::Suffix, suffix.clone()),
+            MatchSpec::Contains(needle) if excluded => (Self::Exclude, needle.clone()),
+            MatchSpec::Contains(needle) => (Self::Contains, needle.clone()),
+            MatchSpec::PrefixSuffix(prefix, suffix) => (Self::PrefixSuffix, format!("{}:{}", prefix, suffix)),
+            MatchSpec::At(offset, text) => (Self::At, format!("{}:{}", offset, text)),
+            MatchSpec::Classes(template) => (Self::Classes, template.to_string()),
+            MatchSpec::Run(n) => (Self::Run, n.to_string()),
+            MatchSpec::Doubled(n) => (Self::Doubled, n.to_string()),
+            MatchSpec::Palindrome(n) => (Self::Palindrome, n.to_string()),
+            MatchSpec::Glob(glob) => (Self::Glob, glob.to_string()),
+            MatchSpec::Wordlist(wordlist) => (Self::Wordlist, wordlist.to_string()),
+            MatchSpec::Expr(expr) => (Self::Expr, expr.to_string()),
+            #[cfg(feature = "regex")]
+            MatchSpec::Regex(regex) => (Self::Regex, regex.as_str().to_string()),
+        }
+    }
+}
+
+/// Validate a pattern and turn it into a spec
+pub(crate) fn pattern_spec(kind: PatternKind, pattern: &str) -> Result<MatchSpec> {
+    if kind == PatternKind::Regex {
+        #[cfg(feature = "regex")]
+        {
+            crate::onion::check_regex_start(pattern)?;
+            return Ok(MatchSpec::Regex(regex::Regex::new(pattern)?));
+        }
+        #[cfg(not(feature = "regex"))]
+        bail!("Regex pattern {:?} requires the `regex` feature", pattern);
+    }
+
+    let spec = match kind {
+        PatternKind::Prefix => MatchSpec::Prefix(address_chars(pattern)?),
+        PatternKind::Suffix => MatchSpec::Suffix(address_chars(pattern)?),
+        PatternKind::PrefixSuffix => {
+            let Some((prefix, suffix)) = pattern.split_once(':') else {
+                bail!("Write prefix-suffix patterns as PREFIX:SUFFIX");
+            };
+            MatchSpec::PrefixSuffix(address_chars(prefix)?, address_chars(suffix)?)
+        }
+        PatternKind::At => MatchSpec::parse_at(pattern)?,
+        PatternKind::Classes => MatchSpec::Classes(CharTemplate::parse(pattern)?),
+        PatternKind::Glob => MatchSpec::Glob(Glob::parse(&pattern.to_lowercase())?),
+        PatternKind::Wordlist => bail!("Wordlists are loaded at startup with --patterns-file"),
+        PatternKind::Expr => PatternExpr::parse(pattern)?,
+        PatternKind::Run | PatternKind::Doubled | PatternKind::Palindrome => {
+            let Ok(n) = pattern.parse() else {
+                bail!("{} patterns are a number of characters, not {:?}", kind.as_str(), pattern);
+            };
+            let spec = match kind {
+                PatternKind::Run => MatchSpec::Run(n),
+                PatternKind::Doubled => MatchSpec::Doubled(n),
+                _ => MatchSpec::Palindrome(n),
+            };
+            spec.check_length()?;
+            spec
+        }
+        _ => MatchSpec::Contains(address_chars(pattern)?),
+    };
+    if spec.expected_attempts().is_some_and(|expected| !expected.is_finite()) {
+        bail!(
+            "{} can never match: addresses are {} characters and end in 'd' preceded by a, i, q or y",
+            spec,
+            ADDRESS_LEN
+        );
+    }
+    Ok(spec)
+}
+
+/// Lowercase `pattern` and check it only uses characters that appear in addresses
+fn address_chars(pattern: &str) -> Result<String> {
+    let pattern = pattern.to_lowercase();
+    if pattern.is_empty() || pattern.len() > ADDRESS_LEN {
+        bail!("Pattern must be 1 to {} characters long", ADDRESS_LEN);
+    }
+    check_address_chars(&pattern)?;
+    Ok(pattern)
+}
@@ -0,0 +1,97 @@
+use crate::StatsSnapshots;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// How often the progress lines are refreshed
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Live terminal view with one line per worker plus an aggregate line
+///
+/// Output printed while the display is active should go through
+/// [`ProgressDisplay::suspend`] so it appears above the progress lines.
+pub(crate) struct ProgressDisplay {
+    multi: MultiProgress,
+    stop: Arc<AtomicBool>,
+    ticker: Option<thread::JoinHandle<()>>,
+}
+
+impl ProgressDisplay {
+    /// Start refreshing a line for each of the named `workers` and their total from `snapshots`
+    pub fn start(workers: Vec<String>, snapshots: StatsSnapshots) -> Self {
+        let multi = MultiProgress::with_draw_target(ProgressDrawTarget::stdout());
+        let style = ProgressStyle::with_template("{spinner:.cyan} {prefix:<18} {msg}")
+            .unwrap_or_else(|_| ProgressStyle::default_spinner());
+
+        let mut lines: Vec<ProgressBar> = workers
+            .into_iter()
+            .map(|name| {
+                let bar = multi.add(ProgressBar::new_spinner().with_style(style.clone()));
+                bar.set_prefix(name);
+                bar
+            })
+            .collect();
+        let total_bar = multi.add(ProgressBar::new_spinner().with_style(style));
+        total_bar.set_prefix("total");
+        lines.push(total_bar);
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let ticker_stop = Arc::clone(&stop);
+        let ticker = thread::Builder::new()
+            .name("onion-progress".to_string())
+            .spawn(move || refresh_lines(lines, snapshots, ticker_stop))
+            .ok();
+
+        Self { multi, stop, ticker }
+    }
+
+    /// Run `f` with the progress lines hidden, e.g. to print a result
+    pub fn suspend<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.multi.suspend(f)
+    }
+
+    /// Stop refreshing and leave the final numbers on screen
+    pub fn finish(mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(ticker) = self.ticker.take() {
+            let _ = ticker.join();
+        }
+        if !self.multi.is_hidden() {
+            crate::print_plain("");
+        }
+    }
+}
+
+/// The last line is the total
+fn refresh_lines(lines: Vec<ProgressBar>, snapshots: StatsSnapshots, stop: Arc<AtomicBool>) {
+    let (total, workers) = lines.split_last().expect("the total line is always there");
+    while !stop.load(Ordering::Relaxed) {
+        thread::sleep(REFRESH_INTERVAL);
+
+        let snapshot = snapshots.latest();
+        for (bar, worker) in workers.iter().zip(&snapshot.workers) {
+            bar.set_message(format_line(worker.generated, worker.rate, worker.found));
+            bar.tick();
+        }
+        let mut message = format_line(snapshot.generated, snapshot.rate, snapshot.found);
+        if snapshot.paused {
+            message.push_str("  (paused)");
+        }
+        total.set_message(message);
+        total.tick();
+    }
+
+    // Leave the average rate over the whole run on screen
+    let snapshot = snapshots.latest();
+    let elapsed = snapshot.elapsed.as_secs_f64().max(f64::EPSILON);
+    for (bar, worker) in workers.iter().zip(&snapshot.workers) {
+        bar.finish_with_message(format_line(worker.generated, worker.generated as f64 / elapsed, worker.found));
+    }
+    total.finish_with_message(format_line(snapshot.generated, snapshot.average_rate(), snapshot.found));
+}
+
+fn format_line(generated: u128, rate: f64, found: u128) -> String {
+    format!("{:>12} keys  {:>9.0} keys/s  {} found", generated, rate, found)
+}
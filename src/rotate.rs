@@ -0,0 +1,180 @@
+use crate::{ErrorCode, ResultExt};
+use anyhow::{Context, Result};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// When a [`RotatingFile`] starts over, and what happens to the old files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rotation {
+    /// Rotate once the file holds this many bytes
+    pub max_bytes: Option<u64>,
+    /// Rotate once the file has been written for this long
+    pub max_age: Option<Duration>,
+    /// Rotated files to keep; older ones are deleted
+    pub keep: usize,
+    /// Gzip rotated files
+    pub compress: bool,
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        Self { max_bytes: None, max_age: None, keep: 7, compress: false }
+    }
+}
+
+/// Append-only file that is rotated at a size or age limit
+///
+/// Rotating moves `<file>` to `<file>.1`, or `<file>.1.gz` when compressing,
+/// after shifting the older ones up by one and deleting the one beyond
+/// `keep`. It only happens between lines, so a record is never split across
+/// two files. The file may hold private keys and is only readable by the
+/// owner.
+pub struct RotatingFile {
+    path: PathBuf,
+    rotation: Rotation,
+    file: File,
+    len: u64,
+    opened: Instant,
+    at_line_start: bool,
+}
+
+impl RotatingFile {
+    /// Open `path` for appending, creating it if needed
+    pub fn open(path: impl Into<PathBuf>, rotation: Rotation) -> Result<Self> {
+        let path = path.into();
+        let file = create_private(&path, true)
+            .with_context(|| format!("Failed to open {}", path.display()))
+            .with_code(ErrorCode::OutputNotWritable)?;
+        let len = file.metadata()?.len();
+        Ok(Self { path, rotation, file, len, opened: Instant::now(), at_line_start: true })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Rotate now, whether or not a limit was reached
+    pub fn rotate(&mut self) -> io::Result<()> {
+        self.file.sync_all()?;
+        let keep = self.rotation.keep;
+        for compressed in [false, true] {
+            remove_if_exists(&rotated_path(&self.path, keep.max(1), compressed))?;
+            for n in (1..keep).rev() {
+                let from = rotated_path(&self.path, n, compressed);
+                if from.exists() {
+                    fs::rename(&from, rotated_path(&self.path, n + 1, compressed))?;
+                }
+            }
+        }
+
+        if keep == 0 {
+            remove_if_exists(&self.path)?;
+        } else if self.rotation.compress {
+            let mut input = File::open(&self.path)?;
+            let mut output = GzEncoder::new(create_private(&rotated_path(&self.path, 1, true), false)?, Compression::default());
+            io::copy(&mut input, &mut output)?;
+            output.finish()?.sync_all()?;
+            fs::remove_file(&self.path)?;
+        } else {
+            fs::rename(&self.path, rotated_path(&self.path, 1, false))?;
+        }
+
+        self.file = create_private(&self.path, true)?;
+        self.len = 0;
+        self.opened = Instant::now();
+        Ok(())
+    }
+
+    fn rotation_due(&self) -> bool {
+        self.len > 0
+            && (self.rotation.max_bytes.is_some_and(|max| self.len >= max)
+                || self.rotation.max_age.is_some_and(|max| self.opened.elapsed() >= max))
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if self.at_line_start && self.rotation_due() {
+            self.rotate()?;
+        }
+        let written = self.file.write(data)?;
+        self.len += written as u64;
+        if written > 0 {
+            self.at_line_start = data[written - 1] == b'\n';
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// `<path>.<n>`, plus `.gz` for compressed files
+fn rotated_path(path: &Path, n: usize, compressed: bool) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    if compressed {
+        name.push(".gz");
+    }
+    PathBuf::from(name)
+}
+
+/// Open `path` for appending, or truncate it, creating it readable only by the owner
+fn create_private(path: &Path, append: bool) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.create(true);
+    if append {
+        options.append(true);
+    } else {
+        options.write(true).truncate(true);
+    }
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn test_rotates_by_size_between_lines() {
+        let dir = TempPath::dir("rotate");
+        let path = dir.join("results.jsonl");
+        let rotation = Rotation { max_bytes: Some(10), keep: 2, compress: true, ..Rotation::default() };
+
+        let mut file = RotatingFile::open(&path, rotation).unwrap();
+        for line in ["first line\n", "second line\n", "third line\n"] {
+            // Written in two parts, which must stay in one file
+            file.write_all(&line.as_bytes()[..4]).unwrap();
+            file.write_all(&line.as_bytes()[4..]).unwrap();
+        }
+        file.rotate().unwrap();
+        drop(file);
+
+        let gunzip = |n: usize| {
+            let mut text = String::new();
+            GzDecoder::new(File::open(rotated_path(&path, n, true)).unwrap()).read_to_string(&mut text).unwrap();
+            text
+        };
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+        assert_eq!(gunzip(1), "third line\n");
+        assert_eq!(gunzip(2), "second line\n");
+        assert!(!rotated_path(&path, 3, true).exists());
+    }
+}
@@ -0,0 +1,144 @@
+use crate::sink::{DirectorySink, ResultSink};
+use crate::{logln, ChatSink, OnionResult, RouteConfig};
+use anyhow::Result;
+
+/// Hands each find to the outputs of the first route whose pattern its address matches
+///
+/// Route patterns are matched against the 56-character address, with `*`
+/// standing for any characters, e.g. `shop*` for addresses starting with
+/// "shop". Finds no route takes go to the fallback sinks. Statistics and
+/// flushes reach every sink.
+#[derive(Default)]
+pub struct RoutingSink {
+    routes: Vec<(String, Vec<Box<dyn ResultSink>>)>,
+    fallback: Vec<Box<dyn ResultSink>>,
+}
+
+impl RoutingSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send finds matching `pattern` to `sinks`; routes added earlier take precedence
+    pub fn route(mut self, pattern: &str, sinks: Vec<Box<dyn ResultSink>>) -> Self {
+        self.routes.push((pattern.to_lowercase(), sinks));
+        self
+    }
+
+    /// Where the finds no route takes go
+    pub fn fallback(mut self, sinks: Vec<Box<dyn ResultSink>>) -> Self {
+        self.fallback.extend(sinks);
+        self
+    }
+
+    /// The routes of the `[[route]]` tables of a config file, in order
+    pub fn from_config(routes: &[RouteConfig]) -> Result<Self> {
+        let mut routing = Self::new();
+        for route in routes {
+            let mut sinks: Vec<Box<dyn ResultSink>> = Vec::new();
+            let mut outputs = Vec::new();
+            if let Some(dir) = &route.output_dir {
+                sinks.push(Box::new(DirectorySink::new(dir)?));
+                outputs.push(dir.display().to_string());
+            }
+            for notify in &route.notify {
+                sinks.push(Box::new(ChatSink::new(notify)?));
+            }
+            if !route.notify.is_empty() {
+                outputs.push(format!("{} notification(s)", route.notify.len()));
+            }
+            logln!("[@] Routing finds matching {} to {}", route.pattern, outputs.join(" and "));
+            routing = routing.route(&route.pattern, sinks);
+        }
+        Ok(routing)
+    }
+
+    fn sinks(&mut self) -> impl Iterator<Item = &mut Box<dyn ResultSink>> {
+        self.routes.iter_mut().flat_map(|(_, sinks)| sinks.iter_mut()).chain(self.fallback.iter_mut())
+    }
+}
+
+impl ResultSink for RoutingSink {
+    fn found(&mut self, result: &OnionResult) -> Result<()> {
+        let address = result.hostname.strip_suffix(".onion").unwrap_or(&result.hostname);
+        let sinks = match self.routes.iter_mut().find(|(pattern, _)| wildcard_match(pattern, address)) {
+            Some((_, sinks)) => sinks,
+            None => &mut self.fallback,
+        };
+        for sink in sinks {
+            sink.found(result)?;
+        }
+        Ok(())
+    }
+
+    fn stats(&mut self, generated: u128, found: u128) -> Result<()> {
+        for sink in self.sinks() {
+            sink.stats(generated, found)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        for sink in self.sinks() {
+            sink.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `address` matches `pattern`, where `*` matches any run of characters
+pub(crate) fn wildcard_match(pattern: &str, address: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = address.strip_prefix(parts.next().unwrap_or_default()) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    // Taking the first occurrence of each middle part leaves the most room for the rest
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    struct Recorder(&'static str, Arc<Mutex<Vec<String>>>);
+
+    impl ResultSink for Recorder {
+        fn found(&mut self, result: &OnionResult) -> Result<()> {
+            self.1.lock().unwrap().push(format!("{} {}", self.0, &result.hostname[..4]));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_finds_follow_the_first_matching_route() {
+        assert!(wildcard_match("shop*", "shopxyz"));
+        assert!(wildcard_match("*xyd", "abcxyd"));
+        assert!(wildcard_match("a*b*b", "aXbYb"));
+        assert!(!wildcard_match("a*b*b", "aXb"));
+        assert!(wildcard_match("abc", "abc"));
+        assert!(!wildcard_match("abc", "abcd"));
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = |name| -> Box<dyn ResultSink> { Box::new(Recorder(name, Arc::clone(&seen))) };
+        let mut routing = RoutingSink::new()
+            .route("SHOP*", vec![sink("a")])
+            .route("sho*", vec![sink("b"), sink("c")])
+            .fallback(vec![sink("rest")]);
+        for hostname in ["shopxyz.onion", "shoexyz.onion", "blogxyz.onion"] {
+            let result = OnionResult { hostname: hostname.to_string(), ..crate::generate_onion_address().unwrap() };
+            routing.found(&result).unwrap();
+        }
+        assert_eq!(*seen.lock().unwrap(), ["a shop", "b shoe", "c shoe", "rest blog"]);
+    }
+}
@@ -0,0 +1,121 @@
+use crate::{ErrorCode, ResultExt};
+use anyhow::{Context, Result};
+use rand::rngs::ThreadRng;
+use rand::Rng;
+use std::fs::{File, OpenOptions};
+use std::io::{LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// A random sample of the non-matching candidates, appended to a file
+///
+/// Every candidate is picked independently with probability `fraction`, so
+/// the sample is uniform over the whole run, e.g. for checking the character
+/// distribution of the generated addresses. Picked candidates are written one
+/// hostname per line; matches are left out since they are reported anyway.
+/// Clones append to the same file.
+#[derive(Debug, Clone)]
+pub struct CandidateSample {
+    fraction: f64,
+    path: PathBuf,
+    out: Arc<Mutex<LineWriter<File>>>,
+}
+
+impl CandidateSample {
+    /// Append to `path`, creating it if needed
+    pub fn create(path: impl Into<PathBuf>, fraction: f64) -> Result<Self> {
+        if !(fraction > 0.0 && fraction <= 1.0) {
+            return Err(anyhow::anyhow!("Sample fraction must be above 0 and at most 1, not {}", fraction))
+                .with_code(ErrorCode::InvalidConfig);
+        }
+        let path = path.into();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open sample file {}", path.display()))
+            .with_code(ErrorCode::OutputNotWritable)?;
+        Ok(Self { fraction, path, out: Arc::new(Mutex::new(LineWriter::new(file))) })
+    }
+
+    pub fn fraction(&self) -> f64 {
+        self.fraction
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Picks candidates for this sample on one thread
+    pub(crate) fn sampler(&self) -> Sampler<'_> {
+        let mut sampler = Sampler { sample: self, rng: rand::thread_rng(), skip: 0 };
+        sampler.skip = sampler.gap();
+        sampler
+    }
+}
+
+/// Decides which candidates of one thread go into a [`CandidateSample`]
+pub(crate) struct Sampler<'a> {
+    sample: &'a CandidateSample,
+    rng: ThreadRng,
+    /// Candidates to pass over before the next pick
+    skip: u64,
+}
+
+impl Sampler<'_> {
+    /// Whether to pick the next candidate
+    #[inline]
+    pub fn pick(&mut self) -> bool {
+        if self.skip > 0 {
+            self.skip -= 1;
+            return false;
+        }
+        self.skip = self.gap();
+        true
+    }
+
+    /// Append a picked candidate to the sample file
+    pub fn record(&self, hostname: &str) -> Result<()> {
+        let mut out = self.sample.out.lock().unwrap();
+        writeln!(out, "{}", hostname)
+            .with_context(|| format!("Failed to write sample file {}", self.sample.path.display()))
+            .with_code(ErrorCode::OutputNotWritable)
+    }
+
+    /// Candidates until the next pick
+    ///
+    /// Geometrically distributed, which picks each candidate with probability
+    /// `fraction` like a draw per candidate would, with one draw per pick.
+    fn gap(&mut self) -> u64 {
+        if self.sample.fraction >= 1.0 {
+            return 0;
+        }
+        let uniform = 1.0 - self.rng.gen::<f64>();
+        (uniform.ln() / (1.0 - self.sample.fraction).ln()) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+
+    #[test]
+    fn test_sampler_picks_the_given_fraction() {
+        let path = TempPath::new("sample.txt");
+        assert!(CandidateSample::create(&path, 0.0).is_err());
+        assert!(CandidateSample::create(&path, 1.5).is_err());
+
+        let sample = CandidateSample::create(&path, 0.01).unwrap();
+        let mut sampler = sample.sampler();
+        let picked = (0..1_000_000).filter(|_| sampler.pick()).count();
+        // Within 5 standard deviations of the expected 10000
+        assert!((9500..=10500).contains(&picked), "{}", picked);
+
+        let every = CandidateSample::create(&path, 1.0).unwrap();
+        let mut all = every.sampler();
+        assert!((0..10).all(|_| all.pick()));
+        all.record("abc.onion").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "abc.onion\n");
+    }
+}
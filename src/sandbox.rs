@@ -0,0 +1,272 @@
+use crate::{ErrorCode, ResultExt};
+use anyhow::{anyhow, Result};
+use std::path::{Component, Path, PathBuf};
+
+#[cfg(target_os = "linux")]
+pub use seccomp::{apply_seccomp_filter, seccomp_filter};
+
+/// `dir/name`, refusing a `name` that is not a single plain file name
+///
+/// Keeps names that end up in output paths, such as hostnames, from reaching
+/// outside `dir` through `..`, separators or absolute paths. Applies on every
+/// platform, with or without [`confine_writes`].
+pub fn confined_join(dir: &Path, name: &str) -> Result<PathBuf> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Ok(dir.join(name)),
+        _ => Err(anyhow!("Refusing to write {:?} outside {}", name, dir.display())).with_code(ErrorCode::OutputNotWritable),
+    }
+}
+
+/// Restrict file writes of the calling thread, and threads it starts later, to beneath `dirs`
+///
+/// Uses Landlock, on Linux 5.13 and later. Reading stays allowed everywhere,
+/// as does writing to `/dev/null` and the terminal. Returns false where the
+/// platform or kernel cannot enforce it, leaving only the [`confined_join`]
+/// checks. Threads that already run are not affected, so call it before
+/// starting the workers.
+pub fn confine_writes(dirs: &[PathBuf]) -> Result<bool> {
+    #[cfg(target_os = "linux")]
+    {
+        use landlock::{
+            path_beneath_rules, AccessFs, Ruleset, RulesetAttr, RulesetCreatedAttr, RulesetStatus, ABI,
+        };
+
+        let write = AccessFs::from_write(ABI::V3);
+        let devices = [Path::new("/dev/null"), Path::new("/dev/tty")];
+        let status = Ruleset::default()
+            .handle_access(write)?
+            .create()?
+            .add_rules(path_beneath_rules(dirs, write))?
+            .add_rules(path_beneath_rules(devices, write))?
+            .restrict_self()?;
+        Ok(status.ruleset != RulesetStatus::NotEnforced)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = dirs;
+        Ok(false)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod seccomp {
+    use anyhow::{Context, Result};
+    use seccompiler::{
+        BpfProgram, SeccompAction, SeccompCmpArgLen, SeccompCmpOp, SeccompCondition, SeccompFilter, SeccompRule,
+        TargetArch,
+    };
+    use std::collections::BTreeMap;
+
+    /// System calls a running search needs, with any arguments
+    ///
+    /// Covers the workers (memory, futexes, time, getrandom), the sinks
+    /// (creating and writing key files, network notifications), the control
+    /// socket and shutdown. Everything that starts another program, changes
+    /// privileges or inspects other processes is missing on purpose.
+    const ALLOWED: &[libc::c_long] = &[
+        // Memory and threads
+        libc::SYS_brk,
+        libc::SYS_mmap,
+        libc::SYS_munmap,
+        libc::SYS_mremap,
+        libc::SYS_mprotect,
+        libc::SYS_madvise,
+        libc::SYS_msync,
+        libc::SYS_futex,
+        libc::SYS_set_robust_list,
+        libc::SYS_rseq,
+        libc::SYS_sched_yield,
+        // Idle priority for --cooperative workers that replace stalled ones under the filter
+        libc::SYS_sched_setscheduler,
+        libc::SYS_setpriority,
+        libc::SYS_sched_getaffinity,
+        libc::SYS_prctl,
+        libc::SYS_gettid,
+        libc::SYS_getpid,
+        libc::SYS_tgkill,
+        libc::SYS_exit,
+        libc::SYS_exit_group,
+        // Signals, e.g. Ctrl-C and panics
+        libc::SYS_rt_sigaction,
+        libc::SYS_rt_sigprocmask,
+        libc::SYS_rt_sigreturn,
+        libc::SYS_sigaltstack,
+        // Time and randomness
+        libc::SYS_clock_gettime,
+        libc::SYS_clock_nanosleep,
+        libc::SYS_nanosleep,
+        libc::SYS_getrandom,
+        // Files
+        libc::SYS_read,
+        libc::SYS_write,
+        libc::SYS_readv,
+        libc::SYS_writev,
+        libc::SYS_pread64,
+        libc::SYS_pwrite64,
+        libc::SYS_openat,
+        libc::SYS_close,
+        libc::SYS_lseek,
+        libc::SYS_fstat,
+        libc::SYS_newfstatat,
+        libc::SYS_statx,
+        libc::SYS_faccessat,
+        libc::SYS_faccessat2,
+        libc::SYS_readlinkat,
+        libc::SYS_getdents64,
+        libc::SYS_mkdirat,
+        libc::SYS_renameat,
+        libc::SYS_renameat2,
+        libc::SYS_unlinkat,
+        libc::SYS_fchmod,
+        libc::SYS_fchmodat,
+        libc::SYS_ftruncate,
+        libc::SYS_fsync,
+        libc::SYS_fdatasync,
+        libc::SYS_fcntl,
+        libc::SYS_ioctl,
+        libc::SYS_dup,
+        libc::SYS_dup3,
+        libc::SYS_pipe2,
+        libc::SYS_getcwd,
+        // Sockets: control socket, notifications and OTLP export
+        libc::SYS_socket,
+        libc::SYS_connect,
+        libc::SYS_accept4,
+        libc::SYS_bind,
+        libc::SYS_listen,
+        libc::SYS_shutdown,
+        libc::SYS_sendto,
+        libc::SYS_recvfrom,
+        libc::SYS_sendmsg,
+        libc::SYS_recvmsg,
+        libc::SYS_sendmmsg,
+        libc::SYS_getsockopt,
+        libc::SYS_setsockopt,
+        libc::SYS_getsockname,
+        libc::SYS_getpeername,
+        libc::SYS_ppoll,
+        libc::SYS_epoll_create1,
+        libc::SYS_epoll_ctl,
+        libc::SYS_epoll_pwait,
+        libc::SYS_eventfd2,
+        libc::SYS_uname,
+    ];
+
+    /// Legacy variants glibc still uses on x86_64
+    #[cfg(target_arch = "x86_64")]
+    const ALLOWED_X86_64: &[libc::c_long] = &[
+        libc::SYS_open,
+        libc::SYS_stat,
+        libc::SYS_lstat,
+        libc::SYS_access,
+        libc::SYS_readlink,
+        libc::SYS_mkdir,
+        libc::SYS_rmdir,
+        libc::SYS_chmod,
+        libc::SYS_rename,
+        libc::SYS_unlink,
+        libc::SYS_getdents,
+        libc::SYS_dup2,
+        libc::SYS_pipe,
+        libc::SYS_poll,
+        libc::SYS_select,
+        libc::SYS_epoll_wait,
+        libc::SYS_arch_prctl,
+        libc::SYS_time,
+        libc::SYS_gettimeofday,
+    ];
+    #[cfg(not(target_arch = "x86_64"))]
+    const ALLOWED_X86_64: &[libc::c_long] = &[];
+
+    /// Errno of denied system calls
+    ///
+    /// ENOSYS rather than EPERM: glibc then falls back from `clone3`, whose
+    /// flags a filter cannot inspect, to `clone`, whose flags it can.
+    const DENIED_ERRNO: u32 = libc::ENOSYS as u32;
+
+    /// Compile the allowlist for the architecture this binary was built for
+    pub fn seccomp_filter() -> Result<BpfProgram> {
+        let arch = TargetArch::try_from(std::env::consts::ARCH)
+            .context("seccomp filters are only available on x86_64, aarch64 and riscv64")?;
+
+        let mut rules: BTreeMap<i64, Vec<SeccompRule>> =
+            ALLOWED.iter().chain(ALLOWED_X86_64).map(|&syscall| (syscall, Vec::new())).collect();
+        // New threads, e.g. restarted workers, but no new processes
+        let thread_only = SeccompCondition::new(
+            0,
+            SeccompCmpArgLen::Qword,
+            SeccompCmpOp::MaskedEq(libc::CLONE_THREAD as u64),
+            libc::CLONE_THREAD as u64,
+        )?;
+        rules.insert(libc::SYS_clone, vec![SeccompRule::new(vec![thread_only])?]);
+
+        let filter = SeccompFilter::new(rules, SeccompAction::Errno(DENIED_ERRNO), SeccompAction::Allow, arch)?;
+        Ok(filter.try_into()?)
+    }
+
+    /// Restrict every thread of the process to [`ALLOWED`] for the rest of its life
+    ///
+    /// Call it once all files, sockets and threads the run needs at startup
+    /// exist. Denied system calls fail with ENOSYS instead of running, so
+    /// starting programs (e.g. `--hsm-import`) is impossible afterwards.
+    pub fn apply_seccomp_filter() -> Result<()> {
+        let program = seccomp_filter()?;
+        seccompiler::apply_filter_all_threads(&program).context("Failed to install the seccomp filter")?;
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_filter_compiles() {
+            // Installing it would confine the whole test process, so only compile it
+            let program = seccomp_filter().unwrap();
+            assert!(!program.is_empty());
+            assert!(!ALLOWED.contains(&libc::SYS_execve));
+            assert!(!ALLOWED.contains(&libc::SYS_ptrace));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+    use std::fs;
+
+    #[test]
+    fn test_confined_join() {
+        let dir = Path::new("keys");
+        let hostname = "abcdefghijklmnopqrstuvwxyzabcdefghijklmnopqrstuvwxyzabcd.onion";
+
+        assert_eq!(confined_join(dir, hostname).unwrap(), dir.join(hostname));
+        for name in ["", "..", "../etc", "a/b", "/etc/passwd", "."] {
+            let error = confined_join(dir, name).unwrap_err();
+            assert_eq!(ErrorCode::of(&error), ErrorCode::OutputNotWritable, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn test_confine_writes() {
+        let root = TempPath::dir("confine");
+        let allowed = root.join("keys");
+        fs::create_dir_all(&allowed).unwrap();
+
+        // Confinement sticks to the thread, so keep it off the test harness threads
+        let (enforced, inside, outside) = std::thread::spawn({
+            let (root, allowed) = (root.to_path_buf(), allowed.clone());
+            move || {
+                let enforced = confine_writes(std::slice::from_ref(&allowed)).unwrap();
+                (enforced, fs::write(allowed.join("hostname"), "x"), fs::write(root.join("escaped"), "x"))
+            }
+        })
+        .join()
+        .unwrap();
+
+        assert!(inside.is_ok());
+        assert_eq!(outside.is_err(), enforced);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::{logln, ErrorCode, FileConfig, LiveMatcher, Matcher, ResultExt};
+use anyhow::{anyhow, Result};
+use chrono::NaiveTime;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// How often the schedule thread looks at the clock
+const CLOCK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Daily time windows in which a running pool searches for other patterns
+///
+/// Outside every window the pool searches for the fallback patterns, those it
+/// was started with. A window from 22:00 to 06:00 runs past midnight, and where
+/// windows overlap the one added first wins. Switching replaces the pool's
+/// [`LiveMatcher`], including patterns added there in the meantime.
+#[derive(Debug, Clone)]
+pub struct PatternSchedule {
+    windows: Vec<ScheduleWindow>,
+    fallback: Matcher,
+}
+
+#[derive(Debug, Clone)]
+struct ScheduleWindow {
+    from: NaiveTime,
+    to: NaiveTime,
+    name: String,
+    matcher: Matcher,
+}
+
+impl ScheduleWindow {
+    fn contains(&self, time: NaiveTime) -> bool {
+        match self.from <= self.to {
+            true => self.from <= time && time < self.to,
+            false => time >= self.from || time < self.to,
+        }
+    }
+}
+
+impl PatternSchedule {
+    pub fn new(fallback: Matcher) -> Self {
+        Self { windows: Vec::new(), fallback }
+    }
+
+    /// Search for `matcher` from `from` until `to`; `name` appears in the log
+    pub fn window(mut self, from: NaiveTime, to: NaiveTime, name: impl Into<String>, matcher: Matcher) -> Self {
+        self.windows.push(ScheduleWindow { from, to, name: name.into(), matcher });
+        self
+    }
+
+    /// The windows of the `[[schedule]]` tables of a config file, in order
+    pub fn from_config(config: &FileConfig, fallback: Matcher) -> Result<Self> {
+        let mut schedule = Self::new(fallback);
+        for window in &config.schedule {
+            let matcher = config.profile(&window.profile)?.generator_config()?.build_matcher()?;
+            let from = parse_time_of_day(&window.from)?;
+            let to = parse_time_of_day(&window.to)?;
+            logln!("[@] Scheduled {} to {}: profile {} ({})", window.from, window.to, window.profile, matcher);
+            schedule = schedule.window(from, to, &window.profile, matcher);
+        }
+        Ok(schedule)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    /// Index of the window covering `time`, or `None` for the fallback patterns
+    pub fn active(&self, time: NaiveTime) -> Option<usize> {
+        self.windows.iter().position(|window| window.contains(time))
+    }
+
+    /// Follow the local clock, switching `live` as windows open and close, until `stop` is set
+    ///
+    /// `live` is expected to hold the fallback patterns to begin with.
+    pub fn spawn(self, live: LiveMatcher, stop: Arc<AtomicBool>) -> JoinHandle<()> {
+        std::thread::spawn(move || {
+            let mut current = None;
+            while !stop.load(Ordering::Relaxed) {
+                let active = self.active(chrono::Local::now().time());
+                if active != current {
+                    let (name, matcher) = match active {
+                        Some(i) => (format!("profile {}", self.windows[i].name), &self.windows[i].matcher),
+                        None => ("the starting patterns".to_string(), &self.fallback),
+                    };
+                    logln!("[@] Schedule: switching to {}: {}", name, matcher);
+                    live.replace(matcher.clone());
+                    current = active;
+                }
+                std::thread::sleep(CLOCK_INTERVAL);
+            }
+        })
+    }
+}
+
+/// Parse a 24-hour `HH:MM` time
+pub(crate) fn parse_time_of_day(text: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(text.trim(), "%H:%M")
+        .map_err(|_| anyhow!("{:?} is not a time of day (HH:MM)", text))
+        .with_code(ErrorCode::InvalidConfig)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(text: &str) -> NaiveTime {
+        parse_time_of_day(text).unwrap()
+    }
+
+    #[test]
+    fn test_windows_wrap_past_midnight() {
+        let schedule = PatternSchedule::new(Matcher::from_prefixes(&["day".to_string()]))
+            .window(time("22:00"), time("06:00"), "night", Matcher::from_prefixes(&["night".to_string()]))
+            .window(time("05:00"), time("08:30"), "dawn", Matcher::from_prefixes(&["dawn".to_string()]));
+
+        assert_eq!(schedule.active(time("23:59")), Some(0));
+        assert_eq!(schedule.active(time("00:00")), Some(0));
+        // The night window comes first, and ends at 06:00 sharp
+        assert_eq!(schedule.active(time("05:30")), Some(0));
+        assert_eq!(schedule.active(time("06:00")), Some(1));
+        assert_eq!(schedule.active(time("8:30")), None);
+        assert_eq!(schedule.active(time("21:59")), None);
+        assert!(parse_time_of_day("24:00").is_err());
+    }
+}
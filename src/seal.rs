@@ -0,0 +1,109 @@
+use crate::{KeyCustody, OnionResult};
+use anyhow::{Context, Result};
+use base64::Engine;
+use crypto_box::{PublicKey, SecretKey};
+use zeroize::Zeroizing;
+
+/// Key pair for receiving sealed private keys
+pub struct SealKeypair {
+    /// Base64 X25519 secret key; keep it on the machine that opens the results
+    pub secret_key: Zeroizing<String>,
+    /// Base64 X25519 public key to pass to `--seal-to`
+    pub public_key: String,
+}
+
+/// Generate a fresh recipient key pair
+pub fn generate_seal_keypair() -> SealKeypair {
+    let secret = SecretKey::generate(&mut rand::rngs::OsRng);
+    SealKeypair {
+        secret_key: Zeroizing::new(encode(&secret.to_bytes())),
+        public_key: encode(secret.public_key().as_bytes()),
+    }
+}
+
+/// Parse an X25519 key given as base64 or 64 hex digits
+pub fn parse_x25519_key(text: &str) -> Result<[u8; 32]> {
+    let text = text.trim();
+    let bytes = if text.len() == 64 && text.bytes().all(|b| b.is_ascii_hexdigit()) {
+        (0..32)
+            .map(|i| u8::from_str_radix(&text[i * 2..i * 2 + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()?
+    } else {
+        base64::engine::general_purpose::STANDARD
+            .decode(text)
+            .context("X25519 key must be base64 or 64 hex digits")?
+    };
+    let bytes = Zeroizing::new(bytes);
+    <[u8; 32]>::try_from(bytes.as_slice()).map_err(|_| anyhow::anyhow!("X25519 key must be 32 bytes"))
+}
+
+/// Replace the private key of `result` with a sealed box for `recipient`
+///
+/// This is libsodium's `crypto_box_seal`: an ephemeral X25519 key agreement
+/// with XSalsa20-Poly1305, so only the holder of the recipient's secret key
+/// can recover the `hs_ed25519_secret_key` file contents, and the sender
+/// keeps nothing that could open it again.
+pub fn seal_result(recipient: &[u8; 32], result: &OnionResult) -> Result<OnionResult> {
+    if result.custody != KeyCustody::Local {
+        return Ok(result.clone());
+    }
+    let engine = base64::engine::general_purpose::STANDARD;
+    let private_key = Zeroizing::new(engine.decode(&result.private_key)?);
+    let sealed = PublicKey::from_bytes(*recipient)
+        .seal(&mut rand::rngs::OsRng, &private_key)
+        .map_err(|_| anyhow::anyhow!("Failed to seal private key"))?;
+
+    Ok(OnionResult {
+        private_key: engine.encode(sealed),
+        custody: KeyCustody::Sealed,
+        seed: None,
+        ..result.clone()
+    })
+}
+
+/// Open a sealed box produced by [`seal_result`]
+pub fn unseal(secret_key: &[u8; 32], sealed: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+    SecretKey::from_bytes(*secret_key)
+        .unseal(sealed)
+        .map(Zeroizing::new)
+        .map_err(|_| anyhow::anyhow!("Cannot open sealed key: wrong secret key or corrupted data"))
+}
+
+fn encode(bytes: &[u8]) -> String {
+    base64::engine::general_purpose::STANDARD.encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate_onion_address;
+
+    #[test]
+    fn test_seal_and_unseal() {
+        let keypair = generate_seal_keypair();
+        let public = parse_x25519_key(&keypair.public_key).unwrap();
+        let secret = parse_x25519_key(&keypair.secret_key).unwrap();
+
+        let result = generate_onion_address().unwrap();
+        let sealed = seal_result(&public, &result).unwrap();
+        assert_eq!(sealed.custody, KeyCustody::Sealed);
+        assert!(sealed.seed.is_none());
+        assert_eq!(sealed.hostname, result.hostname);
+        assert_ne!(sealed.private_key, result.private_key);
+
+        let engine = base64::engine::general_purpose::STANDARD;
+        let opened = unseal(&secret, &engine.decode(&sealed.private_key).unwrap()).unwrap();
+        assert_eq!(engine.encode(opened.as_slice()), result.private_key);
+
+        let other = parse_x25519_key(&generate_seal_keypair().secret_key).unwrap();
+        assert!(unseal(&other, &engine.decode(&sealed.private_key).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_parse_x25519_key() {
+        let hex = "00".repeat(31) + "ff";
+        assert_eq!(parse_x25519_key(&hex).unwrap()[31], 0xff);
+        assert!(parse_x25519_key("AAAA").is_err());
+        assert!(parse_x25519_key("not base64!").is_err());
+    }
+}
@@ -0,0 +1,134 @@
+use crate::{decode_onion_address, generate_onion_address, ErrorCode, KeyCustody, OnionResult, ResultExt};
+use anyhow::{anyhow, bail, Result};
+use ed25519_dalek::hazmat::{raw_sign, ExpandedSecretKey};
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use sha2::Sha512;
+use sha3::{Digest, Sha3_256};
+use zeroize::Zeroizing;
+
+/// Header of a Tor `hs_ed25519_public_key` file, padded to 32 bytes
+const PUBLIC_KEY_HEADER: &[u8; 32] = b"== ed25519v1-public: type0 ==\0\0\0";
+
+/// Header of a Tor `hs_ed25519_secret_key` file, padded to 32 bytes
+const SECRET_KEY_HEADER: &[u8; 32] = b"== ed25519v1-secret: type0 ==\0\0\0";
+
+/// Message signed by every self-check
+const MESSAGE: &[u8] = b"onion-generator self-check";
+
+/// Generate a fresh key the way the workers do and check it end to end
+///
+/// A failure means keys generated on this machine cannot be trusted, e.g.
+/// because of faulty memory or a miscompiled backend, and carries
+/// [`ErrorCode::SelfCheckFailed`]. See [`self_check_result`] for what is
+/// checked.
+pub fn self_check() -> Result<()> {
+    let result = generate_onion_address()?;
+    self_check_result(&result).with_code(ErrorCode::SelfCheckFailed)
+}
+
+/// Check a result that still holds its seed or its secret key file
+///
+/// Signs a message with the key derived from the seed and verifies it with
+/// the key from the public key file, checks that the secret key file gives
+/// the same public key, then derives the hostname again with an
+/// encoder of its own rather than the one that produced it. Results whose
+/// secret key was sealed or imported skip the secret key file check; results
+/// without a seed, like those of [`crate::BatchedKeys`], sign with the
+/// secret key file instead.
+pub fn self_check_result(result: &OnionResult) -> Result<()> {
+    let public_key: [u8; 32] = key_from_file(&result.public_key, PUBLIC_KEY_HEADER)?[..].try_into()?;
+    let expanded = match result.custody {
+        // What Tor does when it loads the secret key file
+        KeyCustody::Local => {
+            let secret = Zeroizing::new(<[u8; 64]>::try_from(&key_from_file(&result.private_key, SECRET_KEY_HEADER)?[..])?);
+            let expanded = ExpandedSecretKey::from_bytes(&secret);
+            if VerifyingKey::from(&expanded).to_bytes() != public_key {
+                bail!("The secret key file of {} belongs to another public key", result.hostname);
+            }
+            Some(expanded)
+        }
+        _ => None,
+    };
+
+    let signature = match (&result.seed, expanded) {
+        (Some(seed), _) => {
+            let signing_key = SigningKey::from_bytes(seed);
+            if signing_key.verifying_key().to_bytes() != public_key {
+                bail!("The public key file of {} does not belong to its private key", result.hostname);
+            }
+            signing_key.sign(MESSAGE)
+        }
+        (None, Some(expanded)) => raw_sign::<Sha512>(&expanded, MESSAGE, &VerifyingKey::from(&expanded)),
+        (None, None) => bail!("{} has no seed to check", result.hostname),
+    };
+    VerifyingKey::from_bytes(&public_key)?
+        .verify_strict(MESSAGE, &signature)
+        .map_err(|e| anyhow!("A signature by the key of {} does not verify: {}", result.hostname, e))?;
+
+    let hostname = hostname_of(&public_key);
+    if result.hostname != hostname {
+        bail!("{} was generated for the key of {}", result.hostname, hostname);
+    }
+    if decode_onion_address(&result.hostname)? != public_key {
+        bail!("{} does not decode to its own public key", result.hostname);
+    }
+    Ok(())
+}
+
+/// The key bytes of a base64 Tor key file starting with `header`
+fn key_from_file(file: &str, header: &[u8; 32]) -> Result<Zeroizing<Vec<u8>>> {
+    use base64::Engine;
+    let data = Zeroizing::new(base64::engine::general_purpose::STANDARD.decode(file)?);
+    match data.strip_prefix(header.as_slice()) {
+        Some(key) => Ok(Zeroizing::new(key.to_vec())),
+        None => bail!("Not a Tor ed25519 key file"),
+    }
+}
+
+/// The v3 hostname of `public_key`, built from the spec without the crate's encoder
+fn hostname_of(public_key: &[u8; 32]) -> String {
+    const ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+    let checksum = Sha3_256::new()
+        .chain_update(b".onion checksum")
+        .chain_update(public_key)
+        .chain_update([3])
+        .finalize();
+
+    let mut data = public_key.to_vec();
+    data.extend_from_slice(&checksum[..2]);
+    data.push(3);
+
+    // 35 bytes are exactly 56 characters of 5 bits
+    let mut hostname = String::with_capacity(62);
+    let (mut bits, mut count) = (0u32, 0);
+    for byte in data {
+        bits = (bits << 8 | byte as u32) & 0xfff;
+        count += 8;
+        while count >= 5 {
+            count -= 5;
+            hostname.push(ALPHABET[(bits >> count) as usize & 31] as char);
+        }
+    }
+    hostname + ".onion"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_self_check_catches_mismatches() {
+        self_check().unwrap();
+
+        let (first, second) = (generate_onion_address().unwrap(), generate_onion_address().unwrap());
+        let wrong_hostname = OnionResult { hostname: second.hostname.clone(), ..first.clone() };
+        let wrong_public_key = OnionResult { public_key: second.public_key.clone(), ..first.clone() };
+        let wrong_secret_key = OnionResult { private_key: second.private_key.clone(), ..first.clone() };
+        assert!(self_check_result(&wrong_hostname).is_err());
+        assert!(self_check_result(&wrong_public_key).is_err());
+        assert!(self_check_result(&wrong_secret_key).is_err());
+
+        let known = "duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczad.onion";
+        assert_eq!(hostname_of(&decode_onion_address(known).unwrap()), known);
+    }
+}
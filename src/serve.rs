@@ -0,0 +1,867 @@
+use crate::sink::ResultSink;
+use crate::{
+    execute_pattern_command, logln, ErrorCode, GenerationStats, GeneratorConfig, LiveMatcher, OnionResult, PatternCommand,
+    PatternKind, PauseHandle, ResultExt, StatsSnapshots, WorkerHealth, WorkerPool,
+};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the accept loop and event streams re-check the stop flag
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often running jobs are rebalanced by weight and checked against their budgets
+const SCHEDULE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Idle connections are dropped after this long without request data
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest accepted request body
+const MAX_BODY: usize = 64 * 1024;
+
+/// Magic value from RFC 6455 used in the WebSocket handshake
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// HTTP server hosting the status endpoint, job API, event stream and metrics
+///
+/// Endpoints, all requiring `Authorization: Bearer <token>` (or `?token=` for
+/// the stream) when a token is configured:
+/// - `GET /status`: totals over all jobs
+/// - `GET /health`: 200 while no worker has stalled, 503 and the stalled
+///   workers of each job otherwise
+/// - `GET /metrics`: Prometheus text format
+/// - `GET /jobs`, `POST /jobs`: list or start searches; jobs competing for
+///   the CPU make attempts in proportion to their `weight`, and stop after
+///   `budget` attempts if one is given
+/// - `GET /jobs/<id>`, `DELETE /jobs/<id>`: inspect or stop a search
+/// - `GET /jobs/<id>/results`: found addresses including private keys
+/// - `GET /jobs/<id>/patterns`: the job's patterns and their difficulty
+/// - `POST /jobs/<id>/patterns`, `DELETE /jobs/<id>/patterns`: add or remove a
+///   pattern of a running job, given as `{"kind": "prefix", "pattern": "abc"}`
+/// - `GET /stream`: WebSocket stream of `found` and `stats` events
+pub struct Server {
+    listener: TcpListener,
+    token: Option<String>,
+    tls: Option<Arc<rustls::ServerConfig>>,
+    state: Arc<ServerState>,
+}
+
+struct ServerState {
+    base_config: GeneratorConfig,
+    jobs: Mutex<BTreeMap<u64, Arc<Job>>>,
+    next_id: AtomicU64,
+    subscribers: Mutex<Vec<mpsc::Sender<String>>>,
+    stop: AtomicBool,
+    started: Instant,
+}
+
+/// One search started through the API or the command line
+struct Job {
+    id: u64,
+    matcher: LiveMatcher,
+    /// For the budgets and the scheduler; what is shown comes from `snapshots`
+    stats: Arc<GenerationStats>,
+    snapshots: StatsSnapshots,
+    health: Arc<WorkerHealth>,
+    stop: Arc<AtomicBool>,
+    pause: PauseHandle,
+    workers: usize,
+    weight: u32,
+    budget: Option<u64>,
+    /// Set when the job was stopped for reaching its budget
+    exhausted: AtomicBool,
+    /// Virtual time the job started at, see [`Job::virtual_time`]
+    virtual_start: f64,
+    results: Mutex<Vec<OnionResult>>,
+    error: Mutex<Option<String>>,
+    started: Instant,
+    handle: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+/// Body of `POST /jobs`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobRequest {
+    #[serde(default)]
+    pub prefixes: Vec<String>,
+    #[serde(default)]
+    pub suffixes: Vec<String>,
+    #[serde(default)]
+    pub regexes: Vec<String>,
+    #[serde(default)]
+    pub excludes: Vec<String>,
+    pub workers: Option<usize>,
+    /// Share of the attempts relative to other jobs, 1 by default
+    pub weight: Option<u32>,
+    /// Stop after this many attempts
+    pub budget: Option<u64>,
+}
+
+/// Body of `POST` and `DELETE /jobs/<id>/patterns`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PatternRequest {
+    kind: PatternKind,
+    pattern: String,
+}
+
+impl Server {
+    /// Listen on `addr`; jobs use `base_config`'s worker count and update interval
+    pub fn bind(addr: &str, base_config: GeneratorConfig) -> Result<Self> {
+        let listener = TcpListener::bind(addr).with_context(|| format!("Failed to listen on {}", addr))?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Self {
+            listener,
+            token: None,
+            tls: None,
+            state: Arc::new(ServerState {
+                base_config,
+                jobs: Mutex::new(BTreeMap::new()),
+                next_id: AtomicU64::new(1),
+                subscribers: Mutex::new(Vec::new()),
+                stop: AtomicBool::new(false),
+                started: Instant::now(),
+            }),
+        })
+    }
+
+    /// Require this bearer token on every request
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token.filter(|token| !token.is_empty());
+        self
+    }
+
+    /// Serve HTTPS using a PEM certificate chain and private key
+    pub fn with_tls(mut self, cert: &Path, key: &Path) -> Result<Self> {
+        use rustls::pki_types::pem::PemObject;
+        use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+        let certs = CertificateDer::pem_file_iter(cert)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| anyhow::anyhow!("Failed to read certificate {}: {}", cert.display(), e))?;
+        let key = PrivateKeyDer::from_pem_file(key)
+            .map_err(|e| anyhow::anyhow!("Failed to read private key {}: {}", key.display(), e))?;
+
+        let config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)?;
+        self.tls = Some(Arc::new(config));
+        Ok(self)
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    pub fn is_tls(&self) -> bool {
+        self.tls.is_some()
+    }
+
+    /// Start a search job and return its id
+    pub fn start_job(&self, request: JobRequest) -> Result<u64> {
+        self.state.start_job(request)
+    }
+
+    /// Accept connections until `stop` is set, then stop all jobs
+    pub fn run(&self, stop: &AtomicBool) -> Result<()> {
+        let mut next_schedule = Instant::now();
+        let mut last_generated = 0;
+        while !stop.load(Ordering::Relaxed) {
+            if Instant::now() >= next_schedule {
+                self.state.schedule(&mut last_generated);
+                next_schedule = Instant::now() + SCHEDULE_INTERVAL;
+            }
+            match self.listener.accept() {
+                Ok((tcp, _)) => self.spawn_connection(tcp),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+                Err(e) => eprintln!("[!] Failed to accept connection: {}", e),
+            }
+        }
+
+        self.state.stop.store(true, Ordering::Relaxed);
+        let jobs: Vec<Arc<Job>> = self.state.jobs.lock().unwrap().values().cloned().collect();
+        for job in &jobs {
+            job.stop.store(true, Ordering::Relaxed);
+        }
+        for job in jobs {
+            if let Some(handle) = job.handle.lock().unwrap().take() {
+                let _ = handle.join();
+            }
+        }
+        Ok(())
+    }
+
+    fn spawn_connection(&self, tcp: TcpStream) {
+        let state = Arc::clone(&self.state);
+        let token = self.token.clone();
+        let tls = self.tls.clone();
+
+        let spawned = thread::Builder::new().name("onion-http".to_string()).spawn(move || {
+            let _ = tcp.set_nonblocking(false);
+            let _ = tcp.set_read_timeout(Some(READ_TIMEOUT));
+            let stream: Box<dyn Stream> = match tls {
+                Some(config) => match rustls::ServerConnection::new(config) {
+                    Ok(conn) => Box::new(rustls::StreamOwned::new(conn, tcp)),
+                    Err(e) => {
+                        eprintln!("[!] TLS error: {}", e);
+                        return;
+                    }
+                },
+                None => Box::new(tcp),
+            };
+            if let Err(e) = handle_connection(stream, &state, token.as_deref()) {
+                // Clients hanging up, e.g. closing a stream, are not worth reporting
+                let hung_up = matches!(
+                    e.kind(),
+                    io::ErrorKind::UnexpectedEof | io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset
+                );
+                if !hung_up {
+                    eprintln!("[!] Connection error: {}", e);
+                }
+            }
+        });
+        if let Err(e) = spawned {
+            eprintln!("[!] Failed to spawn connection thread: {}", e);
+        }
+    }
+}
+
+trait Stream: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Stream for T {}
+
+impl ServerState {
+    fn start_job(self: &Arc<Self>, request: JobRequest) -> Result<u64> {
+        let invalid = match (request.weight, request.budget) {
+            (Some(0), _) => Some("Job weight must be at least 1"),
+            (_, Some(0)) => Some("Job budget must be at least 1 attempt"),
+            _ => None,
+        };
+        if let Some(message) = invalid {
+            return Err(anyhow::anyhow!(message)).with_code(ErrorCode::InvalidConfig);
+        }
+        let patterns = |list: Vec<String>| -> Vec<String> {
+            list.iter().map(|pattern| pattern.trim().to_lowercase()).collect()
+        };
+        let mut config = GeneratorConfig::new(patterns(request.prefixes))
+            .with_suffixes(patterns(request.suffixes))
+            .with_regexes(request.regexes)
+            .with_excludes(patterns(request.excludes))
+            .with_workers(request.workers.unwrap_or(self.base_config.num_workers))
+            .with_update_interval(self.base_config.update_interval);
+        config.num_workers = config.num_workers.max(1);
+        let workers = config.num_workers;
+        let matcher = config.build_matcher()?;
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut pool = WorkerPool::builder(config)
+            .matcher(matcher.clone())
+            .sink(JobSink { state: Arc::clone(self), id })
+            .collect_results(false)
+            .on_worker_start(|_| {})
+            .on_worker_stop(|_| {})
+            .build();
+        let mut jobs = self.jobs.lock().unwrap();
+        // Start level with the job furthest behind rather than at zero, which
+        // would let the new job run alone until it caught up with the others
+        let virtual_start = jobs
+            .values()
+            .filter(|job| !job.stop.load(Ordering::Relaxed))
+            .map(|job| job.virtual_time())
+            .reduce(f64::min)
+            .unwrap_or(0.0);
+        let job = Arc::new(Job {
+            id,
+            matcher: pool.live_matcher(),
+            stats: pool.stats(),
+            snapshots: pool.snapshots(),
+            health: pool.health(),
+            stop: pool.stop_handle(),
+            pause: pool.pause_handle(),
+            workers,
+            weight: request.weight.unwrap_or(1),
+            budget: request.budget,
+            exhausted: AtomicBool::new(false),
+            virtual_start,
+            results: Mutex::new(Vec::new()),
+            error: Mutex::new(None),
+            started: Instant::now(),
+            handle: Mutex::new(None),
+        });
+        jobs.insert(id, Arc::clone(&job));
+        drop(jobs);
+
+        let runner = Arc::clone(&job);
+        let handle = thread::Builder::new().name(format!("onion-job-{}", id)).spawn(move || {
+            let outcome = pool.start().and_then(|_| pool.run()).and(pool.shutdown());
+            if let Err(e) = outcome {
+                *runner.error.lock().unwrap() = Some(e.to_string());
+            }
+            runner.stop.store(true, Ordering::Relaxed);
+        })?;
+        *job.handle.lock().unwrap() = Some(handle);
+
+        logln!("[@] Job {} started: {}", id, matcher);
+        Ok(id)
+    }
+
+    /// Stop jobs that used up their budget and pause those ahead of their weighted share
+    ///
+    /// Jobs only compete while their workers outnumber the CPU cores; below
+    /// that every job runs at full speed. `last_generated` is the number of
+    /// attempts over all jobs at the previous call.
+    fn schedule(&self, last_generated: &mut u128) {
+        let jobs: Vec<Arc<Job>> = self.jobs.lock().unwrap().values().cloned().collect();
+        for job in &jobs {
+            if let Some(budget) = job.budget {
+                if job.stats.get().0 >= u128::from(budget) && !job.stop.swap(true, Ordering::Relaxed) {
+                    job.exhausted.store(true, Ordering::Relaxed);
+                    logln!("[@] Job {} used up its budget of {} attempts", job.id, budget);
+                }
+            }
+        }
+
+        let generated: u128 = jobs.iter().map(|job| job.stats.get().0).sum();
+        let tick = generated.saturating_sub(*last_generated);
+        *last_generated = generated;
+
+        let running: Vec<&Arc<Job>> = jobs.iter().filter(|job| !job.stop.load(Ordering::Relaxed)).collect();
+        let cores = thread::available_parallelism().map_or(1, |cores| cores.get());
+        let contended = running.len() > 1 && running.iter().map(|job| job.workers).sum::<usize>() > cores;
+        let paused = if contended {
+            let virtual_times: Vec<f64> = running.iter().map(|job| job.virtual_time()).collect();
+            let paused: Vec<bool> = running.iter().map(|job| job.pause.is_paused()).collect();
+            let total_weight: f64 = running.iter().map(|job| f64::from(job.weight)).sum();
+            fair_share(&virtual_times, &paused, tick as f64 / total_weight)
+        } else {
+            vec![false; running.len()]
+        };
+        for (job, paused) in running.into_iter().zip(paused) {
+            if paused {
+                job.pause.pause();
+            } else {
+                job.pause.resume();
+            }
+        }
+    }
+
+    fn job(&self, id: u64) -> Option<Arc<Job>> {
+        self.jobs.lock().unwrap().get(&id).cloned()
+    }
+
+    fn publish(&self, event: Value) {
+        let text = event.to_string();
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(text.clone()).is_ok());
+    }
+
+    fn subscribe(&self) -> mpsc::Receiver<String> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+}
+
+/// Which jobs to pause so that each makes attempts in proportion to its weight
+///
+/// Jobs more than `slack` ahead of the job furthest behind in
+/// [virtual time](Job::virtual_time) are paused, and stay paused until it has
+/// caught up, so the jobs take turns instead of switching on every call.
+fn fair_share(virtual_times: &[f64], paused: &[bool], slack: f64) -> Vec<bool> {
+    let behind = virtual_times.iter().copied().fold(f64::INFINITY, f64::min);
+    virtual_times
+        .iter()
+        .zip(paused)
+        .map(|(&time, &paused)| {
+            let ahead = time - behind;
+            ahead > slack || (paused && ahead > 0.0)
+        })
+        .collect()
+}
+
+impl Job {
+    /// Attempts per unit of weight, plus where the job started
+    ///
+    /// Jobs that got their fair share have the same virtual time.
+    fn virtual_time(&self) -> f64 {
+        self.virtual_start + self.stats.get().0 as f64 / f64::from(self.weight)
+    }
+
+    /// Average keys per second since the job started
+    fn rate(&self) -> f64 {
+        self.snapshots.latest().average_rate()
+    }
+
+    fn summary(&self) -> Value {
+        let snapshot = self.snapshots.latest();
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let hostnames: Vec<String> = self
+            .results
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|result| result.hostname.clone())
+            .collect();
+        json!({
+            "id": self.id,
+            "patterns": self.matcher.current().to_string(),
+            "running": !self.stop.load(Ordering::Relaxed),
+            "paused": self.pause.is_paused(),
+            "weight": self.weight,
+            "budget": self.budget,
+            "budget_exhausted": self.exhausted.load(Ordering::Relaxed),
+            "generated": snapshot.generated,
+            "found": snapshot.found,
+            "elapsed_secs": elapsed,
+            "rate": snapshot.average_rate(),
+            "stalled_workers": self.health.stalled(),
+            "hostnames": hostnames,
+            "error": *self.error.lock().unwrap(),
+        })
+    }
+}
+
+/// Records a job's finds and forwards its events to stream subscribers
+struct JobSink {
+    state: Arc<ServerState>,
+    id: u64,
+}
+
+impl ResultSink for JobSink {
+    fn found(&mut self, result: &OnionResult) -> Result<()> {
+        if let Some(job) = self.state.job(self.id) {
+            job.results.lock().unwrap().push(result.clone());
+        }
+        self.state.publish(json!({ "event": "found", "job": self.id, "hostname": result.hostname }));
+        Ok(())
+    }
+
+    fn stats(&mut self, generated: u128, found: u128) -> Result<()> {
+        self.state.publish(json!({
+            "event": "stats",
+            "job": self.id,
+            "generated": generated,
+            "found": found,
+        }));
+        Ok(())
+    }
+}
+
+/// Parsed HTTP request
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+
+    fn is_authorized(&self, token: Option<&str>) -> bool {
+        let Some(token) = token else {
+            return true;
+        };
+        let presented = self
+            .header("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .or_else(|| self.query_param("token"));
+        presented.is_some_and(|presented| constant_time_eq(presented.as_bytes(), token.as_bytes()))
+    }
+}
+
+fn read_request(reader: &mut impl BufRead) -> io::Result<Request> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or("/");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (path, query) = (path.to_string(), query.to_string());
+
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line)?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    let mut request = Request { method, path, query, headers, body: Vec::new() };
+    let length: usize = request.header("content-length").and_then(|len| len.parse().ok()).unwrap_or(0);
+    if length > MAX_BODY {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "request body too large"));
+    }
+    request.body.resize(length, 0);
+    reader.read_exact(&mut request.body)?;
+    Ok(request)
+}
+
+fn handle_connection(stream: Box<dyn Stream>, state: &Arc<ServerState>, token: Option<&str>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let request = read_request(&mut reader)?;
+    let mut stream = reader.into_inner();
+
+    if !request.is_authorized(token) {
+        let error = json!({ "error": "missing or invalid token", "code": ErrorCode::AuthFailed.as_str() });
+        return respond_json(&mut stream, 401, &error);
+    }
+
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["status"]) => respond_json(&mut stream, 200, &status(state)),
+        ("GET", ["health"]) => {
+            let (healthy, body) = health(state);
+            respond_json(&mut stream, if healthy { 200 } else { 503 }, &body)
+        }
+        ("GET", ["metrics"]) => respond(&mut stream, 200, "text/plain; version=0.0.4", metrics(state).as_bytes()),
+        ("GET", ["stream"]) => stream_events(stream, &request, state),
+        ("GET", ["jobs"]) => {
+            let jobs: Vec<Value> = state.jobs.lock().unwrap().values().map(|job| job.summary()).collect();
+            respond_json(&mut stream, 200, &json!(jobs))
+        }
+        ("POST", ["jobs"]) => {
+            let started = serde_json::from_slice::<JobRequest>(&request.body)
+                .map_err(anyhow::Error::from)
+                .and_then(|job| state.start_job(job));
+            match started {
+                Ok(id) => respond_json(&mut stream, 201, &json!({ "id": id })),
+                Err(e) => respond_json(&mut stream, 400, &error_body(&e)),
+            }
+        }
+        (method, ["jobs", id, rest @ ..]) => {
+            let Some(job) = id.parse().ok().and_then(|id| state.job(id)) else {
+                return respond_json(&mut stream, 404, &json!({ "error": "no such job" }));
+            };
+            match (method, rest) {
+                ("GET", []) => respond_json(&mut stream, 200, &job.summary()),
+                ("DELETE", []) => {
+                    job.stop.store(true, Ordering::Relaxed);
+                    respond_json(&mut stream, 200, &job.summary())
+                }
+                ("GET", ["results"]) => {
+                    let results: Vec<Value> = job
+                        .results
+                        .lock()
+                        .unwrap()
+                        .iter()
+                        .map(|result| {
+                            json!({
+                                "hostname": result.hostname,
+                                "public_key": result.public_key,
+                                "private_key": result.private_key,
+                            })
+                        })
+                        .collect();
+                    respond_json(&mut stream, 200, &json!(results))
+                }
+                (_, ["patterns"]) => {
+                    let command = match method {
+                        "GET" => Ok(PatternCommand::List),
+                        "POST" | "DELETE" => serde_json::from_slice::<PatternRequest>(&request.body)
+                            .map(|body| match method {
+                                "POST" => PatternCommand::Add(body.kind, body.pattern),
+                                _ => PatternCommand::Remove(body.kind, body.pattern),
+                            })
+                            .map_err(anyhow::Error::from),
+                        _ => return respond_json(&mut stream, 404, &json!({ "error": "not found" })),
+                    };
+                    match command.and_then(|command| execute_pattern_command(&job.matcher, &command, job.rate())) {
+                        Ok(report) => {
+                            if method != "GET" {
+                                logln!("[i] Job {}: {}", job.id, report.message);
+                            }
+                            respond_json(&mut stream, 200, &json!(report))
+                        }
+                        Err(e) => respond_json(&mut stream, 400, &error_body(&e)),
+                    }
+                }
+                _ => respond_json(&mut stream, 404, &json!({ "error": "not found" })),
+            }
+        }
+        _ => respond_json(&mut stream, 404, &json!({ "error": "not found" })),
+    }
+}
+
+fn status(state: &ServerState) -> Value {
+    let jobs = state.jobs.lock().unwrap();
+    let running = jobs.values().filter(|job| !job.stop.load(Ordering::Relaxed)).count();
+    let (generated, found) = jobs
+        .values()
+        .map(|job| job.snapshots.latest())
+        .fold((0, 0), |acc, snapshot| (acc.0 + snapshot.generated, acc.1 + snapshot.found));
+    let stalled: usize = jobs.values().map(|job| job.health.stalled().len()).sum();
+    json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "uptime_secs": state.started.elapsed().as_secs_f64(),
+        "jobs": jobs.len(),
+        "running": running,
+        "generated": generated,
+        "found": found,
+        "stalled_workers": stalled,
+    })
+}
+
+/// Whether no running job has a stalled worker, and the body of `GET /health`
+fn health(state: &ServerState) -> (bool, Value) {
+    let jobs = state.jobs.lock().unwrap();
+    let stalled: BTreeMap<String, Vec<usize>> = jobs
+        .values()
+        .filter(|job| !job.stop.load(Ordering::Relaxed) && !job.health.is_healthy())
+        .map(|job| (job.id.to_string(), job.health.stalled()))
+        .collect();
+    let healthy = stalled.is_empty();
+    let status = if healthy { "ok" } else { "degraded" };
+    (healthy, json!({ "status": status, "stalled_workers": stalled }))
+}
+
+fn metrics(state: &ServerState) -> String {
+    let jobs = state.jobs.lock().unwrap();
+    let running = jobs.values().filter(|job| !job.stop.load(Ordering::Relaxed)).count();
+
+    let mut out = String::new();
+    out.push_str("# HELP onion_keys_generated_total Candidate keys generated\n");
+    out.push_str("# TYPE onion_keys_generated_total counter\n");
+    let snapshots: Vec<_> = jobs.values().map(|job| (job.id, job.snapshots.latest())).collect();
+    for (id, snapshot) in &snapshots {
+        out.push_str(&format!("onion_keys_generated_total{{job=\"{}\"}} {}\n", id, snapshot.generated));
+    }
+    out.push_str("# HELP onion_addresses_found_total Matching addresses found\n");
+    out.push_str("# TYPE onion_addresses_found_total counter\n");
+    for (id, snapshot) in &snapshots {
+        out.push_str(&format!("onion_addresses_found_total{{job=\"{}\"}} {}\n", id, snapshot.found));
+    }
+    out.push_str("# HELP onion_jobs_running Jobs currently searching\n");
+    out.push_str("# TYPE onion_jobs_running gauge\n");
+    out.push_str(&format!("onion_jobs_running {}\n", running));
+    out.push_str("# HELP onion_workers_stalled Workers without progress for the stall timeout\n");
+    out.push_str("# TYPE onion_workers_stalled gauge\n");
+    for job in jobs.values() {
+        out.push_str(&format!("onion_workers_stalled{{job=\"{}\"}} {}\n", job.id, job.health.stalled().len()));
+    }
+    out
+}
+
+/// Upgrade to a WebSocket and forward events until the client or server goes away
+fn stream_events(mut stream: Box<dyn Stream>, request: &Request, state: &ServerState) -> io::Result<()> {
+    let Some(key) = request.header("sec-websocket-key") else {
+        return respond_json(&mut stream, 400, &json!({ "error": "expected a WebSocket upgrade" }));
+    };
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        websocket_accept(key)
+    )?;
+    stream.flush()?;
+
+    let events = state.subscribe();
+    while !state.stop.load(Ordering::Relaxed) {
+        match events.recv_timeout(POLL_INTERVAL) {
+            Ok(event) => {
+                stream.write_all(&websocket_text_frame(&event))?;
+                stream.flush()?;
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    // Close frame with status 1001 "going away"
+    stream.write_all(&[0x88, 0x02, 0x03, 0xe9])?;
+    stream.flush()
+}
+
+fn websocket_accept(key: &str) -> String {
+    use base64::Engine;
+    let digest = Sha1::digest(format!("{}{}", key.trim(), WEBSOCKET_GUID).as_bytes());
+    base64::engine::general_purpose::STANDARD.encode(digest)
+}
+
+/// Unmasked, unfragmented server-to-client text frame
+fn websocket_text_frame(text: &str) -> Vec<u8> {
+    let payload = text.as_bytes();
+    let mut frame = vec![0x81];
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Body of an error response, with the stable [`ErrorCode`] for clients to branch on
+fn error_body(error: &anyhow::Error) -> Value {
+    json!({ "error": format!("{:#}", error), "code": ErrorCode::of(error).as_str() })
+}
+
+fn respond_json(stream: &mut dyn Stream, status: u16, body: &Value) -> io::Result<()> {
+    respond(stream, status, "application/json", body.to_string().as_bytes())
+}
+
+fn respond(stream: &mut dyn Stream, status: u16, content_type: &str, body: &[u8]) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        reason,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(addr: SocketAddr, raw: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(raw.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_websocket_handshake_and_frames() {
+        // Example from RFC 6455 section 1.3
+        assert_eq!(websocket_accept("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+        assert_eq!(websocket_text_frame("hi"), vec![0x81, 2, b'h', b'i']);
+        assert_eq!(&websocket_text_frame(&"x".repeat(300))[..4], &[0x81, 126, 0x01, 0x2c]);
+    }
+
+    #[test]
+    fn test_fair_share_and_budgets() {
+        // The second job is further ahead than the slack, the third is not
+        assert_eq!(fair_share(&[10.0, 25.0, 12.0], &[false; 3], 5.0), [false, true, false]);
+        // A paused job waits until the others have caught up
+        assert_eq!(fair_share(&[10.0, 12.0], &[false, true], 5.0), [false, true]);
+        assert_eq!(fair_share(&[12.0, 12.0], &[false, true], 5.0), [false, false]);
+
+        let server = Server::bind("127.0.0.1:0", GeneratorConfig::new(Vec::new()).with_workers(1)).unwrap();
+        let prefixes = vec!["zzzzzzzz".to_string()];
+        let weightless = JobRequest { prefixes: prefixes.clone(), weight: Some(0), ..Default::default() };
+        assert_eq!(ErrorCode::of(&server.start_job(weightless).unwrap_err()), ErrorCode::InvalidConfig);
+
+        let id = server.start_job(JobRequest { prefixes, budget: Some(1000), ..Default::default() }).unwrap();
+        let job = server.state.job(id).unwrap();
+        let started = Instant::now();
+        let mut last_generated = 0;
+        while !job.stop.load(Ordering::Relaxed) && started.elapsed() < Duration::from_secs(10) {
+            server.state.schedule(&mut last_generated);
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(job.exhausted.load(Ordering::Relaxed));
+        assert!(job.stats.get().0 >= 1000);
+        job.handle.lock().unwrap().take().unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn test_job_api_with_token() {
+        let config = GeneratorConfig::new(Vec::new()).with_workers(1);
+        let server = Server::bind("127.0.0.1:0", config).unwrap().with_token(Some("secret".to_string()));
+        let addr = server.local_addr().unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let server_stop = Arc::clone(&stop);
+        let handle = thread::spawn(move || server.run(&server_stop).unwrap());
+
+        let unauthorized = request(addr, "GET /status HTTP/1.1\r\n\r\n");
+        assert!(unauthorized.starts_with("HTTP/1.1 401"));
+        assert!(unauthorized.contains(r#""code":"auth_failed""#));
+
+        let body = r#"{"prefixes":["A"]}"#;
+        let created = request(
+            addr,
+            &format!(
+                "POST /jobs HTTP/1.1\r\nAuthorization: Bearer secret\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            ),
+        );
+        assert!(created.starts_with("HTTP/1.1 201"), "{}", created);
+        assert!(created.ends_with(r#"{"id":1}"#));
+
+        let invalid = request(
+            addr,
+            "POST /jobs?token=secret HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}",
+        );
+        assert!(invalid.starts_with("HTTP/1.1 400"));
+        assert!(invalid.contains(r#""code":"invalid_pattern""#), "{}", invalid);
+
+        let job = request(addr, "GET /jobs/1?token=secret HTTP/1.1\r\n\r\n");
+        assert!(job.contains(r#""patterns":"prefix \"a\"""#), "{}", job);
+        let body = r#"{"kind":"suffix","pattern":"id"}"#;
+        let added = request(
+            addr,
+            &format!("POST /jobs/1/patterns?token=secret HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}", body.len(), body),
+        );
+        assert!(added.contains(r#""message":"added suffix \"id\"""#), "{}", added);
+        let job = request(addr, "GET /jobs/1?token=secret HTTP/1.1\r\n\r\n");
+        assert!(job.contains(r#""patterns":"prefix \"a\" or suffix \"id\"""#), "{}", job);
+
+        let metrics = request(addr, "GET /metrics?token=secret HTTP/1.1\r\n\r\n");
+        assert!(metrics.contains("onion_jobs_running 1"));
+        assert!(metrics.contains("onion_workers_stalled{job=\"1\"} 0"));
+
+        let health = request(addr, "GET /health?token=secret HTTP/1.1\r\n\r\n");
+        assert!(health.starts_with("HTTP/1.1 200"), "{}", health);
+        assert!(health.ends_with(r#"{"stalled_workers":{},"status":"ok"}"#), "{}", health);
+
+        stop.store(true, Ordering::Relaxed);
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,279 @@
+use crate::sink::{DirectorySink, RateMeter, ResultSink};
+use crate::{logln, ErrorCode, MatchSpec, Matcher, OnionResult, ResultExt};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Claims and host states not refreshed for this long are considered abandoned
+pub const STALE_AFTER: Duration = Duration::from_secs(10 * 60);
+
+/// Several machines coordinating through one shared directory
+///
+/// Layout under the root:
+/// - `claims/<id>.lock`: one lock file per pattern a host is searching
+/// - `results/<hostname>/`: Tor key directories of every find
+/// - `hosts/<host>.json`: each host's latest statistics
+///
+/// Claims are taken with exclusive file creation, so no server is needed; a
+/// claim whose lock file has not been refreshed for [`STALE_AFTER`] may be
+/// taken over by another host.
+pub struct SharedDir {
+    root: PathBuf,
+    host_id: String,
+    claims: Vec<PathBuf>,
+}
+
+/// Statistics a host publishes in its state file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostState {
+    pub host: String,
+    pub patterns: Vec<String>,
+    pub generated: u128,
+    pub found: u128,
+    pub rate: f64,
+    pub updated: String,
+}
+
+impl SharedDir {
+    /// Open `root` as this machine, identified by hostname and process id
+    pub fn open(root: impl Into<PathBuf>) -> Result<Self> {
+        Self::open_as(root, default_host_id())
+    }
+
+    pub fn open_as(root: impl Into<PathBuf>, host_id: impl Into<String>) -> Result<Self> {
+        let root = root.into();
+        for sub in ["claims", "results", "hosts"] {
+            let dir = root.join(sub);
+            fs::create_dir_all(&dir)
+                .with_context(|| format!("Failed to create shared directory {}", dir.display()))
+                .with_code(ErrorCode::OutputNotWritable)?;
+        }
+
+        Ok(Self {
+            root,
+            host_id: host_id.into(),
+            claims: Vec::new(),
+        })
+    }
+
+    pub fn host_id(&self) -> &str {
+        &self.host_id
+    }
+
+    /// Claim up to `max` include patterns no other host is searching
+    ///
+    /// Returns a matcher with the claimed includes and all of `matcher`'s
+    /// excludes, or an error if every pattern is already claimed.
+    pub fn claim(&mut self, matcher: &Matcher, max: usize) -> Result<Matcher> {
+        let mut claimed = Vec::new();
+
+        for spec in matcher.includes() {
+            if claimed.len() >= max {
+                break;
+            }
+            let lock = self.lock_path(spec);
+            if self.try_lock(&lock, spec)? {
+                self.claims.push(lock);
+                claimed.push(spec.clone());
+            }
+        }
+
+        if claimed.is_empty() {
+            anyhow::bail!("Every pattern is already claimed by another host in {}", self.root.display());
+        }
+        Ok(Matcher::new(claimed, matcher.excludes().to_vec()))
+    }
+
+    /// Create `lock` exclusively, replacing it first if its holder went away
+    fn try_lock(&self, lock: &Path, spec: &MatchSpec) -> Result<bool> {
+        if is_stale(lock) {
+            let _ = fs::remove_file(lock);
+        }
+
+        let mut file = match fs::OpenOptions::new().write(true).create_new(true).open(lock) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => return Ok(false),
+            Err(e) => return Err(e).with_context(|| format!("Failed to create {}", lock.display())),
+        };
+        writeln!(file, "{}\n{}", self.host_id, spec)?;
+        Ok(true)
+    }
+
+    fn lock_path(&self, spec: &MatchSpec) -> PathBuf {
+        let digest = Sha3_256::digest(spec.to_string().as_bytes());
+        let id: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+        self.root.join("claims").join(format!("{}.lock", id))
+    }
+
+    /// Directory that collects the finds of all hosts
+    pub fn results_dir(&self) -> PathBuf {
+        self.root.join("results")
+    }
+
+    /// Publish this host's statistics and keep its claims fresh
+    pub fn publish(&self, state: &HostState) -> Result<()> {
+        let path = self.root.join("hosts").join(format!("{}.json", self.host_id));
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(state)?)?;
+        fs::rename(&tmp, &path)?;
+
+        for lock in &self.claims {
+            fs::write(lock, format!("{}\n{}\n", self.host_id, state.updated))?;
+        }
+        Ok(())
+    }
+
+    /// Latest state of every host that has published within [`STALE_AFTER`]
+    pub fn hosts(&self) -> Result<Vec<HostState>> {
+        let mut hosts = Vec::new();
+        for entry in fs::read_dir(self.root.join("hosts"))? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") && !is_stale(&path) {
+                // Skip files another host is rewriting or that are corrupt
+                if let Ok(state) = serde_json::from_slice(&fs::read(&path)?) {
+                    hosts.push(state);
+                }
+            }
+        }
+        Ok(hosts)
+    }
+
+    /// Remove this host's claims so other hosts can take the patterns over
+    pub fn release(&mut self) {
+        for lock in self.claims.drain(..) {
+            let _ = fs::remove_file(lock);
+        }
+    }
+}
+
+impl Drop for SharedDir {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+/// Writes finds into a [`SharedDir`] and prints statistics for all its hosts
+pub struct SharedDirSink {
+    shared: SharedDir,
+    results: DirectorySink,
+    patterns: Vec<String>,
+    meter: RateMeter,
+}
+
+impl SharedDirSink {
+    /// `matcher` is the one returned by [`SharedDir::claim`]
+    pub fn new(shared: SharedDir, matcher: &Matcher) -> Result<Self> {
+        Ok(Self {
+            results: DirectorySink::new(shared.results_dir())?,
+            patterns: matcher.includes().iter().map(|spec| spec.to_string()).collect(),
+            shared,
+            meter: RateMeter::new(),
+        })
+    }
+}
+
+impl ResultSink for SharedDirSink {
+    fn found(&mut self, result: &OnionResult) -> Result<()> {
+        self.results.found(result)
+    }
+
+    fn stats(&mut self, generated: u128, found: u128) -> Result<()> {
+        self.shared.publish(&HostState {
+            host: self.shared.host_id().to_string(),
+            patterns: self.patterns.clone(),
+            generated,
+            found,
+            rate: self.meter.update(generated),
+            updated: chrono::Local::now().to_rfc3339(),
+        })?;
+
+        let hosts = self.shared.hosts()?;
+        logln!(
+            "[@] Shared: {} hosts, Generated {} addresses, Found {} addresses, ~{:.0} keys/s",
+            hosts.len(),
+            hosts.iter().map(|host| host.generated).sum::<u128>(),
+            hosts.iter().map(|host| host.found).sum::<u128>(),
+            hosts.iter().map(|host| host.rate).sum::<f64>(),
+        );
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.shared.release();
+        Ok(())
+    }
+}
+
+fn is_stale(path: &Path) -> bool {
+    fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age > STALE_AFTER)
+}
+
+fn default_host_id() -> String {
+    let hostname = std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "host".to_string());
+    let hostname: String = hostname
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+        .collect();
+    format!("{}-{}", hostname, std::process::id())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+
+    #[test]
+    fn test_hosts_claim_disjoint_patterns() {
+        let root = TempPath::new("shared-claim");
+        let matcher = Matcher::from_prefixes(&["ab".to_string(), "cd".to_string()]);
+
+        let mut first = SharedDir::open_as(&root, "one").unwrap();
+        let mut second = SharedDir::open_as(&root, "two").unwrap();
+        let mut third = SharedDir::open_as(&root, "three").unwrap();
+
+        let a = first.claim(&matcher, 1).unwrap();
+        let b = second.claim(&matcher, 1).unwrap();
+        assert_ne!(a.to_string(), b.to_string());
+        assert!(third.claim(&matcher, 1).is_err());
+
+        first.release();
+        assert_eq!(third.claim(&matcher, 1).unwrap().to_string(), a.to_string());
+
+    }
+
+    #[test]
+    fn test_publish_and_aggregate_hosts() {
+        let root = TempPath::new("shared-hosts");
+        let state = |host: &str, generated| HostState {
+            host: host.to_string(),
+            patterns: vec!["prefix \"ab\"".to_string()],
+            generated,
+            found: 1,
+            rate: 10.0,
+            updated: String::new(),
+        };
+
+        let first = SharedDir::open_as(&root, "one").unwrap();
+        let second = SharedDir::open_as(&root, "two").unwrap();
+        first.publish(&state("one", u64::MAX.into())).unwrap();
+        second.publish(&state("two", 50)).unwrap();
+
+        // Totals past 2^64 survive the JSON state files and the sum
+        let hosts = first.hosts().unwrap();
+        assert_eq!(hosts.len(), 2);
+        assert_eq!(hosts.iter().map(|host| host.generated).sum::<u128>(), u128::from(u64::MAX) + 50);
+    }
+}
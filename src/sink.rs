@@ -0,0 +1,607 @@
+use crate::addressbook::LABEL_FILE;
+use crate::logging::{log_found, log_stats, write_stdout};
+use crate::{confined_join, json_logs, ErrorCode, ResultExt, GenerationStats, KeyCustody, LogWriter, MatchSpec, Matcher, OnionResult, Rotation, RotatingFile, Sparkline};
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Destination for found addresses and statistics updates
+pub trait ResultSink: Send {
+    /// Handle a newly found address
+    fn found(&mut self, result: &OnionResult) -> Result<()>;
+
+    /// Handle a periodic statistics update
+    fn stats(&mut self, _generated: u128, _found: u128) -> Result<()> {
+        Ok(())
+    }
+
+    /// Flush any buffered output
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Human-readable output to stdout
+pub struct StdoutSink;
+
+impl ResultSink for StdoutSink {
+    fn found(&mut self, result: &OnionResult) -> Result<()> {
+        log_found(result);
+        if !json_logs() {
+            write_stdout(|out| write_result(out, result))?;
+        }
+        Ok(())
+    }
+
+    fn stats(&mut self, generated: u128, found: u128) -> Result<()> {
+        log_stats(generated, found);
+        if !json_logs() {
+            write_stdout(|out| write_stats(out, generated, found, None))?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        write_stdout(|out| out.flush())?;
+        Ok(())
+    }
+}
+
+/// [`StdoutSink`] whose stats lines end with a sparkline of recent keys/s
+///
+/// Each stats update adds the rate since the previous one, so dips from
+/// thermal throttling or background load stand out.
+pub struct SparklineSink {
+    sparkline: Sparkline,
+    last: (u128, Instant),
+}
+
+impl SparklineSink {
+    /// Show the rates of the last `intervals` stats updates
+    pub fn new(intervals: usize) -> Self {
+        Self { sparkline: Sparkline::new(intervals), last: (0, Instant::now()) }
+    }
+}
+
+impl ResultSink for SparklineSink {
+    fn found(&mut self, result: &OnionResult) -> Result<()> {
+        StdoutSink.found(result)
+    }
+
+    fn stats(&mut self, generated: u128, found: u128) -> Result<()> {
+        let now = Instant::now();
+        let (before, then) = std::mem::replace(&mut self.last, (generated, now));
+        let rate = generated.saturating_sub(before) as f64 / now.duration_since(then).as_secs_f64().max(f64::EPSILON);
+        self.sparkline.push(rate);
+
+        log_stats(generated, found);
+        if !json_logs() {
+            let trend = format!("{} {:.0} keys/s", self.sparkline.render(), rate);
+            write_stdout(|out| write_stats(out, generated, found, Some(&trend)))?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        StdoutSink.flush()
+    }
+}
+
+/// Writes each found address as a Tor hidden service directory
+///
+/// Every result gets its own `<dir>/<hostname>/` containing the `hostname`,
+/// `hs_ed25519_public_key` and `hs_ed25519_secret_key` files Tor expects.
+/// Sealed results get `hs_ed25519_secret_key.sealed` instead, and keys imported
+/// into a token no secret key file at all.
+pub struct DirectorySink {
+    dir: PathBuf,
+    labels: Option<Labeler>,
+}
+
+type Labeler = Box<dyn FnMut(&OnionResult) -> Option<String> + Send>;
+
+impl DirectorySink {
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create output directory {}", dir.display()))
+            .with_code(ErrorCode::OutputNotWritable)?;
+        Ok(Self { dir, labels: None })
+    }
+
+    /// Ask `label` what each address is for and save the answer in its directory
+    ///
+    /// The label is shown by [`crate::read_address_book`]; `None` or an empty
+    /// label saves nothing.
+    pub fn labels(mut self, label: impl FnMut(&OnionResult) -> Option<String> + Send + 'static) -> Self {
+        self.labels = Some(Box::new(label));
+        self
+    }
+}
+
+impl ResultSink for DirectorySink {
+    fn found(&mut self, result: &OnionResult) -> Result<()> {
+        use base64::Engine;
+        let engine = base64::engine::general_purpose::STANDARD;
+
+        let service_dir = confined_join(&self.dir, &result.hostname)?;
+        fs::create_dir_all(&service_dir)?;
+        restrict_permissions(&service_dir, 0o700)?;
+
+        fs::write(service_dir.join("hostname"), format!("{}\n", result.hostname))?;
+        fs::write(
+            service_dir.join("hs_ed25519_public_key"),
+            engine.decode(&result.public_key)?,
+        )?;
+        let label = self.labels.as_mut().and_then(|label| label(result));
+        if let Some(label) = label.as_deref().map(str::trim).filter(|label| !label.is_empty()) {
+            fs::write(service_dir.join(LABEL_FILE), format!("{}\n", label))?;
+        }
+
+        // Sealed keys need `unseal` on the recipient's machine before Tor can use them
+        let secret_path = match result.custody {
+            KeyCustody::Local => service_dir.join("hs_ed25519_secret_key"),
+            KeyCustody::Sealed => service_dir.join("hs_ed25519_secret_key.sealed"),
+            KeyCustody::Token | KeyCustody::Discarded => return Ok(()),
+        };
+        write_secret(&secret_path, &engine.decode(&result.private_key)?)?;
+
+        Ok(())
+    }
+}
+
+/// Appends each found address, including its private key, as a JSON line
+///
+/// The file can be rotated by size or age, see [`RotatingFile`].
+pub struct ResultsFileSink {
+    file: RotatingFile,
+}
+
+impl ResultsFileSink {
+    pub fn new(path: impl Into<PathBuf>, rotation: Rotation) -> Result<Self> {
+        Ok(Self { file: RotatingFile::open(path, rotation)? })
+    }
+}
+
+impl ResultSink for ResultsFileSink {
+    fn found(&mut self, result: &OnionResult) -> Result<()> {
+        let record = json!({
+            "timestamp": chrono::Local::now().to_rfc3339(),
+            "hostname": result.hostname,
+            "fingerprint": result.fingerprint(),
+            "public_key": result.public_key,
+            "private_key": (result.custody != KeyCustody::Discarded).then_some(&result.private_key),
+            "custody": result.custody.as_str(),
+        });
+        writeln!(self.file, "{}", record)
+            .with_context(|| format!("Failed to write {}", self.file.path().display()))
+            .with_code(ErrorCode::OutputNotWritable)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+/// Machine-readable progress records on stderr, one JSON object per line
+///
+/// Each statistics update produces a record with totals, the rate since the
+/// previous update and the expected time to the next find for every pattern.
+pub struct JsonProgressSink {
+    patterns: Vec<(String, Option<f64>)>,
+    meter: RateMeter,
+}
+
+impl JsonProgressSink {
+    pub fn new(matcher: &Matcher) -> Self {
+        Self {
+            patterns: matcher
+                .includes()
+                .iter()
+                .map(|spec| (spec.to_string(), spec.expected_attempts()))
+                .collect(),
+            meter: RateMeter::new(),
+        }
+    }
+}
+
+impl ResultSink for JsonProgressSink {
+    fn found(&mut self, _result: &OnionResult) -> Result<()> {
+        Ok(())
+    }
+
+    fn stats(&mut self, generated: u128, found: u128) -> Result<()> {
+        let rate = self.meter.update(generated);
+
+        let patterns: Vec<_> = self
+            .patterns
+            .iter()
+            .map(|(pattern, expected)| {
+                let eta = expected.filter(|e| e.is_finite() && rate > 0.0).map(|e| e / rate);
+                json!({
+                    "pattern": pattern,
+                    "expected_attempts": expected.filter(|e| e.is_finite()),
+                    "eta_secs": eta,
+                })
+            })
+            .collect();
+
+        let record = json!({
+            "event": "progress",
+            "timestamp": chrono::Local::now().to_rfc3339(),
+            "elapsed_secs": self.meter.started.elapsed().as_secs_f64(),
+            "generated": generated,
+            "found": found,
+            "rate": rate,
+            "patterns": patterns,
+        });
+        writeln!(io::stderr().lock(), "{}", record)?;
+        Ok(())
+    }
+}
+
+/// Periodic projection of the chance to find each outstanding pattern
+///
+/// On every statistics update, prints the probability of at least one match
+/// within the next hour, day and week at the rate measured since the previous
+/// update. Patterns drop out of the table once they have matched.
+pub struct ProjectionSink {
+    outstanding: Vec<MatchSpec>,
+    meter: RateMeter,
+}
+
+impl ProjectionSink {
+    pub fn new(matcher: &Matcher) -> Self {
+        Self {
+            outstanding: matcher.includes().to_vec(),
+            meter: RateMeter::new(),
+        }
+    }
+}
+
+impl ResultSink for ProjectionSink {
+    fn found(&mut self, result: &OnionResult) -> Result<()> {
+        let address = result.hostname.strip_suffix(".onion").unwrap_or(&result.hostname);
+        self.outstanding.retain(|spec| !spec.is_match(address));
+        Ok(())
+    }
+
+    fn stats(&mut self, generated: u128, _found: u128) -> Result<()> {
+        let rate = self.meter.update(generated);
+        let mut out = LogWriter::new();
+
+        if self.outstanding.is_empty() {
+            writeln!(out, "[@] Every pattern has been found at least once")?;
+            return Ok(());
+        }
+        writeln!(out, "[@] Projection at {:.0} keys/s:", rate)?;
+        write_pattern_table(&mut out, &self.outstanding, rate, &PROJECTION_HORIZONS)?;
+        Ok(())
+    }
+}
+
+/// Measures the key rate between successive statistics updates
+pub(crate) struct RateMeter {
+    started: Instant,
+    last: Option<(Instant, u128)>,
+}
+
+impl RateMeter {
+    pub(crate) fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            last: None,
+        }
+    }
+
+    /// Record the current total and return the rate since the previous update
+    pub(crate) fn update(&mut self, generated: u128) -> f64 {
+        let now = Instant::now();
+        let (since, base) = self.last.unwrap_or((self.started, 0));
+        let elapsed = now.duration_since(since).as_secs_f64();
+        self.last = Some((now, generated));
+
+        if elapsed > 0.0 {
+            generated.saturating_sub(base) as f64 / elapsed
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Write a found address in the human-readable format
+pub(crate) fn write_result(out: &mut dyn Write, result: &OnionResult) -> io::Result<()> {
+    writeln!(out, "[√] Address generated successfully!")?;
+    writeln!(out, "Hostname:                      {}", result.hostname)?;
+    writeln!(out, "Fingerprint:                   {}", result.fingerprint())?;
+    writeln!(out, "Public Key (Base64 encoded):   {}", result.public_key)?;
+    match result.custody {
+        KeyCustody::Local => writeln!(out, "Private Key (Base64 encoded):  {}\n", result.private_key),
+        KeyCustody::Sealed => writeln!(out, "Sealed Private Key (Base64):   {}\n", result.private_key),
+        KeyCustody::Token => writeln!(out, "Private Key:                   imported into token, not kept locally\n"),
+        KeyCustody::Discarded => writeln!(out, "Private Key:                   discarded, public key only\n"),
+    }
+}
+
+/// Write a statistics line in the human-readable format, with `trend` at the end
+pub(crate) fn write_stats(out: &mut dyn Write, generated: u128, found: u128, trend: Option<&str>) -> io::Result<()> {
+    let now = chrono::Local::now();
+    write!(
+        out,
+        "[@] {}: Generated {} addresses, Found {} addresses",
+        now.format("%H:%M:%S"),
+        generated,
+        found
+    )?;
+    match trend {
+        Some(trend) => writeln!(out, "  {}", trend),
+        None => writeln!(out),
+    }
+}
+
+/// Width of the bars in the summary histogram
+const HISTOGRAM_WIDTH: usize = 40;
+
+/// Write the end-of-run totals and the histogram of attempts between finds
+///
+/// `expected` is the matcher's expected attempts per find, shown next to the
+/// observed mean as a sanity check of the generator.
+pub fn write_summary(
+    out: &mut impl Write,
+    stats: &GenerationStats,
+    expected: Option<f64>,
+    elapsed: Duration,
+) -> io::Result<()> {
+    let (generated, found) = stats.get();
+    let rate = generated as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+    writeln!(
+        out,
+        "[@] Summary: Generated {} addresses in {:.1}s ({:.0} keys/s), Found {} addresses",
+        generated,
+        elapsed.as_secs_f64(),
+        rate,
+        found
+    )?;
+
+    let histogram = stats.find_histogram();
+    if let Some(mean) = histogram.mean() {
+        match expected.filter(|e| e.is_finite()) {
+            Some(expected) => writeln!(
+                out,
+                "[@] Attempts between finds: mean {:.0} (expected {:.0})",
+                mean, expected
+            )?,
+            None => writeln!(out, "[@] Attempts between finds: mean {:.0}", mean)?,
+        }
+        write!(out, "{}", histogram.render(HISTOGRAM_WIDTH))?;
+    }
+    Ok(())
+}
+
+/// Write a table of each include pattern's difficulty at `rate` keys per second
+///
+/// Patterns are listed from easiest to hardest, with the expected number of
+/// attempts and the chance of at least one match within an hour and a day.
+pub fn write_difficulty_table(out: &mut impl Write, matcher: &Matcher, rate: f64) -> io::Result<()> {
+    write_pattern_table(out, matcher.includes(), rate, &DIFFICULTY_HORIZONS)
+}
+
+/// Time spans shown in the startup difficulty table
+const DIFFICULTY_HORIZONS: [(&str, f64); 2] = [("1 hour", 3600.0), ("1 day", 86400.0)];
+
+/// Time spans shown in the periodic projection
+const PROJECTION_HORIZONS: [(&str, f64); 3] =
+    [("1 hour", 3600.0), ("1 day", 86400.0), ("1 week", 604800.0)];
+
+/// Table of patterns, easiest first, with the median time to a match and the
+/// chance of a match within each horizon
+fn write_pattern_table(
+    out: &mut impl Write,
+    specs: &[MatchSpec],
+    rate: f64,
+    horizons: &[(&str, f64)],
+) -> io::Result<()> {
+    let mut rows: Vec<(String, Option<f64>)> = specs
+        .iter()
+        .map(|spec| (spec.to_string(), spec.expected_attempts()))
+        .collect();
+    rows.sort_by(|a, b| {
+        let key = |expected: Option<f64>| expected.unwrap_or(f64::INFINITY);
+        key(a.1).total_cmp(&key(b.1))
+    });
+
+    let width = rows.iter().map(|(pattern, _)| pattern.len()).max().unwrap_or(0).max(7);
+    write!(out, "    {:<width$}  {:>17}  {:>11}", "Pattern", "Expected attempts", "Median time")?;
+    for (label, _) in horizons {
+        write!(out, "  {:>8}", label)?;
+    }
+    writeln!(out)?;
+
+    for (pattern, expected) in rows {
+        match expected {
+            Some(expected) => {
+                write!(
+                    out,
+                    "    {:<width$}  {:>17}  {:>11}",
+                    pattern,
+                    format_attempts(expected),
+                    format_eta(median_seconds(expected, rate))
+                )?;
+                for (_, secs) in horizons {
+                    write!(out, "  {:>7.2}%", success_probability(expected, rate, *secs) * 100.0)?;
+                }
+            }
+            None => {
+                write!(out, "    {:<width$}  {:>17}  {:>11}", pattern, "unknown", "-")?;
+                for _ in horizons {
+                    write!(out, "  {:>8}", "-")?;
+                }
+            }
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Chance of at least one match after `secs` seconds at `rate` keys per second
+fn success_probability(expected: f64, rate: f64, secs: f64) -> f64 {
+    if !expected.is_finite() {
+        return 0.0;
+    }
+    -(-rate * secs / expected).exp_m1()
+}
+
+/// Time by which a match is found with 50% probability at `rate` keys per second
+pub fn median_seconds(expected: f64, rate: f64) -> f64 {
+    if rate > 0.0 {
+        expected * std::f64::consts::LN_2 / rate
+    } else {
+        f64::INFINITY
+    }
+}
+
+/// Human-readable duration for an estimated time to a match
+pub fn format_eta(secs: f64) -> String {
+    const UNITS: [(&str, f64); 5] =
+        [("years", 31_557_600.0), ("days", 86400.0), ("hours", 3600.0), ("min", 60.0), ("s", 1.0)];
+    if !secs.is_finite() {
+        return "never".to_string();
+    }
+    match UNITS.iter().find(|(_, size)| secs >= *size) {
+        Some(&(unit, size)) if secs / size >= 1e6 => format!("{:.1e} {}", secs / size, unit),
+        Some(&(unit, size)) => format!("{:.1} {}", secs / size, unit),
+        None => format!("{:.1} s", secs),
+    }
+}
+
+fn format_attempts(expected: f64) -> String {
+    if !expected.is_finite() {
+        "impossible".to_string()
+    } else if expected < 1e9 {
+        format!("{:.0}", expected)
+    } else {
+        format!("{:.2e}", expected)
+    }
+}
+
+/// Write `bytes` to `path`, readable by the owner only before any of them is written
+fn write_secret(path: &std::path::Path, bytes: &[u8]) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    // The mode only applies to new files; one left over from an earlier run keeps its own
+    restrict_permissions(path, 0o600)?;
+    file.write_all(bytes)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+pub(crate) fn restrict_permissions(path: &std::path::Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn restrict_permissions(_path: &std::path::Path, _mode: u32) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+    use crate::MatchSpec;
+    use base64::Engine;
+
+    #[test]
+    fn test_directory_sink_writes_owner_only_keys() {
+        let dir = TempPath::new("sink");
+        let result = crate::generate_onion_address().unwrap();
+        let service_dir = dir.join(&result.hostname);
+        let secret_path = service_dir.join("hs_ed25519_secret_key");
+        // A secret key file left behind world-readable by an earlier run
+        fs::create_dir_all(&service_dir).unwrap();
+        fs::write(&secret_path, b"stale").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&secret_path, fs::Permissions::from_mode(0o644)).unwrap();
+        }
+
+        DirectorySink::new(&dir).unwrap().found(&result).unwrap();
+        let engine = base64::engine::general_purpose::STANDARD;
+        let read = |name: &str| fs::read(service_dir.join(name)).unwrap();
+        assert_eq!(read("hostname"), format!("{}\n", result.hostname).into_bytes());
+        assert_eq!(read("hs_ed25519_public_key"), engine.decode(&result.public_key).unwrap());
+        assert_eq!(read("hs_ed25519_secret_key"), engine.decode(&result.private_key).unwrap());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |path: &std::path::Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode(&service_dir), 0o700);
+            assert_eq!(mode(&secret_path), 0o600);
+        }
+    }
+
+    #[test]
+    fn test_success_probability() {
+        assert!((success_probability(1000.0, 1000.0, 1.0) - 0.632).abs() < 0.001);
+        assert_eq!(success_probability(f64::INFINITY, 1000.0, 3600.0), 0.0);
+    }
+
+    #[test]
+    fn test_format_eta() {
+        assert_eq!(format_eta(median_seconds(1000.0, 1000.0)), "0.7 s");
+        assert_eq!(format_eta(5400.0), "1.5 hours");
+        assert_eq!(format_eta(f64::INFINITY), "never");
+        assert_eq!(format_eta(median_seconds(32f64.powi(16), 1e7)), "2.7e9 years");
+    }
+
+    #[test]
+    fn test_difficulty_table_sorted_by_expected_attempts() {
+        let matcher = Matcher::new(
+            vec![
+                MatchSpec::Prefix("abcdefgh".to_string()),
+                MatchSpec::Prefix("ab".to_string()),
+                MatchSpec::Suffix("ab".to_string()),
+            ],
+            Vec::new(),
+        );
+        let mut out = Vec::new();
+        write_difficulty_table(&mut out, &matcher, 1000.0).unwrap();
+        let table = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = table.lines().collect();
+
+        assert_eq!(lines.len(), 4);
+        assert!(lines[1].contains("prefix \"ab\"") && lines[1].contains("1024") && lines[1].contains("0.7 s"));
+        assert!(lines[2].contains("1.10e12"));
+        assert!(lines[3].contains("impossible") && lines[3].contains("0.00%"));
+    }
+
+    #[test]
+    fn test_projection_drops_found_patterns() {
+        let matcher = Matcher::new(
+            vec![MatchSpec::Prefix("ab".to_string()), MatchSpec::Prefix("cd".to_string())],
+            Vec::new(),
+        );
+        let mut sink = ProjectionSink::new(&matcher);
+        let result = OnionResult {
+            hostname: format!("ab{}.onion", "x".repeat(54)),
+            public_key: String::new(),
+            private_key: String::new(),
+            custody: KeyCustody::Local,
+            seed: None,
+        };
+        sink.found(&result).unwrap();
+
+        assert_eq!(sink.outstanding.len(), 1);
+        assert_eq!(sink.outstanding[0].to_string(), "prefix \"cd\"");
+    }
+}
@@ -0,0 +1,110 @@
+use crate::GenerationStats;
+use arc_swap::ArcSwap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Counters of one worker in a [`StatsSnapshot`]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct WorkerSnapshot {
+    pub generated: u128,
+    pub found: u128,
+    /// Keys per second since the previous snapshot
+    pub rate: f64,
+}
+
+/// The statistics of a worker pool at one moment, never changed once taken
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StatsSnapshot {
+    pub generated: u128,
+    pub found: u128,
+    /// Keys per second since the previous snapshot
+    pub rate: f64,
+    pub workers: Vec<WorkerSnapshot>,
+    pub paused: bool,
+    /// Time since the pool started
+    pub elapsed: Duration,
+}
+
+impl StatsSnapshot {
+    /// Average keys per second since the pool started
+    pub fn average_rate(&self) -> f64 {
+        self.generated as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// The latest [`StatsSnapshot`] of a pool, readable from any thread without locks
+///
+/// The pool's stats thread takes a snapshot a few times a second. Readers such
+/// as the progress lines, the Enter key, the control socket and the HTTP
+/// server load it with [`latest`](Self::latest) and never touch the counters
+/// the workers update for every candidate. Clones share the snapshot.
+#[derive(Debug, Clone, Default)]
+pub struct StatsSnapshots(Arc<ArcSwap<StatsSnapshot>>);
+
+impl StatsSnapshots {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn latest(&self) -> Arc<StatsSnapshot> {
+        self.0.load_full()
+    }
+
+    /// Read `total` and `workers` and publish them, with rates since the previous snapshot
+    pub(crate) fn take(&self, total: &GenerationStats, workers: &[Arc<GenerationStats>], paused: bool, started: Instant) {
+        let previous = self.0.load();
+        let elapsed = started.elapsed();
+        let seconds = elapsed.saturating_sub(previous.elapsed).as_secs_f64().max(f64::EPSILON);
+        let rate = |generated: u128, before: u128| generated.saturating_sub(before) as f64 / seconds;
+
+        let workers = workers
+            .iter()
+            .enumerate()
+            .map(|(worker_id, stats)| {
+                let (generated, found) = stats.get();
+                let before = previous.workers.get(worker_id).map_or(0, |worker| worker.generated);
+                WorkerSnapshot { generated, found, rate: rate(generated, before) }
+            })
+            .collect();
+        let (generated, found) = total.get();
+        let snapshot = StatsSnapshot { generated, found, rate: rate(generated, previous.generated), workers, paused, elapsed };
+        self.0.store(Arc::new(snapshot));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshots_track_counts_and_rates() {
+        let total = Arc::new(GenerationStats::new());
+        let workers = vec![Arc::new(GenerationStats::child_of(&total)), Arc::new(GenerationStats::child_of(&total))];
+        let snapshots = StatsSnapshots::new();
+        let reader = snapshots.clone();
+        assert_eq!(*reader.latest(), StatsSnapshot::default());
+
+        let started = Instant::now() - Duration::from_secs(2);
+        for _ in 0..300 {
+            workers[0].increment_generated();
+        }
+        workers[1].increment_generated();
+        workers[1].increment_found();
+        snapshots.take(&total, &workers, true, started);
+
+        let snapshot = reader.latest();
+        assert_eq!((snapshot.generated, snapshot.found), (301, 1));
+        assert_eq!(snapshot.workers[0].generated, 300);
+        assert_eq!(snapshot.workers[1].found, 1);
+        assert!(snapshot.paused);
+        // About two seconds since the start, which is also the previous snapshot
+        assert!((140.0..=151.0).contains(&snapshot.rate), "{}", snapshot.rate);
+        assert!((snapshot.average_rate() - snapshot.rate).abs() < 1.0);
+
+        // Counters moving on leave a loaded snapshot as it was
+        workers[0].increment_generated();
+        assert_eq!(snapshot.generated, 301);
+        snapshots.take(&total, &workers, false, started);
+        assert_eq!(reader.latest().generated, 302);
+    }
+}
@@ -0,0 +1,91 @@
+use crate::crypto::{generate_keypair, seeded_keypair, SeededKeys};
+use crate::onion::onion_result_from_key;
+use crate::OnionResult;
+use anyhow::{anyhow, Result};
+use ed25519_dalek::SigningKey;
+use std::sync::Arc;
+
+/// Where a search gets its candidate keys from
+///
+/// The worker pool asks each worker's source for one public key per
+/// candidate and does the filtering, encoding and matching itself, so a
+/// backend only has to produce keys. The private key is asked for only when a
+/// candidate matches, which lets a source that computes public keys in bulk,
+/// e.g. on a GPU or another machine, keep just enough to re-derive it.
+/// [`RandomKeys`], [`SeededKeys`] and [`crate::BatchedKeys`] are the CPU sources.
+pub trait CandidateSource: Send {
+    /// Public key of the next candidate
+    fn next_public_key(&mut self) -> Result<[u8; 32]>;
+
+    /// Private key of the candidate last returned by [`next_public_key`](Self::next_public_key)
+    fn signing_key(&mut self) -> Result<SigningKey>;
+
+    /// Result for the candidate last returned, named `hostname`
+    ///
+    /// Built from [`signing_key`](Self::signing_key) unless the source's keys
+    /// have no seed, like those of [`crate::BatchedKeys`].
+    fn onion_result(&mut self, hostname: String) -> Result<OnionResult> {
+        onion_result_from_key(&self.signing_key()?, hostname)
+    }
+}
+
+/// Builds the candidate source of a worker from its id, see [`crate::WorkerPoolBuilder::candidate_source`]
+pub type SourceFactory = Arc<dyn Fn(usize) -> Result<Box<dyn CandidateSource>> + Send + Sync>;
+
+/// Candidates from the system RNG, the default source
+#[derive(Default)]
+pub struct RandomKeys {
+    last: Option<SigningKey>,
+}
+
+impl RandomKeys {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl CandidateSource for RandomKeys {
+    fn next_public_key(&mut self) -> Result<[u8; 32]> {
+        let (signing_key, verifying_key) = generate_keypair()?;
+        self.last = Some(signing_key);
+        Ok(verifying_key.to_bytes())
+    }
+
+    fn signing_key(&mut self) -> Result<SigningKey> {
+        self.last.clone().ok_or_else(|| anyhow!("No candidate has been drawn yet"))
+    }
+}
+
+/// Keeps only the counter of each candidate and re-derives the key of a match
+impl CandidateSource for SeededKeys {
+    fn next_public_key(&mut self) -> Result<[u8; 32]> {
+        Ok(self.next_keypair().1.to_bytes())
+    }
+
+    fn signing_key(&mut self) -> Result<SigningKey> {
+        let counter = self.counter().checked_sub(1).ok_or_else(|| anyhow!("No candidate has been drawn yet"))?;
+        Ok(seeded_keypair(self.master_seed(), self.shard(), counter).0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sources_hand_out_matching_keys() {
+        let mut sources: Vec<Box<dyn CandidateSource>> =
+            vec![Box::new(RandomKeys::new()), Box::new(SeededKeys::new([9u8; 32], 2).starting_at(5))];
+        assert!(RandomKeys::new().signing_key().is_err());
+        assert!(SeededKeys::new([9u8; 32], 2).signing_key().is_err());
+        for source in &mut sources {
+            let first = source.next_public_key().unwrap();
+            let second = source.next_public_key().unwrap();
+            assert_ne!(first, second);
+            assert_eq!(source.signing_key().unwrap().verifying_key().to_bytes(), second);
+        }
+
+        let mut seeded = SeededKeys::new([9u8; 32], 2).starting_at(5);
+        assert_eq!(seeded.next_public_key().unwrap(), seeded_keypair(&[9u8; 32], 2, 5).1.to_bytes());
+    }
+}
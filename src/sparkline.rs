@@ -0,0 +1,73 @@
+use std::collections::VecDeque;
+
+/// Bar characters from lowest to highest
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// The last few values of a series, drawn as a one-line unicode bar chart
+///
+/// Bars are scaled from zero to the largest value shown, so a drop to half
+/// the usual throughput shows as a bar half as high.
+#[derive(Debug, Clone)]
+pub struct Sparkline {
+    values: VecDeque<f64>,
+    capacity: usize,
+}
+
+impl Sparkline {
+    /// Keep the last `capacity` values
+    pub fn new(capacity: usize) -> Self {
+        Self { values: VecDeque::with_capacity(capacity), capacity }
+    }
+
+    /// Add a value, dropping the oldest once full
+    pub fn push(&mut self, value: f64) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.values.len() == self.capacity {
+            self.values.pop_front();
+        }
+        self.values.push_back(value.max(0.0));
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// One character per value, oldest first
+    pub fn render(&self) -> String {
+        let max = self.values.iter().copied().fold(0.0, f64::max);
+        self.values
+            .iter()
+            .map(|value| match max > 0.0 {
+                true => BARS[((value / max * BARS.len() as f64).ceil() as usize).clamp(1, BARS.len()) - 1],
+                false => BARS[0],
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_scales_to_the_maximum() {
+        let mut sparkline = Sparkline::new(4);
+        assert_eq!(sparkline.render(), "");
+        for value in [0.0, 100.0, 800.0, 400.0, 790.0] {
+            sparkline.push(value);
+        }
+        assert_eq!(sparkline.len(), 4);
+        assert_eq!(sparkline.render(), "▁█▄█");
+
+        let mut idle = Sparkline::new(3);
+        idle.push(0.0);
+        idle.push(0.0);
+        assert_eq!(idle.render(), "▁▁");
+    }
+}
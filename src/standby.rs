@@ -0,0 +1,153 @@
+use crate::sink::restrict_permissions;
+use crate::{generate_onion_address, seal_result, unseal, ErrorCode, KeyCustody, OnionResult, ResultExt};
+use anyhow::{Context, Result};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+const READY_DIR: &str = "ready";
+const CLAIMED_DIR: &str = "claimed";
+
+/// Plain addresses generated ahead of time and kept sealed until they are claimed
+///
+/// Each key is a JSON file under `<dir>/ready/` holding its hostname, public
+/// key and a sealed box of its secret key file for a `seal-keygen` recipient,
+/// so filling the pool needs only the public key and a copy of the directory
+/// gives away nothing. [`claim`](Self::claim) moves one file to
+/// `<dir>/claimed/` with a rename, which hands each key out once even to
+/// claimers racing on the same directory.
+#[derive(Debug, Clone)]
+pub struct StandbyPool {
+    dir: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StandbyRecord {
+    hostname: String,
+    public_key: String,
+    sealed_key: String,
+}
+
+impl StandbyPool {
+    /// Open the pool in `dir`, creating it if needed
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        for sub in [READY_DIR, CLAIMED_DIR] {
+            fs::create_dir_all(dir.join(sub))
+                .with_context(|| format!("Failed to create standby pool {}", dir.display()))
+                .with_code(ErrorCode::OutputNotWritable)?;
+        }
+        restrict_permissions(&dir, 0o700)?;
+        Ok(Self { dir })
+    }
+
+    /// Number of keys waiting to be claimed
+    pub fn ready(&self) -> Result<usize> {
+        Ok(self.ready_files()?.len())
+    }
+
+    /// Generate keys sealed to `recipient` until `count` are ready; returns how many were added
+    pub fn fill(&self, count: usize, recipient: &[u8; 32]) -> Result<usize> {
+        let missing = count.saturating_sub(self.ready()?);
+        for _ in 0..missing {
+            let sealed = seal_result(recipient, &generate_onion_address()?)?;
+            let record = StandbyRecord {
+                hostname: sealed.hostname.clone(),
+                public_key: sealed.public_key,
+                sealed_key: sealed.private_key,
+            };
+            // Written aside and renamed, so claimers never see a partial file
+            let name = format!("{}.json", sealed.hostname);
+            let partial = self.dir.join(READY_DIR).join(format!(".{}.partial", name));
+            fs::write(&partial, serde_json::to_vec(&record)?).with_code(ErrorCode::OutputNotWritable)?;
+            restrict_permissions(&partial, 0o600)?;
+            fs::rename(&partial, self.dir.join(READY_DIR).join(name)).with_code(ErrorCode::OutputNotWritable)?;
+        }
+        Ok(missing)
+    }
+
+    /// Hand out one ready key, opened with `secret_key`, and mark it as used
+    ///
+    /// Returns `None` when the pool is empty. A key that `secret_key` cannot
+    /// open is left in the pool.
+    pub fn claim(&self, secret_key: &[u8; 32]) -> Result<Option<OnionResult>> {
+        let engine = base64::engine::general_purpose::STANDARD;
+        for path in self.ready_files()? {
+            let record: StandbyRecord = match fs::read(&path) {
+                Ok(bytes) => serde_json::from_slice(&bytes)
+                    .with_context(|| format!("Corrupt standby key {}", path.display()))?,
+                // Claimed by someone else since the directory was listed
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let private_key = unseal(secret_key, &engine.decode(&record.sealed_key)?).with_code(ErrorCode::AuthFailed)?;
+            let claimed = self.dir.join(CLAIMED_DIR).join(path.file_name().unwrap_or_default());
+            match fs::rename(&path, claimed) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e).with_code(ErrorCode::OutputNotWritable),
+            }
+            return Ok(Some(OnionResult {
+                hostname: record.hostname,
+                public_key: record.public_key,
+                private_key: engine.encode(private_key.as_slice()),
+                custody: KeyCustody::Local,
+                seed: None,
+            }));
+        }
+        Ok(None)
+    }
+
+    /// The ready key files, oldest name first so claims are predictable
+    fn ready_files(&self) -> Result<Vec<PathBuf>> {
+        let ready = self.dir.join(READY_DIR);
+        let mut files: Vec<PathBuf> = fs::read_dir(&ready)
+            .with_context(|| format!("Failed to read standby pool {}", self.dir.display()))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            // Skips keys still being written, which end in .partial
+            .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+    use crate::{generate_seal_keypair, parse_onion_address, parse_x25519_key};
+
+    #[test]
+    fn test_claims_hand_out_each_key_once() {
+        let dir = TempPath::new("standby");
+        let keypair = generate_seal_keypair();
+        let recipient = parse_x25519_key(&keypair.public_key).unwrap();
+        let secret_key = parse_x25519_key(&keypair.secret_key).unwrap();
+
+        let pool = StandbyPool::open(&dir).unwrap();
+        assert_eq!(pool.fill(3, &recipient).unwrap(), 3);
+        assert_eq!(pool.fill(3, &recipient).unwrap(), 0);
+        // Nothing secret is stored in the clear
+        let stored = fs::read_to_string(&pool.ready_files().unwrap()[0]).unwrap();
+        assert!(stored.contains("sealed_key"));
+
+        let other = parse_x25519_key(&generate_seal_keypair().secret_key).unwrap();
+        let error = pool.claim(&other).unwrap_err();
+        assert_eq!(ErrorCode::of(&error), ErrorCode::AuthFailed);
+        assert_eq!(pool.ready().unwrap(), 3);
+
+        let mut hostnames = Vec::new();
+        while let Some(result) = pool.claim(&secret_key).unwrap() {
+            assert_eq!(result.custody, KeyCustody::Local);
+            assert!(parse_onion_address(&result.hostname).is_ok());
+            hostnames.push(result.hostname);
+        }
+        hostnames.dedup();
+        assert_eq!(hostnames.len(), 3);
+        assert_eq!(pool.ready().unwrap(), 0);
+        assert_eq!(fs::read_dir(dir.join(CLAIMED_DIR)).unwrap().count(), 3);
+    }
+}
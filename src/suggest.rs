@@ -0,0 +1,191 @@
+use crate::{median_seconds, MatchSpec, BASE32_ALPHABET};
+use anyhow::{bail, Result};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::time::Duration;
+
+/// Address characters that look like characters addresses never contain
+const LOOKALIKES: &[(char, &str)] = &[('0', "o"), ('1', "li"), ('8', "b"), ('9', "g")];
+
+/// Readable words for [`suggest_prefixes`], all made of address characters
+const WORDS: &[&str] = &[
+    "ace", "art", "bay", "bee", "box", "cat", "cup", "dew", "elf", "fox", "fun", "gem", "hat", "ink", "joy", "key",
+    "owl", "sky", "sun", "zen",
+    "bird", "blue", "cafe", "code", "dawn", "deer", "echo", "fern", "fire", "gold", "hive", "iris", "jade", "kite",
+    "leaf", "lime", "mint", "moon", "nest", "nova", "onyx", "pine", "rain", "ruby", "sage", "star", "tide", "wave",
+    "wolf", "zinc",
+    "amber", "bloom", "cedar", "cloud", "coral", "delta", "ember", "flame", "frost", "globe", "honey", "lemon",
+    "light", "lotus", "maple", "ocean", "orbit", "pearl", "pixel", "quiet", "river", "solar", "storm", "tiger",
+    "beacon", "bright", "castle", "cobalt", "falcon", "forest", "garden", "harbor", "island", "jungle", "marble",
+    "meadow", "shadow", "silver", "summit", "velvet", "winter",
+    "crystal", "emerald", "horizon", "journal", "lantern", "library", "phoenix", "rainbow", "sunrise", "thunder",
+    "aurora", "blossom", "diamond", "network", "starlight", "moonlight", "wildfire", "waterfall",
+];
+
+/// Length of the longest prefix found in `budget` in half of all searches at `rate` keys per second
+///
+/// Zero when not even a single character fits.
+pub fn max_prefix_length(rate: f64, budget: Duration) -> usize {
+    (1..=54usize)
+        .take_while(|&length| median_seconds(32f64.powi(length as i32), rate) <= budget.as_secs_f64())
+        .last()
+        .unwrap_or(0)
+}
+
+/// Up to `count` random words from a built-in list to search for as prefixes of at most `max_length` characters
+///
+/// The longest words that fit come first, so the suggestions use as much of
+/// the budget as possible.
+pub fn suggest_prefixes(max_length: usize, count: usize, rng: &mut impl Rng) -> Vec<String> {
+    let mut words: Vec<&str> = WORDS.iter().copied().filter(|word| word.len() <= max_length).collect();
+    words.shuffle(rng);
+    words.sort_by_key(|word| std::cmp::Reverse(word.len()));
+    words.into_iter().take(count).map(str::to_string).collect()
+}
+
+/// Cheaper patterns to offer in place of a hard `spec`, easiest first
+///
+/// A prefix gets the same word shortened by one and two characters, the word
+/// anywhere in the address and the cheapest split of the word into a prefix
+/// and a suffix; a suffix gets the shortened and anywhere variants. Only
+/// alternatives that can match and are easier than `spec` are returned.
+pub fn suggest_alternatives(spec: &MatchSpec) -> Vec<MatchSpec> {
+    let Some(expected) = spec.expected_attempts() else {
+        return Vec::new();
+    };
+    let attempts = |spec: &MatchSpec| spec.expected_attempts().unwrap_or(f64::INFINITY);
+
+    let mut alternatives = Vec::new();
+    match spec {
+        MatchSpec::Prefix(word) => {
+            for cut in (1..=2).filter(|cut| *cut < word.len()) {
+                alternatives.push(MatchSpec::Prefix(word[..word.len() - cut].to_string()));
+            }
+            alternatives.push(MatchSpec::Contains(word.clone()));
+            let split = (1..word.len())
+                .map(|at| MatchSpec::PrefixSuffix(word[..at].to_string(), word[at..].to_string()))
+                .min_by(|a, b| attempts(a).total_cmp(&attempts(b)));
+            alternatives.extend(split);
+        }
+        MatchSpec::Suffix(word) => {
+            for cut in (1..=2).filter(|cut| *cut < word.len()) {
+                alternatives.push(MatchSpec::Suffix(word[cut..].to_string()));
+            }
+            alternatives.push(MatchSpec::Contains(word.clone()));
+        }
+        _ => {}
+    }
+
+    alternatives.retain(|alternative| attempts(alternative) < expected);
+    alternatives.sort_by(|a, b| attempts(a).total_cmp(&attempts(b)));
+    alternatives
+}
+
+/// Valid patterns closest to one with characters that never appear in addresses, best first
+///
+/// Uppercase letters are lowercased, `0`, `1`, `8` and `9` are replaced by the
+/// letters they resemble (`1` by `l`, then by `i`), and the last suggestion
+/// simply drops every invalid character. Returns nothing for a valid pattern.
+pub fn nearest_valid_patterns(pattern: &str) -> Vec<String> {
+    if pattern.chars().all(|c| BASE32_ALPHABET.contains(c)) {
+        return Vec::new();
+    }
+    let lower = pattern.to_lowercase();
+    let lookalike = |c: char, choice: usize| {
+        LOOKALIKES
+            .iter()
+            .find(|(from, _)| *from == c)
+            .and_then(|(_, to)| to.chars().nth(choice).or_else(|| to.chars().next()))
+    };
+    let replaced = |choice: usize| -> String {
+        lower
+            .chars()
+            .filter_map(|c| if BASE32_ALPHABET.contains(c) { Some(c) } else { lookalike(c, choice) })
+            .collect()
+    };
+
+    let mut suggestions = Vec::new();
+    for candidate in [replaced(0), replaced(1), lower.chars().filter(|c| BASE32_ALPHABET.contains(*c)).collect()] {
+        if !candidate.is_empty() && !suggestions.contains(&candidate) {
+            suggestions.push(candidate);
+        }
+    }
+    suggestions
+}
+
+/// Fail for a pattern with characters that never appear in addresses, naming the nearest valid ones
+pub fn check_address_chars(pattern: &str) -> Result<()> {
+    let Some(c) = pattern.chars().find(|c| !BASE32_ALPHABET.contains(*c)) else {
+        return Ok(());
+    };
+    let suggestions: Vec<String> = nearest_valid_patterns(pattern).iter().map(|s| format!("{:?}", s)).collect();
+    match suggestions.as_slice() {
+        [] => bail!("{:?} can never match: {:?} never appears in an address; only a-z and 2-7 are used", pattern, c),
+        [only] => bail!("{:?} can never match: {:?} never appears in an address; did you mean {}?", pattern, c, only),
+        [first @ .., last] => bail!(
+            "{:?} can never match: {:?} never appears in an address; did you mean {} or {}?",
+            pattern, c, first.join(", "), last
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suggest_alternatives() {
+        let suggestions: Vec<String> = suggest_alternatives(&MatchSpec::Prefix("torproject".to_string()))
+            .iter()
+            .map(|spec| spec.to_string())
+            .collect();
+        assert_eq!(
+            suggestions,
+            ["prefix \"torproje\"", "containing \"torproject\"", "prefix \"torprojec\""]
+        );
+
+        // Ending in "ad" is 256 times cheaper as a suffix than as part of a prefix
+        let split = suggest_alternatives(&MatchSpec::Prefix("nomad".to_string()))
+            .into_iter()
+            .find(|spec| matches!(spec, MatchSpec::PrefixSuffix(..)))
+            .unwrap();
+        assert_eq!(split.expected_attempts(), Some(32f64.powi(5) / 256.0));
+
+        assert!(suggest_alternatives(&MatchSpec::Suffix("xid".to_string()))
+            .iter()
+            .all(|spec| matches!(spec, MatchSpec::Suffix(_))));
+    }
+
+    #[test]
+    fn test_suggest_prefixes_fit_the_budget() {
+        assert!(WORDS.iter().all(|word| word.chars().all(|c| BASE32_ALPHABET.contains(c))));
+
+        // Six characters take 32^6 ln 2 / 1e6 = 744s in half of all searches
+        assert_eq!(max_prefix_length(1e6, Duration::from_secs(800)), 6);
+        assert_eq!(max_prefix_length(1e6, Duration::from_secs(700)), 5);
+        assert_eq!(max_prefix_length(0.0, Duration::from_secs(3600)), 0);
+
+        let words = suggest_prefixes(5, 30, &mut rand::thread_rng());
+        assert_eq!(words.len(), 30);
+        assert!(words.iter().all(|word| word.len() <= 5));
+        assert!(words.windows(2).all(|pair| pair[0].len() >= pair[1].len()));
+        assert_eq!(words[0].len(), 5);
+        assert!(suggest_prefixes(2, 5, &mut rand::thread_rng()).is_empty());
+    }
+
+    #[test]
+    fn test_nearest_valid_patterns() {
+        assert_eq!(nearest_valid_patterns("c0ffee"), ["coffee", "cffee"]);
+        assert_eq!(nearest_valid_patterns("h1dden"), ["hldden", "hidden", "hdden"]);
+        assert_eq!(nearest_valid_patterns("Tor-8"), ["torb", "tor"]);
+        assert!(nearest_valid_patterns("abc234").is_empty());
+        assert!(nearest_valid_patterns("-").is_empty());
+
+        let error = check_address_chars("c0ffee").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "\"c0ffee\" can never match: '0' never appears in an address; did you mean \"coffee\" or \"cffee\"?"
+        );
+        assert!(check_address_chars("coffee").is_ok());
+    }
+}
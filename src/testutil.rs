@@ -0,0 +1,56 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A path under the system temp directory, removed with its contents on drop
+///
+/// Each path is unique to the process and the call, so tests running in
+/// parallel never share one, and a test that panics still cleans up.
+pub(crate) struct TempPath(PathBuf);
+
+impl TempPath {
+    /// A path ending in `name`, e.g. `results.jsonl`; nothing is created
+    pub(crate) fn new(name: &str) -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let unique = format!("onion-{}-{}-{}", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed), name);
+        Self(std::env::temp_dir().join(unique))
+    }
+
+    /// A new empty directory ending in `name`
+    pub(crate) fn dir(name: &str) -> Self {
+        let dir = Self::new(name);
+        fs::create_dir_all(&dir.0).unwrap();
+        dir
+    }
+}
+
+impl Deref for TempPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempPath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<OsStr> for TempPath {
+    fn as_ref(&self) -> &OsStr {
+        self.0.as_os_str()
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        let _ = match self.0.is_dir() {
+            true => fs::remove_dir_all(&self.0),
+            false => fs::remove_file(&self.0),
+        };
+    }
+}
@@ -0,0 +1,89 @@
+/// No child, since the root is never anyone's child
+const NONE: u32 = 0;
+
+/// Prefix patterns as a trie over the 32 address characters
+///
+/// A candidate is checked by walking its first characters down the trie, so
+/// the work is bounded by the longest prefix however many there are. Each
+/// node is a table of 32 children indexed by the character's 5-bit value.
+#[derive(Debug, Clone)]
+pub(crate) struct PrefixTrie {
+    children: Vec<[u32; 32]>,
+    /// Whether a prefix ends at the node
+    ends: Vec<bool>,
+}
+
+impl PrefixTrie {
+    /// Prefixes with characters that never appear in addresses are left out, as they cannot match
+    pub(crate) fn new<'a>(prefixes: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut trie = Self { children: vec![[NONE; 32]], ends: vec![false] };
+        for prefix in prefixes {
+            let Some(values) = prefix.bytes().map(char_value).collect::<Option<Vec<_>>>() else {
+                continue;
+            };
+            let mut node = 0;
+            for value in values {
+                if trie.children[node][value] == NONE {
+                    trie.children.push([NONE; 32]);
+                    trie.ends.push(false);
+                    trie.children[node][value] = (trie.children.len() - 1) as u32;
+                }
+                node = trie.children[node][value] as usize;
+            }
+            trie.ends[node] = true;
+        }
+        trie
+    }
+
+    /// Whether `address` starts with any of the prefixes
+    pub(crate) fn is_match(&self, address: &str) -> bool {
+        let mut node = 0;
+        for byte in address.bytes() {
+            if self.ends[node] {
+                return true;
+            }
+            match char_value(byte) {
+                Some(value) if self.children[node][value] != NONE => node = self.children[node][value] as usize,
+                _ => return false,
+            }
+        }
+        self.ends[node]
+    }
+}
+
+/// The 5-bit value of an address character
+pub(crate) fn char_value(byte: u8) -> Option<usize> {
+    match byte {
+        b'a'..=b'z' => Some((byte - b'a') as usize),
+        b'2'..=b'7' => Some((byte - b'2') as usize + 26),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BASE32_ALPHABET;
+
+    #[test]
+    fn test_trie_matches_prefixes() {
+        assert!(BASE32_ALPHABET.bytes().enumerate().all(|(i, c)| char_value(c) == Some(i)));
+
+        let trie = PrefixTrie::new(["tor", "torch", "ab7", "h1dden", "x"]);
+        for (address, expected) in [
+            ("torxyz", true),
+            ("torch", true),
+            ("tobxyz", false),
+            ("to", false),
+            ("ab7ab", true),
+            ("ab6ab", false),
+            ("h1dden", false),
+            ("xaaa", true),
+            ("", false),
+        ] {
+            assert_eq!(trie.is_match(address), expected, "{}", address);
+        }
+        assert!(PrefixTrie::new([""]).is_match("anything"));
+        assert!(!PrefixTrie::new([]).is_match("anything"));
+    }
+}
@@ -0,0 +1,227 @@
+use crate::bench::{append_json_line, enabled_features, hardware_fingerprint, read_json_lines};
+use crate::{crypto_backend, format_duration, Sparkline};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// Runs shorter than this are listed but left out of trends, as startup dominates their rate
+const MIN_TREND_SECS: f64 = 10.0;
+
+/// Latest runs this many percent below the median of earlier ones are flagged
+const SLOWDOWN_PERCENT: f64 = 10.0;
+
+/// Runs drawn in the sparkline of each group
+const SPARKLINE_RUNS: usize = 60;
+
+/// Totals of one search, stored as a JSON line in the `--stats-file`
+///
+/// Only counts and a description of the machine are kept, never patterns or
+/// addresses, and nothing is sent anywhere.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub timestamp: String,
+    pub version: String,
+    /// CPU model, architecture and core count, as in [`crate::bench::BenchRecord`]
+    pub hardware: String,
+    pub backend: String,
+    pub features: Vec<String>,
+    pub workers: usize,
+    pub seconds: f64,
+    pub generated: u64,
+    pub found: u64,
+    pub keys_per_sec: f64,
+}
+
+impl UsageRecord {
+    /// The record of a run that ends now
+    pub fn new(workers: usize, generated: u128, found: u128, elapsed: Duration) -> Self {
+        let seconds = elapsed.as_secs_f64();
+        Self {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            hardware: hardware_fingerprint(),
+            backend: crypto_backend().to_string(),
+            features: enabled_features(),
+            workers,
+            seconds,
+            generated: generated as u64,
+            found: found as u64,
+            keys_per_sec: generated as f64 / seconds.max(f64::EPSILON),
+        }
+    }
+}
+
+/// Read all records from a stats file; a missing file has none
+pub fn load_usage(path: &Path) -> Result<Vec<UsageRecord>> {
+    read_json_lines(path)
+}
+
+/// Append a record to a stats file, creating it if needed
+pub fn append_usage(path: &Path, record: &UsageRecord) -> Result<()> {
+    append_json_line(path, record)
+}
+
+/// Write the keys/s trend of `records`, with the last `last` runs of each machine listed
+///
+/// Runs are grouped by hardware and worker count, since only those are
+/// comparable. Each group gets a sparkline of its runs, the mean rate per
+/// version and a warning if its latest run is well below the earlier ones.
+pub fn write_usage_history(out: &mut impl Write, records: &[UsageRecord], last: usize) -> io::Result<()> {
+    let seconds: f64 = records.iter().map(|record| record.seconds).sum();
+    let generated: u64 = records.iter().map(|record| record.generated).sum();
+    let found: u64 = records.iter().map(|record| record.found).sum();
+    writeln!(
+        out,
+        "[@] {} runs, {} of searching, {} keys, {} finds",
+        records.len(),
+        format_duration(Duration::from_secs(seconds.round() as u64)),
+        generated,
+        found
+    )?;
+
+    let mut groups: Vec<(&str, usize)> = Vec::new();
+    for record in records {
+        if !groups.contains(&(record.hardware.as_str(), record.workers)) {
+            groups.push((&record.hardware, record.workers));
+        }
+    }
+    for (hardware, workers) in groups {
+        let runs: Vec<&UsageRecord> =
+            records.iter().filter(|record| record.hardware == hardware && record.workers == workers).collect();
+        writeln!(out)?;
+        writeln!(out, "[@] {}, {} threads: {} runs", hardware, workers, runs.len())?;
+        for run in &runs[runs.len().saturating_sub(last)..] {
+            writeln!(
+                out,
+                "    {}  {:<8} {:>12.0} keys/s  {}",
+                short_timestamp(&run.timestamp),
+                run.version,
+                run.keys_per_sec,
+                format_duration(Duration::from_secs(run.seconds.round() as u64))
+            )?;
+        }
+
+        let trend: Vec<&UsageRecord> = runs.into_iter().filter(|run| run.seconds >= MIN_TREND_SECS).collect();
+        if trend.len() < 2 {
+            continue;
+        }
+        let mut sparkline = Sparkline::new(SPARKLINE_RUNS);
+        trend.iter().for_each(|run| sparkline.push(run.keys_per_sec));
+        writeln!(out, "    Trend: {} (oldest first)", sparkline.render())?;
+
+        let mut versions: Vec<&str> = Vec::new();
+        for run in &trend {
+            if !versions.contains(&run.version.as_str()) {
+                versions.push(&run.version);
+            }
+        }
+        let mut first_mean = None;
+        for version in versions {
+            let rates: Vec<f64> =
+                trend.iter().filter(|run| run.version == version).map(|run| run.keys_per_sec).collect();
+            let mean = rates.iter().sum::<f64>() / rates.len() as f64;
+            match first_mean {
+                None => writeln!(out, "    {}: mean {:.0} keys/s over {} runs", version, mean, rates.len())?,
+                Some(first) => writeln!(
+                    out,
+                    "    {}: mean {:.0} keys/s over {} runs ({:+.1}%)",
+                    version,
+                    mean,
+                    rates.len(),
+                    crate::bench::percent_change(first, mean)
+                )?,
+            }
+            first_mean.get_or_insert(mean);
+        }
+
+        let (latest, earlier) = trend.split_last().unwrap();
+        let mut rates: Vec<f64> = earlier.iter().map(|run| run.keys_per_sec).collect();
+        rates.sort_by(f64::total_cmp);
+        let median = rates[rates.len() / 2];
+        let change = crate::bench::percent_change(median, latest.keys_per_sec);
+        if change <= -SLOWDOWN_PERCENT {
+            writeln!(out, "[!] The latest run is {:.1}% below the median of the earlier ones ({:.0} keys/s)", -change, median)?;
+        }
+    }
+    Ok(())
+}
+
+/// `2024-05-01 14:03` from an RFC 3339 timestamp, or the text itself if it is not one
+fn short_timestamp(timestamp: &str) -> String {
+    chrono::DateTime::parse_from_rfc3339(timestamp)
+        .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|_| timestamp.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+
+    fn record(hardware: &str, version: &str, keys_per_sec: f64, seconds: f64) -> UsageRecord {
+        UsageRecord {
+            timestamp: "2024-05-01T14:03:00+02:00".to_string(),
+            version: version.to_string(),
+            hardware: hardware.to_string(),
+            backend: String::new(),
+            features: Vec::new(),
+            workers: 4,
+            seconds,
+            generated: (keys_per_sec * seconds) as u64,
+            found: 1,
+            keys_per_sec,
+        }
+    }
+
+    fn records() -> Vec<UsageRecord> {
+        vec![
+            record("cpu", "0.1.0", 1000.0, 60.0),
+            record("cpu", "0.1.0", 1100.0, 60.0),
+            record("other", "0.1.0", 5000.0, 60.0),
+            record("cpu", "0.2.0", 900.0, 60.0),
+            // Too short to count towards the trend
+            record("cpu", "0.2.0", 10.0, 1.0),
+            record("cpu", "0.2.0", 700.0, 60.0),
+        ]
+    }
+
+    fn usage_history(records: &[UsageRecord]) -> String {
+        let mut out = Vec::new();
+        write_usage_history(&mut out, records, 3).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_usage_roundtrip() {
+        let path = TempPath::new("usage.jsonl");
+        let records = records();
+        for record in &records {
+            append_usage(&path, record).unwrap();
+        }
+        assert_eq!(load_usage(&path).unwrap().len(), records.len());
+    }
+
+    #[test]
+    fn test_usage_history_groups_runs() {
+        let text = usage_history(&records());
+        assert!(text.starts_with("[@] 6 runs, "), "{}", text);
+        assert!(text.contains("[@] cpu, 4 threads: 5 runs"), "{}", text);
+        assert_eq!(text.matches("2024-05-01 14:03").count(), 4, "{}", text);
+        assert!(text.contains("0.1.0: mean 1050 keys/s over 2 runs\n"), "{}", text);
+        assert!(text.contains("0.2.0: mean 800 keys/s over 2 runs (-23.8%)"), "{}", text);
+        // A single comparable run has no trend
+        assert!(!text.contains("mean 5000"), "{}", text);
+    }
+
+    #[test]
+    fn test_usage_history_warns_about_slow_runs() {
+        let mut records = records();
+        let text = usage_history(&records);
+        assert!(text.contains("[!] The latest run is 30.0% below"), "{}", text);
+
+        records.push(record("cpu", "0.2.0", 1000.0, 60.0));
+        assert!(!usage_history(&records).contains("[!]"));
+    }
+}
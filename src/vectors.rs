@@ -0,0 +1,91 @@
+use crate::{onion_result_from_signing_key, seeded_keypair};
+use anyhow::Result;
+use ed25519_dalek::SigningKey;
+use serde::{Deserialize, Serialize};
+
+/// Master seed the published vectors are derived from
+pub const VECTOR_MASTER_SEED: [u8; 32] = *b"onion-generator golden vectors 1";
+
+/// Number of vectors in the published set
+pub const DEFAULT_VECTOR_COUNT: usize = 16;
+
+/// One ed25519 seed with the hostname and Tor key files that belong to it
+///
+/// Byte strings are hex. The key files are the exact contents Tor expects in
+/// `hs_ed25519_public_key` and `hs_ed25519_secret_key`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestVector {
+    pub seed: String,
+    pub public_key: String,
+    pub hostname: String,
+    pub public_key_file: String,
+    pub secret_key_file: String,
+}
+
+/// A reproducible set of [`TestVector`]s, as written by `gen-vectors`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestVectors {
+    pub description: String,
+    pub master_seed: String,
+    pub vectors: Vec<TestVector>,
+}
+
+impl TestVectors {
+    /// Derive `count` vectors from `master_seed`
+    ///
+    /// Seed `i` is key `i` of shard 0 of a seeded run, see [`seeded_keypair`],
+    /// so the same master seed always gives the same set.
+    pub fn generate(master_seed: &[u8; 32], count: usize) -> Result<Self> {
+        let vectors = (0..count as u64)
+            .map(|counter| TestVector::from_signing_key(&seeded_keypair(master_seed, 0, counter).0))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            description: "Tor v3 onion service vectors: ed25519 seed, public key, hostname and key files; \
+                          seed i is SHA3-256(master_seed || 0u64 LE || i as u64 LE)"
+                .to_string(),
+            master_seed: hex(master_seed),
+            vectors,
+        })
+    }
+}
+
+impl TestVector {
+    pub fn from_signing_key(signing_key: &SigningKey) -> Result<Self> {
+        use base64::Engine;
+        let engine = base64::engine::general_purpose::STANDARD;
+        let result = onion_result_from_signing_key(signing_key)?;
+        Ok(Self {
+            seed: hex(&signing_key.to_bytes()),
+            public_key: hex(&signing_key.verifying_key().to_bytes()),
+            hostname: result.hostname,
+            public_key_file: hex(&engine.decode(&result.public_key)?),
+            secret_key_file: hex(&engine.decode(&result.private_key)?),
+        })
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_published_vectors_are_reproduced() {
+        let published: TestVectors = serde_json::from_str(include_str!("../testdata/vectors.json")).unwrap();
+        let generated = TestVectors::generate(&VECTOR_MASTER_SEED, DEFAULT_VECTOR_COUNT).unwrap();
+        assert_eq!(generated, published);
+
+        // RFC 8032, section 7.1, test 1
+        let seed = [
+            0x9d, 0x61, 0xb1, 0x9d, 0xef, 0xfd, 0x5a, 0x60, 0xba, 0x84, 0x4a, 0xf4, 0x92, 0xec, 0x2c, 0xc4,
+            0x44, 0x49, 0xc5, 0x69, 0x7b, 0x32, 0x69, 0x19, 0x70, 0x3b, 0xac, 0x03, 0x1c, 0xae, 0x7f, 0x60,
+        ];
+        let vector = TestVector::from_signing_key(&SigningKey::from_bytes(&seed)).unwrap();
+        assert_eq!(vector.public_key, "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a");
+        assert!(vector.public_key_file.ends_with(&vector.public_key));
+        assert_eq!(vector.secret_key_file.len(), 2 * 96);
+    }
+}
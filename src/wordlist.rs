@@ -0,0 +1,193 @@
+use crate::trie::char_value;
+use std::sync::Arc;
+
+/// Prefix characters packed into one key; longer prefixes are confirmed as text
+const KEY_CHARS: usize = 12;
+
+/// Filter bits per prefix, for about 1% false positives
+const BITS_PER_PREFIX: usize = 16;
+
+/// Filter bits set per prefix, all within one 64-bit block
+const HASHES: u32 = 6;
+
+/// Length tag of the keys of prefixes longer than [`KEY_CHARS`]
+const LONG: u64 = KEY_CHARS as u64 + 1;
+
+/// A large prefix list, checked with a Bloom filter and confirmed on a hit
+///
+/// Prefixes of up to 12 characters are packed into 8-byte keys, 5 bits per
+/// character plus the length, and kept sorted. A candidate computes the key
+/// of each of its starts whose length occurs in the list and looks it up in a
+/// blocked Bloom filter, which costs one memory access and rejects almost all
+/// of them; only hits are confirmed by binary search. Longer prefixes are
+/// filtered on their first 12 characters and compared as text. Clones share
+/// the list.
+#[derive(Debug, Clone)]
+pub struct Wordlist(Arc<WordlistIndex>);
+
+#[derive(Debug)]
+struct WordlistIndex {
+    filter: Vec<u64>,
+    keys: Vec<u64>,
+    long: Vec<Box<str>>,
+    /// Bit n is set if some prefix has n characters, up to [`KEY_CHARS`]
+    lengths: u32,
+    /// Chance that a random address starts with one of the prefixes
+    probability: f64,
+}
+
+impl Wordlist {
+    /// Prefixes with characters that never appear in addresses are left out, as they cannot match
+    pub fn new<'a>(prefixes: impl IntoIterator<Item = &'a str>) -> Self {
+        let (mut keys, mut long) = (Vec::new(), Vec::new());
+        for prefix in prefixes {
+            if !prefix.bytes().all(|byte| char_value(byte).is_some()) {
+                continue;
+            }
+            match prefix.len() <= KEY_CHARS {
+                true => keys.push(pack(prefix.as_bytes())),
+                false => long.push(Box::from(prefix)),
+            }
+        }
+        keys.sort_unstable();
+        keys.dedup();
+        long.sort_unstable();
+        long.dedup();
+
+        let blocks = ((keys.len() + long.len()) * BITS_PER_PREFIX / 64).max(1);
+        let mut index = WordlistIndex { filter: vec![0; blocks], keys, long, lengths: 0, probability: 0.0 };
+        for i in 0..index.keys.len() {
+            let key = index.keys[i];
+            index.insert(key);
+            index.lengths |= 1 << (key >> 60);
+            index.probability += 32f64.powi(-((key >> 60) as i32));
+        }
+        for i in 0..index.long.len() {
+            let key = pack(&index.long[i].as_bytes()[..KEY_CHARS]) & !(0xF << 60) | LONG << 60;
+            index.insert(key);
+            index.probability += 32f64.powi(-(index.long[i].len() as i32));
+        }
+        Self(Arc::new(index))
+    }
+
+    /// Number of distinct prefixes
+    pub fn len(&self) -> usize {
+        self.0.keys.len() + self.0.long.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether `address` starts with one of the prefixes
+    pub fn is_match(&self, address: &str) -> bool {
+        let index = &self.0;
+        let mut packed = 0;
+        for (i, &byte) in address.as_bytes().iter().take(KEY_CHARS).enumerate() {
+            let Some(value) = char_value(byte) else {
+                return false;
+            };
+            packed = packed << 5 | value as u64;
+            let length = i as u64 + 1;
+            if index.lengths & 1 << length != 0 {
+                let key = length << 60 | packed;
+                if index.may_contain(key) && index.keys.binary_search(&key).is_ok() {
+                    return true;
+                }
+            }
+        }
+        if index.long.is_empty() || address.len() <= KEY_CHARS || !index.may_contain(LONG << 60 | packed) {
+            return false;
+        }
+        let start = &address[..KEY_CHARS];
+        let first = index.long.partition_point(|prefix| &prefix[..KEY_CHARS] < start);
+        index.long[first..]
+            .iter()
+            .take_while(|prefix| &prefix[..KEY_CHARS] == start)
+            .any(|prefix| address.starts_with(&**prefix))
+    }
+
+    /// Expected number of candidates until one starts with a prefix of the list
+    pub fn expected_attempts(&self) -> f64 {
+        1.0 / self.0.probability.min(1.0)
+    }
+}
+
+impl std::fmt::Display for Wordlist {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} prefixes", self.len())
+    }
+}
+
+impl WordlistIndex {
+    /// The block of `key` and the bits it sets there
+    fn slot(&self, key: u64) -> (usize, u64) {
+        let hash = mix(key);
+        let block = ((hash as u128 * self.filter.len() as u128) >> 64) as usize;
+        let bits = mix(hash);
+        let mask = (0..HASHES).fold(0, |mask, i| mask | 1 << (bits >> (i * 6) & 63));
+        (block, mask)
+    }
+
+    fn insert(&mut self, key: u64) {
+        let (block, mask) = self.slot(key);
+        self.filter[block] |= mask;
+    }
+
+    fn may_contain(&self, key: u64) -> bool {
+        let (block, mask) = self.slot(key);
+        self.filter[block] & mask == mask
+    }
+}
+
+/// Key of a prefix of up to [`KEY_CHARS`] address characters: the length in the top 4 bits, then 5 bits per character
+fn pack(prefix: &[u8]) -> u64 {
+    let packed = prefix.iter().fold(0, |packed, &byte| packed << 5 | char_value(byte).unwrap_or(0) as u64);
+    (prefix.len() as u64) << 60 | packed
+}
+
+/// The splitmix64 finalizer
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BASE32_ALPHABET;
+    use rand::seq::SliceRandom;
+    use rand::Rng;
+
+    #[test]
+    fn test_wordlist_agrees_with_prefixes() {
+        let mut rng = rand::thread_rng();
+        let alphabet = BASE32_ALPHABET.as_bytes();
+        let word = |rng: &mut rand::rngs::ThreadRng, length: usize| -> String {
+            (0..length).map(|_| *alphabet.choose(rng).unwrap() as char).collect()
+        };
+        let mut prefixes: Vec<String> = (0..50_000).map(|_| { let length = rng.gen_range(2..=5); word(&mut rng, length) }).collect();
+        prefixes.extend((0..100).map(|_| format!("ab{}", word(&mut rng, 12))));
+        prefixes.push("h1dden".to_string());
+        let wordlist = Wordlist::new(prefixes.iter().map(String::as_str));
+        assert!(wordlist.len() < prefixes.len());
+
+        for _ in 0..20_000 {
+            let address = word(&mut rng, 56);
+            let expected = prefixes.iter().any(|prefix| address.starts_with(prefix.as_str()));
+            assert_eq!(wordlist.is_match(&address), expected, "{}", address);
+        }
+        for prefix in prefixes.iter().filter(|prefix| !prefix.contains('1')) {
+            assert!(wordlist.is_match(&format!("{}{}", prefix, word(&mut rng, 56 - prefix.len()))), "{}", prefix);
+        }
+        assert!(!wordlist.is_match("h1ddenxyz"));
+
+        // Most candidates are turned away by the filter alone
+        let index = &wordlist.0;
+        let passed = (0..10_000).filter(|_| index.may_contain(LONG << 60 | rng.gen::<u64>() >> 4)).count();
+        assert!(passed < 500, "{}", passed);
+        let one = Wordlist::new(["abcd"]);
+        assert_eq!(one.expected_attempts(), 32f64.powi(4));
+    }
+}
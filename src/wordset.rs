@@ -0,0 +1,92 @@
+use crate::trie::PrefixTrie;
+use crate::MatchSpec;
+use aho_corasick::{AhoCorasick, Anchored, Input, StartKind};
+
+/// The prefix and contains patterns of a [`crate::Matcher`] side, compiled into Aho-Corasick automatons
+///
+/// Comparing a candidate with thousands of words one by one dominates the
+/// search; the automatons find whether any of them occurs in one pass over the
+/// address. Prefixes may go into a [`PrefixTrie`] instead. Other patterns are
+/// still checked one by one.
+#[derive(Debug, Clone)]
+pub(crate) struct WordSet {
+    prefixes: Option<PrefixIndex>,
+    contains: Option<AhoCorasick>,
+    others: Vec<MatchSpec>,
+}
+
+#[derive(Debug, Clone)]
+enum PrefixIndex {
+    Automaton(AhoCorasick),
+    Trie(PrefixTrie),
+}
+
+impl WordSet {
+    /// `None` if `specs` has no prefix or contains patterns
+    ///
+    /// With `trie`, prefixes go into a [`PrefixTrie`] rather than an automaton.
+    pub(crate) fn build(specs: &[MatchSpec], trie: bool) -> Option<Self> {
+        let (mut prefixes, mut contains, mut others) = (Vec::new(), Vec::new(), Vec::new());
+        for spec in specs {
+            match spec {
+                MatchSpec::Prefix(prefix) => prefixes.push(prefix.as_str()),
+                MatchSpec::Contains(needle) => contains.push(needle.as_str()),
+                _ => others.push(spec.clone()),
+            }
+        }
+        if prefixes.is_empty() && contains.is_empty() {
+            return None;
+        }
+        let automaton = |words: Vec<&str>, start: StartKind| {
+            (!words.is_empty()).then(|| AhoCorasick::builder().start_kind(start).build(words))
+        };
+        let prefixes = match trie {
+            true => (!prefixes.is_empty()).then(|| PrefixIndex::Trie(PrefixTrie::new(prefixes))),
+            false => automaton(prefixes, StartKind::Anchored).transpose().ok()?.map(PrefixIndex::Automaton),
+        };
+        Some(Self {
+            prefixes,
+            contains: automaton(contains, StartKind::Unanchored).transpose().ok()?,
+            others,
+        })
+    }
+
+    /// Whether any of the patterns matches `address`, without `.onion`
+    pub(crate) fn any_match(&self, address: &str) -> bool {
+        let prefix_match = match &self.prefixes {
+            Some(PrefixIndex::Automaton(automaton)) => automaton.is_match(Input::new(address).anchored(Anchored::Yes)),
+            Some(PrefixIndex::Trie(trie)) => trie.is_match(address),
+            None => false,
+        };
+        prefix_match
+            || self.others.iter().any(|spec| spec.is_match(address))
+            || self.contains.as_ref().is_some_and(|contains| contains.is_match(address))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_word_set_agrees_with_specs() {
+        let words: Vec<String> = (0..200).map(|i| format!("{}{}", crate::BASE32_ALPHABET.as_bytes()[i % 32] as char, i % 6 + 2)).collect();
+        let mut specs: Vec<MatchSpec> = words.iter().take(150).cloned().map(MatchSpec::Prefix).collect();
+        specs.extend(words.iter().skip(150).map(|word| MatchSpec::Contains(format!("{}zz", word))));
+        specs.push(MatchSpec::Suffix("yd".to_string()));
+
+        assert!(WordSet::build(&specs[specs.len() - 1..], false).is_none());
+        for trie in [false, true] {
+            let set = WordSet::build(&specs, trie).unwrap();
+            for _ in 0..1000 {
+                let address = crate::generate_onion_address().unwrap().hostname.replace(".onion", "");
+                assert_eq!(set.any_match(&address), specs.iter().any(|spec| spec.is_match(&address)), "{}", address);
+            }
+            assert!(set.any_match(&format!("{}xxxxxxd", words[0])));
+            assert!(!set.any_match(&format!("x{}xxxxxd", words[0])));
+            assert!(set.any_match(&format!("xxx{}zzxxd", words[150])));
+            assert!(!set.any_match(&format!("xxx{}xxxd", words[150])));
+            assert!(set.any_match("xxxxxxxxyd"));
+        }
+    }
+}
@@ -1,90 +1,790 @@
-use crate::{generate_with_prefix, OnionResult, GeneratorConfig, get_stats};
+use crate::progress::ProgressDisplay;
+use crate::sink::{write_result, write_stats, ResultSink, StdoutSink};
+use crate::logging::{log_found, log_stats, log_worker, write_stdout};
+use crate::onion::generate_with_live_matcher_while;
+use crate::{
+    generate_with_matcher_until, lower_thread_priority, CandidateSample, CandidateSource, Cooperative, GenerationStats, GeneratorConfig, json_logs, logln, seal_result, self_check, ErrorCode, LiveMatcher, Matcher, OnionResult,
+    RandomKeys, ResultExt, ResultsJournal, SeededKeys, SourceFactory, StatsSnapshots, TokenImport, without_private_key,
+};
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::mpsc;
 use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
 use anyhow::Result;
+use zeroize::Zeroizing;
 
 /// Message types for worker communication
 #[derive(Debug)]
-pub enum WorkerMessage {
+pub(crate) enum WorkerMessage {
     Found(OnionResult),
-    Stats(u64, u64),
-    Shutdown,
+    Stats(u128, u128),
+    WorkerPanicked {
+        worker_id: usize,
+        message: String,
+        restarting: bool,
+    },
+    /// A worker generated no candidates for `idle`
+    WorkerStalled {
+        worker_id: usize,
+        idle: Duration,
+    },
+    /// A stalled worker is generating candidates again
+    WorkerRecovered {
+        worker_id: usize,
+    },
+    /// A periodic self-check failed, see [`self_check`]
+    SelfCheckFailed {
+        error: String,
+    },
+    /// A worker hit an error retrying cannot fix and stopped
+    WorkerFailed {
+        worker_id: usize,
+        error: String,
+        code: ErrorCode,
+    },
+}
+
+/// What to do when a worker thread panics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// Fail the pool on the first worker panic
+    Never,
+    /// Restart panicked workers up to this many times in total, then fail the pool
+    Limit(usize),
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy::Limit(3)
+    }
+}
+
+/// What to do when a worker stalls, see [`WorkerHealth`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StallPolicy {
+    /// Only report stalled workers
+    #[default]
+    Report,
+    /// Replace stalled workers with new threads up to this many times in total
+    ///
+    /// The stalled thread is told to exit and left behind; if it ever wakes
+    /// up, it delivers a find it was holding and stops. Seeded pools only
+    /// report, since a replacement could not resume the stalled shard.
+    Restart(usize),
+}
+
+/// How long a worker waits before retrying after a transient error
+///
+/// The wait starts at `initial` and doubles with each error in a row, up to
+/// `max`; it starts over once the worker has generated keys again. Errors
+/// that are not transient stop the pool instead, see [`WorkerPoolBuilder::error_backoff`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorBackoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Default for ErrorBackoff {
+    fn default() -> Self {
+        Self { initial: Duration::from_millis(100), max: Duration::from_secs(10) }
+    }
+}
+
+impl ErrorBackoff {
+    /// Wait after the `failures`-th error in a row, counting from 1
+    pub fn delay(&self, failures: u32) -> Duration {
+        let factor = 2u32.saturating_pow(failures.saturating_sub(1));
+        self.initial.saturating_mul(factor).min(self.max)
+    }
+}
+
+/// How often blocked threads re-check the stop flag
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Most messages taken off the channel at once, see [`WorkerPool::run`]
+const MAX_BATCH: usize = 1024;
+
+/// How often the stats thread publishes a [`StatsSnapshot`](crate::StatsSnapshot) and checks the worker heartbeats
+const SNAPSHOT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Default time without progress after which a worker counts as stalled
+pub const DEFAULT_STALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Shared pause flag of a [`WorkerPool`]
+///
+/// While paused, workers sleep between candidates instead of generating them,
+/// keeping their threads, shards and statistics, so resuming continues the
+/// same search. Clones control the same pool.
+#[derive(Debug, Clone, Default)]
+pub struct PauseHandle(Arc<AtomicBool>);
+
+impl PauseHandle {
+    /// Pause the workers; returns false if they already were
+    pub fn pause(&self) -> bool {
+        !self.0.swap(true, Ordering::Relaxed)
+    }
+
+    /// Let the workers continue; returns false if they were not paused
+    pub fn resume(&self) -> bool {
+        self.0.swap(false, Ordering::Relaxed)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Callback invoked for each found address
+pub(crate) type FoundHook = Box<dyn Fn(&OnionResult) + Send + Sync>;
+
+/// Callback invoked for each statistics update with (generated, found)
+pub(crate) type StatsHook = Box<dyn Fn(u128, u128) + Send + Sync>;
+
+/// Callback invoked on a worker thread with its worker id
+pub(crate) type WorkerHook = Arc<dyn Fn(usize) + Send + Sync>;
+
+/// Sending half of the worker channel, bounded or unbounded
+#[derive(Clone)]
+enum MessageSender {
+    Unbounded(mpsc::Sender<WorkerMessage>),
+    Bounded(mpsc::SyncSender<WorkerMessage>),
+}
+
+impl MessageSender {
+    fn send(&self, message: WorkerMessage) -> Result<(), mpsc::SendError<WorkerMessage>> {
+        match self {
+            MessageSender::Unbounded(sender) => sender.send(message),
+            MessageSender::Bounded(sender) => sender.send(message),
+        }
+    }
+}
+
+/// Builder for configuring a [`WorkerPool`]
+pub struct WorkerPoolBuilder {
+    config: GeneratorConfig,
+    channel_capacity: Option<usize>,
+    thread_name_prefix: String,
+    restart_policy: RestartPolicy,
+    sinks: Option<Vec<Box<dyn ResultSink>>>,
+    progress_display: bool,
+    seed: Option<Zeroizing<[u8; 32]>>,
+    seed_counters: Vec<u64>,
+    source: Option<SourceFactory>,
+    sample: Option<CandidateSample>,
+    public_only: bool,
+    collect_results: bool,
+    matcher: Option<Matcher>,
+    seal_to: Option<[u8; 32]>,
+    token_import: Option<TokenImport>,
+    journal: Option<ResultsJournal>,
+    stall_timeout: Duration,
+    stall_policy: StallPolicy,
+    self_check_interval: Option<Duration>,
+    error_backoff: ErrorBackoff,
+    cooperative: Option<Cooperative>,
+    on_found: Option<FoundHook>,
+    on_stats: Option<StatsHook>,
+    on_worker_start: Option<WorkerHook>,
+    on_worker_stop: Option<WorkerHook>,
+}
+
+impl WorkerPoolBuilder {
+    pub fn new(config: GeneratorConfig) -> Self {
+        Self {
+            config,
+            channel_capacity: None,
+            thread_name_prefix: "onion".to_string(),
+            restart_policy: RestartPolicy::default(),
+            sinks: None,
+            progress_display: false,
+            seed: None,
+            seed_counters: Vec::new(),
+            source: None,
+            sample: None,
+            public_only: false,
+            collect_results: true,
+            matcher: None,
+            seal_to: None,
+            token_import: None,
+            journal: None,
+            stall_timeout: DEFAULT_STALL_TIMEOUT,
+            stall_policy: StallPolicy::default(),
+            self_check_interval: None,
+            error_backoff: ErrorBackoff::default(),
+            cooperative: None,
+            on_found: None,
+            on_stats: None,
+            on_worker_start: None,
+            on_worker_stop: None,
+        }
+    }
+
+    /// Bound the worker channel; workers block when it is full
+    pub fn channel_capacity(mut self, capacity: usize) -> Self {
+        self.channel_capacity = Some(capacity);
+        self
+    }
+
+    /// Name spawned threads `<prefix>-worker-<id>` and `<prefix>-stats` (default: `onion`)
+    pub fn thread_name_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.thread_name_prefix = prefix.into();
+        self
+    }
+
+    /// Set how panicking workers are handled (default: restart up to 3 times)
+    pub fn restart_policy(mut self, policy: RestartPolicy) -> Self {
+        self.restart_policy = policy;
+        self
+    }
+
+    /// Add an output sink; adding any sink replaces the default stdout output
+    pub fn sink(mut self, sink: impl ResultSink + 'static) -> Self {
+        self.sinks.get_or_insert_with(Vec::new).push(Box::new(sink));
+        self
+    }
+
+    /// Show live per-worker progress lines on stdout instead of worker start/stop log lines
+    pub fn progress_display(mut self, enabled: bool) -> Self {
+        self.progress_display = enabled;
+        self
+    }
+
+    /// Derive candidates from `master_seed`, with worker N searching shard N
+    ///
+    /// See [`SeededKeys`]; the same seed and worker count reproduce the run.
+    pub fn seed(mut self, master_seed: [u8; 32]) -> Self {
+        self.seed = Some(Zeroizing::new(master_seed));
+        self
+    }
+
+    /// Counter each shard of a seeded run starts at, indexed by shard (default: 0)
+    ///
+    /// Continues a run where it stopped, or searches a counter range another
+    /// machine does not.
+    pub fn seed_counters(mut self, counters: Vec<u64>) -> Self {
+        self.seed_counters = counters;
+        self
+    }
+
+    /// Draw each worker's candidates from the source `factory` builds for its id
+    ///
+    /// This is how other key generators, e.g. on a GPU or a remote machine,
+    /// plug into the pool; the pool filters, matches and reports their
+    /// candidates like its own. Takes precedence over [`seed`](Self::seed).
+    /// The factory runs again for a worker replaced after a stall.
+    pub fn candidate_source(
+        mut self,
+        factory: impl Fn(usize) -> Result<Box<dyn CandidateSource>> + Send + Sync + 'static,
+    ) -> Self {
+        self.source = Some(Arc::new(factory));
+        self
+    }
+
+    /// Write a random sample of the non-matching candidates to `sample`
+    pub fn sample(mut self, sample: CandidateSample) -> Self {
+        self.sample = Some(sample);
+        self
+    }
+
+    /// Drop every private key in the worker that found it, keeping only hostnames and public keys
+    ///
+    /// Results reach the journal, hooks and sinks as [`crate::KeyCustody::Discarded`],
+    /// so no secret key is ever written or sent anywhere.
+    pub fn public_only(mut self, enabled: bool) -> Self {
+        self.public_only = enabled;
+        self
+    }
+
+    /// Keep every find for [`WorkerPool::shutdown`] to return (default: true)
+    ///
+    /// Turn it off for long runs whose finds all go to sinks, so they do not
+    /// pile up in memory.
+    pub fn collect_results(mut self, enabled: bool) -> Self {
+        self.collect_results = enabled;
+        self
+    }
+
+    /// Seal every private key to this X25519 public key before hooks and sinks see it
+    pub fn seal_to(mut self, recipient: [u8; 32]) -> Self {
+        self.seal_to = Some(recipient);
+        self
+    }
+
+    /// Hand every private key to a token import command before hooks and sinks see it
+    ///
+    /// A key whose import fails is kept and reported as usual.
+    pub fn token_import(mut self, import: TokenImport) -> Self {
+        self.token_import = Some(import);
+        self
+    }
+
+    /// Record every find in `journal` before the hooks and sinks see it
+    ///
+    /// Finds a previous run recorded but did not deliver are delivered by
+    /// `start()`, see [`ResultsJournal`].
+    pub fn journal(mut self, journal: ResultsJournal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Flag workers that generate no candidates for this long (default: 30s)
+    ///
+    /// See [`WorkerHealth`]; stalled workers are logged and repeated with each
+    /// statistics update.
+    pub fn stall_timeout(mut self, timeout: Duration) -> Self {
+        self.stall_timeout = timeout;
+        self
+    }
+
+    /// Set what happens to stalled workers (default: only report them)
+    pub fn stall_policy(mut self, policy: StallPolicy) -> Self {
+        self.stall_policy = policy;
+        self
+    }
+
+    /// Check a freshly generated key end to end this often, see [`self_check`]
+    ///
+    /// The pool stops and `run()` fails with [`ErrorCode::SelfCheckFailed`]
+    /// on the first check that does not pass.
+    pub fn self_check_interval(mut self, interval: Duration) -> Self {
+        self.self_check_interval = Some(interval);
+        self
+    }
+
+    /// Set how long workers wait before retrying after a transient error (default: 100ms doubling up to 10s)
+    ///
+    /// Only a failing OS random number generator and interrupted or timed out
+    /// I/O count as transient. Any other worker error stops the pool, and
+    /// `run()` fails with the error's [`ErrorCode`].
+    pub fn error_backoff(mut self, backoff: ErrorBackoff) -> Self {
+        self.error_backoff = backoff;
+        self
+    }
+
+    /// Run the workers at idle priority and pace them, see [`Cooperative`]
+    pub fn cooperative(mut self, cooperative: Cooperative) -> Self {
+        self.cooperative = Some(cooperative);
+        self
+    }
+
+    /// Search with this matcher instead of one built from the config's patterns
+    pub fn matcher(mut self, matcher: Matcher) -> Self {
+        self.matcher = Some(matcher);
+        self
+    }
+
+    pub fn on_found(mut self, hook: impl Fn(&OnionResult) + Send + Sync + 'static) -> Self {
+        self.on_found = Some(Box::new(hook));
+        self
+    }
+
+    pub fn on_stats(mut self, hook: impl Fn(u128, u128) + Send + Sync + 'static) -> Self {
+        self.on_stats = Some(Box::new(hook));
+        self
+    }
+
+    /// Replace the default "worker started" log line
+    pub fn on_worker_start(mut self, hook: impl Fn(usize) + Send + Sync + 'static) -> Self {
+        self.on_worker_start = Some(Arc::new(hook));
+        self
+    }
+
+    /// Replace the default "worker stopped" log line
+    pub fn on_worker_stop(mut self, hook: impl Fn(usize) + Send + Sync + 'static) -> Self {
+        self.on_worker_stop = Some(Arc::new(hook));
+        self
+    }
+
+    pub fn build(self) -> WorkerPool {
+        let (sender, receiver) = match self.channel_capacity {
+            Some(capacity) => {
+                let (sender, receiver) = mpsc::sync_channel(capacity);
+                (MessageSender::Bounded(sender), receiver)
+            }
+            None => {
+                let (sender, receiver) = mpsc::channel();
+                (MessageSender::Unbounded(sender), receiver)
+            }
+        };
+
+        // The progress display replaces the default worker lifecycle log lines
+        let (default_start, default_stop): (WorkerHook, WorkerHook) = if self.progress_display {
+            (Arc::new(|_| {}), Arc::new(|_| {}))
+        } else {
+            (
+                Arc::new(|_| log_worker(&current_thread_name(), true)),
+                Arc::new(|_| log_worker(&current_thread_name(), false)),
+            )
+        };
+
+        let stats = Arc::new(GenerationStats::new());
+        let worker_stats = (0..self.config.num_workers)
+            .map(|_| Arc::new(GenerationStats::child_of(&stats)))
+            .collect();
+        let health = Arc::new(WorkerHealth::new(self.config.num_workers));
+        let seeded = self.source.is_none() && self.seed.is_some();
+        let source = match (self.source, self.seed) {
+            (Some(source), _) => source,
+            (None, Some(seed)) => {
+                let counters = self.seed_counters;
+                Arc::new(move |worker_id: usize| {
+                    let first_counter = counters.get(worker_id).copied().unwrap_or(0);
+                    Ok(Box::new(SeededKeys::new(*seed, worker_id as u64).starting_at(first_counter)) as Box<dyn CandidateSource>)
+                })
+            }
+            (None, None) => Arc::new(|_| Ok(Box::new(RandomKeys::new()) as Box<dyn CandidateSource>)),
+        };
+
+        WorkerPool {
+            started: Instant::now(),
+            snapshots: StatsSnapshots::new(),
+            config: Arc::new(self.config),
+            stats,
+            worker_stats,
+            stop: Arc::new(AtomicBool::new(false)),
+            pause: PauseHandle::default(),
+            sender,
+            receiver,
+            backlog: VecDeque::new(),
+            thread_name_prefix: self.thread_name_prefix,
+            restart_policy: self.restart_policy,
+            restarts: Arc::new(AtomicUsize::new(0)),
+            sinks: self.sinks.unwrap_or_else(|| vec![Box::new(StdoutSink)]),
+            progress_display: self.progress_display,
+            progress: None,
+            source,
+            seeded,
+            sample: self.sample,
+            public_only: self.public_only,
+            collected: self.collect_results.then(Vec::new),
+            matcher_from_config: self.matcher.is_none(),
+            matcher: LiveMatcher::new(self.matcher.unwrap_or_default()),
+            seal_to: self.seal_to,
+            token_import: self.token_import,
+            journal: self.journal,
+            stall_timeout: self.stall_timeout,
+            stall_policy: self.stall_policy,
+            stall_restarts: 0,
+            health,
+            self_check_interval: self.self_check_interval,
+            error_backoff: self.error_backoff,
+            cooperative: self.cooperative,
+            on_found: self.on_found,
+            on_stats: self.on_stats,
+            on_worker_start: self.on_worker_start.unwrap_or(default_start),
+            on_worker_stop: self.on_worker_stop.unwrap_or(default_stop),
+            workers: Vec::new(),
+            retired: Vec::new(),
+            stats_thread: None,
+        }
+    }
+}
+
+/// What a pool found and counted, returned by [`WorkerPool::shutdown`]
+///
+/// Iterating it yields the owned results.
+#[derive(Debug, Clone, Default)]
+pub struct PoolSummary {
+    /// Every find in delivery order, as the sinks got it, including those
+    /// delivered from the journal; empty with
+    /// [`collect_results(false)`](WorkerPoolBuilder::collect_results)
+    pub results: Vec<OnionResult>,
+    pub generated: u128,
+    pub found: u128,
+    /// Time since the pool was built
+    pub elapsed: Duration,
+}
+
+impl IntoIterator for PoolSummary {
+    type Item = OnionResult;
+    type IntoIter = std::vec::IntoIter<OnionResult>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.results.into_iter()
+    }
 }
 
 /// Worker pool for parallel onion generation
 pub struct WorkerPool {
     config: Arc<GeneratorConfig>,
-    sender: mpsc::Sender<WorkerMessage>,
+    stats: Arc<GenerationStats>,
+    snapshots: StatsSnapshots,
+    started: Instant,
+    worker_stats: Vec<Arc<GenerationStats>>,
+    stop: Arc<AtomicBool>,
+    pause: PauseHandle,
+    sender: MessageSender,
     receiver: mpsc::Receiver<WorkerMessage>,
+    /// Messages taken off the channel but not handled yet, finds first
+    backlog: VecDeque<WorkerMessage>,
+    thread_name_prefix: String,
+    restart_policy: RestartPolicy,
+    restarts: Arc<AtomicUsize>,
+    sinks: Vec<Box<dyn ResultSink>>,
+    progress_display: bool,
+    progress: Option<ProgressDisplay>,
+    source: SourceFactory,
+    /// Seeded workers are never replaced, as a replacement would repeat their shard
+    seeded: bool,
+    sample: Option<CandidateSample>,
+    public_only: bool,
+    /// Finds as the sinks got them, for [`shutdown`](WorkerPool::shutdown) to return
+    collected: Option<Vec<OnionResult>>,
+    matcher: LiveMatcher,
+    matcher_from_config: bool,
+    seal_to: Option<[u8; 32]>,
+    token_import: Option<TokenImport>,
+    journal: Option<ResultsJournal>,
+    stall_timeout: Duration,
+    stall_policy: StallPolicy,
+    stall_restarts: usize,
+    health: Arc<WorkerHealth>,
+    self_check_interval: Option<Duration>,
+    error_backoff: ErrorBackoff,
+    cooperative: Option<Cooperative>,
+    on_found: Option<FoundHook>,
+    on_stats: Option<StatsHook>,
+    on_worker_start: WorkerHook,
+    on_worker_stop: WorkerHook,
+    /// Current thread of each worker, indexed by worker id
     workers: Vec<thread::JoinHandle<()>>,
+    /// Tells the current thread of each worker to exit, for stall restarts
+    retired: Vec<Arc<AtomicBool>>,
     stats_thread: Option<thread::JoinHandle<()>>,
 }
 
 impl WorkerPool {
-    /// Create a new worker pool
+    /// Create a new worker pool with default settings
     pub fn new(config: GeneratorConfig) -> Self {
-        let (sender, receiver) = mpsc::channel();
-        
-        Self {
-            config: Arc::new(config),
-            sender,
-            receiver,
-            workers: Vec::new(),
-            stats_thread: None,
-        }
+        WorkerPoolBuilder::new(config).build()
+    }
+
+    /// Start configuring a new worker pool
+    pub fn builder(config: GeneratorConfig) -> WorkerPoolBuilder {
+        WorkerPoolBuilder::new(config)
+    }
+
+    /// Add an output sink after building, e.g. one that needs `stats()` or `live_matcher()`
+    pub fn add_sink(&mut self, sink: impl ResultSink + 'static) {
+        self.sinks.push(Box::new(sink));
+    }
+
+    /// Shared handle to this pool's generation statistics
+    pub fn stats(&self) -> Arc<GenerationStats> {
+        Arc::clone(&self.stats)
+    }
+
+    /// The latest statistics, refreshed a few times a second while the pool runs
+    ///
+    /// Prefer this to `stats()` for display; see [`StatsSnapshots`].
+    pub fn snapshots(&self) -> StatsSnapshots {
+        self.snapshots.clone()
+    }
+
+    /// Per-worker statistics, indexed by worker id; they add up to `stats()`
+    pub fn worker_stats(&self) -> &[Arc<GenerationStats>] {
+        &self.worker_stats
+    }
+
+    /// Shared handle to which workers have stalled
+    pub fn health(&self) -> Arc<WorkerHealth> {
+        Arc::clone(&self.health)
+    }
+
+    /// Signal all threads to stop; `run()` returns shortly afterwards
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// Shared stop flag, e.g. for signal handlers; setting it is equivalent to `stop()`
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+
+    /// Stop generating candidates until [`resume`](Self::resume), keeping all state
+    ///
+    /// Workers finish the candidate they are on and then sleep, so the CPU is
+    /// free again within a few milliseconds. Stall detection and self-checks
+    /// are suspended meanwhile. Returns false if the pool already was paused.
+    pub fn pause(&self) -> bool {
+        self.pause.pause()
+    }
+
+    /// Continue a paused search where it left off; returns false if it was not paused
+    pub fn resume(&self) -> bool {
+        self.pause.resume()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.pause.is_paused()
+    }
+
+    /// Shared pause flag, e.g. for the control socket; equivalent to `pause()` and `resume()`
+    pub fn pause_handle(&self) -> PauseHandle {
+        self.pause.clone()
+    }
+
+    /// Handle for changing the patterns while the workers are running
+    ///
+    /// Without a builder-supplied matcher, it only holds the config's patterns
+    /// once `start()` has been called.
+    pub fn live_matcher(&self) -> LiveMatcher {
+        self.matcher.clone()
     }
 
     /// Start all workers
     pub fn start(&mut self) -> Result<()> {
+        if self.matcher_from_config {
+            self.matcher.replace(self.config.build_matcher()?);
+            self.matcher_from_config = false;
+        }
+        self.deliver_journaled()?;
+        self.started = Instant::now();
+
         // Start worker threads
         for worker_id in 0..self.config.num_workers {
-            let config = Arc::clone(&self.config);
-            let sender = self.sender.clone();
-            
-            let handle = thread::spawn(move || {
-                worker_thread(worker_id, config, sender);
-            });
-            
+            let (handle, retired) = self.spawn_worker(worker_id)?;
             self.workers.push(handle);
+            self.retired.push(retired);
         }
 
         // Start statistics thread
         let stats_sender = self.sender.clone();
+        let stats = Arc::clone(&self.stats);
+        let snapshots = self.snapshots.clone();
+        let heartbeats = Heartbeats {
+            workers: self.worker_stats.clone(),
+            health: Arc::clone(&self.health),
+            timeout: self.stall_timeout,
+            pause: self.pause.clone(),
+        };
+        let stop = Arc::clone(&self.stop);
         let update_interval = self.config.update_interval;
+        let self_check_interval = self.self_check_interval;
         
-        let stats_handle = thread::spawn(move || {
-            stats_thread(stats_sender, update_interval);
-        });
+        let stats_handle = self.thread_builder("stats").spawn(move || {
+            stats_thread(stats_sender, stats, heartbeats, &snapshots, self_check_interval, stop, update_interval);
+        })?;
         
         self.stats_thread = Some(stats_handle);
         
-        println!("[@] Started {} worker threads", self.config.num_workers);
-        println!("[@] Generating addresses...");
+        logln!("[@] Started {} worker threads", self.config.num_workers);
+        if let Some(cooperative) = self.cooperative {
+            logln!("[@] Cooperative mode: idle priority, working {}% of the time", cooperative.duty_cycle());
+        }
+        logln!("[@] Generating addresses...");
+
+        if self.progress_display {
+            let workers = (0..self.config.num_workers)
+                .map(|worker_id| format!("{}-worker-{}", self.thread_name_prefix, worker_id))
+                .collect();
+            self.progress = Some(ProgressDisplay::start(workers, self.snapshots.clone()));
+        }
         
         Ok(())
     }
 
-    /// Process messages from workers
-    pub fn run(&self) -> Result<()> {
-        loop {
-            match self.receiver.recv() {
-                Ok(WorkerMessage::Found(result)) => {
-                    println!("[√] Address generated successfully!");
-                    println!("Hostname:                      {}", result.hostname);
-                    println!("Public Key (Base64 encoded):   {}", result.public_key);
-                    println!("Private Key (Base64 encoded):  {}\n", result.private_key);
-                }
-                Ok(WorkerMessage::Stats(generated, found)) => {
-                    let now = chrono::Local::now();
-                    println!("[@] {}: Generated {} addresses, Found {} addresses", 
-                             now.format("%H:%M:%S"), generated, found);
+    /// Start a thread for `worker_id`, returning it and its flag for retiring it
+    fn spawn_worker(&self, worker_id: usize) -> Result<(thread::JoinHandle<()>, Arc<AtomicBool>)> {
+        let matcher = self.matcher.clone();
+        let stats = Arc::clone(&self.worker_stats[worker_id]);
+        let stop = Arc::clone(&self.stop);
+        let pause = self.pause.clone();
+        let retired = Arc::new(AtomicBool::new(false));
+        let sender = self.sender.clone();
+        let on_start = Arc::clone(&self.on_worker_start);
+        let on_stop = Arc::clone(&self.on_worker_stop);
+        let policy = self.restart_policy;
+        let restarts = Arc::clone(&self.restarts);
+        let source = (self.source)(worker_id)?;
+        let sample = self.sample.clone();
+        let public_only = self.public_only;
+        let backoff = self.error_backoff;
+        let cooperative = self.cooperative;
+
+        let thread_retired = Arc::clone(&retired);
+        let handle = self.thread_builder(&format!("worker-{}", worker_id))
+            .spawn(move || {
+                // Kept outside the supervised body so a restart resumes the shard
+                let source = RefCell::new(source);
+                let pacer = cooperative.map(|cooperative| cooperative.pacer());
+                if cooperative.is_some() {
+                    if let Err(e) = lower_thread_priority() {
+                        // Every worker fails alike, so one line is enough
+                        if worker_id == 0 {
+                            eprintln!("[!] Could not lower the worker priority, only pacing them: {}", e);
+                        }
+                    }
                 }
-                Ok(WorkerMessage::Shutdown) => {
-                    break;
+                on_start(worker_id);
+                supervise(worker_id, policy, &restarts, &sender, || {
+                    let running = || !stop.load(Ordering::Relaxed) && !thread_retired.load(Ordering::Relaxed);
+                    // Checked between candidates, so a paused worker idles mid-search and loses nothing
+                    let running = || {
+                        while pause.is_paused() && running() {
+                            thread::sleep(POLL_INTERVAL);
+                        }
+                        if let Some(pacer) = &pacer {
+                            pacer.pace();
+                        }
+                        running()
+                    };
+                    let mut source = source.borrow_mut();
+                    let search = |running: &dyn Fn() -> bool| {
+                        let found = generate_with_live_matcher_while(&matcher, &mut **source, &stats, sample.as_ref(), running);
+                        match public_only {
+                            true => found.map(|result| result.map(without_private_key)),
+                            false => found,
+                        }
+                    };
+                    worker_thread(worker_id, search, &stats, running, &sender, backoff)
+                });
+                on_stop(worker_id);
+            })?;
+        Ok((handle, retired))
+    }
+
+    /// Apply the stall policy to a worker that just stalled
+    fn handle_stall(&mut self, worker_id: usize) -> Result<()> {
+        let StallPolicy::Restart(limit) = self.stall_policy else {
+            return Ok(());
+        };
+        if self.seeded || self.stall_restarts >= limit || self.stop.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        self.stall_restarts += 1;
+        let name = format!("{}-worker-{}", self.thread_name_prefix, worker_id);
+        logln!("[!] Replacing {} ({} of {} stall restarts)", name, self.stall_restarts, limit);
+        let (handle, retired) = self.spawn_worker(worker_id)?;
+        self.retired[worker_id].store(true, Ordering::Relaxed);
+        // The stalled thread is detached, so shutdown never waits for it
+        drop(std::mem::replace(&mut self.workers[worker_id], handle));
+        self.retired[worker_id] = retired;
+        Ok(())
+    }
+
+    /// Process messages from workers until the pool is stopped
+    ///
+    /// Finds are delivered ahead of everything else waiting, so a find never
+    /// queues behind a pile of statistics updates for slow sinks; at most it
+    /// waits for the one message being handled when it arrives.
+    pub fn run(&mut self) -> Result<()> {
+        while !self.stop.load(Ordering::Relaxed) {
+            match self.receiver.recv_timeout(POLL_INTERVAL) {
+                Ok(message) => {
+                    self.queue(message);
+                    while let Some(message) = self.backlog.pop_front() {
+                        self.handle_message(message)?;
+                    }
                 }
-                Err(_) => {
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
                     // Channel closed, exit
                     break;
                 }
@@ -94,11 +794,71 @@ impl WorkerPool {
         Ok(())
     }
 
-    /// Shutdown all workers
-    pub fn shutdown(self) -> Result<()> {
-        // Send shutdown signal
-        for _ in 0..self.config.num_workers {
-            let _ = self.sender.send(WorkerMessage::Shutdown);
+    /// Move `first` and whatever else is waiting to the backlog, finds first
+    ///
+    /// Statistics updates are running totals, so only the newest one waiting
+    /// is kept.
+    fn queue(&mut self, first: WorkerMessage) {
+        let waiting = std::iter::once(first).chain(self.receiver.try_iter().take(MAX_BATCH));
+        let (finds, others): (Vec<_>, Vec<_>) =
+            waiting.partition(|message| matches!(message, WorkerMessage::Found(_)));
+        let is_stats = |message: &WorkerMessage| matches!(message, WorkerMessage::Stats(..));
+        let newest = others.iter().rposition(is_stats);
+        self.backlog.extend(finds);
+        self.backlog.extend(
+            others
+                .into_iter()
+                .enumerate()
+                .filter(|(index, message)| Some(*index) == newest || !is_stats(message))
+                .map(|(_, message)| message),
+        );
+    }
+
+    /// Shutdown all workers and return what they found
+    ///
+    /// Workers check the stop flag between candidates, so this returns within
+    /// roughly one candidate generation plus `POLL_INTERVAL`. Results that are
+    /// still in flight are drained into the hooks and sinks before returning,
+    /// so a match found just before the stop signal is never lost. Threads
+    /// replaced after a stall are not waited for.
+    pub fn shutdown(mut self) -> Result<PoolSummary> {
+        self.stop();
+
+        // Keep delivering results until every thread has exited; this also
+        // unblocks workers waiting on a full bounded channel
+        let mut first_error = None;
+        while let Some(message) = self.backlog.pop_front() {
+            if let Err(e) = self.drain_message(message) {
+                first_error.get_or_insert(e);
+            }
+        }
+        let threads_running = |pool: &Self| {
+            pool.workers.iter().any(|handle| !handle.is_finished())
+                || pool.stats_thread.as_ref().is_some_and(|handle| !handle.is_finished())
+        };
+        while threads_running(&self) {
+            if let Ok(message) = self.receiver.recv_timeout(POLL_INTERVAL) {
+                if let Err(e) = self.drain_message(message) {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        while let Ok(message) = self.receiver.try_recv() {
+            if let Err(e) = self.drain_message(message) {
+                first_error.get_or_insert(e);
+            }
+        }
+
+        for sink in &mut self.sinks {
+            if let Err(e) = sink.flush() {
+                first_error.get_or_insert(e);
+            }
+        }
+
+        // The workers' last candidates may have come after the stats thread's last snapshot
+        self.snapshots.take(&self.stats, &self.worker_stats, self.pause.is_paused(), self.started);
+        if let Some(progress) = self.progress.take() {
+            progress.finish();
         }
 
         // Wait for all workers to finish
@@ -111,74 +871,877 @@ impl WorkerPool {
             let _ = handle.join();
         }
 
-        println!("[!] All workers stopped");
+        logln!("[!] All workers stopped");
+        if let Some(e) = first_error {
+            return Err(e);
+        }
+        let (generated, found) = self.stats.get();
+        Ok(PoolSummary { results: self.collected.unwrap_or_default(), generated, found, elapsed: self.started.elapsed() })
+    }
+
+    /// Deliver a single worker message to the hooks and sinks
+    ///
+    /// Output is printed with the progress display suspended so it is not
+    /// overwritten by the next refresh.
+    fn handle_message(&mut self, message: WorkerMessage) -> Result<()> {
+        let progress = self.progress.as_ref();
+        let suspended = |f: &mut dyn FnMut() -> Result<()>| match progress {
+            Some(progress) => progress.suspend(f),
+            None => f(),
+        };
+
+        match message {
+            WorkerMessage::Found(mut result) => suspended(&mut || {
+                if let Some(import) = &self.token_import {
+                    match import.import(&result) {
+                        Ok(imported) => result = imported,
+                        Err(e) => eprintln!("[!] Keeping the key of {} locally: {}", result.hostname, e),
+                    }
+                }
+                if let Some(recipient) = &self.seal_to {
+                    result = seal_result(recipient, &result)?;
+                }
+                let entry = self.journal.as_mut().map(|journal| journal.append(&result)).transpose()?;
+                if let Some(hook) = &self.on_found {
+                    hook(&result);
+                }
+                for sink in &mut self.sinks {
+                    sink.found(&result)?;
+                }
+                if let (Some(journal), Some(entry)) = (&mut self.journal, entry) {
+                    journal.mark_delivered(entry)?;
+                }
+                if let Some(collected) = &mut self.collected {
+                    collected.push(result.clone());
+                }
+                Ok(())
+            })?,
+            WorkerMessage::Stats(generated, found) => suspended(&mut || {
+                if let Some(hook) = &self.on_stats {
+                    hook(generated, found);
+                }
+                for sink in &mut self.sinks {
+                    sink.stats(generated, found)?;
+                }
+                let stalled = self.health.stalled();
+                if !stalled.is_empty() {
+                    let names: Vec<String> = stalled
+                        .iter()
+                        .map(|worker_id| format!("{}-worker-{}", self.thread_name_prefix, worker_id))
+                        .collect();
+                    logln!("[!] {} of {} workers stalled: {}", stalled.len(), self.config.num_workers, names.join(", "));
+                }
+                Ok(())
+            })?,
+            WorkerMessage::WorkerStalled { worker_id, idle } => {
+                suspended(&mut || {
+                    logln!(
+                        "[!] {}-worker-{} stalled: no candidates for {}s",
+                        self.thread_name_prefix, worker_id, idle.as_secs()
+                    );
+                    Ok(())
+                })?;
+                self.handle_stall(worker_id)?;
+            }
+            WorkerMessage::WorkerRecovered { worker_id } => suspended(&mut || {
+                logln!("[i] {}-worker-{} is generating again", self.thread_name_prefix, worker_id);
+                Ok(())
+            })?,
+            WorkerMessage::SelfCheckFailed { error } => {
+                self.stop();
+                return Err(anyhow::anyhow!("Self-check failed, keys from this machine cannot be trusted: {}", error))
+                    .with_code(ErrorCode::SelfCheckFailed);
+            }
+            WorkerMessage::WorkerFailed { worker_id, error, code } => {
+                self.stop();
+                return Err(anyhow::anyhow!("{}-worker-{} failed: {}", self.thread_name_prefix, worker_id, error))
+                    .with_code(code);
+            }
+            WorkerMessage::WorkerPanicked { worker_id, message, restarting } => {
+                if !restarting {
+                    return Err(anyhow::anyhow!(
+                        "Worker {} panicked and the restart limit was reached: {}",
+                        worker_id, message
+                    ));
+                }
+                suspended(&mut || {
+                    eprintln!("[!] Worker {} panicked, restarting: {}", worker_id, message);
+                    Ok(())
+                })?;
+            }
+        }
+
         Ok(())
     }
+
+    /// Handle a message received while shutting down; stats updates are dropped
+    fn drain_message(&mut self, message: WorkerMessage) -> Result<()> {
+        match message {
+            WorkerMessage::Stats(..) | WorkerMessage::WorkerStalled { .. } | WorkerMessage::WorkerRecovered { .. } => Ok(()),
+            message => self.handle_message(message),
+        }
+    }
+
+    /// Hand finds a previous run journaled but never delivered to the hooks and sinks
+    fn deliver_journaled(&mut self) -> Result<()> {
+        let Some(journal) = &mut self.journal else {
+            return Ok(());
+        };
+        let pending = journal.pending()?;
+        if pending.is_empty() {
+            return Ok(());
+        }
+        logln!("[@] Delivering {} undelivered find(s) from {}", pending.len(), journal.path().display());
+        for (entry, result) in pending {
+            if let Some(hook) = &self.on_found {
+                hook(&result);
+            }
+            for sink in &mut self.sinks {
+                sink.found(&result)?;
+            }
+            journal.mark_delivered(entry)?;
+            if let Some(collected) = &mut self.collected {
+                collected.push(result);
+            }
+        }
+        Ok(())
+    }
+
+    fn thread_builder(&self, role: &str) -> thread::Builder {
+        thread::Builder::new().name(format!("{}-{}", self.thread_name_prefix, role))
+    }
 }
 
-/// Worker thread function
-fn worker_thread(
+/// Run a worker body, catching panics and restarting it according to `policy`
+///
+/// `restarts` is shared by all workers of a pool, so the limit applies to the
+/// pool as a whole rather than to each worker.
+fn supervise(
     worker_id: usize,
-    config: Arc<GeneratorConfig>,
-    sender: mpsc::Sender<WorkerMessage>,
+    policy: RestartPolicy,
+    restarts: &AtomicUsize,
+    sender: &MessageSender,
+    body: impl Fn(),
 ) {
-    println!("[+] Worker {} started", worker_id);
-    
     loop {
-        match generate_with_prefix(&config.prefixes) {
-            Ok(result) => {
+        let payload = match panic::catch_unwind(AssertUnwindSafe(&body)) {
+            Ok(()) => return,
+            Err(payload) => payload,
+        };
+
+        let restarting = match policy {
+            RestartPolicy::Never => false,
+            RestartPolicy::Limit(limit) => restarts.fetch_add(1, Ordering::SeqCst) < limit,
+        };
+
+        let message = WorkerMessage::WorkerPanicked {
+            worker_id,
+            message: panic_message(payload.as_ref()),
+            restarting,
+        };
+        if sender.send(message).is_err() || !restarting {
+            return;
+        }
+    }
+}
+
+/// Extract the message from a panic payload
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// Worker thread function; runs `search` while `running` returns true, i.e.
+/// until the pool stops or a replacement has taken over
+///
+/// Transient errors are retried after `backoff`; any other error is reported
+/// to the pool, which stops, and ends the worker.
+fn worker_thread(
+    worker_id: usize,
+    mut search: impl FnMut(&dyn Fn() -> bool) -> Result<Option<OnionResult>>,
+    stats: &GenerationStats,
+    running: impl Fn() -> bool,
+    sender: &MessageSender,
+    backoff: ErrorBackoff,
+) {
+    let mut failures = 0;
+    let mut generated_at_failure = 0;
+    while running() {
+        match search(&running) {
+            Ok(Some(result)) => {
                 if sender.send(WorkerMessage::Found(result)).is_err() {
                     break; // Channel closed
                 }
             }
+            Ok(None) => break, // Stopped
+
+            Err(e) if is_transient(&e) => {
+                let generated = stats.get().0;
+                if generated != generated_at_failure {
+                    failures = 0;
+                }
+                failures += 1;
+                generated_at_failure = generated;
+                let delay = backoff.delay(failures);
+                eprintln!("[!] {} error, retrying in {}ms: {:#}", current_thread_name(), delay.as_millis(), e);
+                if !sleep_while(delay, &running) {
+                    break;
+                }
+            }
             Err(e) => {
-                eprintln!("[!] Worker {} error: {}", worker_id, e);
-                thread::sleep(Duration::from_millis(100));
+                let code = ErrorCode::of(&e);
+                let _ = sender.send(WorkerMessage::WorkerFailed { worker_id, error: format!("{:#}", e), code });
+                break;
             }
         }
     }
-    
-    println!("[-] Worker {} stopped", worker_id);
 }
 
-/// Statistics reporting thread
-fn stats_thread(sender: mpsc::Sender<WorkerMessage>, interval_seconds: u64) {
+/// Whether a worker error may go away by itself, like a failing OS RNG or interrupted I/O
+///
+/// Errors with an [`ErrorCode`], e.g. an output that cannot be written, are
+/// never transient.
+fn is_transient(error: &anyhow::Error) -> bool {
+    use std::io::ErrorKind;
+    ErrorCode::of(error) == ErrorCode::Other
+        && error.chain().any(|cause| {
+            cause.is::<rand::Error>()
+                || cause.downcast_ref::<std::io::Error>().is_some_and(|e| {
+                    matches!(e.kind(), ErrorKind::Interrupted | ErrorKind::WouldBlock | ErrorKind::TimedOut)
+                })
+        })
+}
+
+/// Name of the calling thread, for log lines
+pub(crate) fn current_thread_name() -> String {
+    thread::current().name().unwrap_or("unnamed").to_string()
+}
+
+/// Which workers of a pool have stalled
+///
+/// A worker's generated counter is its heartbeat: it advances with every
+/// candidate. The stats thread flags a worker whose counter has not moved for
+/// the pool's stall timeout, e.g. one starved by the scheduler, stuck on a
+/// hung RNG or blocked on a full channel, and clears the flag once it moves
+/// again.
+#[derive(Debug)]
+pub struct WorkerHealth {
+    stalled: Vec<AtomicBool>,
+}
+
+impl WorkerHealth {
+    fn new(num_workers: usize) -> Self {
+        Self { stalled: (0..num_workers).map(|_| AtomicBool::new(false)).collect() }
+    }
+
+    /// Ids of the workers currently stalled
+    pub fn stalled(&self) -> Vec<usize> {
+        self.stalled
+            .iter()
+            .enumerate()
+            .filter(|(_, stalled)| stalled.load(Ordering::Relaxed))
+            .map(|(worker_id, _)| worker_id)
+            .collect()
+    }
+
+    /// True while no worker is stalled
+    pub fn is_healthy(&self) -> bool {
+        self.stalled.iter().all(|stalled| !stalled.load(Ordering::Relaxed))
+    }
+}
+
+/// What the stats thread needs to watch the workers' heartbeats
+struct Heartbeats {
+    workers: Vec<Arc<GenerationStats>>,
+    health: Arc<WorkerHealth>,
+    timeout: Duration,
+    pause: PauseHandle,
+}
+
+impl Heartbeats {
+    /// Compare each worker's counter with its last value, returning the stall changes to report
+    ///
+    /// Paused workers are idle on purpose; their timeouts start over once resumed.
+    fn check(&self, last: &mut [(u128, Instant)], now: Instant) -> Vec<WorkerMessage> {
+        if self.pause.is_paused() {
+            for (stats, (beats, moved)) in self.workers.iter().zip(last.iter_mut()) {
+                *beats = stats.get().0;
+                *moved = now;
+            }
+            return Vec::new();
+        }
+        let mut changes = Vec::new();
+        for (worker_id, (stats, (beats, moved))) in self.workers.iter().zip(last.iter_mut()).enumerate() {
+            let current = stats.get().0;
+            let flag = &self.health.stalled[worker_id];
+            if current != *beats {
+                *beats = current;
+                *moved = now;
+                if flag.swap(false, Ordering::Relaxed) {
+                    changes.push(WorkerMessage::WorkerRecovered { worker_id });
+                }
+            } else if now.duration_since(*moved) >= self.timeout && !flag.swap(true, Ordering::Relaxed) {
+                changes.push(WorkerMessage::WorkerStalled { worker_id, idle: now.duration_since(*moved) });
+            }
+        }
+        changes
+    }
+}
+
+/// Statistics reporting thread, which also publishes the snapshots, watches the worker heartbeats and runs the self-checks
+fn stats_thread(
+    sender: MessageSender,
+    stats: Arc<GenerationStats>,
+    heartbeats: Heartbeats,
+    snapshots: &StatsSnapshots,
+    self_check_interval: Option<Duration>,
+    stop: Arc<AtomicBool>,
+    interval_seconds: u64,
+) {
     let interval = Duration::from_secs(interval_seconds);
+    let tick = SNAPSHOT_INTERVAL.min(heartbeats.timeout).max(POLL_INTERVAL);
+    let started = Instant::now();
+    let mut last: Vec<(u128, Instant)> = heartbeats.workers.iter().map(|stats| (stats.get().0, started)).collect();
+    let mut next_stats = started + interval;
+    let mut next_self_check = self_check_interval.map(|interval| started + interval);
     
     loop {
-        thread::sleep(interval);
-        
-        let (generated, found) = get_stats();
-        if sender.send(WorkerMessage::Stats(generated, found)).is_err() {
+        if !sleep_while(tick, || !stop.load(Ordering::Relaxed)) {
+            break;
+        }
+
+        snapshots.take(&stats, &heartbeats.workers, heartbeats.pause.is_paused(), started);
+        let now = Instant::now();
+        let mut messages = heartbeats.check(&mut last, now);
+        if now >= next_stats {
+            let (generated, found) = stats.get();
+            messages.push(WorkerMessage::Stats(generated, found));
+            next_stats += interval;
+        }
+        if let (Some(interval), Some(due)) = (self_check_interval, next_self_check.as_mut()) {
+            // A paused pool is meant to leave the CPU alone
+            if now >= *due && !heartbeats.pause.is_paused() {
+                if let Err(e) = self_check() {
+                    messages.push(WorkerMessage::SelfCheckFailed { error: format!("{:#}", e) });
+                }
+                *due += interval;
+            }
+        }
+        if messages.into_iter().any(|message| sender.send(message).is_err()) {
             break; // Channel closed
         }
     }
 }
 
+/// Print a generated address and its keys
+pub fn print_result(result: &OnionResult) {
+    log_found(result);
+    if !json_logs() {
+        let _ = write_stdout(|out| write_result(out, result));
+    }
+}
+
+/// Print a statistics line in the selected log format
+pub fn print_stats(generated: u128, found: u128) {
+    log_stats(generated, found);
+    if !json_logs() {
+        let _ = write_stdout(|out| write_stats(out, generated, found, None));
+    }
+}
+
+/// Sleep for `duration` in short slices; returns false if `running` turned false meanwhile
+fn sleep_while(duration: Duration, running: impl Fn() -> bool) -> bool {
+    let deadline = Instant::now().checked_add(duration);
+
+    while running() {
+        let remaining = match deadline {
+            Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+            None => POLL_INTERVAL,
+        };
+        if remaining.is_zero() {
+            return true;
+        }
+        thread::sleep(POLL_INTERVAL.min(remaining));
+    }
+
+    false
+}
+
 /// Simple single-threaded generator for comparison
-pub fn run_single_threaded(prefixes: &[String]) -> Result<()> {
-    println!("[@] Running in single-threaded mode");
-    println!("[@] Generating addresses...");
+///
+/// Runs until `stop` is set; a match found before that is always printed.
+pub fn run_single_threaded(
+    matcher: &Matcher,
+    stats: &GenerationStats,
+    stop: &AtomicBool,
+) -> Result<()> {
+    logln!("[@] Running in single-threaded mode");
+    logln!("[@] Generating addresses...");
     
     let start_time = Instant::now();
     let mut last_stats_time = start_time;
     
-    loop {
-        let result = generate_with_prefix(prefixes)?;
-        
-        println!("[√] Address generated successfully!");
-        println!("Hostname:                      {}", result.hostname);
-        println!("Public Key (Base64 encoded):   {}", result.public_key);
-        println!("Private Key (Base64 encoded):  {}\n", result.private_key);
+    while let Some(result) = generate_with_matcher_until(matcher, stats, stop)? {
+        print_result(&result);
         
         // Print stats every 30 seconds
         let now = Instant::now();
         if now.duration_since(last_stats_time) >= Duration::from_secs(30) {
-            let (generated, found) = get_stats();
-            let current_time = chrono::Local::now();
-            println!("[@] {}: Generated {} addresses, Found {} addresses", 
-                     current_time.format("%H:%M:%S"), generated, found);
+            let (generated, found) = stats.get();
+            print_stats(generated, found);
             last_stats_time = now;
         }
     }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempPath;
+
+    fn panicking_worker(policy: RestartPolicy) -> (usize, Vec<bool>) {
+        let (sender, receiver) = mpsc::channel();
+        let sender = MessageSender::Unbounded(sender);
+        let restarts = AtomicUsize::new(0);
+        let runs = AtomicUsize::new(0);
+
+        supervise(0, policy, &restarts, &sender, || {
+            runs.fetch_add(1, Ordering::SeqCst);
+            panic!("boom");
+        });
+        drop(sender);
+
+        let restarting = receiver
+            .iter()
+            .map(|message| match message {
+                WorkerMessage::WorkerPanicked { message, restarting, .. } => {
+                    assert_eq!(message, "boom");
+                    restarting
+                }
+                other => panic!("unexpected message: {:?}", other),
+            })
+            .collect();
+        (runs.load(Ordering::SeqCst), restarting)
+    }
+
+    #[test]
+    fn test_shutdown_stops_workers() {
+        let config = GeneratorConfig::new(vec!["zzzzzzzz".to_string()]).with_workers(2);
+        let mut pool = WorkerPool::builder(config)
+            .on_worker_start(|_| {})
+            .on_worker_stop(|_| {})
+            .build();
+        pool.start().unwrap();
+
+        let started = Instant::now();
+        pool.shutdown().unwrap();
+
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_shutdown_drains_pending_results() {
+        let found = Arc::new(AtomicUsize::new(0));
+        let config = GeneratorConfig::new(vec!["a".to_string()]).with_workers(2);
+        let counter = Arc::clone(&found);
+        let mut pool = WorkerPool::builder(config)
+            .channel_capacity(1)
+            .sink(NullSink)
+            .on_found(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            })
+            .on_worker_start(|_| {})
+            .on_worker_stop(|_| {})
+            .build();
+        pool.start().unwrap();
+
+        // Let workers fill the bounded channel without anyone reading it
+        thread::sleep(Duration::from_millis(200));
+        let stats = pool.stats();
+        let summary = pool.shutdown().unwrap();
+
+        assert_eq!(found.load(Ordering::SeqCst) as u128, stats.get().1);
+        assert!(stats.get().1 > 0);
+        // Every find comes back to the caller, though the only sink dropped them
+        assert_eq!((summary.generated, summary.found), stats.get());
+        assert_eq!(summary.results.len() as u128, summary.found);
+        assert!(summary.into_iter().all(|result| result.hostname.starts_with('a')));
+    }
+
+    #[test]
+    fn test_blocked_worker_is_flagged_stalled() {
+        let config = GeneratorConfig::new(vec!["a".to_string()]).with_workers(1).with_update_interval(3600);
+        let mut pool = WorkerPool::builder(config)
+            .channel_capacity(1)
+            .stall_timeout(Duration::from_millis(200))
+            .sink(NullSink)
+            .on_worker_start(|_| {})
+            .on_worker_stop(|_| {})
+            .build();
+        let health = pool.health();
+        pool.start().unwrap();
+
+        // Nobody reads the channel, so the worker blocks on its second find
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while health.is_healthy() && Instant::now() < deadline {
+            thread::sleep(POLL_INTERVAL);
+        }
+        assert_eq!(health.stalled(), vec![0]);
+        pool.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_error_backoff_and_classification() {
+        let backoff = ErrorBackoff { initial: Duration::from_millis(100), max: Duration::from_secs(1) };
+        assert_eq!(backoff.delay(1), Duration::from_millis(100));
+        assert_eq!(backoff.delay(3), Duration::from_millis(400));
+        assert_eq!(backoff.delay(u32::MAX), Duration::from_secs(1));
+
+        let rng = anyhow::Error::new(rand::Error::new(std::io::Error::other("no entropy")))
+            .context("OS random number generator failed");
+        let output = Err::<(), _>(std::io::Error::from(std::io::ErrorKind::Interrupted))
+            .with_code(ErrorCode::OutputNotWritable)
+            .unwrap_err();
+        assert!(is_transient(&rng));
+        assert!(!is_transient(&output));
+        assert!(!is_transient(&anyhow::anyhow!("Public key must be 32 bytes")));
+    }
+
+    #[test]
+    fn test_worker_failure_stops_pool() {
+        let config = GeneratorConfig::new(vec!["a".to_string()]).with_workers(1);
+        let mut pool = WorkerPool::builder(config).sink(NullSink).build();
+        let failure = WorkerMessage::WorkerFailed { worker_id: 0, error: "disk full".to_string(), code: ErrorCode::OutputNotWritable };
+        pool.sender.send(failure).unwrap();
+
+        let error = pool.run().unwrap_err();
+        assert_eq!(ErrorCode::of(&error), ErrorCode::OutputNotWritable);
+        assert_eq!(error.to_string(), "onion-worker-0 failed: disk full");
+        assert!(pool.stop.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_stalled_worker_is_replaced() {
+        let starts = Arc::new(AtomicUsize::new(0));
+        let started = Arc::clone(&starts);
+        let blocked_once = AtomicBool::new(false);
+        let config = GeneratorConfig::new(vec!["a".to_string()]).with_workers(1).with_update_interval(3600);
+        let mut pool = WorkerPool::builder(config)
+            .channel_capacity(1)
+            .stall_timeout(Duration::from_millis(200))
+            .stall_policy(StallPolicy::Restart(1))
+            .sink(NullSink)
+            // Hold up delivery once so the worker blocks on the full channel
+            .on_found(move |_| {
+                if !blocked_once.swap(true, Ordering::SeqCst) {
+                    thread::sleep(Duration::from_secs(1));
+                }
+            })
+            .on_worker_start(move |_| {
+                started.fetch_add(1, Ordering::SeqCst);
+            })
+            .on_worker_stop(|_| {})
+            .build();
+        pool.start().unwrap();
+
+        let stop = pool.stop_handle();
+        let stopper = thread::spawn(move || {
+            thread::sleep(Duration::from_secs(3));
+            stop.store(true, Ordering::Relaxed);
+        });
+        pool.run().unwrap();
+        stopper.join().unwrap();
+        pool.shutdown().unwrap();
+
+        assert_eq!(starts.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_builder_delivers_finds_to_hooks_and_sinks() {
+        let dir = TempPath::new("pool-sinks");
+        let results_path = dir.join("results.jsonl");
+
+        let (found_tx, found_rx) = mpsc::channel();
+        let found_tx = std::sync::Mutex::new(found_tx);
+        let (starts, stops) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let (started, stopped) = (Arc::clone(&starts), Arc::clone(&stops));
+        let config = GeneratorConfig::new(vec!["a".to_string()]).with_workers(2).with_update_interval(3600);
+        let mut pool = WorkerPool::builder(config)
+            .sink(crate::DirectorySink::new(&dir).unwrap())
+            .sink(crate::ResultsFileSink::new(&results_path, crate::Rotation::default()).unwrap())
+            .on_found(move |result| {
+                let _ = found_tx.lock().unwrap().send(result.hostname.clone());
+            })
+            .on_worker_start(move |_| {
+                started.fetch_add(1, Ordering::SeqCst);
+            })
+            .on_worker_stop(move |_| {
+                stopped.fetch_add(1, Ordering::SeqCst);
+            })
+            .build();
+        pool.start().unwrap();
+        let stop = pool.stop_handle();
+        let stopper = thread::spawn(move || {
+            let hostnames: Vec<String> = found_rx.iter().take(3).collect();
+            stop.store(true, Ordering::Relaxed);
+            hostnames
+        });
+        pool.run().unwrap();
+        let hostnames = stopper.join().unwrap();
+        pool.shutdown().unwrap();
+
+        assert_eq!(starts.load(Ordering::SeqCst), 2);
+        assert_eq!(stops.load(Ordering::SeqCst), 2);
+        let results = std::fs::read_to_string(&results_path).unwrap();
+        for hostname in &hostnames {
+            assert!(results.contains(hostname.as_str()));
+            let service_dir = dir.join(hostname);
+            assert_eq!(std::fs::read_to_string(service_dir.join("hostname")).unwrap(), format!("{}\n", hostname));
+            let secret_path = service_dir.join("hs_ed25519_secret_key");
+            let secret = std::fs::read(&secret_path).unwrap();
+            let expanded = ed25519_dalek::hazmat::ExpandedSecretKey::from_bytes(&secret[32..].try_into().unwrap());
+            let public_key = ed25519_dalek::VerifyingKey::from(&expanded).to_bytes();
+            assert_eq!(crate::decode_onion_address(hostname).unwrap(), public_key);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let mode = |path: &std::path::Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+                assert_eq!(mode(&service_dir), 0o700);
+                assert_eq!(mode(&secret_path), 0o600);
+            }
+        }
+    }
+
+    #[test]
+    fn test_start_delivers_journaled_finds() {
+        let path = TempPath::new("pool-journal.bin");
+        let leftover = crate::generate_onion_address().unwrap();
+        ResultsJournal::open(&path).unwrap().append(&leftover).unwrap();
+
+        let delivered = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let hostnames = Arc::clone(&delivered);
+        let config = GeneratorConfig::new(vec!["zzzzzzzz".to_string()]).with_workers(1);
+        let mut pool = WorkerPool::builder(config)
+            .journal(ResultsJournal::open(&path).unwrap())
+            .sink(NullSink)
+            .on_found(move |result| hostnames.lock().unwrap().push(result.hostname.clone()))
+            .on_worker_start(|_| {})
+            .on_worker_stop(|_| {})
+            .build();
+        pool.start().unwrap();
+        pool.shutdown().unwrap();
+
+        assert_eq!(*delivered.lock().unwrap(), vec![leftover.hostname]);
+        assert!(ResultsJournal::open(&path).unwrap().pending().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_worker_stats_add_up_to_total() {
+        let config = GeneratorConfig::new(vec!["zzzzzzzz".to_string()]).with_workers(2);
+        let mut pool = WorkerPool::builder(config)
+            .on_worker_start(|_| {})
+            .on_worker_stop(|_| {})
+            .build();
+        assert_eq!(pool.worker_stats().len(), 2);
+        pool.start().unwrap();
+
+        thread::sleep(Duration::from_millis(200));
+        let stats = pool.stats();
+        let worker_stats = pool.worker_stats().to_vec();
+        pool.shutdown().unwrap();
+
+        let per_worker: u128 = worker_stats.iter().map(|s| s.get().0).sum();
+        assert_eq!(per_worker, stats.get().0);
+        assert!(worker_stats.iter().all(|s| s.get().0 > 0));
+    }
+
+    #[test]
+    fn test_pause_idles_workers_until_resumed() {
+        let config = GeneratorConfig::new(vec!["zzzzzzzz".to_string()]).with_workers(2).with_update_interval(3600);
+        let mut pool = WorkerPool::builder(config)
+            .stall_timeout(Duration::from_millis(200))
+            .sink(NullSink)
+            .on_worker_start(|_| {})
+            .on_worker_stop(|_| {})
+            .build();
+        pool.start().unwrap();
+        let stats = pool.stats();
+        thread::sleep(Duration::from_millis(100));
+
+        assert!(pool.pause());
+        assert!(!pool.pause());
+        // Give the workers time to finish their current candidate
+        thread::sleep(Duration::from_millis(100));
+        let paused_at = stats.get().0;
+        thread::sleep(Duration::from_millis(400));
+        assert_eq!(stats.get().0, paused_at);
+        assert!(pool.health().is_healthy());
+
+        assert!(pool.resume());
+        assert!(!pool.is_paused());
+        thread::sleep(Duration::from_millis(100));
+        assert!(stats.get().0 > paused_at);
+
+        // Paused workers still notice the stop flag
+        pool.pause_handle().pause();
+        let started = Instant::now();
+        pool.shutdown().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_finds_skip_ahead_of_stats() {
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let (found_events, stats_events) = (Arc::clone(&events), Arc::clone(&events));
+        let config = GeneratorConfig::new(vec!["a".to_string()]).with_workers(1);
+        let mut pool = WorkerPool::builder(config)
+            .sink(NullSink)
+            .on_found(move |result| found_events.lock().unwrap().push(result.hostname.clone()))
+            .on_stats(move |generated, _| stats_events.lock().unwrap().push(format!("stats {}", generated)))
+            .build();
+
+        for generated in 1..=3 {
+            pool.sender.send(WorkerMessage::Stats(generated, 0)).unwrap();
+        }
+        let result = crate::generate_onion_address().unwrap();
+        pool.sender.send(WorkerMessage::Found(result.clone())).unwrap();
+        let first = pool.receiver.recv().unwrap();
+        pool.queue(first);
+        while let Some(message) = pool.backlog.pop_front() {
+            pool.handle_message(message).unwrap();
+        }
+
+        assert_eq!(*events.lock().unwrap(), [result.hostname, "stats 3".to_string()]);
+    }
+
+    #[test]
+    fn test_public_only_drops_keys_in_the_worker() {
+        let config = GeneratorConfig::new(vec!["a".to_string()]).with_workers(1).with_update_interval(3600);
+        let mut pool = WorkerPool::builder(config)
+            .public_only(true)
+            .sink(NullSink)
+            .on_worker_start(|_| {})
+            .on_worker_stop(|_| {})
+            .build();
+        pool.start().unwrap();
+
+        let result = loop {
+            if let WorkerMessage::Found(result) = pool.receiver.recv().unwrap() {
+                break result;
+            }
+        };
+        assert!(result.hostname.starts_with('a'));
+        assert!(!result.public_key.is_empty());
+        assert_eq!(result.custody, crate::KeyCustody::Discarded);
+        assert!(result.private_key.is_empty() && result.seed.is_none());
+        pool.stop_handle().store(true, Ordering::Relaxed);
+        pool.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_seeded_shards_continue_at_their_counters() {
+        let seed = [5u8; 32];
+        let first_match = |from: u64| {
+            (from..)
+                .map(|counter| crate::onion_result_from_signing_key(&crate::seeded_keypair(&seed, 1, counter).0).unwrap())
+                .find(|result| result.hostname.starts_with('a'))
+                .unwrap()
+        };
+        let skipped = first_match(0);
+        let expected = first_match(1000);
+        assert_ne!(skipped.hostname, expected.hostname);
+
+        let config = GeneratorConfig::new(vec!["a".to_string()]).with_workers(2).with_update_interval(3600);
+        let mut pool = WorkerPool::builder(config)
+            .seed(seed)
+            .seed_counters(vec![0, 1000])
+            .sink(NullSink)
+            .on_worker_start(|_| {})
+            .on_worker_stop(|_| {})
+            .build();
+        pool.start().unwrap();
+        let mut hostnames = Vec::new();
+        while !hostnames.contains(&expected.hostname) {
+            if let WorkerMessage::Found(result) = pool.receiver.recv().unwrap() {
+                hostnames.push(result.hostname);
+            }
+        }
+        pool.stop_handle().store(true, Ordering::Relaxed);
+        pool.shutdown().unwrap();
+        assert!(!hostnames.contains(&skipped.hostname));
+    }
+
+    #[test]
+    fn test_candidate_source_feeds_the_workers() {
+        let seed = [6u8; 32];
+        let expected = (0..)
+            .map(|counter| crate::onion_result_from_signing_key(&crate::seeded_keypair(&seed, 7, counter).0).unwrap())
+            .find(|result| result.hostname.starts_with('a'))
+            .unwrap();
+
+        let config = GeneratorConfig::new(vec!["a".to_string()]).with_workers(1).with_update_interval(3600);
+        let mut pool = WorkerPool::builder(config)
+            .seed([0u8; 32])
+            .candidate_source(move |_| Ok(Box::new(SeededKeys::new(seed, 7)) as Box<dyn CandidateSource>))
+            .sink(NullSink)
+            .on_worker_start(|_| {})
+            .on_worker_stop(|_| {})
+            .build();
+        pool.start().unwrap();
+        let found = loop {
+            if let WorkerMessage::Found(result) = pool.receiver.recv().unwrap() {
+                break result;
+            }
+        };
+        pool.stop_handle().store(true, Ordering::Relaxed);
+        pool.shutdown().unwrap();
+        assert_eq!(found.hostname, expected.hostname);
+        assert_eq!(found.private_key, expected.private_key);
+
+        // A source that cannot be set up fails the start
+        let config = GeneratorConfig::new(vec!["a".to_string()]).with_workers(2);
+        let mut pool = WorkerPool::builder(config)
+            .candidate_source(|worker_id| match worker_id {
+                0 => Ok(Box::new(RandomKeys::new()) as Box<dyn CandidateSource>),
+                _ => Err(anyhow::anyhow!("no device for worker {}", worker_id)),
+            })
+            .sink(NullSink)
+            .on_worker_start(|_| {})
+            .on_worker_stop(|_| {})
+            .build();
+        assert!(pool.start().unwrap_err().to_string().contains("no device for worker 1"));
+        pool.stop_handle().store(true, Ordering::Relaxed);
+        let _ = pool.shutdown();
+    }
+
+    struct NullSink;
+
+    impl ResultSink for NullSink {
+        fn found(&mut self, _result: &OnionResult) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_restart_policy_limit() {
+        let (runs, restarting) = panicking_worker(RestartPolicy::Limit(2));
+
+        assert_eq!(runs, 3);
+        assert_eq!(restarting, vec![true, true, false]);
+    }
+
+    #[test]
+    fn test_restart_policy_never() {
+        let (runs, restarting) = panicking_worker(RestartPolicy::Never);
+
+        assert_eq!(runs, 1);
+        assert_eq!(restarting, vec![false]);
+    }
 }